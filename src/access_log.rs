@@ -0,0 +1,174 @@
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+use actix_web::dev::ServiceResponse;
+use actix_web::HttpMessage;
+use chrono::{DateTime, Utc};
+use log::{info, error};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::audit::AuditContext;
+use crate::web::proxy::client_ip;
+use crate::web::request_id;
+
+// Size at which the access log file is rotated
+const DEFAULT_ACCESS_LOG_MAX_BYTES: u64 = 100 * 1024 * 1024;
+// Rotated files kept next to the current one
+const DEFAULT_ACCESS_LOG_MAX_FILES: usize = 5;
+
+/// Environment variables for configuring the access log:
+///
+/// - `ACCESS_LOG`: Where to write one JSON line per HTTP request: "stdout", a file path,
+///   or "off" (default: "off"). Application logs keep going to stderr.
+/// - `ACCESS_LOG_MAX_BYTES`: Size at which the access log file is rotated (default: 104857600)
+/// - `ACCESS_LOG_MAX_FILES`: Rotated files kept as `<path>.1` ... `<path>.N` (default: 5)
+
+#[derive(Debug, Serialize)]
+struct AccessEntry<'a> {
+    ts: DateTime<Utc>,
+    method: &'a str,
+    path: &'a str,
+    status: u16,
+    latency_ms: f64,
+    client: Option<String>,
+    request_id: Option<String>,
+    user: Option<String>,
+    session_id: Option<Uuid>,
+    prompt_tokens: usize,
+    completion_tokens: usize,
+}
+
+// A log file that is renamed aside once it grows past `max_bytes`
+struct RotatingFile {
+    path: String,
+    max_bytes: u64,
+    max_files: usize,
+    // Opened lazily on the first write
+    file: Option<File>,
+    written: u64,
+}
+
+impl RotatingFile {
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.file.is_some() && self.written + line.len() as u64 + 1 > self.max_bytes {
+            self.rotate()?;
+        }
+        if self.file.is_none() {
+            let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+            self.written = file.metadata()?.len();
+            self.file = Some(file);
+        }
+        if let Some(file) = self.file.as_mut() {
+            writeln!(file, "{}", line)?;
+            self.written += line.len() as u64 + 1;
+        }
+        Ok(())
+    }
+
+    // Shift `<path>.1` .. `<path>.N-1` up by one and move the current file to `<path>.1`
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+        if self.max_files == 0 {
+            return fs::remove_file(&self.path);
+        }
+        for n in (1..self.max_files).rev() {
+            let from = format!("{}.{}", self.path, n);
+            if fs::metadata(&from).is_ok() {
+                fs::rename(&from, format!("{}.{}", self.path, n + 1))?;
+            }
+        }
+        fs::rename(&self.path, format!("{}.1", self.path))
+    }
+}
+
+enum Sink {
+    Off,
+    Stdout,
+    File(Mutex<RotatingFile>),
+}
+
+// Structured per-request log for ingestion by log pipelines, separate from the application log
+pub struct AccessLog {
+    sink: Sink,
+}
+
+static ACCESS_LOG: OnceLock<AccessLog> = OnceLock::new();
+
+pub fn access_log() -> &'static AccessLog {
+    ACCESS_LOG.get_or_init(|| {
+        let target = env::var("ACCESS_LOG").unwrap_or_default();
+        let sink = match target.trim() {
+            "" | "off" => Sink::Off,
+            "stdout" | "-" => Sink::Stdout,
+            path => Sink::File(Mutex::new(RotatingFile {
+                path: path.to_string(),
+                max_bytes: env::var("ACCESS_LOG_MAX_BYTES")
+                    .ok()
+                    .and_then(|v| v.parse::<u64>().ok())
+                    .unwrap_or(DEFAULT_ACCESS_LOG_MAX_BYTES)
+                    .max(1),
+                max_files: env::var("ACCESS_LOG_MAX_FILES")
+                    .ok()
+                    .and_then(|v| v.parse::<usize>().ok())
+                    .unwrap_or(DEFAULT_ACCESS_LOG_MAX_FILES),
+                file: None,
+                written: 0,
+            })),
+        };
+        if !matches!(sink, Sink::Off) {
+            info!("Writing access log to {}", target.trim());
+        }
+        AccessLog { sink }
+    })
+}
+
+impl AccessLog {
+    // Record a finished request; called by the logging middleware for every request
+    pub fn record<B>(&self, res: &ServiceResponse<B>, started: Instant) {
+        if matches!(self.sink, Sink::Off) {
+            return;
+        }
+
+        let req = res.request();
+        let context = req.extensions().get::<AuditContext>().cloned().unwrap_or_default();
+        let entry = AccessEntry {
+            ts: Utc::now(),
+            method: req.method().as_str(),
+            path: req.path(),
+            status: res.status().as_u16(),
+            latency_ms: started.elapsed().as_secs_f64() * 1000.0,
+            client: client_ip(req).map(|ip| ip.to_string()),
+            request_id: request_id::current(),
+            user: context.user,
+            session_id: context.session_id,
+            prompt_tokens: context.prompt_tokens,
+            completion_tokens: context.completion_tokens,
+        };
+        let line = match serde_json::to_string(&entry) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Failed to serialize access log entry: {}", e);
+                return;
+            }
+        };
+
+        match &self.sink {
+            Sink::Off => {}
+            Sink::Stdout => {
+                let mut stdout = io::stdout().lock();
+                if let Err(e) = writeln!(stdout, "{}", line) {
+                    error!("Failed to write access log entry: {}", e);
+                }
+            }
+            Sink::File(file) => {
+                let Ok(mut file) = file.lock() else { return };
+                if let Err(e) = file.write_line(&line) {
+                    error!("Failed to write access log entry to {}: {}", file.path, e);
+                }
+            }
+        }
+    }
+}
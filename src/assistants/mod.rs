@@ -0,0 +1,198 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use log::{info, warn};
+
+use crate::prompts::is_valid_name;
+use crate::web::models::{Message, Role};
+
+// Default directory holding the assistant profiles
+const DEFAULT_ASSISTANTS_DIR: &str = "assistants";
+// Most few-shot examples an assistant can carry
+pub const MAX_EXAMPLES: usize = 20;
+
+/// Environment variables for configuring assistant profiles:
+///
+/// - `ASSISTANTS_DIR`: Directory of assistant profiles, one `<name>.toml` file each (default: "assistants")
+///
+/// An assistant bundles a system prompt with sampling defaults, a model choice and the tools
+/// it may use; every field except the system prompt is optional:
+///
+/// ```toml
+/// description = "Helps with Rust code"
+/// system_prompt = "You are a senior Rust engineer. Answer with idiomatic, compiling code."
+/// model = "codellama"
+/// temperature = 0.2
+/// top_p = 0.9
+/// max_tokens = 1024
+/// tools = ["search_docs"]
+/// post_amble = "Please provide a detailed and comprehensive answer."  # appended to each message
+///
+/// # Example exchanges sent, in order, after the system prompt of every request
+/// [[examples]]
+/// user = "Reverse a string"
+/// assistant = "```rust\nlet reversed: String = s.chars().rev().collect();\n```"
+/// ```
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Assistant {
+    #[serde(skip_deserializing)]
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub system_prompt: String,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub max_tokens: Option<usize>,
+    // Names of the tools this assistant is allowed to call
+    #[serde(default)]
+    pub tools: Vec<String>,
+    // Instruction appended to every user message sent to the model; history keeps the
+    // message as written
+    #[serde(default)]
+    pub post_amble: Option<String>,
+    // Few-shot exchanges showing the model the expected answers; kept last so they serialize
+    // as TOML tables after the plain values
+    #[serde(default)]
+    pub examples: Vec<FewShotExample>,
+}
+
+// A user message and the answer the assistant should give to it
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FewShotExample {
+    pub user: String,
+    pub assistant: String,
+}
+
+impl FewShotExample {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.user.trim().is_empty() || self.assistant.trim().is_empty() {
+            return Err("Examples need both a user and an assistant message".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl Assistant {
+    // A user message as sent to the model, followed by the post-amble if the assistant has one
+    pub fn with_post_amble(&self, message: &str) -> String {
+        match self.post_amble.as_deref().map(str::trim).filter(|post_amble| !post_amble.is_empty()) {
+            Some(post_amble) => format!("{}\n\n{}", message, post_amble),
+            None => message.to_string(),
+        }
+    }
+
+    // The examples as conversation turns, to go between the system prompt and the history
+    pub fn example_messages(&self) -> Vec<Message> {
+        self.examples
+            .iter()
+            .flat_map(|example| [
+                Message { role: Role::User, content: example.user.clone() },
+                Message { role: Role::Assistant, content: example.assistant.clone() },
+            ])
+            .collect()
+    }
+}
+
+// Assistant profiles persisted as files in the assistants directory
+pub struct AssistantStore {
+    dir: PathBuf,
+    assistants: RwLock<BTreeMap<String, Assistant>>,
+}
+
+impl AssistantStore {
+    pub fn load() -> Self {
+        let dir = PathBuf::from(env::var("ASSISTANTS_DIR").unwrap_or_else(|_| DEFAULT_ASSISTANTS_DIR.to_string()));
+        let mut assistants = BTreeMap::new();
+
+        match fs::read_dir(&dir) {
+            Ok(entries) => {
+                for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+                    if path.extension().and_then(|ext| ext.to_str()) != Some("toml") {
+                        continue;
+                    }
+                    match Self::read_assistant(&path) {
+                        Ok(assistant) => {
+                            assistants.insert(assistant.name.clone(), assistant);
+                        }
+                        Err(e) => warn!("Skipping assistant profile {}: {:#}", path.display(), e),
+                    }
+                }
+                info!("Loaded {} assistant profile(s) from {}", assistants.len(), dir.display());
+            }
+            Err(_) => info!("No assistant profiles directory at {}", dir.display()),
+        }
+
+        Self {
+            dir,
+            assistants: RwLock::new(assistants),
+        }
+    }
+
+    fn read_assistant(path: &Path) -> Result<Assistant> {
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .filter(|stem| is_valid_name(stem))
+            .ok_or_else(|| anyhow::anyhow!("Invalid assistant file name"))?
+            .to_string();
+        let contents = fs::read_to_string(path)?;
+        let mut assistant: Assistant = toml::from_str(&contents)?;
+        assistant.name = name;
+        Ok(assistant)
+    }
+
+    pub fn list(&self) -> Vec<Assistant> {
+        self.assistants
+            .read()
+            .map(|assistants| assistants.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<Assistant> {
+        self.assistants.read().ok()?.get(name).cloned()
+    }
+
+    // Create or replace an assistant and persist it to disk.
+    // Returns true when an existing assistant was replaced.
+    pub fn save(&self, mut assistant: Assistant, name: &str) -> Result<bool> {
+        if !is_valid_name(name) {
+            return Err(anyhow::anyhow!("Assistant names may only contain letters, digits, '-' and '_'"));
+        }
+        assistant.name = name.to_string();
+
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.dir.join(format!("{}.toml", name)), toml::to_string(&assistant)?)?;
+
+        let mut assistants = self.assistants
+            .write()
+            .map_err(|_| anyhow::anyhow!("Assistant store lock poisoned"))?;
+        Ok(assistants.insert(name.to_string(), assistant).is_some())
+    }
+
+    // Returns false when no assistant had that name
+    pub fn delete(&self, name: &str) -> Result<bool> {
+        let mut assistants = self.assistants
+            .write()
+            .map_err(|_| anyhow::anyhow!("Assistant store lock poisoned"))?;
+        if assistants.remove(name).is_none() {
+            return Ok(false);
+        }
+
+        let path = self.dir.join(format!("{}.toml", name));
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,227 @@
+pub mod usage;
+
+use std::collections::VecDeque;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+use actix_web::dev::ServiceResponse;
+use actix_web::{HttpMessage, HttpRequest};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use log::error;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::web::proxy::client_ip;
+use crate::web::request_id;
+
+// Default file receiving audit entries
+const DEFAULT_AUDIT_LOG_PATH: &str = "audit.jsonl";
+// Entries returned by a query when no limit is given, and the most it may ask for
+const DEFAULT_AUDIT_QUERY_LIMIT: usize = 100;
+const MAX_AUDIT_QUERY_LIMIT: usize = 1000;
+
+/// Environment variables for configuring the audit log:
+///
+/// - `AUDIT_LOG_PATH`: Append-only JSON lines file recording every API call (default: "audit.jsonl")
+
+// Details only the handler knows, attached to the request for the audit and access logs
+#[derive(Debug, Clone, Default)]
+pub struct AuditContext {
+    pub user: Option<String>,
+    pub session_id: Option<Uuid>,
+    // Model that served a generation
+    pub model: Option<String>,
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    // Price of those tokens at the model's configured rates
+    pub estimated_cost: f64,
+}
+
+// Attach audit details to a request; later calls replace earlier ones
+pub fn annotate(req: &HttpRequest, context: AuditContext) {
+    req.extensions_mut().insert(context);
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AuditEntry {
+    pub at: DateTime<Utc>,
+    pub method: String,
+    pub endpoint: String,
+    pub status: u16,
+    pub duration_ms: u64,
+    pub client: Option<String>,
+    pub user: Option<String>,
+    pub session_id: Option<Uuid>,
+    #[serde(default)]
+    pub model: Option<String>,
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    #[serde(default)]
+    pub estimated_cost: f64,
+    // Matches the `X-Request-Id` of the response and the application log lines
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+// Filters accepted by the audit query endpoint
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditQuery {
+    pub user: Option<String>,
+    // Matches endpoints starting with this path
+    pub endpoint: Option<String>,
+    pub session_id: Option<Uuid>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.user.as_ref().is_none_or(|user| entry.user.as_ref() == Some(user))
+            && self.endpoint.as_ref().is_none_or(|endpoint| entry.endpoint.starts_with(endpoint.as_str()))
+            && self.session_id.is_none_or(|id| entry.session_id == Some(id))
+            && self.since.is_none_or(|since| entry.at >= since)
+            && self.until.is_none_or(|until| entry.at <= until)
+    }
+}
+
+// Append-only record of API calls
+pub struct AuditLog {
+    path: String,
+    // Opened lazily on the first write
+    file: Mutex<Option<File>>,
+}
+
+static AUDIT: OnceLock<AuditLog> = OnceLock::new();
+
+pub fn audit() -> &'static AuditLog {
+    AUDIT.get_or_init(|| AuditLog {
+        path: env::var("AUDIT_LOG_PATH").unwrap_or_else(|_| DEFAULT_AUDIT_LOG_PATH.to_string()),
+        file: Mutex::new(None),
+    })
+}
+
+impl AuditLog {
+    // Record a finished API call; called by the audit middleware for every /api request
+    pub fn record_response<B>(&self, res: &ServiceResponse<B>, started: Instant) {
+        let req = res.request();
+        if !req.path().starts_with("/api/") {
+            return;
+        }
+
+        let context = req.extensions().get::<AuditContext>().cloned().unwrap_or_default();
+        let entry = AuditEntry {
+            at: Utc::now(),
+            method: req.method().to_string(),
+            endpoint: req.path().to_string(),
+            status: res.status().as_u16(),
+            duration_ms: started.elapsed().as_millis() as u64,
+            client: client_ip(req).map(|ip| ip.to_string()),
+            user: context.user,
+            session_id: context.session_id,
+            model: context.model,
+            prompt_tokens: context.prompt_tokens,
+            completion_tokens: context.completion_tokens,
+            estimated_cost: context.estimated_cost,
+            request_id: request_id::current(),
+        };
+
+        if let Err(e) = self.append(&entry) {
+            error!("Failed to write audit entry to {}: {}", self.path, e);
+        }
+    }
+
+    fn append(&self, entry: &AuditEntry) -> Result<()> {
+        let line = serde_json::to_string(entry)?;
+        let mut file = self.file.lock().map_err(|_| anyhow::anyhow!("audit log lock poisoned"))?;
+        if file.is_none() {
+            *file = Some(OpenOptions::new().create(true).append(true).open(&self.path)?);
+        }
+        if let Some(file) = file.as_mut() {
+            writeln!(file, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    // Most recent entries matching the query, newest first
+    pub fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>> {
+        let limit = query.limit.unwrap_or(DEFAULT_AUDIT_QUERY_LIMIT).clamp(1, MAX_AUDIT_QUERY_LIMIT);
+        let mut matches = VecDeque::with_capacity(limit);
+        self.scan(|entry| {
+            if !query.matches(&entry) {
+                return;
+            }
+            if matches.len() == limit {
+                matches.pop_front();
+            }
+            matches.push_back(entry);
+        })?;
+
+        Ok(matches.into_iter().rev().collect())
+    }
+
+    // Every entry recorded for a user, oldest first
+    pub fn entries_for_user(&self, user: &str) -> Result<Vec<AuditEntry>> {
+        let mut entries = Vec::new();
+        self.scan(|entry| {
+            if entry.user.as_deref() == Some(user) {
+                entries.push(entry);
+            }
+        })?;
+        Ok(entries)
+    }
+
+    // Rewrite the log without a user's entries, returning how many were removed.
+    // This is the only operation that modifies existing entries.
+    pub fn purge_user(&self, user: &str) -> Result<usize> {
+        // Hold the writer lock so no entry is appended to the file being replaced
+        let mut file = self.file.lock().map_err(|_| anyhow::anyhow!("audit log lock poisoned"))?;
+        let mut kept = Vec::new();
+        let mut removed = 0;
+        self.scan(|entry| {
+            if entry.user.as_deref() == Some(user) {
+                removed += 1;
+            } else {
+                kept.push(entry);
+            }
+        })?;
+        if removed == 0 {
+            return Ok(0);
+        }
+
+        let tmp_path = format!("{}.tmp", self.path);
+        {
+            let mut tmp = File::create(&tmp_path)?;
+            for entry in &kept {
+                writeln!(tmp, "{}", serde_json::to_string(entry)?)?;
+            }
+            tmp.sync_all()?;
+        }
+        fs::rename(&tmp_path, &self.path)?;
+        // The old handle points at the replaced file
+        *file = None;
+        Ok(removed)
+    }
+
+    fn scan(&self, mut f: impl FnMut(AuditEntry)) -> Result<()> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            // Skip a partially written last line rather than failing the read
+            if let Ok(entry) = serde_json::from_str::<AuditEntry>(&line) {
+                f(entry);
+            }
+        }
+        Ok(())
+    }
+}
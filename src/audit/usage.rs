@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use super::{AuditEntry, AuditLog};
+use crate::stats::ANONYMOUS_USER;
+
+// Model recorded for generations logged before audit entries carried one
+const UNKNOWN_MODEL: &str = "unknown";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum UsageGrouping {
+    #[default]
+    Day,
+    User,
+    Model,
+}
+
+// Filters accepted by the usage endpoint
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UsageQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    // "day" (default), "user" or "model"
+    #[serde(default)]
+    pub group_by: UsageGrouping,
+}
+
+// Token usage of one day
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct UsagePoint {
+    pub day: NaiveDate,
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub estimated_cost: f64,
+}
+
+// Token usage of one group, with its daily breakdown; for `day` grouping each series is a single day
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct UsageSeries {
+    pub key: String,
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub estimated_cost: f64,
+    pub points: Vec<UsagePoint>,
+}
+
+impl UsagePoint {
+    fn add(&mut self, entry: &AuditEntry) {
+        self.requests += 1;
+        self.prompt_tokens += entry.prompt_tokens as u64;
+        self.completion_tokens += entry.completion_tokens as u64;
+        self.estimated_cost += entry.estimated_cost;
+    }
+}
+
+// Whether an entry records a generation rather than some other API call
+fn is_generation(entry: &AuditEntry) -> bool {
+    entry.model.is_some() || entry.prompt_tokens + entry.completion_tokens > 0
+}
+
+impl AuditLog {
+    // Aggregate the token usage recorded in the audit log, groups sorted by key
+    pub fn usage(&self, query: &UsageQuery) -> Result<Vec<UsageSeries>> {
+        // Daily totals of each group
+        let mut groups: BTreeMap<String, BTreeMap<NaiveDate, UsagePoint>> = BTreeMap::new();
+        self.scan(|entry| {
+            if !is_generation(&entry)
+                || query.from.is_some_and(|from| entry.at < from)
+                || query.to.is_some_and(|to| entry.at > to)
+            {
+                return;
+            }
+            let day = entry.at.date_naive();
+            let key = match query.group_by {
+                UsageGrouping::Day => day.to_string(),
+                UsageGrouping::User => entry.user.clone().unwrap_or_else(|| ANONYMOUS_USER.to_string()),
+                UsageGrouping::Model => entry.model.clone().unwrap_or_else(|| UNKNOWN_MODEL.to_string()),
+            };
+            groups
+                .entry(key)
+                .or_default()
+                .entry(day)
+                .or_insert_with(|| UsagePoint { day, requests: 0, prompt_tokens: 0, completion_tokens: 0, estimated_cost: 0.0 })
+                .add(&entry);
+        })?;
+
+        Ok(groups
+            .into_iter()
+            .map(|(key, days)| {
+                let points: Vec<UsagePoint> = days.into_values().collect();
+                UsageSeries {
+                    key,
+                    requests: points.iter().map(|p| p.requests).sum(),
+                    prompt_tokens: points.iter().map(|p| p.prompt_tokens).sum(),
+                    completion_tokens: points.iter().map(|p| p.completion_tokens).sum(),
+                    estimated_cost: points.iter().map(|p| p.estimated_cost).sum(),
+                    points,
+                }
+            })
+            .collect())
+    }
+}
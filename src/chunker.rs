@@ -0,0 +1,47 @@
+// Boundaries text is split at, from the most to the least natural
+const SEPARATORS: [&str; 4] = ["\n\n", "\n", ". ", " "];
+
+// Split text into consecutive chunks of at most `max_tokens` tokens as counted by `estimate`,
+// breaking between paragraphs where possible, then between lines, sentences and words. Joined
+// back together, the chunks give the original text.
+pub fn chunk(text: &str, max_tokens: usize, estimate: impl Fn(&str) -> usize) -> Vec<String> {
+    let mut pieces = Vec::new();
+    split_to_fit(text, max_tokens, &estimate, 0, &mut pieces);
+
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for piece in pieces {
+        if !current.is_empty() && estimate(&current) + estimate(piece) > max_tokens {
+            chunks.push(std::mem::take(&mut current));
+        }
+        current.push_str(piece);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks.retain(|chunk| !chunk.trim().is_empty());
+    chunks
+}
+
+// Break text at the separator of `level` until every piece fits, cutting between characters
+// once no separator is left
+fn split_to_fit<'a>(text: &'a str, max_tokens: usize, estimate: &impl Fn(&str) -> usize, level: usize, pieces: &mut Vec<&'a str>) {
+    if estimate(text) <= max_tokens {
+        pieces.push(text);
+        return;
+    }
+    let Some(separator) = SEPARATORS.get(level) else {
+        let mut start = 0;
+        for (index, _) in text.char_indices().skip(1) {
+            if estimate(&text[start..index]) >= max_tokens {
+                pieces.push(&text[start..index]);
+                start = index;
+            }
+        }
+        pieces.push(&text[start..]);
+        return;
+    };
+    for part in text.split_inclusive(separator) {
+        split_to_fit(part, max_tokens, estimate, level + 1, pieces);
+    }
+}
@@ -0,0 +1,406 @@
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::time::{Duration, Instant};
+use serde_json::{json, Map, Value};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::mpsc::UnboundedSender;
+use uuid::Uuid;
+
+use crate::http_client::http_client;
+use crate::prompts::PromptLibrary;
+
+#[cfg(feature = "tui")]
+pub mod tui;
+
+// Server the client talks to unless `--url` is given
+const DEFAULT_CLI_SERVER_URL: &str = "http://127.0.0.1:8080";
+// Backend used by `--direct` when MISTRAL_SERVER_URL is unset
+const DEFAULT_BACKEND_URL: &str = "http://localhost:8081";
+
+/// `llama-web-app chat [--url <server>] [--direct] [--model <name>] [--assistant <name>] [--session <id>]`
+///
+/// Chats from the terminal. By default it talks to a running server's `/api/chat`
+/// (`--url`, or `LLAMA_SERVER_URL`, default: "http://127.0.0.1:8080"), so sessions,
+/// assistants, moderation and usage accounting apply. With `--direct` it talks to the
+/// backend's OpenAI-compatible API (`MISTRAL_SERVER_URL`, first replica) without a server,
+/// keeping the conversation locally and streaming the answer as it is generated.
+///
+/// Lines starting with `/` are commands; see `HELP`.
+///
+/// `llama-web-app ask [options] [--template <name>] [--max-tokens <n>] [question]`
+///
+/// Answers one question and exits, printing only the answer to stdout, for use in shell
+/// pipelines. Text piped to stdin is the question, or follows it when both are given. A
+/// template gets the question as `message`; with `--direct` it is rendered from `PROMPTS_DIR`.
+
+const HELP: &str = "\
+Commands:
+  /new             start a new session
+  /temp <value>    set the sampling temperature (0 to 2), or /temp to reset it
+  /model <name>    switch model, or /model to use the default
+  /save <path>     save the transcript as markdown
+  /help            show this help
+  /quit            exit (also Ctrl-D)";
+
+// Where streamed answer text goes
+#[derive(Clone)]
+#[cfg_attr(not(feature = "tui"), allow(dead_code))]
+enum Output {
+    Stdout,
+    Channel(UnboundedSender<String>),
+}
+
+// An answer and what it took to generate
+#[cfg_attr(not(feature = "tui"), allow(dead_code))]
+struct Reply {
+    text: String,
+    // Reported by the server or backend, or counted from the streamed chunks
+    completion_tokens: Option<usize>,
+    latency: Duration,
+}
+
+#[derive(Clone)]
+struct ChatClient {
+    server_url: String,
+    direct: bool,
+    model: Option<String>,
+    assistant: Option<String>,
+    session_id: Uuid,
+    temperature: Option<f32>,
+    template: Option<String>,
+    max_tokens: Option<usize>,
+    // The conversation so far, as (role, content)
+    transcript: Vec<(String, String)>,
+    output: Output,
+}
+
+impl ChatClient {
+    // Parse the options shared by `chat` and `ask`, returning the remaining positional arguments
+    fn from_args(args: &[String]) -> Result<(Self, Vec<String>), String> {
+        let mut client = Self {
+            server_url: env::var("LLAMA_SERVER_URL").unwrap_or_else(|_| DEFAULT_CLI_SERVER_URL.to_string()),
+            direct: false,
+            model: None,
+            assistant: None,
+            session_id: Uuid::new_v4(),
+            temperature: None,
+            template: None,
+            max_tokens: None,
+            transcript: Vec::new(),
+            output: Output::Stdout,
+        };
+        let mut positional = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().cloned().ok_or_else(|| format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--url" => client.server_url = value()?,
+                "--direct" => client.direct = true,
+                "--model" => client.model = Some(value()?),
+                "--assistant" => client.assistant = Some(value()?),
+                "--session" => {
+                    client.session_id = value()?.parse().map_err(|_| "--session needs a session ID".to_string())?
+                }
+                "--template" => client.template = Some(value()?),
+                "--max-tokens" => {
+                    client.max_tokens = Some(value()?.parse().map_err(|_| "--max-tokens needs a number".to_string())?)
+                }
+                other if other.starts_with("--") => return Err(format!("Unknown argument: {}", other)),
+                other => positional.push(other.to_string()),
+            }
+        }
+        if client.direct {
+            client.server_url = env::var("MISTRAL_SERVER_URL")
+                .ok()
+                .and_then(|urls| urls.split(',').next().map(|url| url.trim().to_string()))
+                .filter(|url| !url.is_empty())
+                .unwrap_or_else(|| DEFAULT_BACKEND_URL.to_string());
+        }
+        Ok((client, positional))
+    }
+
+    // Handle a `/command`, returning the feedback to show, or None when the client should exit
+    async fn command(&mut self, line: &str) -> Option<String> {
+        let (name, arg) = line.split_once(' ').map_or((line, ""), |(name, arg)| (name, arg.trim()));
+        let feedback = match name {
+            "/quit" | "/exit" => return None,
+            "/help" => HELP.to_string(),
+            "/new" => {
+                self.session_id = Uuid::new_v4();
+                self.transcript.clear();
+                // Carry the temperature over to the new session
+                let saved = if self.temperature.is_some() { self.save_settings().await } else { Ok(()) };
+                match saved {
+                    Ok(()) => format!("Started session {}", self.session_id),
+                    Err(e) => format!("Started session {}, but setting its temperature failed: {}", self.session_id, e),
+                }
+            }
+            "/temp" => {
+                let temperature = if arg.is_empty() {
+                    None
+                } else {
+                    match arg.parse::<f32>() {
+                        Ok(t) if (0.0..=2.0).contains(&t) => Some(t),
+                        _ => return Some("Temperature must be a number between 0 and 2".to_string()),
+                    }
+                };
+                self.temperature = temperature;
+                match self.save_settings().await {
+                    Ok(()) => format!("Temperature: {}", temperature.map_or("default".to_string(), |t| t.to_string())),
+                    Err(e) => format!("Error: {}", e),
+                }
+            }
+            "/model" => {
+                self.model = (!arg.is_empty()).then(|| arg.to_string());
+                format!("Model: {}", self.model.as_deref().unwrap_or("default"))
+            }
+            "/save" if arg.is_empty() => "Usage: /save <path>".to_string(),
+            "/save" => match fs::write(arg, self.transcript_markdown()) {
+                Ok(()) => format!("Saved transcript to {}", arg),
+                Err(e) => format!("Error: {}", e),
+            },
+            _ => format!("Unknown command {}; /help lists the commands", name),
+        };
+        Some(feedback)
+    }
+
+    // A server session keeps its temperature in the session settings
+    async fn save_settings(&self) -> Result<(), String> {
+        if self.direct {
+            return Ok(());
+        }
+        let url = format!("{}/api/sessions/{}/settings", self.server_url, self.session_id);
+        let response = http_client()
+            .put(url)
+            .json(&json!({ "temperature": self.temperature }))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(error_message(response).await);
+        }
+        Ok(())
+    }
+
+    fn transcript_markdown(&self) -> String {
+        let mut markdown = format!("# Chat session {}\n", self.session_id);
+        for (role, content) in &self.transcript {
+            markdown.push_str(&format!("\n**{}:**\n\n{}\n", role, content));
+        }
+        markdown
+    }
+
+    // Write part of an answer as it arrives
+    fn emit(&self, text: &str) {
+        match &self.output {
+            Output::Stdout => {
+                print!("{}", text);
+                let _ = io::stdout().flush();
+            }
+            Output::Channel(sender) => {
+                let _ = sender.send(text.to_string());
+            }
+        }
+    }
+
+    // Generate an answer without adding it to the transcript
+    async fn generate(&self, message: &str) -> Result<Reply, String> {
+        let started = Instant::now();
+        let (text, completion_tokens) = if self.direct {
+            self.send_direct(message).await?
+        } else {
+            self.send_to_server(message).await?
+        };
+        if matches!(self.output, Output::Stdout) {
+            println!();
+        }
+        Ok(Reply { text, completion_tokens, latency: started.elapsed() })
+    }
+
+    fn record(&mut self, message: &str, reply: &Reply) {
+        self.transcript.push(("user".to_string(), message.to_string()));
+        self.transcript.push(("assistant".to_string(), reply.text.clone()));
+    }
+
+    async fn send(&mut self, message: &str) -> Result<(), String> {
+        let reply = self.generate(message).await?;
+        self.record(message, &reply);
+        Ok(())
+    }
+
+    async fn send_to_server(&self, message: &str) -> Result<(String, Option<usize>), String> {
+        let response = http_client()
+            .post(format!("{}/api/chat", self.server_url))
+            .json(&json!({
+                "message": message,
+                "session_id": self.session_id,
+                "model": self.model,
+                "assistant": self.assistant,
+                "template": self.template,
+                "max_tokens": self.max_tokens,
+            }))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(error_message(response).await);
+        }
+        let body: Value = response.json().await.map_err(|e| e.to_string())?;
+        let reply = body["response"].as_str().unwrap_or_default().to_string();
+        // The server answers in one piece
+        self.emit(&reply);
+        let tokens = body["usage"]["completion_tokens"].as_u64().map(|tokens| tokens as usize);
+        Ok((reply, tokens))
+    }
+
+    // Stream a reply from the backend, emitting each chunk as it arrives
+    async fn send_direct(&self, message: &str) -> Result<(String, Option<usize>), String> {
+        // Templates are rendered here, as the backend doesn't know them
+        let message = match &self.template {
+            Some(template) => {
+                let mut vars = Map::new();
+                vars.insert("message".to_string(), json!(message));
+                PromptLibrary::load().render(template, &vars).map_err(|e| format!("{:#}", e))?
+            }
+            None => message.to_string(),
+        };
+        let mut messages: Vec<Value> = self.transcript
+            .iter()
+            .map(|(role, content)| json!({ "role": role, "content": content }))
+            .collect();
+        messages.push(json!({ "role": "user", "content": message }));
+
+        let mut payload = json!({
+            "model": self.model.clone().or_else(|| env::var("MODEL_NAME").ok()).unwrap_or_else(|| "local-model".to_string()),
+            "messages": messages,
+            "stream": true,
+        });
+        if let Some(temperature) = self.temperature {
+            payload["temperature"] = json!(temperature);
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            payload["max_tokens"] = json!(max_tokens);
+        }
+
+        let mut response = http_client()
+            .post(format!("{}/v1/chat/completions", self.server_url))
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(error_message(response).await);
+        }
+
+        // Server-sent events: `data: {...}` lines, ending with `data: [DONE]`
+        let mut reply = String::new();
+        let mut chunks = 0;
+        let mut reported_tokens = None;
+        let mut pending = String::new();
+        while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+            pending.push_str(&String::from_utf8_lossy(&chunk));
+            while let Some(end) = pending.find('\n') {
+                let line: String = pending.drain(..=end).collect();
+                let Some(data) = line.trim().strip_prefix("data:") else { continue };
+                let data = data.trim();
+                if data == "[DONE]" {
+                    break;
+                }
+                let Ok(event) = serde_json::from_str::<Value>(data) else { continue };
+                if let Some(delta) = event["choices"][0]["delta"]["content"].as_str() {
+                    self.emit(delta);
+                    reply.push_str(delta);
+                    chunks += 1;
+                }
+                if let Some(tokens) = event["usage"]["completion_tokens"].as_u64() {
+                    reported_tokens = Some(tokens as usize);
+                }
+            }
+        }
+        // Backends stream about one token per chunk when they don't report usage
+        Ok((reply, reported_tokens.or(Some(chunks))))
+    }
+}
+
+async fn error_message(response: reqwest::Response) -> String {
+    let status = response.status();
+    let body: Value = response.json().await.unwrap_or_default();
+    let message = body["error"]["message"]
+        .as_str()
+        .or_else(|| body["error"].as_str())
+        .unwrap_or("request failed");
+    format!("{} ({})", message, status)
+}
+
+// Run the interactive chat client until the user quits
+pub async fn chat(args: &[String]) -> io::Result<()> {
+    let mut client = match ChatClient::from_args(args) {
+        Ok((client, positional)) if positional.is_empty() => client,
+        Ok((_, positional)) => {
+            eprintln!("Unknown argument: {}", positional[0]);
+            std::process::exit(2);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    let target = if client.direct { "backend" } else { "server" };
+    println!("Chatting with the {} at {} (session {}). /help lists commands.", target, client.server_url, client.session_id);
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+        let Some(line) = lines.next_line().await? else { break };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('/') {
+            match client.command(line).await {
+                Some(feedback) => println!("{}", feedback),
+                None => break,
+            }
+            continue;
+        }
+        if let Err(e) = client.send(line).await {
+            println!("Error: {}", e);
+        }
+    }
+    Ok(())
+}
+
+// Answer a single question from the arguments and/or stdin, then exit
+pub async fn ask(args: &[String]) -> io::Result<()> {
+    let (mut client, positional) = match ChatClient::from_args(args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    let mut message = positional.join(" ");
+    if !io::stdin().is_terminal() {
+        let mut piped = String::new();
+        io::stdin().read_to_string(&mut piped)?;
+        if !piped.trim().is_empty() {
+            if !message.is_empty() {
+                message.push_str("\n\n");
+            }
+            message.push_str(piped.trim_end());
+        }
+    }
+    if message.trim().is_empty() {
+        eprintln!("Nothing to ask: pass a question or pipe text to stdin");
+        std::process::exit(2);
+    }
+
+    if let Err(e) = client.send(&message).await {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,278 @@
+use std::io;
+use std::time::Duration;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{Frame, Terminal};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use uuid::Uuid;
+
+use super::{ChatClient, Output, Reply};
+
+// How long the UI waits for a key before redrawing streamed text
+const INPUT_POLL: Duration = Duration::from_millis(50);
+// Width of the session list pane
+const SESSION_PANE_WIDTH: u16 = 24;
+
+/// `llama-web-app tui [--url <server>] [--direct] [--model <name>] [--assistant <name>] [--session <id>]`
+///
+/// The `chat` client as a terminal UI (built with `--features tui`): sessions opened in this
+/// run on the left, the conversation on the right, and a status bar with the tokens and
+/// latency of the last answer. The same `/commands` work in the input line.
+///
+/// Keys: Enter sends, Ctrl-N starts a session, Ctrl-Up/Ctrl-Down switch sessions,
+/// PageUp/PageDown scroll the conversation, Esc or Ctrl-C quits.
+
+// A session opened in this run, with its conversation
+struct SessionTab {
+    id: Uuid,
+    transcript: Vec<(String, String)>,
+}
+
+// An answer being generated in the background
+struct Pending {
+    message: String,
+    streamed: String,
+    chunks: UnboundedReceiver<String>,
+    result: tokio::task::JoinHandle<Result<Reply, String>>,
+}
+
+struct App {
+    client: ChatClient,
+    sessions: Vec<SessionTab>,
+    current: usize,
+    input: String,
+    status: String,
+    // Lines scrolled up from the bottom of the conversation
+    scroll: u16,
+    pending: Option<Pending>,
+}
+
+impl App {
+    fn new(client: ChatClient) -> Self {
+        let status = format!(
+            "{} at {}; /help lists commands",
+            if client.direct { "Backend" } else { "Server" },
+            client.server_url,
+        );
+        Self {
+            sessions: vec![SessionTab { id: client.session_id, transcript: Vec::new() }],
+            client,
+            current: 0,
+            input: String::new(),
+            status,
+            scroll: 0,
+            pending: None,
+        }
+    }
+
+    // Switch to another session, keeping the current one's conversation
+    fn switch_to(&mut self, index: usize) {
+        if index >= self.sessions.len() || index == self.current || self.pending.is_some() {
+            return;
+        }
+        self.sessions[self.current].transcript = std::mem::take(&mut self.client.transcript);
+        self.current = index;
+        self.client.session_id = self.sessions[index].id;
+        self.client.transcript = std::mem::take(&mut self.sessions[index].transcript);
+        self.scroll = 0;
+        self.status = format!("Session {}", self.client.session_id);
+    }
+
+    // Add a tab for the session `/new` just started, keeping the previous session's conversation
+    fn open_new_session(&mut self, previous_transcript: Vec<(String, String)>) {
+        self.sessions[self.current].transcript = previous_transcript;
+        self.sessions.push(SessionTab { id: self.client.session_id, transcript: Vec::new() });
+        self.current = self.sessions.len() - 1;
+        self.scroll = 0;
+    }
+
+    async fn submit(&mut self) -> bool {
+        let line = std::mem::take(&mut self.input);
+        let line = line.trim();
+        if line.is_empty() || self.pending.is_some() {
+            return true;
+        }
+        if line.starts_with('/') {
+            let previous = self.client.session_id;
+            let previous_transcript = self.client.transcript.clone();
+            let Some(feedback) = self.client.command(line).await else { return false };
+            if self.client.session_id != previous {
+                self.open_new_session(previous_transcript);
+            }
+            // Feedback is shown in the status bar, so /help's lines are joined
+            self.status = feedback.lines().collect::<Vec<_>>().join("  ");
+            return true;
+        }
+
+        let (sender, chunks) = mpsc::unbounded_channel();
+        let mut client = self.client.clone();
+        client.output = Output::Channel(sender);
+        let message = line.to_string();
+        let task_message = message.clone();
+        self.pending = Some(Pending {
+            message,
+            streamed: String::new(),
+            chunks,
+            result: tokio::spawn(async move { client.generate(&task_message).await }),
+        });
+        self.scroll = 0;
+        self.status = "Generating…".to_string();
+        true
+    }
+
+    // Collect streamed text and the finished answer, if any
+    async fn poll_pending(&mut self) {
+        let Some(pending) = &mut self.pending else { return };
+        while let Ok(chunk) = pending.chunks.try_recv() {
+            pending.streamed.push_str(&chunk);
+        }
+        if !pending.result.is_finished() {
+            return;
+        }
+        let pending = self.pending.take().expect("pending answer");
+        match pending.result.await {
+            Ok(Ok(reply)) => {
+                self.status = format!(
+                    "{} tokens in {:.1}s{}",
+                    reply.completion_tokens.map_or("?".to_string(), |tokens| tokens.to_string()),
+                    reply.latency.as_secs_f64(),
+                    reply.completion_tokens
+                        .filter(|_| reply.latency.as_secs_f64() > 0.0)
+                        .map_or(String::new(), |tokens| format!(" ({:.1} tokens/s)", tokens as f64 / reply.latency.as_secs_f64())),
+                );
+                self.client.record(&pending.message, &reply);
+            }
+            Ok(Err(e)) => self.status = format!("Error: {}", e),
+            Err(e) => self.status = format!("Error: {}", e),
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(3), Constraint::Length(1)])
+            .split(frame.size());
+        let panes = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Length(SESSION_PANE_WIDTH), Constraint::Min(1)])
+            .split(rows[0]);
+
+        let items: Vec<ListItem> = self.sessions
+            .iter()
+            .map(|session| ListItem::new(session.id.to_string()[..8].to_string()))
+            .collect();
+        let mut state = ListState::default().with_selected(Some(self.current));
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title("Sessions"))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, panes[0], &mut state);
+
+        let mut lines = Vec::new();
+        let mut push_message = |role: &str, content: &str| {
+            let color = if role == "user" { Color::Cyan } else { Color::Green };
+            lines.push(Line::from(Span::styled(role.to_string(), Style::default().fg(color).add_modifier(Modifier::BOLD))));
+            lines.extend(content.lines().map(|line| Line::from(line.to_string())));
+            lines.push(Line::from(""));
+        };
+        for (role, content) in &self.client.transcript {
+            push_message(role, content);
+        }
+        if let Some(pending) = &self.pending {
+            push_message("user", &pending.message);
+            push_message("assistant", &pending.streamed);
+        }
+
+        // Keep the newest text in view unless the user scrolled up
+        let width = panes[1].width.saturating_sub(2).max(1) as usize;
+        let height = panes[1].height.saturating_sub(2) as usize;
+        let wrapped: usize = lines.iter().map(|line| line.width().max(1).div_ceil(width)).sum();
+        let bottom = wrapped.saturating_sub(height) as u16;
+        let conversation = Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title(format!("Session {}", self.client.session_id)))
+            .wrap(Wrap { trim: false })
+            .scroll((bottom.saturating_sub(self.scroll), 0));
+        frame.render_widget(conversation, panes[1]);
+
+        let input = Paragraph::new(self.input.as_str())
+            .block(Block::default().borders(Borders::ALL).title("Message"));
+        frame.render_widget(input, rows[1]);
+        frame.set_cursor(rows[1].x + 1 + self.input.chars().count() as u16, rows[1].y + 1);
+
+        let model = self.client.model.as_deref().unwrap_or("default model");
+        let temperature = self.client.temperature.map_or("default".to_string(), |t| t.to_string());
+        let status = Paragraph::new(format!(" {} | temperature {} | {}", model, temperature, self.status))
+            .style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_widget(status, rows[2]);
+    }
+
+    // Handle a key press, returning false when the UI should exit
+    async fn key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> bool {
+        let ctrl = modifiers.contains(KeyModifiers::CONTROL);
+        match code {
+            KeyCode::Esc => return false,
+            KeyCode::Char('c') if ctrl => return false,
+            KeyCode::Char('n') if ctrl => {
+                self.input = "/new".to_string();
+                return self.submit().await;
+            }
+            KeyCode::Up if ctrl => self.switch_to(self.current.saturating_sub(1)),
+            KeyCode::Down if ctrl => self.switch_to(self.current + 1),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_add(10),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_sub(10),
+            KeyCode::Enter => return self.submit().await,
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Char(c) => self.input.push(c),
+            _ => {}
+        }
+        true
+    }
+}
+
+async fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, mut app: App) -> io::Result<()> {
+    loop {
+        app.poll_pending().await;
+        terminal.draw(|frame| app.draw(frame))?;
+        if !event::poll(INPUT_POLL)? {
+            continue;
+        }
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press && !app.key(key.code, key.modifiers).await {
+                return Ok(());
+            }
+        }
+    }
+}
+
+// Run the terminal UI until the user quits
+pub async fn run(args: &[String]) -> io::Result<()> {
+    let client = match ChatClient::from_args(args) {
+        Ok((client, positional)) if positional.is_empty() => client,
+        Ok((_, positional)) => {
+            eprintln!("Unknown argument: {}", positional[0]);
+            std::process::exit(2);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+    let result = run_app(&mut terminal, App::new(client)).await;
+
+    // Restore the terminal even when the UI failed
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    result
+}
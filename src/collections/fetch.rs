@@ -0,0 +1,298 @@
+use std::env;
+use std::net::IpAddr;
+use std::sync::OnceLock;
+use std::time::Duration;
+use log::{info, warn};
+use reqwest::{header, redirect, Client, Response, Url};
+
+// Default time allowed for a fetch, robots.txt and redirects included
+const DEFAULT_URL_FETCH_TIMEOUT_SECS: u64 = 20;
+// Default size of the largest page fetched
+const DEFAULT_URL_FETCH_MAX_BYTES: usize = 5 * 1024 * 1024;
+// Redirects followed before giving up
+const MAX_REDIRECTS: usize = 5;
+// Part of a robots.txt that is read; RFC 9309 asks crawlers to read at least 500 KiB
+const MAX_ROBOTS_BYTES: usize = 512 * 1024;
+// Product token the fetcher goes by, matched against robots.txt user-agent lines
+const ROBOTS_AGENT: &str = "llama-on-rust";
+
+/// Environment variables for fetching web pages into collections:
+///
+/// - `URL_FETCH_TIMEOUT_SECS`: Seconds allowed for fetching a page, its robots.txt and any
+///   redirects (default: 20)
+/// - `URL_FETCH_MAX_BYTES`: Largest page fetched; larger ones are rejected (default: 5242880)
+/// - `URL_FETCH_ALLOW_PRIVATE`: Fetch from loopback, private and link-local addresses, which are
+///   refused by default so that the endpoint can't be used to reach internal services
+///   (default: false)
+
+// Why a page couldn't be fetched
+#[derive(Debug)]
+pub enum FetchError {
+    // Not an http(s) URL
+    Invalid(String),
+    // The URL points at an internal address
+    Blocked(String),
+    // The site's robots.txt doesn't allow fetching the URL
+    Disallowed(String),
+    // The page is larger than URL_FETCH_MAX_BYTES
+    TooLarge(usize),
+    // The site couldn't be reached or answered with an error
+    Failed(String),
+}
+
+impl std::fmt::Display for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FetchError::Invalid(msg) => write!(f, "Invalid URL: {}", msg),
+            FetchError::Blocked(host) => write!(f, "{} resolves to an internal address", host),
+            FetchError::Disallowed(url) => write!(f, "robots.txt doesn't allow fetching {}", url),
+            FetchError::TooLarge(limit) => write!(f, "The page exceeds {} bytes", limit),
+            FetchError::Failed(msg) => write!(f, "Fetch failed: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+// A fetched page or file
+pub struct Fetched {
+    // Where the body came from, after redirects
+    pub url: Url,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+fn env_parse<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse::<T>().ok())
+        .unwrap_or(default)
+}
+
+// Client for fetching from the web. It is separate from the shared client, whose settings suit
+// backends: redirects are followed by hand here, so that every hop is checked.
+fn client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        Client::builder()
+            .redirect(redirect::Policy::none())
+            .user_agent(format!("{}/{}", ROBOTS_AGENT, env!("CARGO_PKG_VERSION")))
+            .build()
+            .unwrap_or_else(|e| {
+                warn!("Failed to build the URL fetch client, using defaults: {}", e);
+                Client::new()
+            })
+    })
+}
+
+// Loopback, private, link-local and other addresses that aren't on the public internet
+fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified()
+                || ip.is_broadcast() || ip.is_documentation()
+                // Carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && (b & 0xc0) == 64)
+        }
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            ip.is_loopback() || ip.is_unspecified()
+                // Unique local fc00::/7 and link-local fe80::/10
+                || (first & 0xfe00) == 0xfc00 || (first & 0xffc0) == 0xfe80
+                || ip.to_ipv4_mapped().is_some_and(|ip| is_internal(IpAddr::V4(ip)))
+        }
+    }
+}
+
+// Refuse URLs that aren't http(s), or whose host resolves to an internal address
+async fn check_url(url: &Url) -> Result<(), FetchError> {
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(FetchError::Invalid(format!("{} is not an http or https URL", url)));
+    }
+    if env_parse("URL_FETCH_ALLOW_PRIVATE", false) {
+        return Ok(());
+    }
+    check_public(url).await
+}
+
+// Refuse URLs whose host resolves to an internal address; also used for webhook callbacks
+pub async fn check_public(url: &Url) -> Result<(), FetchError> {
+    let Some(host) = url.host_str() else {
+        return Err(FetchError::Invalid(format!("{} has no host", url)));
+    };
+    // IPv6 hosts are bracketed in URLs, but not when resolved; IP addresses resolve to themselves
+    let port = url.port_or_known_default().unwrap_or(80);
+    let mut addresses = tokio::net::lookup_host((host.trim_start_matches('[').trim_end_matches(']'), port))
+        .await
+        .map_err(|e| FetchError::Failed(format!("{}: {}", host, e)))?;
+    if addresses.any(|address| is_internal(address.ip())) {
+        return Err(FetchError::Blocked(host.to_string()));
+    }
+    Ok(())
+}
+
+// Read a response body of at most `max` bytes; past that, the body is cut off when `truncate`
+// is set and refused otherwise
+async fn read_body(mut response: Response, max: usize, truncate: bool) -> Result<Vec<u8>, FetchError> {
+    if !truncate && response.content_length().is_some_and(|length| length > max as u64) {
+        return Err(FetchError::TooLarge(max));
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| FetchError::Failed(e.to_string()))? {
+        if body.len() + chunk.len() > max {
+            if !truncate {
+                return Err(FetchError::TooLarge(max));
+            }
+            body.extend_from_slice(&chunk[..max - body.len()]);
+            break;
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+// Whether a robots.txt path pattern matches a path; `*` matches any characters and a trailing
+// `$` anchors the pattern at the end of the path
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = path.strip_prefix(parts.next().unwrap_or_default()) else { return false };
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        if anchored && i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(position) => rest = &rest[position + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+// Whether a robots.txt lets `agent` fetch `path`, as RFC 9309 reads it: the rules of the groups
+// naming the agent apply, or else those of the `*` groups, and the longest matching rule wins,
+// with allow winning ties
+fn robots_allows(robots: &str, agent: &str, path: &str) -> bool {
+    // Each group is its user agents and its rules, true for allow
+    let mut groups: Vec<(Vec<String>, Vec<(bool, String)>)> = Vec::new();
+    let mut in_agents = false;
+    for line in robots.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let Some((key, value)) = line.split_once(':') else { continue };
+        let (key, value) = (key.trim().to_ascii_lowercase(), value.trim());
+        match key.as_str() {
+            "user-agent" => {
+                // Consecutive user-agent lines share a group
+                if !in_agents {
+                    groups.push((Vec::new(), Vec::new()));
+                }
+                if let Some((agents, _)) = groups.last_mut() {
+                    agents.push(value.to_ascii_lowercase());
+                }
+                in_agents = true;
+            }
+            "allow" | "disallow" => {
+                in_agents = false;
+                if let Some((_, rules)) = groups.last_mut().filter(|_| !value.is_empty()) {
+                    rules.push((key == "allow", value.to_string()));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let agent = agent.to_ascii_lowercase();
+    let names_agent = groups.iter().any(|(agents, _)| agents.contains(&agent));
+    let wanted = if names_agent { agent.as_str() } else { "*" };
+    groups
+        .iter()
+        .filter(|(agents, _)| agents.iter().any(|a| a == wanted))
+        .flat_map(|(_, rules)| rules)
+        .filter(|(_, pattern)| pattern_matches(pattern, path))
+        .max_by_key(|(allow, pattern)| (pattern.len(), *allow))
+        .map_or(true, |(allow, _)| *allow)
+}
+
+// Check the site's robots.txt. A missing one (4xx) allows everything; one that can't be read
+// because the site is failing (5xx, or no answer) allows nothing, as RFC 9309 asks.
+async fn check_robots(url: &Url) -> Result<(), FetchError> {
+    let mut robots_url = url.clone();
+    robots_url.set_path("/robots.txt");
+    robots_url.set_query(None);
+    robots_url.set_fragment(None);
+
+    let response = client()
+        .get(robots_url.clone())
+        .send()
+        .await
+        .map_err(|e| FetchError::Failed(format!("{}: {}", robots_url, e)))?;
+    let status = response.status();
+    if status.is_server_error() {
+        return Err(FetchError::Failed(format!("{} answered {}", robots_url, status)));
+    }
+    if !status.is_success() {
+        return Ok(());
+    }
+    let body = read_body(response, MAX_ROBOTS_BYTES, true).await?;
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+    if robots_allows(&String::from_utf8_lossy(&body), ROBOTS_AGENT, &path) {
+        Ok(())
+    } else {
+        Err(FetchError::Disallowed(url.to_string()))
+    }
+}
+
+// Fetch a URL, following redirects, within URL_FETCH_TIMEOUT_SECS and URL_FETCH_MAX_BYTES.
+// Every hop must be a public http(s) URL that its site's robots.txt lets us fetch.
+pub async fn fetch(url: Url) -> Result<Fetched, FetchError> {
+    let timeout = Duration::from_secs(env_parse("URL_FETCH_TIMEOUT_SECS", DEFAULT_URL_FETCH_TIMEOUT_SECS).max(1));
+    let max_bytes = env_parse("URL_FETCH_MAX_BYTES", DEFAULT_URL_FETCH_MAX_BYTES);
+    match tokio::time::timeout(timeout, fetch_following(url, max_bytes)).await {
+        Ok(result) => result,
+        Err(_) => Err(FetchError::Failed(format!("timed out after {}s", timeout.as_secs()))),
+    }
+}
+
+async fn fetch_following(mut url: Url, max_bytes: usize) -> Result<Fetched, FetchError> {
+    for _ in 0..=MAX_REDIRECTS {
+        check_url(&url).await?;
+        check_robots(&url).await?;
+        let response = client()
+            .get(url.clone())
+            .send()
+            .await
+            .map_err(|e| FetchError::Failed(format!("{}: {}", url, e)))?;
+        let status = response.status();
+        if status.is_redirection() {
+            let location = response
+                .headers()
+                .get(header::LOCATION)
+                .and_then(|value| value.to_str().ok())
+                .ok_or_else(|| FetchError::Failed(format!("{} redirected without a Location", url)))?;
+            url = url
+                .join(location)
+                .map_err(|e| FetchError::Failed(format!("{} redirected to an invalid URL: {}", url, e)))?;
+            continue;
+        }
+        if !status.is_success() {
+            return Err(FetchError::Failed(format!("{} answered {}", url, status)));
+        }
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let body = read_body(response, max_bytes, false).await?;
+        info!("Fetched {} ({} bytes)", url, body.len());
+        return Ok(Fetched { url, content_type, body });
+    }
+    Err(FetchError::Failed(format!("more than {} redirects", MAX_REDIRECTS)))
+}
@@ -0,0 +1,325 @@
+use std::io::{Cursor, Read};
+use anyhow::{Context, Result};
+use quick_xml::events::{BytesStart, Event};
+use regex::Regex;
+use scraper::{ElementRef, Html, Node, Selector};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+// Elements whose content is never part of a page's text
+const SKIPPED_ELEMENTS: [&str; 14] = [
+    "script", "style", "noscript", "template", "svg", "canvas", "iframe", "form", "button",
+    "nav", "header", "footer", "aside", "dialog",
+];
+// Elements that start a new paragraph
+const BLOCK_ELEMENTS: [&str; 25] = [
+    "p", "div", "section", "article", "main", "li", "ul", "ol", "dl", "dt", "dd", "tr", "table",
+    "blockquote", "figure", "figcaption", "br", "hr", "h1", "h2", "h3", "h4", "h5", "h6", "caption",
+];
+// Class and id words of navigation, ads and other page furniture
+const BOILERPLATE_HINTS: [&str; 14] = [
+    "nav", "navbar", "menu", "footer", "header", "sidebar", "breadcrumb", "breadcrumbs", "cookie",
+    "banner", "advert", "ads", "share", "comments",
+];
+// ARIA roles of page furniture
+const BOILERPLATE_ROLES: [&str; 5] = ["navigation", "banner", "contentinfo", "complementary", "search"];
+
+// Kind of file a document was read from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DocumentFormat {
+    #[default]
+    Text,
+    Markdown,
+    Pdf,
+    Docx,
+    Html,
+    Csv,
+}
+
+impl DocumentFormat {
+    // Format of a file from its Content-Type, or its name's extension for generic types
+    pub fn detect(content_type: Option<&str>, name: &str) -> Option<Self> {
+        let mime = content_type
+            .and_then(|value| value.split(';').next())
+            .map(|mime| mime.trim().to_ascii_lowercase());
+        let by_type = match mime.as_deref() {
+            Some("application/pdf") => Some(Self::Pdf),
+            Some("application/vnd.openxmlformats-officedocument.wordprocessingml.document") => Some(Self::Docx),
+            Some("text/html" | "application/xhtml+xml") => Some(Self::Html),
+            Some("text/csv") => Some(Self::Csv),
+            Some("text/markdown") => Some(Self::Markdown),
+            _ => None,
+        };
+        let extension = name.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase());
+        by_type.or(match extension.as_deref() {
+            Some("pdf") => Some(Self::Pdf),
+            Some("docx") => Some(Self::Docx),
+            Some("html" | "htm" | "xhtml") => Some(Self::Html),
+            Some("csv" | "tsv") => Some(Self::Csv),
+            Some("md" | "markdown") => Some(Self::Markdown),
+            Some("txt" | "text") => Some(Self::Text),
+            // Plain text files are often named without a known extension
+            _ if mime.as_deref() == Some("text/plain") => Some(Self::Text),
+            _ => None,
+        })
+    }
+}
+
+// Text of a document file. Headings come out as markdown `#` lines, so that chunking can keep
+// to their sections; CSV rows become one line of "column: value" pairs each.
+pub fn extract(format: DocumentFormat, bytes: &[u8]) -> Result<String> {
+    let text = match format {
+        DocumentFormat::Text | DocumentFormat::Markdown => utf8(bytes)?.to_string(),
+        DocumentFormat::Pdf => pdf_text(bytes)?,
+        DocumentFormat::Docx => docx_text(bytes)?,
+        DocumentFormat::Html => html_text(utf8(bytes)?),
+        DocumentFormat::Csv => csv_text(bytes)?,
+    };
+    Ok(tidy(&text))
+}
+
+fn utf8(bytes: &[u8]) -> Result<&str> {
+    let text = std::str::from_utf8(bytes).context("the file is not valid UTF-8")?;
+    Ok(text.trim_start_matches('\u{feff}'))
+}
+
+// Normalize line endings and trailing spaces, keeping at most one blank line between paragraphs
+fn tidy(text: &str) -> String {
+    let mut tidy = String::with_capacity(text.len());
+    let mut blank = true;
+    for line in text.replace("\r\n", "\n").replace('\r', "\n").lines() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            if !blank {
+                tidy.push('\n');
+            }
+            blank = true;
+            continue;
+        }
+        tidy.push_str(line);
+        tidy.push('\n');
+        blank = false;
+    }
+    tidy.trim_end().to_string()
+}
+
+fn pdf_text(bytes: &[u8]) -> Result<String> {
+    let text = pdf_extract::extract_text_from_mem(bytes).context("the file is not a readable PDF")?;
+    // Page breaks end paragraphs, and words hyphenated at the end of a line are joined
+    let text = text.replace('\u{c}', "\n\n");
+    let hyphenated = Regex::new(r"(\p{L})-\n(\p{Ll})").expect("valid regex");
+    Ok(hyphenated.replace_all(&text, "$1$2").into_owned())
+}
+
+// Append a paragraph, as a markdown heading when it has a level
+fn push_block(text: &mut String, block: &str, heading: Option<usize>) {
+    let block = block.trim();
+    if block.is_empty() {
+        return;
+    }
+    if let Some(level) = heading {
+        text.push_str(&"#".repeat(level));
+        text.push(' ');
+    }
+    text.push_str(block);
+    text.push_str("\n\n");
+}
+
+// Heading level of a DOCX paragraph style, e.g. 2 for "Heading2"
+fn docx_heading_level(style: &BytesStart) -> Option<usize> {
+    let value = style.try_get_attribute("w:val").ok()??.unescape_value().ok()?;
+    if value == "Title" {
+        return Some(1);
+    }
+    let level = value.strip_prefix("Heading")?.parse::<usize>().ok()?;
+    (1..=6).contains(&level).then_some(level)
+}
+
+fn docx_text(bytes: &[u8]) -> Result<String> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).context("the file is not a DOCX document")?;
+    let mut xml = String::new();
+    archive
+        .by_name("word/document.xml")
+        .context("the DOCX document has no word/document.xml")?
+        .read_to_string(&mut xml)?;
+
+    let mut reader = quick_xml::Reader::from_str(&xml);
+    let mut text = String::new();
+    let mut paragraph = String::new();
+    let mut heading = None;
+    let mut in_text = false;
+    loop {
+        match reader.read_event().context("the DOCX document is malformed")? {
+            Event::Start(e) if e.name().as_ref() == b"w:t" => in_text = true,
+            Event::End(e) if e.name().as_ref() == b"w:t" => in_text = false,
+            Event::Text(t) if in_text => paragraph.push_str(&t.unescape()?),
+            Event::Empty(e) => match e.name().as_ref() {
+                b"w:tab" => paragraph.push('\t'),
+                b"w:br" | b"w:cr" => paragraph.push('\n'),
+                b"w:pStyle" => heading = docx_heading_level(&e),
+                _ => {}
+            },
+            Event::End(e) if e.name().as_ref() == b"w:p" => {
+                push_block(&mut text, &paragraph, heading);
+                paragraph.clear();
+                heading = None;
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(text)
+}
+
+// Paragraphs of a page, collected while walking its elements
+#[derive(Default)]
+struct Blocks {
+    text: String,
+    current: String,
+    heading: Option<usize>,
+}
+
+impl Blocks {
+    fn end(&mut self) {
+        let block = self.current.split_whitespace().collect::<Vec<_>>().join(" ");
+        push_block(&mut self.text, &block, self.heading.take());
+        self.current.clear();
+    }
+}
+
+// Navigation, ads and other furniture, recognized by their role, class or id
+fn is_boilerplate(element: &ElementRef) -> bool {
+    let value = element.value();
+    if value.attr("hidden").is_some() || value.attr("aria-hidden") == Some("true") {
+        return true;
+    }
+    if value.attr("role").is_some_and(|role| BOILERPLATE_ROLES.contains(&role)) {
+        return true;
+    }
+    [value.attr("class"), value.attr("id")]
+        .into_iter()
+        .flatten()
+        .flat_map(|names| names.split(|c: char| c.is_whitespace() || c == '-' || c == '_'))
+        .any(|word| BOILERPLATE_HINTS.contains(&word.to_ascii_lowercase().as_str()))
+}
+
+fn walk(element: ElementRef, blocks: &mut Blocks) {
+    let name = element.value().name();
+    if SKIPPED_ELEMENTS.contains(&name) || is_boilerplate(&element) {
+        return;
+    }
+    // Code keeps its layout, as its own paragraph
+    if name == "pre" {
+        blocks.end();
+        let code: String = element.text().collect();
+        push_block(&mut blocks.text, &code, None);
+        return;
+    }
+    let block = BLOCK_ELEMENTS.contains(&name);
+    if block {
+        blocks.end();
+    }
+    if let Some(level) = name.strip_prefix('h').and_then(|level| level.parse::<usize>().ok()) {
+        blocks.heading = Some(level);
+    }
+    match name {
+        "li" => blocks.current.push_str("- "),
+        "td" | "th" => blocks.current.push_str(" | "),
+        _ => {}
+    }
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => blocks.current.push_str(text),
+            Node::Element(_) => {
+                if let Some(child) = ElementRef::wrap(child) {
+                    walk(child, blocks);
+                }
+            }
+            _ => {}
+        }
+    }
+    if block {
+        blocks.end();
+    }
+}
+
+// Main text of a web page, without navigation, ads and other furniture (in the manner of
+// readability): the page's article or main element when it has one, else its body
+fn html_text(html: &str) -> String {
+    let document = Html::parse_document(html);
+    let root = ["article", "main", "[role=main]", "body"]
+        .iter()
+        .filter_map(|selector| Selector::parse(selector).ok())
+        .find_map(|selector| document.select(&selector).next())
+        .unwrap_or_else(|| document.root_element());
+    let mut blocks = Blocks::default();
+    walk(root, &mut blocks);
+    blocks.end();
+    blocks.text
+}
+
+// Title of a web page, for naming it; None when it has none or isn't valid UTF-8
+pub fn html_title(bytes: &[u8]) -> Option<String> {
+    let document = Html::parse_document(utf8(bytes).ok()?);
+    let selector = Selector::parse("title").ok()?;
+    let title = document.select(&selector).next()?.text().collect::<Vec<_>>().join(" ");
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!title.is_empty()).then_some(title)
+}
+
+fn csv_text(bytes: &[u8]) -> Result<String> {
+    // The delimiter is whichever of comma, semicolon and tab the header row uses most
+    let header = bytes.split(|&b| b == b'\n').next().unwrap_or_default();
+    let delimiter = [b',', b';', b'\t']
+        .into_iter()
+        .max_by_key(|&d| header.iter().filter(|&&b| b == d).count())
+        .unwrap_or(b',');
+    let mut reader = csv::ReaderBuilder::new().delimiter(delimiter).flexible(true).from_reader(bytes);
+    let headers = reader.headers().context("the file is not valid CSV")?.clone();
+
+    let mut text = String::new();
+    for record in reader.records() {
+        let record = record.context("the file is not valid CSV")?;
+        let fields: Vec<String> = record
+            .iter()
+            .enumerate()
+            .filter(|(_, value)| !value.trim().is_empty())
+            .map(|(i, value)| match headers.get(i).map(str::trim).filter(|h| !h.is_empty()) {
+                Some(header) => format!("{}: {}", header, value.trim()),
+                None => value.trim().to_string(),
+            })
+            .collect();
+        if !fields.is_empty() {
+            text.push_str(&fields.join("; "));
+            text.push('\n');
+        }
+    }
+    Ok(text)
+}
+
+fn is_heading(line: &str) -> bool {
+    let rest = line.trim_start_matches('#');
+    let level = line.len() - rest.len();
+    (1..=6).contains(&level) && rest.starts_with(' ')
+}
+
+// Consecutive parts of a document that no chunk should span. Documents with headings are cut
+// before each one, so a chunk stays within a section. PDFs have no reliable headings and are
+// chunked by paragraph; CSVs are chunked by row, since each row stands on its own.
+pub fn sections(text: &str, format: DocumentFormat) -> Vec<&str> {
+    if matches!(format, DocumentFormat::Pdf | DocumentFormat::Csv) {
+        return vec![text];
+    }
+    let mut sections = Vec::new();
+    let (mut start, mut offset) = (0, 0);
+    for line in text.split_inclusive('\n') {
+        if offset > start && is_heading(line) {
+            sections.push(&text[start..offset]);
+            start = offset;
+        }
+        offset += line.len();
+    }
+    sections.push(&text[start..]);
+    sections
+}
@@ -0,0 +1,412 @@
+pub mod fetch;
+pub mod ingest;
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use log::{info, warn, error};
+use regex::Regex;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::chunker;
+use crate::http_client::http_client;
+use crate::model::cache::{cosine_similarity, request_embedding};
+use crate::model::LlamaModel;
+use ingest::DocumentFormat;
+
+// Default file holding the collections and their indexed chunks
+const DEFAULT_COLLECTIONS_PATH: &str = "collections.json";
+// Default number of chunks retrieved for a message
+const DEFAULT_RAG_TOP_K: usize = 4;
+// Default size of a chunk, in tokens
+const DEFAULT_RAG_CHUNK_TOKENS: usize = 300;
+// Most collections a session can search
+pub const MAX_SESSION_COLLECTIONS: usize = 10;
+// Longest collection name accepted, in characters
+pub const MAX_COLLECTION_NAME_CHARS: usize = 100;
+
+/// Environment variables for configuring document collections:
+///
+/// - `COLLECTIONS_PATH`: JSON file holding the collections and their indexed chunks
+///   (default: "collections.json")
+/// - `RAG_TOP_K`: Chunks most relevant to a message that are added to the system prompt (default: 4)
+/// - `RAG_CHUNK_TOKENS`: Size documents are split into for retrieval, in tokens (default: 300)
+///
+/// Chunks are ranked by embedding similarity using `EMBEDDING_SERVER_URL` and `EMBEDDING_MODEL`
+/// (see the response cache), falling back to word overlap when embeddings are unavailable.
+
+// A named set of documents that sessions can search
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Collection {
+    pub id: Uuid,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub documents: Vec<Document>,
+}
+
+// A document added to a collection
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Document {
+    pub id: Uuid,
+    pub name: String,
+    // Kind of file the text was read from
+    #[serde(default)]
+    pub format: DocumentFormat,
+    // Page the document was fetched from, for documents added by URL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    // Length of the indexed text, in characters
+    pub chars: usize,
+    pub chunks: usize,
+    pub created_at: DateTime<Utc>,
+}
+
+// A piece of a document, indexed for retrieval
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Chunk {
+    document_id: Uuid,
+    // Position of the chunk in its document, from 0
+    index: usize,
+    // Character range of the chunk in the document's text
+    start: usize,
+    end: usize,
+    text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    embedding: Option<Vec<f32>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredCollection {
+    #[serde(flatten)]
+    collection: Collection,
+    #[serde(default)]
+    chunks: Vec<Chunk>,
+}
+
+// A chunk retrieved for a message and how relevant it is
+#[derive(Debug, Clone)]
+pub struct Passage {
+    pub collection_id: Uuid,
+    pub document_id: Uuid,
+    // Name of the document the chunk is from
+    pub document: String,
+    // Index of the chunk within its document
+    pub chunk: usize,
+    // Character range of the chunk in the document's text
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+    pub score: f32,
+}
+
+// A passage an answer was grounded in, numbered as the `[n]` marker that cites it
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Citation {
+    pub marker: usize,
+    pub collection_id: Uuid,
+    pub document_id: Uuid,
+    pub document: String,
+    // Index of the chunk within its document
+    pub chunk: usize,
+    // Character range of the chunk in the document's text, end exclusive
+    pub start: usize,
+    pub end: usize,
+    // Relevance of the chunk to the message
+    pub score: f32,
+    // Whether the answer contains the marker
+    pub cited: bool,
+}
+
+// Document collections, persisted as JSON
+pub struct CollectionStore {
+    path: String,
+    top_k: usize,
+    chunk_tokens: usize,
+    client: Client,
+    embedding_url: String,
+    embedding_model: String,
+    collections: Mutex<HashMap<Uuid, StoredCollection>>,
+}
+
+fn env_usize(name: &str, default: usize) -> usize {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(default)
+}
+
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.len() > 2)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+// Fraction of the message's words found in the chunk, for when embeddings are unavailable
+fn word_overlap(message: &HashSet<String>, chunk: &str) -> f32 {
+    if message.is_empty() {
+        return 0.0;
+    }
+    let chunk = words(chunk);
+    message.intersection(&chunk).count() as f32 / message.len() as f32
+}
+
+// Split a document into chunks of about `max_tokens`, with the character range of each
+fn split(text: &str, format: DocumentFormat, max_tokens: usize) -> Vec<(usize, usize, String)> {
+    let mut pieces = Vec::new();
+    let (mut byte_pos, mut char_pos) = (0, 0);
+    let chunks = ingest::sections(text, format)
+        .into_iter()
+        .flat_map(|section| chunker::chunk(section, max_tokens, LlamaModel::estimate_tokens));
+    for chunk in chunks {
+        // Chunks are consecutive, though blank ones were dropped in between
+        let start = byte_pos + text[byte_pos..].find(&chunk).unwrap_or(0);
+        char_pos += text[byte_pos..start].chars().count();
+        let chars = chunk.chars().count();
+        byte_pos = start + chunk.len();
+        pieces.push((char_pos, char_pos + chars, chunk));
+        char_pos += chars;
+    }
+    pieces
+}
+
+impl CollectionStore {
+    pub fn load() -> Self {
+        let path = env::var("COLLECTIONS_PATH").unwrap_or_else(|_| DEFAULT_COLLECTIONS_PATH.to_string());
+        let embedding_url = env::var("EMBEDDING_SERVER_URL")
+            .or_else(|_| env::var("MISTRAL_SERVER_URL"))
+            .unwrap_or_else(|_| "http://localhost:8081".to_string());
+
+        let collections = if Path::new(&path).exists() {
+            match Self::read(&path) {
+                Ok(collections) => collections,
+                Err(e) => {
+                    error!("Failed to load collections from {}: {:#}", path, e);
+                    HashMap::new()
+                }
+            }
+        } else {
+            HashMap::new()
+        };
+        info!("Loaded {} document collection(s) from {}", collections.len(), path);
+
+        Self {
+            path,
+            top_k: env_usize("RAG_TOP_K", DEFAULT_RAG_TOP_K),
+            chunk_tokens: env_usize("RAG_CHUNK_TOKENS", DEFAULT_RAG_CHUNK_TOKENS).max(16),
+            client: http_client().clone(),
+            // MISTRAL_SERVER_URL may list several replicas; any of them can embed
+            embedding_url: embedding_url.split(',').next().unwrap_or_default().trim().trim_end_matches('/').to_string(),
+            embedding_model: env::var("EMBEDDING_MODEL").unwrap_or_else(|_| "default".to_string()),
+            collections: Mutex::new(collections),
+        }
+    }
+
+    fn read(path: &str) -> Result<HashMap<Uuid, StoredCollection>> {
+        let contents = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+        serde_json::from_str(&contents).with_context(|| format!("Invalid collections file {}", path))
+    }
+
+    // Write all collections, replacing the file atomically
+    fn save(&self, collections: &HashMap<Uuid, StoredCollection>) {
+        let tmp_path = format!("{}.tmp", self.path);
+        let result = serde_json::to_vec(collections)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| fs::write(&tmp_path, json).map_err(anyhow::Error::from))
+            .and_then(|_| fs::rename(&tmp_path, &self.path).map_err(anyhow::Error::from));
+        if let Err(e) = result {
+            error!("Failed to save collections to {}: {}", self.path, e);
+        }
+    }
+
+    // All collections, oldest first
+    pub fn list(&self) -> Vec<Collection> {
+        let Ok(collections) = self.collections.lock() else { return Vec::new() };
+        let mut list: Vec<Collection> = collections.values().map(|c| c.collection.clone()).collect();
+        list.sort_by_key(|c| c.created_at);
+        list
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<Collection> {
+        self.collections.lock().ok()?.get(&id).map(|c| c.collection.clone())
+    }
+
+    pub fn create(&self, name: String, description: Option<String>) -> Collection {
+        let collection = Collection { id: Uuid::new_v4(), name, description, created_at: Utc::now(), documents: Vec::new() };
+        if let Ok(mut collections) = self.collections.lock() {
+            collections.insert(collection.id, StoredCollection { collection: collection.clone(), chunks: Vec::new() });
+            self.save(&collections);
+        }
+        collection
+    }
+
+    pub fn delete(&self, id: Uuid) -> bool {
+        let Ok(mut collections) = self.collections.lock() else { return false };
+        let deleted = collections.remove(&id).is_some();
+        if deleted {
+            self.save(&collections);
+        }
+        deleted
+    }
+
+    async fn embed(&self, text: &str) -> Option<Vec<f32>> {
+        match request_embedding(&self.client, &self.embedding_url, &self.embedding_model, text).await {
+            Ok(embedding) => Some(embedding),
+            Err(e) => {
+                warn!("Failed to embed text for a collection: {}", e);
+                None
+            }
+        }
+    }
+
+    // Chunk, embed and add a document to a collection; None when the collection doesn't exist
+    pub async fn add_document(&self, id: Uuid, name: String, format: DocumentFormat, url: Option<String>, text: &str) -> Option<Document> {
+        self.get(id)?;
+        let pieces = split(text, format, self.chunk_tokens);
+        let embeddings = join_all(pieces.iter().map(|(_, _, chunk)| self.embed(chunk))).await;
+
+        let document = Document {
+            id: Uuid::new_v4(),
+            name,
+            format,
+            url,
+            chars: text.chars().count(),
+            chunks: pieces.len(),
+            created_at: Utc::now(),
+        };
+        let chunks = pieces
+            .into_iter()
+            .zip(embeddings)
+            .enumerate()
+            .map(|(index, ((start, end, text), embedding))| Chunk { document_id: document.id, index, start, end, text, embedding });
+
+        // The collection may have been deleted while the chunks were embedded
+        let mut collections = self.collections.lock().ok()?;
+        let stored = collections.get_mut(&id)?;
+        stored.collection.documents.push(document.clone());
+        stored.chunks.extend(chunks);
+        self.save(&collections);
+        info!("Added document {} ({} chunk(s)) to collection {}", document.name, document.chunks, id);
+        Some(document)
+    }
+
+    pub fn delete_document(&self, id: Uuid, document_id: Uuid) -> bool {
+        let Ok(mut collections) = self.collections.lock() else { return false };
+        let Some(stored) = collections.get_mut(&id) else { return false };
+        let before = stored.collection.documents.len();
+        stored.collection.documents.retain(|d| d.id != document_id);
+        if stored.collection.documents.len() == before {
+            return false;
+        }
+        stored.chunks.retain(|c| c.document_id != document_id);
+        self.save(&collections);
+        true
+    }
+
+    // The chunks of the given collections most relevant to a message, best first. Collections
+    // that no longer exist are skipped.
+    pub async fn retrieve(&self, ids: &[Uuid], message: &str) -> Vec<Passage> {
+        if ids.is_empty() || self.top_k == 0 {
+            return Vec::new();
+        }
+        let candidates: Vec<(Uuid, Vec<Document>, Vec<Chunk>)> = match self.collections.lock() {
+            Ok(collections) => ids
+                .iter()
+                .filter_map(|id| collections.get(id))
+                .map(|c| (c.collection.id, c.collection.documents.clone(), c.chunks.clone()))
+                .collect(),
+            Err(_) => return Vec::new(),
+        };
+        if candidates.iter().all(|(_, _, chunks)| chunks.is_empty()) {
+            return Vec::new();
+        }
+
+        let embedding = self.embed(message).await;
+        let embedding = &embedding;
+        let message_words = &words(message);
+        let mut passages: Vec<Passage> = candidates
+            .into_iter()
+            .flat_map(move |(collection_id, documents, chunks)| {
+                let names: HashMap<Uuid, String> = documents.into_iter().map(|d| (d.id, d.name)).collect();
+                chunks.into_iter().map(move |chunk| {
+                    let score = match (embedding, &chunk.embedding) {
+                        (Some(a), Some(b)) => cosine_similarity(a, b),
+                        _ => word_overlap(message_words, &chunk.text),
+                    };
+                    Passage {
+                        collection_id,
+                        document_id: chunk.document_id,
+                        document: names.get(&chunk.document_id).cloned().unwrap_or_default(),
+                        chunk: chunk.index,
+                        start: chunk.start,
+                        end: chunk.end,
+                        text: chunk.text,
+                        score,
+                    }
+                })
+            })
+            .collect();
+        passages.sort_by(|a, b| b.score.total_cmp(&a.score));
+        passages.truncate(self.top_k);
+        passages
+    }
+}
+
+// Retrieved passages formatted for the system prompt, numbered for the answer to cite
+pub fn context(passages: &[Passage]) -> Option<String> {
+    if passages.is_empty() {
+        return None;
+    }
+    let excerpts: Vec<String> = passages
+        .iter()
+        .enumerate()
+        .map(|(i, p)| format!("[{}] From \"{}\":\n{}", i + 1, p.document, p.text.trim()))
+        .collect();
+    Some(format!(
+        "Excerpts from the user's documents that may help answer. Use them where relevant and \
+        say so when they don't cover the question. Cite the excerpts you use by their number in \
+        square brackets, such as [1] or [1][3], right after the statements they support:\n\n{}",
+        excerpts.join("\n\n")
+    ))
+}
+
+// Citations of the passages given as context, marked cited when the answer has their marker.
+// Markers are read as `[1]`, and in lists such as `[1, 3]`.
+pub fn citations(passages: &[Passage], answer: &str) -> Vec<Citation> {
+    let markers = Regex::new(r"\[(\d+(?:\s*,\s*\d+)*)\]").expect("valid regex");
+    let cited: HashSet<usize> = markers
+        .captures_iter(answer)
+        .flat_map(|captures| {
+            captures[1]
+                .split(',')
+                .filter_map(|n| n.trim().parse::<usize>().ok())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    passages
+        .iter()
+        .enumerate()
+        .map(|(i, p)| Citation {
+            marker: i + 1,
+            collection_id: p.collection_id,
+            document_id: p.document_id,
+            document: p.document.clone(),
+            chunk: p.chunk,
+            start: p.start,
+            end: p.end,
+            score: p.score,
+            cited: cited.contains(&(i + 1)),
+        })
+        .collect()
+}
@@ -0,0 +1,81 @@
+use std::collections::HashSet;
+use anyhow::Result;
+use futures::future::try_join_all;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::model::{Completion, GenerationOptions, LlamaModel};
+
+// Most answers a request may sample
+pub const MAX_CONSENSUS_SAMPLES: usize = 8;
+// Lowest temperature answers are sampled at, so they can disagree
+const MIN_SAMPLE_TEMPERATURE: f32 = 0.8;
+// Word overlap above which two answers count as the same answer
+const AGREEMENT_THRESHOLD: f32 = 0.5;
+
+// How a consensus answer was reached
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+pub struct Consensus {
+    // Answers sampled
+    pub samples: usize,
+    // Share of the samples that agree with the answer returned, between 0 and 1
+    pub agreement: f32,
+}
+
+// Lowercased words of an answer, ignoring punctuation
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+// Jaccard similarity of two answers' words: 1 for the same words, 0 for none in common
+fn similarity(a: &HashSet<String>, b: &HashSet<String>) -> f32 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(b).count() as f32 / union as f32
+}
+
+// Index of the answer most others agree with, and the share of answers that do, itself
+// included. Ties go to the answer closest to all the others.
+fn vote(answers: &[String]) -> (usize, f32) {
+    let answer_words: Vec<HashSet<String>> = answers.iter().map(|answer| words(answer)).collect();
+    let mut best = (0, 0, f32::MIN);
+    for (i, a) in answer_words.iter().enumerate() {
+        let similarities: Vec<f32> = answer_words.iter().map(|b| similarity(a, b)).collect();
+        let agreeing = similarities.iter().filter(|&&s| s >= AGREEMENT_THRESHOLD).count();
+        let closeness: f32 = similarities.iter().sum();
+        if agreeing > best.1 || (agreeing == best.1 && closeness > best.2) {
+            best = (i, agreeing, closeness);
+        }
+    }
+    (best.0, best.1 as f32 / answers.len().max(1) as f32)
+}
+
+// Sample `samples` answers to `prompt` concurrently, at a raised temperature and bypassing the
+// response cache, and return the one most of them agree with. Its token counts are those of
+// all the samples, which are all paid for.
+pub async fn sample(
+    model: &LlamaModel,
+    prompt: &str,
+    history: &[String],
+    options: &GenerationOptions,
+    samples: usize,
+) -> Result<(Completion, Consensus)> {
+    let mut options = options.clone();
+    options.use_cache = false;
+    options.temperature = Some(options.temperature.unwrap_or_else(|| model.default_temperature()).max(MIN_SAMPLE_TEMPERATURE));
+    let completions = try_join_all((0..samples).map(|_| model.generate_response(prompt, history, &options))).await?;
+
+    let answers: Vec<String> = completions.iter().map(|completion| completion.text.clone()).collect();
+    let (chosen, agreement) = vote(&answers);
+    let mut answer = completions[chosen].clone();
+    answer.prompt_tokens = completions.iter().map(|c| c.prompt_tokens).sum();
+    answer.completion_tokens = completions.iter().map(|c| c.completion_tokens).sum();
+    answer.cached_tokens = completions.iter().map(|c| c.cached_tokens).sum();
+    answer.queue_wait = completions.iter().map(|c| c.queue_wait).max().unwrap_or_default();
+    Ok((answer, Consensus { samples, agreement }))
+}
@@ -0,0 +1,205 @@
+use std::collections::HashSet;
+use std::env;
+use actix_web::web::Data;
+use log::{info, warn, error};
+use serenity::all::{
+    Channel, ChannelId, Client, Context, CreateMessage, EditMessage, EventHandler, GatewayIntents, Message, Ready,
+};
+use serenity::async_trait;
+use serde_json::Map;
+use uuid::Uuid;
+
+use crate::web::handlers::respond_to_chat;
+use crate::web::models::ChatRequest;
+use crate::AppState;
+
+// Longest message Discord accepts, in characters
+const DISCORD_MESSAGE_LIMIT: usize = 2000;
+// Upper half of the session IDs derived from Discord channel IDs
+const DISCORD_SESSION_NAMESPACE: u64 = 0xd15c_0bd0_0000_0000;
+// Shown while the model is generating
+const PLACEHOLDER: &str = "…";
+
+/// Environment variables for the Discord bot (built with `--features discord`):
+///
+/// - `DISCORD_BOT_TOKEN`: Bot token; the bot only starts when it is set
+/// - `DISCORD_CHANNELS`: Comma-separated IDs of the channels the bot answers in, including
+///   threads under them (default: every channel it can read)
+/// - `DISCORD_RESPOND_TO_ALL`: Answer every message in server channels instead of only those
+///   mentioning the bot; direct messages are always answered (default: false)
+/// - `DISCORD_MODEL`: Model used for replies (default: the registry default)
+/// - `DISCORD_ASSISTANT`: Assistant profile used for replies (default: none)
+///
+/// Every channel, thread and direct message conversation is its own chat session, and the
+/// Discord user is recorded as the session's `user` (`discord:<id>`). The bot needs the
+/// Message Content privileged intent.
+
+struct Handler {
+    data: Data<AppState>,
+    channels: Option<HashSet<u64>>,
+    respond_to_all: bool,
+    model: Option<String>,
+    assistant: Option<String>,
+}
+
+// The chat session of a channel or thread; derived from its ID so it survives bot reconnects
+fn session_id(channel: ChannelId) -> Uuid {
+    Uuid::from_u64_pair(DISCORD_SESSION_NAMESPACE, channel.get())
+}
+
+// Split a reply into messages Discord accepts, preferring to break at line ends
+fn split_message(text: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    for line in text.split_inclusive('\n') {
+        if current.chars().count() + line.chars().count() > DISCORD_MESSAGE_LIMIT && !current.is_empty() {
+            parts.push(std::mem::take(&mut current));
+        }
+        // A single line longer than the limit is cut wherever it has to be
+        let mut line = line;
+        while line.chars().count() > DISCORD_MESSAGE_LIMIT {
+            let cut = line.char_indices().nth(DISCORD_MESSAGE_LIMIT).map_or(line.len(), |(i, _)| i);
+            parts.push(line[..cut].to_string());
+            line = &line[cut..];
+        }
+        current.push_str(line);
+    }
+    if !current.trim().is_empty() {
+        parts.push(current);
+    }
+    parts
+}
+
+impl Handler {
+    // Whether the bot may answer in this channel, or in the channel this thread belongs to
+    async fn is_allowed(&self, ctx: &Context, msg: &Message) -> bool {
+        let Some(channels) = &self.channels else { return true };
+        if channels.contains(&msg.channel_id.get()) {
+            return true;
+        }
+        match msg.channel(ctx).await {
+            Ok(Channel::Guild(channel)) => channel.parent_id.is_some_and(|parent| channels.contains(&parent.get())),
+            _ => false,
+        }
+    }
+}
+
+#[async_trait]
+impl EventHandler for Handler {
+    async fn ready(&self, _ctx: Context, ready: Ready) {
+        info!("Discord bot connected as {}", ready.user.name);
+    }
+
+    async fn message(&self, ctx: Context, msg: Message) {
+        if msg.author.bot {
+            return;
+        }
+        let is_direct = msg.guild_id.is_none();
+        if !is_direct {
+            if !self.respond_to_all && !msg.mentions_me(&ctx).await.unwrap_or(false) {
+                return;
+            }
+            if !self.is_allowed(&ctx, &msg).await {
+                return;
+            }
+        }
+
+        // Drop the bot's own mention from the message
+        let bot_id = ctx.cache.current_user().id;
+        let message = msg.content
+            .replace(&format!("<@{}>", bot_id), "")
+            .replace(&format!("<@!{}>", bot_id), "")
+            .trim()
+            .to_string();
+        if message.is_empty() {
+            return;
+        }
+
+        let typing = msg.channel_id.start_typing(&ctx.http);
+        let mut placeholder = match msg.reply(&ctx, PLACEHOLDER).await {
+            Ok(placeholder) => placeholder,
+            Err(e) => {
+                error!("Failed to reply in Discord channel {}: {}", msg.channel_id, e);
+                return;
+            }
+        };
+
+        let req = ChatRequest {
+            message,
+            session_id: Some(session_id(msg.channel_id)),
+            max_tokens: None,
+            model: self.model.clone(),
+            bypass_cache: false,
+            template: None,
+            vars: Map::new(),
+            assistant: self.assistant.clone(),
+            user: Some(format!("discord:{}", msg.author.id)),
+            extra_body: None,
+            reasoning_effort: None,
+            thinking_budget_tokens: None,
+            confirm: false,
+            language: None,
+            length: None,
+            refine: false,
+            samples: None,
+        };
+        let reply = match respond_to_chat(None, &self.data, &req).await {
+            Ok(response) => response.response,
+            Err(e) => {
+                warn!("Discord reply in channel {} failed: {}", msg.channel_id, e.message());
+                format!("Sorry, I couldn't answer that: {}", e.message())
+            }
+        };
+        typing.stop();
+
+        // The placeholder becomes the first part of the reply; longer replies continue below it
+        let mut parts = split_message(&reply).into_iter();
+        let first = parts.next().unwrap_or_else(|| "(empty reply)".to_string());
+        if let Err(e) = placeholder.edit(&ctx, EditMessage::new().content(first)).await {
+            error!("Failed to edit Discord reply in channel {}: {}", msg.channel_id, e);
+            return;
+        }
+        for part in parts {
+            if let Err(e) = msg.channel_id.send_message(&ctx, CreateMessage::new().content(part)).await {
+                error!("Failed to send Discord reply in channel {}: {}", msg.channel_id, e);
+                return;
+            }
+        }
+    }
+}
+
+// Connect to the Discord gateway in the background, when a bot token is configured
+pub fn start(data: Data<AppState>) {
+    let Some(token) = env::var("DISCORD_BOT_TOKEN").ok().filter(|token| !token.is_empty()) else {
+        return;
+    };
+    let channels = env::var("DISCORD_CHANNELS").ok().map(|ids| {
+        ids.split(',')
+            .filter_map(|id| id.trim().parse::<u64>().ok())
+            .collect::<HashSet<u64>>()
+    });
+    let handler = Handler {
+        data,
+        channels,
+        respond_to_all: env::var("DISCORD_RESPOND_TO_ALL")
+            .ok()
+            .and_then(|v| v.parse::<bool>().ok())
+            .unwrap_or(false),
+        model: env::var("DISCORD_MODEL").ok(),
+        assistant: env::var("DISCORD_ASSISTANT").ok(),
+    };
+
+    tokio::spawn(async move {
+        let intents = GatewayIntents::GUILD_MESSAGES | GatewayIntents::DIRECT_MESSAGES | GatewayIntents::MESSAGE_CONTENT;
+        let mut client = match Client::builder(&token, intents).event_handler(handler).await {
+            Ok(client) => client,
+            Err(e) => {
+                error!("Failed to create Discord client: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = client.start().await {
+            error!("Discord client stopped: {}", e);
+        }
+    });
+}
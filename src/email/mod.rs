@@ -0,0 +1,307 @@
+use std::env;
+use std::time::Duration;
+use actix_web::web::Data;
+use anyhow::Result;
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use log::{info, warn, error};
+use mail_parser::{HeaderValue, MessageParser};
+use serde_json::Map;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::web::handlers::respond_to_chat;
+use crate::web::models::ChatRequest;
+use crate::AppState;
+
+// Default seconds between mailbox checks
+const DEFAULT_EMAIL_POLL_SECS: u64 = 60;
+// Default IMAP port, with implicit TLS
+const DEFAULT_IMAP_PORT: u16 = 993;
+// Default SMTP port, with implicit TLS
+const DEFAULT_SMTP_PORT: u16 = 465;
+
+/// Environment variables for the email gateway (built with `--features email`):
+///
+/// - `EMAIL_IMAP_HOST`: IMAP server polled for new mail; the gateway only starts when it is set
+/// - `EMAIL_IMAP_PORT`: IMAP port, using TLS (default: 993)
+/// - `EMAIL_MAILBOX`: Mailbox polled (default: "INBOX")
+/// - `EMAIL_SMTP_HOST`: SMTP server replies are sent through (default: `EMAIL_IMAP_HOST`)
+/// - `EMAIL_SMTP_PORT`: SMTP port, using TLS (default: 465)
+/// - `EMAIL_USERNAME` / `EMAIL_PASSWORD`: Login for both servers
+/// - `EMAIL_ADDRESS`: Address replies are sent from (default: `EMAIL_USERNAME`)
+/// - `EMAIL_ALLOWED_SENDERS`: Comma-separated addresses or `@domain` suffixes whose mail is
+///   answered; mail from anyone else is marked read and ignored (required)
+/// - `EMAIL_POLL_SECS`: Seconds between mailbox checks (default: 60)
+/// - `EMAIL_MODEL`: Model used for replies (default: the registry default)
+/// - `EMAIL_ASSISTANT`: Assistant profile used for replies (default: none)
+///
+/// Every unread email becomes a prompt and is answered by reply. An email thread is one chat
+/// session, identified by the first message of the thread, and the sender is recorded as the
+/// session's `user` (`email:<address>`).
+
+#[derive(Clone)]
+struct EmailConfig {
+    imap_host: String,
+    imap_port: u16,
+    mailbox: String,
+    smtp_host: String,
+    smtp_port: u16,
+    username: String,
+    password: String,
+    address: String,
+    allowed_senders: Vec<String>,
+    poll_interval: Duration,
+    model: Option<String>,
+    assistant: Option<String>,
+}
+
+impl EmailConfig {
+    fn from_env() -> Option<Self> {
+        let imap_host = env::var("EMAIL_IMAP_HOST").ok().filter(|host| !host.is_empty())?;
+        let username = env::var("EMAIL_USERNAME").unwrap_or_default();
+        Some(Self {
+            smtp_host: env::var("EMAIL_SMTP_HOST").unwrap_or_else(|_| imap_host.clone()),
+            imap_port: env::var("EMAIL_IMAP_PORT")
+                .ok()
+                .and_then(|v| v.parse::<u16>().ok())
+                .unwrap_or(DEFAULT_IMAP_PORT),
+            imap_host,
+            mailbox: env::var("EMAIL_MAILBOX").unwrap_or_else(|_| "INBOX".to_string()),
+            smtp_port: env::var("EMAIL_SMTP_PORT")
+                .ok()
+                .and_then(|v| v.parse::<u16>().ok())
+                .unwrap_or(DEFAULT_SMTP_PORT),
+            password: env::var("EMAIL_PASSWORD").unwrap_or_default(),
+            address: env::var("EMAIL_ADDRESS").unwrap_or_else(|_| username.clone()),
+            username,
+            allowed_senders: env::var("EMAIL_ALLOWED_SENDERS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|sender| sender.trim().to_lowercase())
+                .filter(|sender| !sender.is_empty())
+                .collect(),
+            poll_interval: Duration::from_secs(
+                env::var("EMAIL_POLL_SECS")
+                    .ok()
+                    .and_then(|v| v.parse::<u64>().ok())
+                    .unwrap_or(DEFAULT_EMAIL_POLL_SECS)
+                    .max(1),
+            ),
+            model: env::var("EMAIL_MODEL").ok(),
+            assistant: env::var("EMAIL_ASSISTANT").ok(),
+        })
+    }
+
+    fn is_allowed(&self, sender: &str) -> bool {
+        let sender = sender.to_lowercase();
+        self.allowed_senders.iter().any(|allowed| {
+            if allowed.starts_with('@') {
+                sender.ends_with(allowed.as_str())
+            } else {
+                sender == *allowed
+            }
+        })
+    }
+}
+
+// An incoming email that will be answered
+struct IncomingEmail {
+    from: String,
+    subject: String,
+    body: String,
+    message_id: Option<String>,
+    references: Vec<String>,
+}
+
+impl IncomingEmail {
+    fn parse(raw: &[u8]) -> Option<Self> {
+        let message = MessageParser::default().parse(raw)?;
+        let from = message.from()?.first()?.address()?.to_string();
+        let mut references = message_ids(message.references());
+        if references.is_empty() {
+            references = message_ids(message.in_reply_to());
+        }
+        Some(Self {
+            from,
+            subject: message.subject().unwrap_or_default().to_string(),
+            body: strip_quoted(&message.body_text(0)?),
+            message_id: message.message_id().map(|id| id.to_string()),
+            references,
+        })
+    }
+
+    // The chat session of this email's thread, derived from the thread's first message
+    fn session_id(&self) -> Uuid {
+        let root = self.references
+            .first()
+            .or(self.message_id.as_ref())
+            .cloned()
+            .unwrap_or_else(|| format!("{}:{}", self.from, self.subject));
+        let digest = Sha256::digest(root.as_bytes());
+        Uuid::from_slice(&digest[..16]).unwrap_or_else(|_| Uuid::new_v4())
+    }
+}
+
+fn message_ids(value: &HeaderValue) -> Vec<String> {
+    match value {
+        HeaderValue::Text(id) => vec![id.to_string()],
+        HeaderValue::TextList(ids) => ids.iter().map(|id| id.to_string()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+// Drop the quoted earlier messages from a reply; the session already holds them
+fn strip_quoted(body: &str) -> String {
+    let mut lines: Vec<&str> = body
+        .lines()
+        .take_while(|line| !line.starts_with('>'))
+        .collect();
+    // The "On <date>, <someone> wrote:" line introducing the quote
+    if lines.last().is_some_and(|line| line.trim_end().ends_with("wrote:")) {
+        lines.pop();
+    }
+    lines.join("\n").trim().to_string()
+}
+
+// Fetch and mark read the unread emails in the mailbox. The IMAP client is
+// blocking, so this runs on the blocking thread pool.
+fn fetch_unread(config: &EmailConfig) -> Result<Vec<Vec<u8>>> {
+    let tls = native_tls::TlsConnector::builder().build()?;
+    let client = imap::connect((config.imap_host.as_str(), config.imap_port), &config.imap_host, &tls)?;
+    let mut session = client
+        .login(&config.username, &config.password)
+        .map_err(|(e, _)| e)?;
+    session.select(&config.mailbox)?;
+
+    let unseen = session.search("UNSEEN")?;
+    let mut messages = Vec::new();
+    if !unseen.is_empty() {
+        let set = unseen.iter().map(|seq| seq.to_string()).collect::<Vec<_>>().join(",");
+        // Fetching the full message marks it read
+        for fetch in session.fetch(set, "RFC822")?.iter() {
+            if let Some(body) = fetch.body() {
+                messages.push(body.to_vec());
+            }
+        }
+    }
+    session.logout()?;
+    Ok(messages)
+}
+
+async fn send_reply(
+    config: &EmailConfig,
+    mailer: &AsyncSmtpTransport<Tokio1Executor>,
+    email: &IncomingEmail,
+    text: String,
+) -> Result<()> {
+    let subject = if email.subject.to_lowercase().starts_with("re:") {
+        email.subject.clone()
+    } else {
+        format!("Re: {}", email.subject)
+    };
+    let mut builder = Message::builder()
+        .from(config.address.parse()?)
+        .to(email.from.parse()?)
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN);
+    // Threading headers, so mail clients and the next reply keep the conversation together
+    if let Some(message_id) = &email.message_id {
+        let mut references = email.references.clone();
+        references.push(message_id.clone());
+        let references = references.iter().map(|id| format!("<{}>", id)).collect::<Vec<_>>().join(" ");
+        builder = builder
+            .in_reply_to(format!("<{}>", message_id))
+            .references(references);
+    }
+    mailer.send(builder.body(text)?).await?;
+    Ok(())
+}
+
+async fn answer(data: &AppState, config: &EmailConfig, mailer: &AsyncSmtpTransport<Tokio1Executor>, raw: &[u8]) {
+    let Some(email) = IncomingEmail::parse(raw) else {
+        warn!("Skipping an email that could not be parsed");
+        return;
+    };
+    if !config.is_allowed(&email.from) {
+        warn!("Ignoring email from {}, who is not in EMAIL_ALLOWED_SENDERS", email.from);
+        return;
+    }
+    if email.body.is_empty() {
+        return;
+    }
+
+    let req = ChatRequest {
+        message: email.body.clone(),
+        session_id: Some(email.session_id()),
+        max_tokens: None,
+        model: config.model.clone(),
+        bypass_cache: false,
+        template: None,
+        vars: Map::new(),
+        assistant: config.assistant.clone(),
+        user: Some(format!("email:{}", email.from)),
+        extra_body: None,
+        reasoning_effort: None,
+        thinking_budget_tokens: None,
+        confirm: false,
+        language: None,
+        length: None,
+        refine: false,
+        samples: None,
+    };
+    let reply = match respond_to_chat(None, data, &req).await {
+        Ok(response) => response.response,
+        Err(e) => {
+            warn!("Email reply to {} failed: {}", email.from, e.message());
+            format!("Sorry, I couldn't answer that: {}", e.message())
+        }
+    };
+    match send_reply(config, mailer, &email, reply).await {
+        Ok(()) => info!("Answered email from {}", email.from),
+        Err(e) => error!("Failed to send email reply to {}: {:#}", email.from, e),
+    }
+}
+
+// Poll the mailbox in the background and answer new mail, when an IMAP server is configured
+pub fn start(data: Data<AppState>) {
+    let Some(config) = EmailConfig::from_env() else { return };
+    if config.allowed_senders.is_empty() {
+        warn!("Email gateway not started: EMAIL_ALLOWED_SENDERS is empty");
+        return;
+    }
+    let mailer = match AsyncSmtpTransport::<Tokio1Executor>::relay(&config.smtp_host) {
+        Ok(builder) => builder
+            .port(config.smtp_port)
+            .credentials(Credentials::new(config.username.clone(), config.password.clone()))
+            .build(),
+        Err(e) => {
+            error!("Email gateway not started: invalid SMTP server {}: {}", config.smtp_host, e);
+            return;
+        }
+    };
+    info!("Email gateway polling {} on {} every {:?}", config.mailbox, config.imap_host, config.poll_interval);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(config.poll_interval);
+        loop {
+            ticker.tick().await;
+            let poll_config = config.clone();
+            let messages = match tokio::task::spawn_blocking(move || fetch_unread(&poll_config)).await {
+                Ok(Ok(messages)) => messages,
+                Ok(Err(e)) => {
+                    error!("Failed to check mailbox {}: {:#}", config.mailbox, e);
+                    continue;
+                }
+                Err(e) => {
+                    error!("Mailbox check panicked: {}", e);
+                    continue;
+                }
+            };
+            for raw in messages {
+                answer(&data, &config, &mailer, &raw).await;
+            }
+        }
+    });
+}
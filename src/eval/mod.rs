@@ -0,0 +1,359 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use log::{info, warn, error};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::model::LlamaModel;
+use crate::prompts::is_valid_name;
+use crate::web::models::{Message, Role};
+
+// Default directory holding the evaluation suites
+const DEFAULT_EVAL_DIR: &str = "evals";
+// Default directory receiving the results of each run
+const DEFAULT_EVAL_RESULTS_DIR: &str = "eval_results";
+// Answer length when a suite doesn't set max_tokens
+const DEFAULT_EVAL_MAX_TOKENS: usize = 256;
+
+const JUDGE_PROMPT: &str = "You grade answers for an automated test. Given a question, the expected answer \
+and a candidate answer, decide whether the candidate answer is correct and consistent with the expected one. \
+Wording may differ. Reply with PASS or FAIL only.";
+
+/// Environment variables for configuring the evaluation harness:
+///
+/// - `EVAL_DIR`: Directory of evaluation suites, one `<name>.toml` file each (default: "evals")
+/// - `EVAL_RESULTS_DIR`: Directory receiving the results of each run as `<run id>.json` (default: "eval_results")
+///
+/// A suite lists prompts with their expected answers and how answers are scored: `exact`
+/// (equal after trimming, ignoring case), `regex` (`expected` is a pattern the answer must
+/// match) or `judge` (a judge model decides whether the answer agrees with `expected`):
+///
+/// ```toml
+/// description = "Arithmetic smoke test"
+/// system_prompt = "Answer with the number only."
+/// scoring = "exact"       # default for the cases below
+/// temperature = 0.0       # optional (default: 0)
+/// max_tokens = 32         # optional (default: 256)
+///
+/// [[cases]]
+/// prompt = "What is 12 * 12?"
+/// expected = "144"
+///
+/// [[cases]]
+/// prompt = "Name the capital of France."
+/// expected = "(?i)paris"
+/// scoring = "regex"
+/// ```
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Scoring {
+    #[default]
+    Exact,
+    Regex,
+    Judge,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct EvalCase {
+    pub prompt: String,
+    pub expected: String,
+    // Overrides the suite's scoring for this case
+    #[serde(default)]
+    pub scoring: Option<Scoring>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct EvalSuite {
+    #[serde(skip_deserializing)]
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    #[serde(default)]
+    pub scoring: Scoring,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub max_tokens: Option<usize>,
+    pub cases: Vec<EvalCase>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    Running,
+    Completed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CaseResult {
+    pub prompt: String,
+    pub expected: String,
+    pub scoring: Scoring,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub answer: Option<String>,
+    // Generation or judging failure; the case counts as failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub passed: bool,
+    pub latency_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct EvalRun {
+    pub id: Uuid,
+    pub suite: String,
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub judge_model: Option<String>,
+    pub status: RunStatus,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub passed: usize,
+    pub total: usize,
+    // Previous completed run of the same suite on the same model, if any
+    pub previous_run: Option<Uuid>,
+    // Indexes of cases that passed in the previous run and fail in this one
+    pub regressions: Vec<usize>,
+    pub cases: Vec<CaseResult>,
+}
+
+// Evaluation suites read from disk and the results of their runs
+pub struct EvalStore {
+    results_dir: PathBuf,
+    suites: BTreeMap<String, EvalSuite>,
+    runs: Mutex<HashMap<Uuid, EvalRun>>,
+}
+
+impl EvalStore {
+    pub fn load() -> Self {
+        let dir = PathBuf::from(env::var("EVAL_DIR").unwrap_or_else(|_| DEFAULT_EVAL_DIR.to_string()));
+        let results_dir = PathBuf::from(env::var("EVAL_RESULTS_DIR").unwrap_or_else(|_| DEFAULT_EVAL_RESULTS_DIR.to_string()));
+
+        let mut suites = BTreeMap::new();
+        match fs::read_dir(&dir) {
+            Ok(entries) => {
+                for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+                    if path.extension().and_then(|ext| ext.to_str()) != Some("toml") {
+                        continue;
+                    }
+                    match Self::read_suite(&path) {
+                        Ok(suite) => {
+                            suites.insert(suite.name.clone(), suite);
+                        }
+                        Err(e) => warn!("Skipping evaluation suite {}: {:#}", path.display(), e),
+                    }
+                }
+                info!("Loaded {} evaluation suite(s) from {}", suites.len(), dir.display());
+            }
+            Err(_) => info!("No evaluation suites directory at {}", dir.display()),
+        }
+
+        // Earlier runs are kept so new ones can be compared against them
+        let mut runs = HashMap::new();
+        if let Ok(entries) = fs::read_dir(&results_dir) {
+            for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+                if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                    continue;
+                }
+                match fs::read_to_string(&path).map_err(anyhow::Error::from).and_then(|json| Ok(serde_json::from_str::<EvalRun>(&json)?)) {
+                    Ok(run) => {
+                        runs.insert(run.id, run);
+                    }
+                    Err(e) => warn!("Skipping evaluation result {}: {:#}", path.display(), e),
+                }
+            }
+        }
+
+        Self {
+            results_dir,
+            suites,
+            runs: Mutex::new(runs),
+        }
+    }
+
+    fn read_suite(path: &Path) -> Result<EvalSuite> {
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .filter(|stem| is_valid_name(stem))
+            .ok_or_else(|| anyhow::anyhow!("Invalid suite file name"))?
+            .to_string();
+        let contents = fs::read_to_string(path)?;
+        let mut suite: EvalSuite = toml::from_str(&contents)?;
+        if suite.cases.is_empty() {
+            return Err(anyhow::anyhow!("Suite has no cases"));
+        }
+        // Catch broken patterns at load time rather than in the middle of a run
+        for case in &suite.cases {
+            if case.scoring.unwrap_or(suite.scoring) == Scoring::Regex {
+                Regex::new(&case.expected)?;
+            }
+        }
+        suite.name = name;
+        Ok(suite)
+    }
+
+    pub fn suites(&self) -> Vec<EvalSuite> {
+        self.suites.values().cloned().collect()
+    }
+
+    pub fn suite(&self, name: &str) -> Option<&EvalSuite> {
+        self.suites.get(name)
+    }
+
+    pub fn get_run(&self, id: Uuid) -> Option<EvalRun> {
+        self.runs.lock().ok()?.get(&id).cloned()
+    }
+
+    // Runs, newest first, optionally limited to one suite and model
+    pub fn runs(&self, suite: Option<&str>, model: Option<&str>) -> Vec<EvalRun> {
+        let Ok(runs) = self.runs.lock() else { return Vec::new() };
+        let mut runs: Vec<EvalRun> = runs
+            .values()
+            .filter(|run| suite.is_none_or(|suite| run.suite == suite))
+            .filter(|run| model.is_none_or(|model| run.model == model))
+            .cloned()
+            .collect();
+        runs.sort_by(|a, b| b.started_at.cmp(&a.started_at));
+        runs
+    }
+
+    // Start running a suite in the background; the returned run is updated as cases finish
+    pub fn start(self: &Arc<Self>, suite: EvalSuite, model: Arc<LlamaModel>, judge: Option<Arc<LlamaModel>>) -> EvalRun {
+        let previous = self
+            .runs(Some(&suite.name), Some(model.name()))
+            .into_iter()
+            .find(|run| run.status == RunStatus::Completed);
+        let run = EvalRun {
+            id: Uuid::new_v4(),
+            suite: suite.name.clone(),
+            model: model.name().to_string(),
+            judge_model: judge.as_ref().map(|judge| judge.name().to_string()),
+            status: RunStatus::Running,
+            started_at: Utc::now(),
+            finished_at: None,
+            passed: 0,
+            total: suite.cases.len(),
+            previous_run: previous.as_ref().map(|run| run.id),
+            regressions: Vec::new(),
+            cases: Vec::new(),
+        };
+        if let Ok(mut runs) = self.runs.lock() {
+            runs.insert(run.id, run.clone());
+        }
+
+        let store = self.clone();
+        let id = run.id;
+        tokio::spawn(async move {
+            info!("Evaluation run {}: suite {} on model {}", id, suite.name, model.name());
+            for case in &suite.cases {
+                let result = run_case(&suite, case, &model, judge.as_deref()).await;
+                if let Ok(mut runs) = store.runs.lock() {
+                    if let Some(run) = runs.get_mut(&id) {
+                        run.passed += usize::from(result.passed);
+                        run.cases.push(result);
+                    }
+                }
+            }
+            store.finish(id, previous.as_ref());
+        });
+        run
+    }
+
+    // Mark a run completed, compare it with the previous one and persist it
+    fn finish(&self, id: Uuid, previous: Option<&EvalRun>) {
+        let Some(run) = self.runs.lock().ok().and_then(|mut runs| {
+            let run = runs.get_mut(&id)?;
+            run.status = RunStatus::Completed;
+            run.finished_at = Some(Utc::now());
+            if let Some(previous) = previous {
+                run.regressions = run.cases
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, case)| !case.passed && previous.cases.get(*i).is_some_and(|before| before.passed))
+                    .map(|(i, _)| i)
+                    .collect();
+            }
+            Some(run.clone())
+        }) else {
+            return;
+        };
+
+        info!("Evaluation run {} finished: {}/{} passed, {} regression(s)", id, run.passed, run.total, run.regressions.len());
+        let result = fs::create_dir_all(&self.results_dir)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| Ok(serde_json::to_string_pretty(&run)?))
+            .and_then(|json| Ok(fs::write(self.results_dir.join(format!("{}.json", id)), json)?));
+        if let Err(e) = result {
+            error!("Failed to save evaluation run {}: {}", id, e);
+        }
+    }
+}
+
+async fn run_case(suite: &EvalSuite, case: &EvalCase, model: &LlamaModel, judge: Option<&LlamaModel>) -> CaseResult {
+    let scoring = case.scoring.unwrap_or(suite.scoring);
+    let mut result = CaseResult {
+        prompt: case.prompt.clone(),
+        expected: case.expected.clone(),
+        scoring,
+        answer: None,
+        error: None,
+        passed: false,
+        latency_ms: 0,
+    };
+
+    let mut messages = Vec::new();
+    if let Some(system_prompt) = &suite.system_prompt {
+        messages.push(Message { role: Role::System, content: system_prompt.clone() });
+    }
+    messages.push(Message { role: Role::User, content: case.prompt.clone() });
+
+    let started = Instant::now();
+    let answer = model
+        .complete_messages(&messages, suite.max_tokens.unwrap_or(DEFAULT_EVAL_MAX_TOKENS), suite.temperature.unwrap_or(0.0))
+        .await;
+    result.latency_ms = started.elapsed().as_millis() as u64;
+    let answer = match answer {
+        Ok(answer) => answer,
+        Err(e) => {
+            result.error = Some(e.to_string());
+            return result;
+        }
+    };
+
+    match score(scoring, case, &answer, judge.unwrap_or(model)).await {
+        Ok(passed) => result.passed = passed,
+        Err(e) => result.error = Some(e.to_string()),
+    }
+    result.answer = Some(answer);
+    result
+}
+
+async fn score(scoring: Scoring, case: &EvalCase, answer: &str, judge: &LlamaModel) -> Result<bool> {
+    match scoring {
+        Scoring::Exact => Ok(answer.trim().to_lowercase() == case.expected.trim().to_lowercase()),
+        Scoring::Regex => Ok(Regex::new(&case.expected)?.is_match(answer)),
+        Scoring::Judge => {
+            let messages = [
+                Message { role: Role::System, content: JUDGE_PROMPT.to_string() },
+                Message {
+                    role: Role::User,
+                    content: format!("Question: {}\n\nExpected answer: {}\n\nCandidate answer: {}", case.prompt, case.expected, answer),
+                },
+            ];
+            let verdict = judge.complete_messages(&messages, 8, 0.0).await?;
+            Ok(verdict.trim().to_uppercase().starts_with("PASS"))
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use utoipa::ToSchema;
+
+// Overall tone of a text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Sentiment {
+    Positive,
+    Neutral,
+    Negative,
+    Mixed,
+}
+
+// Kind of a named entity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EntityKind {
+    Person,
+    Organization,
+    Location,
+    Date,
+    Product,
+    Event,
+    Other,
+}
+
+// Something a text names
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Entity {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: EntityKind,
+}
+
+// Metadata extracted from a text
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Metadata {
+    // A short title for the text, whether or not it has one
+    pub title: String,
+    pub keywords: Vec<String>,
+    pub entities: Vec<Entity>,
+    pub sentiment: Sentiment,
+}
+
+// JSON Schema the model's reply is held to; kept in step with `Metadata`
+pub fn metadata_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "title": { "type": "string" },
+            "keywords": { "type": "array", "items": { "type": "string" } },
+            "entities": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "type": {
+                            "type": "string",
+                            "enum": ["person", "organization", "location", "date", "product", "event", "other"]
+                        }
+                    },
+                    "required": ["name", "type"],
+                    "additionalProperties": false
+                }
+            },
+            "sentiment": { "type": "string", "enum": ["positive", "neutral", "negative", "mixed"] }
+        },
+        "required": ["title", "keywords", "entities", "sentiment"],
+        "additionalProperties": false
+    })
+}
+
+// Instructions for extracting a text's metadata; the schema is spelled out as well for
+// backends that don't constrain their output to it
+pub fn metadata_prompt(text: &str, max_keywords: usize) -> String {
+    format!(
+        "Extract metadata from the text below. Reply with a JSON object only, with these fields:\n\
+        - \"title\": a short title for the text\n\
+        - \"keywords\": up to {} keywords or key phrases, most important first\n\
+        - \"entities\": the people, organizations, locations, dates, products and events it names, \
+        each as {{\"name\": ..., \"type\": ...}} with type one of \"person\", \"organization\", \
+        \"location\", \"date\", \"product\", \"event\" or \"other\"\n\
+        - \"sentiment\": \"positive\", \"neutral\", \"negative\" or \"mixed\"\n\n\
+        Text:\n{}",
+        max_keywords, text
+    )
+}
+
+// Parse a model's JSON reply. Backends that can't constrain their output may wrap the object
+// in a code fence or a sentence, so the outermost object is used when the reply isn't JSON.
+pub fn parse_reply<T: DeserializeOwned>(reply: &str) -> Result<T> {
+    let reply = reply.trim();
+    if let Ok(value) = serde_json::from_str(reply) {
+        return Ok(value);
+    }
+    let object = match (reply.find('{'), reply.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &reply[start..=end],
+        _ => anyhow::bail!("the model's reply is not a JSON object"),
+    };
+    serde_json::from_str(object).context("the model's reply doesn't match the expected schema")
+}
+
+// A label assigned to a text and how sure the model is of it
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct LabelScore {
+    pub label: String,
+    // Between 0 and 1
+    pub confidence: f32,
+}
+
+#[derive(Deserialize)]
+struct ClassificationReply {
+    labels: Vec<LabelScore>,
+}
+
+// JSON Schema of a classification reply: the `top_k` best of the given labels
+pub fn classification_schema(labels: &[String], top_k: usize) -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "labels": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "label": { "type": "string", "enum": labels },
+                        "confidence": { "type": "number", "minimum": 0, "maximum": 1 }
+                    },
+                    "required": ["label", "confidence"],
+                    "additionalProperties": false
+                },
+                "minItems": 1,
+                "maxItems": top_k
+            }
+        },
+        "required": ["labels"],
+        "additionalProperties": false
+    })
+}
+
+pub fn classification_prompt(text: &str, labels: &[String], top_k: usize) -> String {
+    let choice = if top_k == 1 {
+        "the one label that fits it best".to_string()
+    } else {
+        format!("the {} labels that fit it best, best first", top_k)
+    };
+    let labels = labels.iter().map(|label| format!("- {}", label)).collect::<Vec<_>>().join("\n");
+    format!(
+        "Classify the text below. Choose {} from these labels, spelled exactly as given:\n{}\n\n\
+        Reply with a JSON object only: {{\"labels\": [{{\"label\": ..., \"confidence\": ...}}]}}, \
+        with confidence between 0 and 1.\n\nText:\n{}",
+        choice, labels, text
+    )
+}
+
+// Parse a classification reply into at most `top_k` of the given labels, best first. Labels are
+// matched case-insensitively and returned as given; others and repeats are dropped.
+pub fn parse_classification(reply: &str, labels: &[String], top_k: usize) -> Result<Vec<LabelScore>> {
+    let reply: ClassificationReply = parse_reply(reply)?;
+    let mut scores: Vec<LabelScore> = Vec::new();
+    for score in reply.labels {
+        let Some(label) = labels.iter().find(|label| label.trim().eq_ignore_ascii_case(score.label.trim())) else {
+            continue;
+        };
+        if scores.iter().any(|s| s.label == *label) {
+            continue;
+        }
+        let confidence = if score.confidence.is_finite() { score.confidence.clamp(0.0, 1.0) } else { 0.0 };
+        scores.push(LabelScore { label: label.clone(), confidence });
+    }
+    if scores.is_empty() {
+        anyhow::bail!("the model's reply doesn't name any of the labels");
+    }
+    scores.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    scores.truncate(top_k);
+    Ok(scores)
+}
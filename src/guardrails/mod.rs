@@ -0,0 +1,255 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use log::{info, warn};
+
+use crate::metrics::metrics;
+use crate::model::ModelManager;
+use crate::moderation::Stage;
+
+// Default location of the guardrail rules
+const DEFAULT_GUARDRAILS_CONFIG: &str = "guardrails.toml";
+
+/// Environment variables for configuring guardrails:
+///
+/// - `GUARDRAILS_CONFIG`: Path of the TOML guardrail rules (default: "guardrails.toml");
+///   guardrails are off when the file doesn't exist
+///
+/// Rules are checked in order on the user message (`input`, the default) or on the model's reply
+/// (`output`). Every condition given must hold for a rule to apply:
+///
+/// ```toml
+/// [[rules]]
+/// name = "no-sql-dumps"
+/// stage = "input"
+/// pattern = "(?i)\\bdrop\\s+table\\b"   # regular expression found in the text
+/// min_chars = 20                        # text length bounds, in characters
+/// max_chars = 20000
+/// languages = ["deu", "french"]         # detected language, ISO 639-3 code or English name
+/// tools = ["run_sql"]                   # the assistant may use one of these tools ("*": any)
+/// action = "confirm"                    # "block", "rewrite", "confirm" or "route"
+/// message = "This looks destructive; resend with \"confirm\": true to go ahead"
+///
+/// [[rules]]
+/// name = "long-documents"
+/// min_chars = 8000
+/// action = "route"
+/// model = "mistral-32k"                 # registry model answering instead
+/// ```
+///
+/// `rewrite` replaces the matches of `pattern` with `replacement`, or the whole text when the
+/// rule has no pattern; later rules see the rewritten text. `confirm` and `route` only apply to
+/// input.
+
+// What happens when a rule applies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardrailAction {
+    Block,
+    Rewrite,
+    Confirm,
+    Route,
+}
+
+impl GuardrailAction {
+    fn as_str(self) -> &'static str {
+        match self {
+            GuardrailAction::Block => "block",
+            GuardrailAction::Rewrite => "rewrite",
+            GuardrailAction::Confirm => "confirm",
+            GuardrailAction::Route => "route",
+        }
+    }
+}
+
+fn input_stage() -> Stage {
+    Stage::Input
+}
+
+#[derive(Debug, Deserialize)]
+struct GuardrailRule {
+    name: String,
+    #[serde(default = "input_stage")]
+    stage: Stage,
+    pattern: Option<String>,
+    min_chars: Option<usize>,
+    max_chars: Option<usize>,
+    #[serde(default)]
+    languages: Vec<String>,
+    #[serde(default)]
+    tools: Vec<String>,
+    action: GuardrailAction,
+    replacement: Option<String>,
+    model: Option<String>,
+    message: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct GuardrailsConfig {
+    #[serde(default)]
+    rules: Vec<GuardrailRule>,
+}
+
+struct CompiledRule {
+    rule: GuardrailRule,
+    regex: Option<Regex>,
+    languages: Vec<String>,
+}
+
+// What a request brings besides its text
+pub struct RuleContext<'a> {
+    // Tools the request's assistant may use
+    pub tools: &'a [String],
+    // Whether the caller confirmed rules asking for it
+    pub confirmed: bool,
+}
+
+// Outcome of checking a text against the rules
+pub enum Guarded {
+    // The text to use, possibly rewritten, and the model to send it to instead, if any
+    Allowed { text: String, route: Option<String> },
+    Blocked { rules: Vec<String>, message: String },
+    ConfirmationRequired { rules: Vec<String>, message: String },
+}
+
+// Declarative rules checked before and after generation
+pub struct Guardrails {
+    rules: Vec<CompiledRule>,
+}
+
+impl CompiledRule {
+    fn applies(&self, stage: Stage, text: &str, context: &RuleContext) -> bool {
+        let rule = &self.rule;
+        if rule.stage != stage {
+            return false;
+        }
+        if self.regex.as_ref().is_some_and(|regex| !regex.is_match(text)) {
+            return false;
+        }
+        let chars = text.chars().count();
+        if rule.min_chars.is_some_and(|min| chars < min) || rule.max_chars.is_some_and(|max| chars > max) {
+            return false;
+        }
+        if !self.languages.is_empty() {
+            // Text too short or mixed to tell its language doesn't match any
+            let Some(lang) = whatlang::detect_lang(text) else { return false };
+            let (code, name) = (lang.code(), lang.eng_name().to_lowercase());
+            if !self.languages.iter().any(|l| l == code || *l == name) {
+                return false;
+            }
+        }
+        if !rule.tools.is_empty()
+            && !rule.tools.iter().any(|tool| (tool == "*" && !context.tools.is_empty()) || context.tools.contains(tool))
+        {
+            return false;
+        }
+        true
+    }
+}
+
+impl Guardrails {
+    pub fn load(models: &ModelManager) -> Result<Self> {
+        let path = env::var("GUARDRAILS_CONFIG")
+            .unwrap_or_else(|_| DEFAULT_GUARDRAILS_CONFIG.to_string());
+
+        let config: GuardrailsConfig = if Path::new(&path).exists() {
+            let contents = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read guardrails config {}", path))?;
+            toml::from_str(&contents)
+                .with_context(|| format!("Invalid guardrails config {}", path))?
+        } else {
+            info!("No guardrails config found at {}, guardrails disabled", path);
+            GuardrailsConfig::default()
+        };
+
+        let rules = config.rules
+            .into_iter()
+            .map(|rule| {
+                if rule.stage == Stage::Output && matches!(rule.action, GuardrailAction::Confirm | GuardrailAction::Route) {
+                    anyhow::bail!("Guardrail rule {}: {} only applies to input", rule.name, rule.action.as_str());
+                }
+                if rule.action == GuardrailAction::Rewrite && rule.replacement.is_none() {
+                    anyhow::bail!("Guardrail rule {}: rewrite needs a replacement", rule.name);
+                }
+                if rule.action == GuardrailAction::Route {
+                    let model = rule.model.as_deref()
+                        .ok_or_else(|| anyhow::anyhow!("Guardrail rule {}: route needs a model", rule.name))?;
+                    if models.get(Some(model)).is_none() {
+                        anyhow::bail!("Guardrail rule {}: unknown model {}", rule.name, model);
+                    }
+                }
+                let regex = rule.pattern
+                    .as_deref()
+                    .map(Regex::new)
+                    .transpose()
+                    .with_context(|| format!("Invalid pattern in guardrail rule {}", rule.name))?;
+                let languages = rule.languages.iter().map(|l| l.trim().to_lowercase()).collect();
+                Ok(CompiledRule { rule, regex, languages })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if !rules.is_empty() {
+            info!("Loaded {} guardrail rule(s) from {}", rules.len(), path);
+        }
+        Ok(Self { rules })
+    }
+
+    // Apply the rules of a stage in order
+    pub fn check(&self, stage: Stage, text: &str, context: &RuleContext) -> Guarded {
+        let mut text = text.to_string();
+        let mut route = None;
+        let mut unconfirmed = Vec::new();
+        let mut confirm_messages = Vec::new();
+
+        for compiled in &self.rules {
+            if !compiled.applies(stage, &text, context) {
+                continue;
+            }
+            let rule = &compiled.rule;
+            Self::record_hit(rule);
+
+            match rule.action {
+                GuardrailAction::Block => {
+                    return Guarded::Blocked {
+                        rules: vec![rule.name.clone()],
+                        message: rule.message.clone().unwrap_or_else(|| "Blocked by a guardrail rule".to_string()),
+                    };
+                }
+                GuardrailAction::Rewrite => {
+                    let replacement = rule.replacement.as_deref().unwrap_or_default();
+                    text = match &compiled.regex {
+                        Some(regex) => regex.replace_all(&text, replacement).into_owned(),
+                        None => replacement.to_string(),
+                    };
+                }
+                GuardrailAction::Confirm if !context.confirmed => {
+                    unconfirmed.push(rule.name.clone());
+                    confirm_messages.extend(rule.message.clone());
+                }
+                GuardrailAction::Confirm => {}
+                // The first matching route wins
+                GuardrailAction::Route => {
+                    route = route.or_else(|| rule.model.clone());
+                }
+            }
+        }
+
+        if !unconfirmed.is_empty() {
+            let message = if confirm_messages.is_empty() {
+                "This message needs confirmation; resend it with \"confirm\": true".to_string()
+            } else {
+                confirm_messages.join(" ")
+            };
+            return Guarded::ConfirmationRequired { rules: unconfirmed, message };
+        }
+        Guarded::Allowed { text, route }
+    }
+
+    fn record_hit(rule: &GuardrailRule) {
+        warn!("Guardrail rule {} applied ({})", rule.name, rule.action.as_str());
+        metrics().increment("guardrail_hits_total", &[("rule", rule.name.as_str()), ("action", rule.action.as_str())]);
+    }
+}
@@ -0,0 +1,70 @@
+use std::env;
+use std::sync::OnceLock;
+use std::time::Duration;
+use log::{info, error};
+use reqwest::Client;
+
+// Defaults for the shared HTTP client
+const DEFAULT_HTTP_POOL_MAX_IDLE_PER_HOST: usize = 32;
+const DEFAULT_HTTP_POOL_IDLE_TIMEOUT_SECS: u64 = 90;
+const DEFAULT_HTTP_TCP_KEEPALIVE_SECS: u64 = 60;
+const DEFAULT_HTTP_CONNECT_TIMEOUT_SECS: u64 = 10;
+
+/// Environment variables for tuning the HTTP client shared by all outgoing requests
+/// (backends, embeddings and external moderation):
+///
+/// - `HTTP_POOL_MAX_IDLE_PER_HOST`: Idle connections kept open per host for reuse (default: 32)
+/// - `HTTP_POOL_IDLE_TIMEOUT_SECS`: Seconds an idle pooled connection is kept, 0 to keep it
+///   until the server closes it (default: 90)
+/// - `HTTP_TCP_KEEPALIVE_SECS`: Interval of TCP keep-alive probes, 0 to disable (default: 60)
+/// - `HTTP_TCP_NODELAY`: Disable Nagle's algorithm so small requests go out at once (default: true)
+/// - `HTTP_CONNECT_TIMEOUT_SECS`: Seconds to wait for a connection to be established (default: 10)
+/// - `HTTP2_PRIOR_KNOWLEDGE`: Speak HTTP/2 without negotiation, for backends known to support
+///   it over plain TCP; all requests then share one connection per host (default: false)
+
+static HTTP_CLIENT: OnceLock<Client> = OnceLock::new();
+
+// The process-wide HTTP client; clones share its connection pool
+pub fn http_client() -> &'static Client {
+    HTTP_CLIENT.get_or_init(build)
+}
+
+fn env_parse<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse::<T>().ok())
+        .unwrap_or(default)
+}
+
+fn build() -> Client {
+    let max_idle = env_parse("HTTP_POOL_MAX_IDLE_PER_HOST", DEFAULT_HTTP_POOL_MAX_IDLE_PER_HOST);
+    let idle_timeout = env_parse("HTTP_POOL_IDLE_TIMEOUT_SECS", DEFAULT_HTTP_POOL_IDLE_TIMEOUT_SECS);
+    let keepalive = env_parse("HTTP_TCP_KEEPALIVE_SECS", DEFAULT_HTTP_TCP_KEEPALIVE_SECS);
+    let nodelay = env_parse("HTTP_TCP_NODELAY", true);
+    let connect_timeout = env_parse("HTTP_CONNECT_TIMEOUT_SECS", DEFAULT_HTTP_CONNECT_TIMEOUT_SECS);
+    let http2 = env_parse("HTTP2_PRIOR_KNOWLEDGE", false);
+
+    let mut builder = Client::builder()
+        .pool_max_idle_per_host(max_idle)
+        .pool_idle_timeout((idle_timeout > 0).then(|| Duration::from_secs(idle_timeout)))
+        .tcp_keepalive((keepalive > 0).then(|| Duration::from_secs(keepalive)))
+        .tcp_nodelay(nodelay)
+        .connect_timeout(Duration::from_secs(connect_timeout.max(1)));
+    if http2 {
+        builder = builder.http2_prior_knowledge();
+    }
+
+    match builder.build() {
+        Ok(client) => {
+            info!(
+                "HTTP client: {} idle connection(s) per host, idle timeout {}s, keep-alive {}s, nodelay {}, HTTP/2 {}",
+                max_idle, idle_timeout, keepalive, nodelay, http2
+            );
+            client
+        }
+        Err(e) => {
+            error!("Failed to build tuned HTTP client, using defaults: {}", e);
+            Client::new()
+        }
+    }
+}
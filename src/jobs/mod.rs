@@ -0,0 +1,230 @@
+pub mod webhook;
+
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use tokio::sync::mpsc;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use log::{info, error};
+
+use crate::model::{GenerationOptions, LlamaModel};
+use crate::web::request_id;
+use webhook::WebhookSender;
+
+// Default number of background workers executing jobs
+const DEFAULT_JOB_WORKERS: usize = 1;
+// Default time finished jobs are kept for polling
+const DEFAULT_JOB_RETENTION_SECS: i64 = 3600;
+
+/// Environment variables for configuring async generation jobs:
+///
+/// - `JOB_WORKERS`: Number of jobs executed concurrently in the background (default: 1)
+/// - `JOB_RETENTION_SECS`: Seconds a finished job's result stays available (default: 3600)
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+}
+
+impl JobStatus {
+    fn is_finished(self) -> bool {
+        matches!(self, JobStatus::Completed | JobStatus::Failed)
+    }
+}
+
+// State of an async generation job as returned to polling clients
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Job {
+    pub id: Uuid,
+    pub status: JobStatus,
+    pub model: String,
+    pub result: Option<String>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    // User the job was submitted for; not exposed to polling clients
+    #[serde(skip)]
+    pub user: Option<String>,
+}
+
+// Work item handed to the background workers
+struct JobRequest {
+    id: Uuid,
+    model: Arc<LlamaModel>,
+    prompt: String,
+    options: GenerationOptions,
+    callback_url: Option<String>,
+    // ID of the request that submitted the job, kept on its log lines and backend calls
+    request_id: Option<String>,
+}
+
+pub struct JobQueue {
+    jobs: Mutex<HashMap<Uuid, Job>>,
+    sender: mpsc::UnboundedSender<JobRequest>,
+    webhooks: Arc<WebhookSender>,
+    retention: Duration,
+}
+
+impl JobQueue {
+    // Create the queue and spawn its background workers
+    pub fn start() -> Arc<Self> {
+        let workers = env::var("JOB_WORKERS")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_JOB_WORKERS)
+            .max(1);
+        let retention = env::var("JOB_RETENTION_SECS")
+            .ok()
+            .and_then(|v| v.parse::<i64>().ok())
+            .unwrap_or(DEFAULT_JOB_RETENTION_SECS);
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let queue = Arc::new(Self {
+            jobs: Mutex::new(HashMap::new()),
+            sender,
+            webhooks: Arc::new(WebhookSender::from_env()),
+            retention: Duration::seconds(retention),
+        });
+
+        let receiver = Arc::new(tokio::sync::Mutex::new(receiver));
+        for worker in 0..workers {
+            let queue = queue.clone();
+            let receiver = receiver.clone();
+            tokio::spawn(async move {
+                loop {
+                    // Hold the receiver lock only while waiting for the next job
+                    let request = receiver.lock().await.recv().await;
+                    match request {
+                        Some(request) => match request.request_id.clone() {
+                            Some(id) => request_id::scope(id, queue.run(worker, request)).await,
+                            None => queue.run(worker, request).await,
+                        },
+                        None => break,
+                    }
+                }
+            });
+        }
+
+        info!("Started {} async job worker(s)", workers);
+        queue
+    }
+
+    // Queue a generation and return its job ID immediately.
+    // When a callback URL is given, the finished job is also POSTed there.
+    pub fn submit(
+        &self,
+        model: Arc<LlamaModel>,
+        prompt: String,
+        options: GenerationOptions,
+        callback_url: Option<String>,
+    ) -> Result<Uuid, String> {
+        let id = Uuid::new_v4();
+        let job = Job {
+            id,
+            status: JobStatus::Queued,
+            model: model.name().to_string(),
+            result: None,
+            error: None,
+            created_at: Utc::now(),
+            completed_at: None,
+            user: options.user.clone(),
+        };
+
+        {
+            let mut jobs = self.jobs.lock().map_err(|e| e.to_string())?;
+            self.prune(&mut jobs);
+            jobs.insert(id, job);
+        }
+
+        self.sender
+            .send(JobRequest { id, model, prompt, options, callback_url, request_id: request_id::current() })
+            .map_err(|_| "Job queue is closed".to_string())?;
+
+        Ok(id)
+    }
+
+    pub fn get(&self, id: &Uuid) -> Option<Job> {
+        self.jobs.lock().ok()?.get(id).cloned()
+    }
+
+    // Jobs submitted for a user
+    pub fn for_user(&self, user: &str) -> Vec<Job> {
+        self.jobs
+            .lock()
+            .map(|jobs| jobs.values().filter(|job| job.user.as_deref() == Some(user)).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    // Forget a user's jobs, returning how many were removed. Jobs still running
+    // finish, but their results are no longer stored.
+    pub fn purge_user(&self, user: &str) -> usize {
+        let Ok(mut jobs) = self.jobs.lock() else { return 0 };
+        let before = jobs.len();
+        jobs.retain(|_, job| job.user.as_deref() != Some(user));
+        before - jobs.len()
+    }
+
+    // Number of jobs waiting for a worker
+    pub fn queued(&self) -> usize {
+        self.jobs
+            .lock()
+            .map(|jobs| jobs.values().filter(|job| job.status == JobStatus::Queued).count())
+            .unwrap_or(0)
+    }
+
+    async fn run(&self, worker: usize, request: JobRequest) {
+        info!("Worker {} running job {}", worker, request.id);
+        self.update(&request.id, |job| job.status = JobStatus::Running);
+
+        let outcome = request.model
+            .generate_response(&request.prompt, &[], &request.options)
+            .await;
+
+        self.update(&request.id, |job| {
+            match outcome {
+                Ok(completion) => {
+                    job.status = JobStatus::Completed;
+                    job.result = Some(completion.text);
+                }
+                Err(e) => {
+                    error!("Job {} failed: {}", job.id, e);
+                    job.status = JobStatus::Failed;
+                    job.error = Some(e.to_string());
+                }
+            }
+            job.completed_at = Some(Utc::now());
+        });
+        
+        if let Some(url) = request.callback_url {
+            if let Some(job) = self.get(&request.id) {
+                self.webhooks.deliver(url, job);
+            }
+        }
+    }
+
+    fn update(&self, id: &Uuid, f: impl FnOnce(&mut Job)) {
+        match self.jobs.lock() {
+            Ok(mut jobs) => {
+                if let Some(job) = jobs.get_mut(id) {
+                    f(job);
+                }
+            }
+            Err(e) => error!("Failed to lock job store: {}", e),
+        }
+    }
+
+    // Drop finished jobs older than the retention window
+    fn prune(&self, jobs: &mut HashMap<Uuid, Job>) {
+        let cutoff = Utc::now() - self.retention;
+        jobs.retain(|_, job| {
+            !job.status.is_finished() || job.completed_at.is_none_or(|done| done > cutoff)
+        });
+    }
+}
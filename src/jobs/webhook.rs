@@ -0,0 +1,182 @@
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::{redirect, Client, Url};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use log::{info, warn, error};
+
+use super::{Job, JobStatus};
+use crate::collections::fetch::{check_public, FetchError};
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Default number of delivery attempts before a webhook is dead-lettered
+const DEFAULT_WEBHOOK_MAX_ATTEMPTS: u32 = 5;
+// Delay before the first retry; doubled after each failed attempt
+const WEBHOOK_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+// Timeout for a single delivery attempt
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+// Default file receiving undeliverable webhooks
+const DEFAULT_DEAD_LETTER_PATH: &str = "webhook_dead_letter.jsonl";
+
+/// Environment variables for configuring job completion and scheduled prompt webhooks:
+///
+/// - `WEBHOOK_SECRET`: Key used to sign payloads with HMAC-SHA256 in the `X-Signature-256`
+///   header; payloads are unsigned when unset
+/// - `WEBHOOK_MAX_ATTEMPTS`: Delivery attempts before giving up (default: 5)
+/// - `WEBHOOK_DEAD_LETTER_PATH`: JSON lines file recording undeliverable webhooks
+///   (default: "webhook_dead_letter.jsonl")
+/// - `WEBHOOK_ALLOW_PRIVATE`: Deliver to loopback, private and link-local addresses, which are
+///   refused by default so that callbacks can't be used to reach internal services
+///   (default: false)
+///
+/// Redirects are not followed, so a receiver can't send the signed payload on to another host.
+
+// Client for webhook deliveries. It is separate from the shared client, whose settings suit
+// backends, so that redirects aren't followed past the address check.
+fn client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        Client::builder()
+            .redirect(redirect::Policy::none())
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+            .unwrap_or_else(|e| {
+                warn!("Failed to build the webhook client, using defaults: {}", e);
+                Client::new()
+            })
+    })
+}
+
+// Refuse callback URLs that aren't http(s), or that point at an internal address unless
+// WEBHOOK_ALLOW_PRIVATE is set
+pub async fn check_url(url: &str) -> Result<(), FetchError> {
+    let parsed = Url::parse(url).map_err(|e| FetchError::Invalid(format!("{}: {}", url, e)))?;
+    if parsed.scheme() != "http" && parsed.scheme() != "https" {
+        return Err(FetchError::Invalid(format!("{} is not an http or https URL", url)));
+    }
+    let allow_private = env::var("WEBHOOK_ALLOW_PRIVATE")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+    if allow_private {
+        return Ok(());
+    }
+    check_public(&parsed).await
+}
+
+pub struct WebhookSender {
+    client: Client,
+    secret: Option<String>,
+    max_attempts: u32,
+    dead_letter_path: String,
+}
+
+impl WebhookSender {
+    pub fn from_env() -> Self {
+        Self {
+            client: client().clone(),
+            secret: env::var("WEBHOOK_SECRET").ok().filter(|s| !s.is_empty()),
+            max_attempts: env::var("WEBHOOK_MAX_ATTEMPTS")
+                .ok()
+                .and_then(|v| v.parse::<u32>().ok())
+                .unwrap_or(DEFAULT_WEBHOOK_MAX_ATTEMPTS)
+                .max(1),
+            dead_letter_path: env::var("WEBHOOK_DEAD_LETTER_PATH")
+                .unwrap_or_else(|_| DEFAULT_DEAD_LETTER_PATH.to_string()),
+        }
+    }
+
+    // Deliver the job result in the background so workers aren't held up by slow receivers
+    pub fn deliver(self: &Arc<Self>, url: String, job: Job) {
+        let event = match job.status {
+            JobStatus::Failed => "job.failed",
+            _ => "job.completed",
+        };
+        self.deliver_event(url, event, json!({ "job": job }));
+    }
+
+    // Deliver an event in the background; `fields` are sent alongside the event name
+    pub fn deliver_event(self: &Arc<Self>, url: String, event: &'static str, fields: Value) {
+        let sender = self.clone();
+        tokio::spawn(async move {
+            let mut body = json!({ "event": event });
+            if let (Some(body), Value::Object(fields)) = (body.as_object_mut(), fields) {
+                body.extend(fields);
+            }
+            sender.deliver_with_retry(&url, event, body.to_string()).await;
+        });
+    }
+
+    async fn deliver_with_retry(&self, url: &str, event: &str, body: String) {
+        let mut backoff = WEBHOOK_INITIAL_BACKOFF;
+        let mut last_error = String::new();
+
+        for attempt in 1..=self.max_attempts {
+            // Checked before every attempt, as the host may resolve differently by then
+            if let Err(e) = check_url(url).await {
+                self.dead_letter(url, &body, &e.to_string());
+                return;
+            }
+            let mut request = self.client
+                .post(url)
+                .timeout(WEBHOOK_TIMEOUT)
+                .header("Content-Type", "application/json")
+                .header("X-Webhook-Event", event)
+                .body(body.clone());
+            if let Some(signature) = self.sign(&body) {
+                request = request.header("X-Signature-256", format!("sha256={}", signature));
+            }
+
+            match request.send().await {
+                Ok(response) if response.status().is_success() => {
+                    info!("Delivered {} webhook to {} (attempt {})", event, url, attempt);
+                    return;
+                }
+                Ok(response) => last_error = format!("status {}", response.status()),
+                Err(e) => last_error = e.to_string(),
+            }
+
+            warn!("Webhook delivery to {} failed (attempt {}/{}): {}", url, attempt, self.max_attempts, last_error);
+            if attempt < self.max_attempts {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+        }
+
+        self.dead_letter(url, &body, &last_error);
+    }
+
+    // Hex-encoded HMAC-SHA256 of the body, when a secret is configured
+    fn sign(&self, body: &str) -> Option<String> {
+        let secret = self.secret.as_ref()?;
+        let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).ok()?;
+        mac.update(body.as_bytes());
+        Some(hex::encode(mac.finalize().into_bytes()))
+    }
+
+    // Record an undeliverable webhook so it can be replayed by hand
+    fn dead_letter(&self, url: &str, body: &str, error: &str) {
+        error!("Giving up on webhook delivery to {}: {}", url, error);
+
+        let record = json!({
+            "url": url,
+            "payload": body,
+            "error": error,
+            "failed_at": Utc::now(),
+        });
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.dead_letter_path)
+            .and_then(|mut file| writeln!(file, "{}", record));
+        if let Err(e) = result {
+            error!("Failed to write webhook dead letter to {}: {}", self.dead_letter_path, e);
+        }
+    }
+}
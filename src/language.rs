@@ -0,0 +1,67 @@
+use std::env;
+use log::warn;
+use whatlang::Lang;
+
+/// Environment variables for the response language:
+///
+/// - `RESPONSE_LANGUAGE`: Language of replies when neither the request nor its session sets
+///   `language`; "auto" to answer in the language of each message, or a language (default: none,
+///   the model decides)
+///
+/// Languages are given by ISO 639-3 code ("fra") or English name ("French"). Languages are
+/// detected with whatlang; short or mixed messages may not be recognized, and in "auto" mode
+/// get no instruction.
+
+// Which language replies should be in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LanguagePreference {
+    // The language each message is written in
+    Auto,
+    Forced(Lang),
+}
+
+// Parse a `language` setting
+pub fn parse(value: &str) -> Result<LanguagePreference, String> {
+    let value = value.trim().to_lowercase();
+    if value == "auto" {
+        return Ok(LanguagePreference::Auto);
+    }
+    Lang::from_code(&value)
+        .or_else(|| Lang::all().iter().copied().find(|lang| lang.eng_name().to_lowercase() == value))
+        .map(LanguagePreference::Forced)
+        .ok_or_else(|| format!("Unknown language: {}; use \"auto\", an ISO 639-3 code or an English name", value))
+}
+
+// The preference of the request, then of the session, then of the environment
+pub fn preference(request: Option<&str>, session: Option<&str>) -> Result<Option<LanguagePreference>, String> {
+    match request.or(session) {
+        Some(value) => parse(value).map(Some),
+        None => Ok(env::var("RESPONSE_LANGUAGE")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .and_then(|v| parse(&v).map_err(|e| warn!("Ignoring RESPONSE_LANGUAGE: {}", e)).ok())),
+    }
+}
+
+// English name of a language setting for use in prompts; values whatlang doesn't know,
+// like "pt-BR", are passed on as given
+pub fn display_name(value: &str) -> String {
+    match parse(value) {
+        Ok(LanguagePreference::Forced(lang)) => lang.eng_name().to_string(),
+        _ => value.trim().to_string(),
+    }
+}
+
+// Language of a text, when whatlang is confident about it
+pub fn detect(text: &str) -> Option<Lang> {
+    whatlang::detect(text).filter(|info| info.is_reliable()).map(|info| info.lang())
+}
+
+// Instruction added to the system message so the reply comes in the preferred language
+pub fn instruction(preference: LanguagePreference, detected: Option<Lang>) -> Option<String> {
+    let lang = match preference {
+        LanguagePreference::Auto => detected?,
+        LanguagePreference::Forced(lang) => lang,
+    };
+    Some(format!("Always reply in {}, whatever language the message or earlier context is in.", lang.eng_name()))
+}
@@ -0,0 +1,56 @@
+use std::env;
+use std::path::PathBuf;
+
+// Address and port served when nothing else is configured
+const DEFAULT_HOST: &str = "127.0.0.1";
+const DEFAULT_PORT: u16 = 8080;
+
+/// Where the server listens, from environment variables or the matching command line flags
+/// (`llama-web-app [--host <addr>] [--port <n>] [--workers <n>] [--unix-socket <path>]`);
+/// flags win over the environment:
+///
+/// - `HOST` / `--host`: Address to bind, e.g. "0.0.0.0" inside a container (default: "127.0.0.1")
+/// - `PORT` / `--port`: Port serving plain HTTP, or redirecting to HTTPS (default: 8080)
+/// - `WORKERS` / `--workers`: Worker threads handling requests (default: one per CPU core)
+/// - `UNIX_SOCKET` / `--unix-socket`: Serve plain HTTP on this Unix domain socket instead of a
+///   TCP port, for a reverse proxy on the same host; a stale socket file is replaced (default: none)
+
+pub struct ListenSettings {
+    pub host: String,
+    pub port: u16,
+    pub workers: Option<usize>,
+    pub unix_socket: Option<PathBuf>,
+}
+
+impl ListenSettings {
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut settings = Self {
+            host: env::var("HOST").ok().filter(|host| !host.is_empty()).unwrap_or_else(|| DEFAULT_HOST.to_string()),
+            port: match env::var("PORT") {
+                Ok(port) => port.parse().map_err(|_| format!("PORT must be a port number, got {}", port))?,
+                Err(_) => DEFAULT_PORT,
+            },
+            workers: match env::var("WORKERS") {
+                Ok(workers) => Some(workers.parse().map_err(|_| format!("WORKERS must be a number, got {}", workers))?),
+                Err(_) => None,
+            },
+            unix_socket: env::var("UNIX_SOCKET").ok().filter(|path| !path.is_empty()).map(PathBuf::from),
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().cloned().ok_or_else(|| format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--host" => settings.host = value()?,
+                "--port" => settings.port = value()?.parse().map_err(|_| "--port needs a port number".to_string())?,
+                "--workers" => settings.workers = Some(value()?.parse().map_err(|_| "--workers needs a number".to_string())?),
+                "--unix-socket" => settings.unix_socket = Some(PathBuf::from(value()?)),
+                other => return Err(format!("Unknown argument: {}", other)),
+            }
+        }
+        if settings.workers == Some(0) {
+            return Err("The number of workers must be at least 1".to_string());
+        }
+        Ok(settings)
+    }
+}
@@ -1,36 +1,162 @@
+mod access_log;
+mod assistants;
+mod audit;
+mod chunker;
+mod cli;
+mod collections;
+mod consensus;
+#[cfg(feature = "discord")]
+mod discord;
+#[cfg(feature = "email")]
+mod email;
+mod eval;
+mod extract;
+mod guardrails;
+mod http_client;
+mod jobs;
+mod language;
+mod listen;
+mod markdown;
+mod members;
+mod memory;
+mod metrics;
 mod model;
+mod moderation;
+mod pii;
+mod pipelines;
+mod privacy;
+mod prompt_log;
+mod prompts;
+mod refine;
+mod schedules;
+mod session;
+mod stats;
+mod summarize;
+mod systemd;
+mod tls;
 mod web;
 
-use actix_web::{App, HttpServer, web::Data};
-use actix_files as fs;
+use actix_web::{App, HttpMessage, HttpServer, dev::Service, web::Data};
+use actix_web::middleware::{Compress, Condition};
+use actix_web::http::header::{HeaderName, HeaderValue};
 use dotenv::dotenv;
-use log::{info, error};
-use std::sync::Mutex;
-use std::collections::HashMap;
+use log::{info, warn, error};
+use std::env;
+use std::sync::{Arc, RwLock};
+use std::io::Write;
+use std::time::Instant;
 use tera::Tera;
 
+use access_log::access_log;
+use assistants::AssistantStore;
+use audit::audit;
+use collections::CollectionStore;
+use eval::EvalStore;
+use jobs::JobQueue;
+use listen::ListenSettings;
+use members::MemberStore;
+use memory::MemoryStore;
 use model::ModelManager;
+use model::downloads::DownloadManager;
+use model::mock::MockBackend;
+use guardrails::Guardrails;
+use moderation::ModerationPipeline;
+use pii::PiiFilter;
+use pipelines::PipelineStore;
+use privacy::PrivacyManager;
+use prompt_log::prompt_log;
+use prompts::PromptLibrary;
+use schedules::ScheduleStore;
+use session::SessionStore;
+use systemd::ActivatedListener;
+use tls::TlsSettings;
+use web::compression;
+use web::csrf;
+use web::idempotency::IdempotencyStore;
+use web::identity;
+use web::mode::ModeSwitch;
+use web::request_id::{self, REQUEST_ID_HEADER};
 use web::routes;
 
 // App state structure
 struct AppState {
-    tera: Tera,
+    tera: RwLock<Tera>,
     model: Data<ModelManager>,
-    sessions: Mutex<HashMap<uuid::Uuid, Vec<String>>>,
+    sessions: SessionStore,
+    jobs: Arc<JobQueue>,
+    prompts: PromptLibrary,
+    pipelines: PipelineStore,
+    assistants: AssistantStore,
+    moderation: ModerationPipeline,
+    guardrails: Guardrails,
+    pii: PiiFilter,
+    privacy: PrivacyManager,
+    idempotency: IdempotencyStore,
+    members: MemberStore,
+    memory: Arc<MemoryStore>,
+    collections: CollectionStore,
+    evals: Arc<EvalStore>,
+    schedules: ScheduleStore,
+    mode: ModeSwitch,
+    downloads: Arc<DownloadManager>,
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Initialize environment
     dotenv().ok();
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+    
+    // `chat`, `ask` and `tui` run the terminal client instead of the server
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("chat") => return cli::chat(&args[2..]).await,
+        Some("ask") => return cli::ask(&args[2..]).await,
+        #[cfg(feature = "tui")]
+        Some("tui") => return cli::tui::run(&args[2..]).await,
+        _ => {}
+    }
+    
+    env_logger::Builder::from_env(env_logger::Env::new().default_filter_or("info"))
+        // Tag lines logged while handling a request with its ID
+        .format(|buf, record| match request_id::current() {
+            Some(id) => writeln!(buf, "[{} {:<5} {}] [{}] {}", buf.timestamp(), record.level(), record.target(), id, record.args()),
+            None => writeln!(buf, "[{} {:<5} {}] {}", buf.timestamp(), record.level(), record.target(), record.args()),
+        })
+        .init();
     
     info!("Starting LLaMa web application");
     
+    let listen = match ListenSettings::from_args(&args[1..]) {
+        Ok(listen) => listen,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    
+    // Stand in for mistral.rs with the built-in mock backend when asked to
+    if let Some(mock) = MockBackend::from_env() {
+        match mock.start() {
+            Ok(url) => env::set_var("MISTRAL_SERVER_URL", url),
+            Err(e) => {
+                error!("Failed to start mock backend: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    
     // Initialize the model manager (connection to mistral.rs server)
     let model_manager = match ModelManager::new().await {
         Ok(manager) => {
             info!("Connection to mistral.rs server initialized");
+            if let Err(e) = manager.wait_for_backends().await {
+                error!("{}", e);
+                std::process::exit(1);
+            }
+            manager.detect_context_windows().await;
+            // Health checks run first so a replica that wasn't up yet rejoins while warming up
+            manager.start_health_checks();
+            manager.warm_up().await;
             Data::new(manager)
         },
         Err(e) => {
@@ -39,32 +165,222 @@ async fn main() -> std::io::Result<()> {
         }
     };
     
-    // Initialize template engine
-    let mut tera = match Tera::new("templates/**/*") {
+    // Initialize template engine; templates on disk override the embedded ones
+    let tera = match web::templates::load() {
         Ok(t) => t,
         Err(e) => {
-            error!("Template parsing error: {}", e);
+            error!("Template parsing error: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+    
+    // Load the content moderation pipeline
+    let moderation = match ModerationPipeline::load(&model_manager) {
+        Ok(pipeline) => pipeline,
+        Err(e) => {
+            error!("Failed to load moderation config: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+    
+    // Load the guardrail rules
+    let guardrails = match Guardrails::load(&model_manager) {
+        Ok(guardrails) => guardrails,
+        Err(e) => {
+            error!("Failed to load guardrails config: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+    
+    // Load the PII redaction filter
+    let pii = match PiiFilter::from_env() {
+        Ok(filter) => filter,
+        Err(e) => {
+            error!("Failed to configure PII redaction: {:#}", e);
             std::process::exit(1);
         }
     };
-    tera.autoescape_on(vec![".html", ".sql"]);
+    
+    // Mask personal data in logged prompts
+    if prompt_log().redacts_pii() {
+        match PiiFilter::masking() {
+            Ok(filter) => prompt_log().add_redactor(Box::new(filter)),
+            Err(e) => {
+                error!("Failed to configure prompt log redaction: {:#}", e);
+                std::process::exit(1);
+            }
+        }
+    }
     
     // Create app state
     let app_state = Data::new(AppState {
-        tera,
+        tera: RwLock::new(tera),
         model: model_manager.clone(),
-        sessions: Mutex::new(HashMap::new()),
+        sessions: SessionStore::default(),
+        jobs: JobQueue::start(),
+        prompts: PromptLibrary::load(),
+        pipelines: PipelineStore::load(&model_manager),
+        assistants: AssistantStore::load(),
+        moderation,
+        guardrails,
+        pii,
+        privacy: PrivacyManager::from_env(),
+        idempotency: IdempotencyStore::from_env(),
+        members: MemberStore::load(),
+        memory: Arc::new(MemoryStore::load()),
+        collections: CollectionStore::load(),
+        evals: Arc::new(EvalStore::load()),
+        schedules: ScheduleStore::load(),
+        mode: ModeSwitch::default(),
+        downloads: Arc::new(DownloadManager::from_env()),
     });
+    privacy::start_purger(app_state.clone());
+    session::trash::start_purger(app_state.clone());
+    schedules::start_scheduler(app_state.clone());
+    #[cfg(feature = "discord")]
+    discord::start(app_state.clone());
+    #[cfg(feature = "email")]
+    email::start(app_state.clone());
+    web::templates::start_hot_reload(app_state.clone());
+    systemd::start_watchdog(model_manager.clone());
     
     // Start web server
-    HttpServer::new(move || {
+    web::assets::log_source();
+    let compress = compression::enabled();
+    let mut server = HttpServer::new(move || {
         App::new()
             .app_data(app_state.clone())
             .app_data(model_manager.clone())
+            .app_data(web::limits::json_config())
             .configure(routes::configure)
-            .service(fs::Files::new("/static", "./static").show_files_listing())
+            // Turn away state-changing browser requests that didn't come from our own pages
+            .wrap_fn(|req, srv| {
+                let fut = match csrf::check(&req) {
+                    Ok(()) => Ok(srv.call(req)),
+                    Err(e) => Err(req.error_response(e)),
+                };
+                async move {
+                    match fut {
+                        Ok(fut) => Ok(fut.await?.map_into_left_body()),
+                        Err(res) => Ok(res.map_into_right_body()),
+                    }
+                }
+            })
+            // Record every request in the access log and every API call in the audit log
+            .wrap_fn(|req, srv| {
+                let started = Instant::now();
+                let fut = srv.call(req);
+                async move {
+                    let res = fut.await?;
+                    access_log().record(&res, started);
+                    audit().record_response(&res, started);
+                    Ok(res)
+                }
+            })
+            // Give every browser an anonymous identity its sessions are tied to
+            .wrap_fn(|req, srv| {
+                let (browser, issued) = identity::identify(&req);
+                req.extensions_mut().insert(browser);
+                let fut = srv.call(req);
+                async move {
+                    let mut res = fut.await?;
+                    if issued {
+                        identity::set_cookie(&mut res, browser);
+                    }
+                    Ok(res)
+                }
+            })
+            // Tag every request with an ID that follows it through logs, errors and backend calls
+            .wrap_fn(|req, srv| {
+                let id = request_id::from_request(req.request());
+                let fut = request_id::sync_scope(id.clone(), || srv.call(req));
+                request_id::scope(id.clone(), async move {
+                    let mut res = fut.await?;
+                    if let Ok(value) = HeaderValue::from_str(&id) {
+                        res.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+                    }
+                    Ok(res)
+                })
+            })
+            // Keep event streams out of the compressor below
+            .wrap_fn(|req, srv| {
+                let fut = srv.call(req);
+                async move {
+                    let mut res = fut.await?;
+                    compression::exclude_event_streams(&mut res);
+                    Ok(res)
+                }
+            })
+            // Compress responses according to the client's Accept-Encoding
+            .wrap(Condition::new(compress, Compress::default()))
+            .configure(web::assets::configure)
+    });
+    if let Some(workers) = listen.workers {
+        server = server.workers(workers);
+    }
+    
+    // A socket handed over by systemd replaces every configured address
+    match systemd::activated_listener()? {
+        Some(ActivatedListener::Tcp(listener)) => {
+            info!("Serving HTTP on socket {} passed by systemd", listener.local_addr()?);
+            return systemd::serve(server.listen(listener)?.run()).await;
+        }
+        #[cfg(unix)]
+        Some(ActivatedListener::Unix(listener)) => {
+            info!("Serving HTTP on Unix socket passed by systemd");
+            return systemd::serve(server.listen_uds(listener)?.run()).await;
+        }
+        None => {}
+    }
+    
+    // Behind a local reverse proxy, which terminates TLS itself
+    if let Some(path) = &listen.unix_socket {
+        if TlsSettings::from_env().is_some() {
+            warn!("TLS_CERT_PATH and TLS_KEY_PATH are ignored when serving on a Unix socket");
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::FileTypeExt;
+            // A socket left behind by an earlier run would make the bind fail
+            if std::fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+                std::fs::remove_file(path)?;
+            }
+            info!("Serving HTTP on Unix socket {}", path.display());
+            return systemd::serve(server.bind_uds(path)?.run()).await;
+        }
+        #[cfg(not(unix))]
+        {
+            error!("Unix sockets are not supported on this platform");
+            std::process::exit(1);
+        }
+    }
+    
+    let Some(tls) = TlsSettings::from_env() else {
+        info!("Serving HTTP on {}:{}", listen.host, listen.port);
+        return systemd::serve(server.bind((listen.host.as_str(), listen.port))?.run()).await;
+    };
+    
+    // Terminate HTTPS directly; plain HTTP is only kept open to redirect
+    let tls_config = match tls.server_config() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Failed to load TLS configuration: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+    info!("Serving HTTPS on port {}", tls.port);
+    let https = server.bind_rustls_021((listen.host.as_str(), tls.port), tls_config)?.run();
+    if !tls.redirect_http {
+        return systemd::serve(https).await;
+    }
+    
+    let https_port = tls.port;
+    let redirect = HttpServer::new(move || {
+        App::new()
+            .app_data(Data::new(https_port))
+            .default_service(actix_web::web::to(tls::redirect_to_https))
     })
-    .bind(("127.0.0.1", 8080))?
-    .run()
-    .await
+    .bind((listen.host.as_str(), listen.port))?
+    .run();
+    systemd::serve(async move { futures::future::try_join(https, redirect).await.map(|_| ()) }).await
 }
@@ -0,0 +1,146 @@
+use std::borrow::Cow;
+use std::env;
+use std::sync::OnceLock;
+use log::{info, warn};
+use pulldown_cmark::{html, CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+// Theme used when CODE_HIGHLIGHT_THEME is unset
+const DEFAULT_CODE_HIGHLIGHT_THEME: &str = "InspiredGitHub";
+// Highlighted spans get classes like `hl-keyword`, styled by `highlight_css`
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+/// Environment variables for rendering markdown:
+///
+/// - `CODE_HIGHLIGHT_THEME`: Syntax highlighting theme for fenced code blocks, one of
+///   "InspiredGitHub", "Solarized (light)", "Solarized (dark)", "base16-ocean.light",
+///   "base16-ocean.dark", "base16-eighties.dark" or "base16-mocha.dark", or "off" to
+///   disable highlighting (default: "InspiredGitHub")
+
+struct Highlighter {
+    syntaxes: SyntaxSet,
+    // None when highlighting is off
+    theme: Option<Theme>,
+}
+
+static HIGHLIGHTER: OnceLock<Highlighter> = OnceLock::new();
+
+fn highlighter() -> &'static Highlighter {
+    HIGHLIGHTER.get_or_init(|| {
+        let name = env::var("CODE_HIGHLIGHT_THEME").unwrap_or_else(|_| DEFAULT_CODE_HIGHLIGHT_THEME.to_string());
+        let theme = if name == "off" {
+            None
+        } else {
+            let mut themes = ThemeSet::load_defaults().themes;
+            let theme = themes.remove(&name).or_else(|| {
+                warn!("Unknown CODE_HIGHLIGHT_THEME {}; using {}", name, DEFAULT_CODE_HIGHLIGHT_THEME);
+                themes.remove(DEFAULT_CODE_HIGHLIGHT_THEME)
+            });
+            info!("Highlighting code blocks with theme {}", name);
+            theme
+        };
+        Highlighter {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            theme,
+        }
+    })
+}
+
+// Stylesheet for the classes of highlighted code; empty when highlighting is off
+pub fn highlight_css() -> String {
+    let Some(theme) = &highlighter().theme else { return String::new() };
+    css_for_theme_with_class_style(theme, CLASS_STYLE).unwrap_or_else(|e| {
+        warn!("Failed to generate highlighting CSS: {}", e);
+        String::new()
+    })
+}
+
+// Highlight a fenced code block, or None when the language is unknown or highlighting is off
+fn highlight(code: &str, lang: &str) -> Option<String> {
+    let highlighter = highlighter();
+    highlighter.theme.as_ref()?;
+    // Info strings may carry more than the language, e.g. "rust,ignore"
+    let lang = lang.split([' ', ',']).next().unwrap_or_default();
+    if lang.is_empty() || !lang.chars().all(|c| c.is_ascii_alphanumeric() || "+#-_".contains(c)) {
+        return None;
+    }
+    let syntax = highlighter.syntaxes.find_syntax_by_token(lang)?;
+
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, &highlighter.syntaxes, CLASS_STYLE);
+    for line in LinesWithEndings::from(code) {
+        generator.parse_html_for_line_which_includes_newline(line).ok()?;
+    }
+    // `hl-code` carries the theme's background and default text colour
+    Some(format!("<pre class=\"hl-code\"><code class=\"language-{}\">{}</code></pre>\n", lang, generator.finalize()))
+}
+
+// Render model output written in markdown to HTML that is safe to embed in a page.
+// Raw HTML in the input and anything unsafe the markdown produces (scripts, event
+// handlers, `javascript:` links) are stripped.
+pub fn render(markdown: &str) -> String {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_FOOTNOTES;
+
+    // Fenced code blocks are collected and replaced by their highlighted HTML
+    let mut events = Vec::new();
+    let mut block: Option<(String, Vec<Event>)> = None;
+    for event in Parser::new_ext(markdown, options) {
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(ref lang))) => {
+                block = Some((lang.to_string(), vec![event.clone()]));
+            }
+            Event::End(TagEnd::CodeBlock) if block.is_some() => {
+                let Some((lang, mut original)) = block.take() else { continue };
+                let code: String = original
+                    .iter()
+                    .filter_map(|event| match event {
+                        Event::Text(text) => Some(text.as_ref()),
+                        _ => None,
+                    })
+                    .collect();
+                match highlight(&code, &lang) {
+                    Some(html) => events.push(Event::Html(html.into())),
+                    None => {
+                        original.push(event);
+                        events.extend(original);
+                    }
+                }
+            }
+            event => match block.as_mut() {
+                Some((_, original)) => original.push(event),
+                None => events.push(event),
+            },
+        }
+    }
+
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, events.into_iter());
+    sanitize(&unsafe_html)
+}
+
+fn sanitize(html: &str) -> String {
+    ammonia::Builder::default()
+        .add_tag_attributes("pre", &["class"])
+        .add_tag_attributes("code", &["class"])
+        .add_tag_attributes("span", &["class"])
+        .add_tag_attributes("input", &["type", "checked", "disabled"])
+        .add_tags(&["input"])
+        .attribute_filter(allowed_class)
+        .clean(html)
+        .to_string()
+}
+
+// Only the classes produced by the highlighter and the code block language survive
+fn allowed_class<'u>(element: &str, attribute: &str, value: &'u str) -> Option<Cow<'u, str>> {
+    let allowed = match (element, attribute) {
+        ("pre" | "span", "class") => value.split_whitespace().all(|class| class.starts_with("hl-")),
+        ("code", "class") => value.starts_with("language-"),
+        _ => true,
+    };
+    allowed.then_some(Cow::Borrowed(value))
+}
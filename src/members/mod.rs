@@ -0,0 +1,258 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use log::{info, error};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+// Default file holding members and pending invitations
+const DEFAULT_MEMBERS_PATH: &str = "members.json";
+// Default lifetime of an invitation
+const DEFAULT_INVITE_TTL_HOURS: i64 = 7 * 24;
+
+/// Environment variables for workspace membership:
+///
+/// - `MEMBERS_PATH`: JSON file holding members and pending invitations (default: "members.json")
+/// - `INVITE_TTL_HOURS`: Hours an invitation can be accepted for (default: 168)
+
+// What a member may do in the workspace
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum MemberRole {
+    Admin,
+    #[default]
+    Member,
+    Viewer,
+}
+
+// Someone who accepted an invitation to the workspace
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Member {
+    pub id: Uuid,
+    pub email: String,
+    pub role: MemberRole,
+    pub joined_at: DateTime<Utc>,
+}
+
+// An invitation waiting to be accepted
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Invite {
+    pub id: Uuid,
+    pub email: String,
+    // Role the invitee gets on accepting
+    pub role: MemberRole,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredInvite {
+    #[serde(flatten)]
+    invite: Invite,
+    // Only a hash of the token is kept, so the file doesn't hold usable invitations
+    token_hash: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Members {
+    #[serde(default)]
+    members: Vec<Member>,
+    #[serde(default)]
+    invites: Vec<StoredInvite>,
+}
+
+// Why an invitation couldn't be created or accepted
+#[derive(Debug, PartialEq, Eq)]
+pub enum MembershipError {
+    // The email belongs to a member already
+    AlreadyMember,
+    // No pending invitation has this token
+    InvalidToken,
+    // The invitation was found but is past its expiry
+    Expired,
+}
+
+impl std::fmt::Display for MembershipError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MembershipError::AlreadyMember => write!(f, "This email belongs to a member already"),
+            MembershipError::InvalidToken => write!(f, "Unknown invitation token"),
+            MembershipError::Expired => write!(f, "The invitation has expired"),
+        }
+    }
+}
+
+impl std::error::Error for MembershipError {}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+// Emails are compared case-insensitively
+fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+// Workspace members and their pending invitations, persisted as JSON
+pub struct MemberStore {
+    path: String,
+    invite_ttl: Duration,
+    members: Mutex<Members>,
+}
+
+impl MemberStore {
+    pub fn load() -> Self {
+        let path = env::var("MEMBERS_PATH").unwrap_or_else(|_| DEFAULT_MEMBERS_PATH.to_string());
+        let ttl_hours = env::var("INVITE_TTL_HOURS")
+            .ok()
+            .and_then(|v| v.parse::<i64>().ok())
+            .filter(|hours| *hours > 0)
+            .unwrap_or(DEFAULT_INVITE_TTL_HOURS);
+
+        let members = if Path::new(&path).exists() {
+            match Self::read(&path) {
+                Ok(members) => {
+                    info!("Loaded {} member(s) and {} invitation(s) from {}", members.members.len(), members.invites.len(), path);
+                    members
+                }
+                Err(e) => {
+                    error!("Failed to load members from {}: {:#}", path, e);
+                    Members::default()
+                }
+            }
+        } else {
+            Members::default()
+        };
+
+        Self {
+            path,
+            invite_ttl: Duration::hours(ttl_hours),
+            members: Mutex::new(members),
+        }
+    }
+
+    fn read(path: &str) -> Result<Members> {
+        let contents = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+        serde_json::from_str(&contents).with_context(|| format!("Invalid members file {}", path))
+    }
+
+    // Write members and invitations, replacing the file atomically
+    fn save(&self, members: &Members) {
+        let tmp_path = format!("{}.tmp", self.path);
+        let result = serde_json::to_vec(members)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| fs::write(&tmp_path, json).map_err(anyhow::Error::from))
+            .and_then(|_| fs::rename(&tmp_path, &self.path).map_err(anyhow::Error::from));
+        if let Err(e) = result {
+            error!("Failed to save members to {}: {}", self.path, e);
+        }
+    }
+
+    // Members, and the invitations that are still pending
+    pub fn list(&self) -> (Vec<Member>, Vec<Invite>) {
+        let Ok(members) = self.members.lock() else { return (Vec::new(), Vec::new()) };
+        let now = Utc::now();
+        let invites = members.invites
+            .iter()
+            .filter(|i| i.invite.expires_at > now)
+            .map(|i| i.invite.clone())
+            .collect();
+        (members.members.clone(), invites)
+    }
+
+    // Invite an email with a role, returning the invitation and its token. The token is only
+    // shown here; inviting the same email again replaces its pending invitation.
+    pub fn invite(&self, email: &str, role: MemberRole) -> Result<(Invite, String), MembershipError> {
+        let email = normalize_email(email);
+        let mut members = self.members.lock().unwrap_or_else(PoisonError::into_inner);
+        if members.members.iter().any(|m| m.email == email) {
+            return Err(MembershipError::AlreadyMember);
+        }
+
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let token = hex::encode(bytes);
+        let now = Utc::now();
+        let invite = Invite {
+            id: Uuid::new_v4(),
+            email: email.clone(),
+            role,
+            created_at: now,
+            expires_at: now + self.invite_ttl,
+        };
+        members.invites.retain(|i| i.invite.email != email && i.invite.expires_at > now);
+        members.invites.push(StoredInvite { invite: invite.clone(), token_hash: hash_token(&token) });
+        self.save(&members);
+        info!("Invited {} as {:?}", email, role);
+        Ok((invite, token))
+    }
+
+    // Turn the invitation with this token into a membership
+    pub fn accept(&self, token: &str) -> Result<Member, MembershipError> {
+        let mut members = self.members.lock().unwrap_or_else(PoisonError::into_inner);
+        let hash = hash_token(token.trim());
+        let position = members.invites
+            .iter()
+            .position(|i| i.token_hash == hash)
+            .ok_or(MembershipError::InvalidToken)?;
+        let invite = members.invites.remove(position).invite;
+        if invite.expires_at <= Utc::now() {
+            self.save(&members);
+            return Err(MembershipError::Expired);
+        }
+        if members.members.iter().any(|m| m.email == invite.email) {
+            self.save(&members);
+            return Err(MembershipError::AlreadyMember);
+        }
+
+        let member = Member {
+            id: Uuid::new_v4(),
+            email: invite.email,
+            role: invite.role,
+            joined_at: Utc::now(),
+        };
+        members.members.push(member.clone());
+        self.save(&members);
+        info!("{} joined as {:?}", member.email, member.role);
+        Ok(member)
+    }
+
+    // Returns None when no member has this ID
+    pub fn set_role(&self, id: Uuid, role: MemberRole) -> Option<Member> {
+        let mut members = self.members.lock().ok()?;
+        let member = members.members.iter_mut().find(|m| m.id == id)?;
+        member.role = role;
+        let member = member.clone();
+        self.save(&members);
+        Some(member)
+    }
+
+    pub fn remove(&self, id: Uuid) -> bool {
+        let Ok(mut members) = self.members.lock() else { return false };
+        let before = members.members.len();
+        members.members.retain(|m| m.id != id);
+        let removed = members.members.len() < before;
+        if removed {
+            self.save(&members);
+        }
+        removed
+    }
+
+    // Withdraw a pending invitation
+    pub fn revoke(&self, id: Uuid) -> bool {
+        let Ok(mut members) = self.members.lock() else { return false };
+        let before = members.invites.len();
+        members.invites.retain(|i| i.invite.id != id);
+        let revoked = members.invites.len() < before;
+        if revoked {
+            self.save(&members);
+        }
+        revoked
+    }
+}
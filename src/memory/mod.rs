@@ -0,0 +1,290 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::{info, warn, error};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::http_client::http_client;
+use crate::model::cache::{cosine_similarity, request_embedding};
+use crate::model::LlamaModel;
+use crate::web::models::{Message, Role};
+
+// Default file holding the memories of all users
+const DEFAULT_MEMORY_PATH: &str = "memories.json";
+// Default number of new messages in a session that triggers an extraction
+const DEFAULT_MEMORY_EXTRACT_EVERY: usize = 6;
+// Default number of memories added to the system prompt
+const DEFAULT_MEMORY_TOP_K: usize = 5;
+// Default number of memories kept per user; the oldest are dropped first
+const DEFAULT_MEMORY_MAX_PER_USER: usize = 200;
+// Token budget of the extraction call
+const EXTRACTION_MAX_TOKENS: usize = 300;
+
+const EXTRACTION_PROMPT: &str = "You maintain long-term memory for an assistant. From the conversation below, \
+list facts about the user that will still be useful in future conversations: preferences, background, \
+ongoing projects, decisions. Write one short, self-contained fact per line, without numbering. \
+Skip small talk and anything specific to this conversation only. If there is nothing worth remembering, \
+answer NONE.";
+
+/// Environment variables for configuring long-term memory:
+///
+/// - `MEMORY`: Remember facts about users across sessions, "true" or "false" (default: false).
+///   Only requests that name a `user` and carry the admin token, which vouches for the user
+///   field, are remembered and reminded; the Discord and email integrations name their users
+///   themselves.
+/// - `MEMORY_PATH`: JSON file holding the memories (default: "memories.json")
+/// - `MEMORY_EXTRACT_EVERY`: New messages in a session after which facts are extracted from it (default: 6)
+/// - `MEMORY_TOP_K`: Memories most relevant to a message that are added to the system prompt (default: 5)
+/// - `MEMORY_MAX_PER_USER`: Memories kept per user, oldest dropped first (default: 200)
+///
+/// Memories are ranked by embedding similarity using `EMBEDDING_SERVER_URL` and `EMBEDDING_MODEL`
+/// (see the response cache), falling back to word overlap when embeddings are unavailable.
+
+// A fact remembered about a user
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Memory {
+    pub id: Uuid,
+    pub text: String,
+    // Session the fact was extracted from
+    pub session_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredMemory {
+    #[serde(flatten)]
+    memory: Memory,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    embedding: Option<Vec<f32>>,
+}
+
+// Facts extracted from users' conversations, persisted as JSON
+pub struct MemoryStore {
+    enabled: bool,
+    path: String,
+    extract_every: usize,
+    top_k: usize,
+    max_per_user: usize,
+    client: Client,
+    embedding_url: String,
+    embedding_model: String,
+    memories: Mutex<HashMap<String, Vec<StoredMemory>>>,
+}
+
+fn env_usize(name: &str, default: usize) -> usize {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(default)
+}
+
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.len() > 2)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+// Fraction of the message's words found in the memory, for when embeddings are unavailable
+fn word_overlap(message: &HashSet<String>, memory: &str) -> f32 {
+    if message.is_empty() {
+        return 0.0;
+    }
+    let memory = words(memory);
+    message.intersection(&memory).count() as f32 / message.len() as f32
+}
+
+impl MemoryStore {
+    pub fn load() -> Self {
+        let enabled = env::var("MEMORY").map(|v| v == "true" || v == "1").unwrap_or(false);
+        let path = env::var("MEMORY_PATH").unwrap_or_else(|_| DEFAULT_MEMORY_PATH.to_string());
+        let embedding_url = env::var("EMBEDDING_SERVER_URL")
+            .or_else(|_| env::var("MISTRAL_SERVER_URL"))
+            .unwrap_or_else(|_| "http://localhost:8081".to_string());
+
+        let memories = if enabled && Path::new(&path).exists() {
+            match Self::read(&path) {
+                Ok(memories) => memories,
+                Err(e) => {
+                    error!("Failed to load memories from {}: {:#}", path, e);
+                    HashMap::new()
+                }
+            }
+        } else {
+            HashMap::new()
+        };
+        if enabled {
+            info!("Long-term memory enabled with {} user(s) from {}", memories.len(), path);
+        }
+
+        Self {
+            enabled,
+            path,
+            extract_every: env_usize("MEMORY_EXTRACT_EVERY", DEFAULT_MEMORY_EXTRACT_EVERY).max(2),
+            top_k: env_usize("MEMORY_TOP_K", DEFAULT_MEMORY_TOP_K),
+            max_per_user: env_usize("MEMORY_MAX_PER_USER", DEFAULT_MEMORY_MAX_PER_USER).max(1),
+            client: http_client().clone(),
+            // MISTRAL_SERVER_URL may list several replicas; any of them can embed
+            embedding_url: embedding_url.split(',').next().unwrap_or_default().trim().trim_end_matches('/').to_string(),
+            embedding_model: env::var("EMBEDDING_MODEL").unwrap_or_else(|_| "default".to_string()),
+            memories: Mutex::new(memories),
+        }
+    }
+
+    fn read(path: &str) -> Result<HashMap<String, Vec<StoredMemory>>> {
+        let contents = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+        serde_json::from_str(&contents).with_context(|| format!("Invalid memory file {}", path))
+    }
+
+    // Write all memories, replacing the file atomically
+    fn save(&self, memories: &HashMap<String, Vec<StoredMemory>>) {
+        let tmp_path = format!("{}.tmp", self.path);
+        let result = serde_json::to_vec(memories)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| fs::write(&tmp_path, json).map_err(anyhow::Error::from))
+            .and_then(|_| fs::rename(&tmp_path, &self.path).map_err(anyhow::Error::from));
+        if let Err(e) = result {
+            error!("Failed to save memories to {}: {}", self.path, e);
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // Number of new session messages after which facts are extracted
+    pub fn extract_every(&self) -> usize {
+        self.extract_every
+    }
+
+    pub fn list(&self, user: &str) -> Vec<Memory> {
+        self.memories
+            .lock()
+            .ok()
+            .and_then(|memories| memories.get(user).map(|list| list.iter().map(|m| m.memory.clone()).collect()))
+            .unwrap_or_default()
+    }
+
+    pub fn delete(&self, user: &str, id: Uuid) -> bool {
+        let Ok(mut memories) = self.memories.lock() else { return false };
+        let Some(list) = memories.get_mut(user) else { return false };
+        let before = list.len();
+        list.retain(|m| m.memory.id != id);
+        let deleted = list.len() < before;
+        if list.is_empty() {
+            memories.remove(user);
+        }
+        if deleted {
+            self.save(&memories);
+        }
+        deleted
+    }
+
+    // Forget everything about a user, returning how many memories were removed
+    pub fn purge_user(&self, user: &str) -> usize {
+        let Ok(mut memories) = self.memories.lock() else { return 0 };
+        let removed = memories.remove(user).map_or(0, |list| list.len());
+        if removed > 0 {
+            self.save(&memories);
+        }
+        removed
+    }
+
+    async fn embed(&self, text: &str) -> Option<Vec<f32>> {
+        match request_embedding(&self.client, &self.embedding_url, &self.embedding_model, text).await {
+            Ok(embedding) => Some(embedding),
+            Err(e) => {
+                warn!("Failed to embed text for memory: {}", e);
+                None
+            }
+        }
+    }
+
+    // The user's memories most relevant to a message, formatted for the system prompt
+    pub async fn recall(&self, user: &str, message: &str) -> Option<String> {
+        if !self.enabled || self.top_k == 0 {
+            return None;
+        }
+        let candidates = self.memories.lock().ok()?.get(user)?.clone();
+        if candidates.is_empty() {
+            return None;
+        }
+
+        let embedding = self.embed(message).await;
+        let message_words = words(message);
+        let mut scored: Vec<(f32, &Memory)> = candidates
+            .iter()
+            .map(|m| {
+                let score = match (&embedding, &m.embedding) {
+                    (Some(a), Some(b)) => cosine_similarity(a, b),
+                    _ => word_overlap(&message_words, &m.memory.text),
+                };
+                (score, &m.memory)
+            })
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let facts: Vec<String> = scored
+            .into_iter()
+            .take(self.top_k)
+            .map(|(_, memory)| format!("- {}", memory.text))
+            .collect();
+        Some(format!(
+            "What you remember about the user from earlier conversations:\n{}",
+            facts.join("\n")
+        ))
+    }
+
+    // Ask the model for lasting facts in a stretch of conversation and store the new ones
+    pub async fn extract(&self, model: &LlamaModel, user: &str, session_id: Uuid, transcript: &[String]) {
+        let messages = [
+            Message { role: Role::System, content: EXTRACTION_PROMPT.to_string() },
+            Message { role: Role::User, content: transcript.join("\n") },
+        ];
+        let reply = match model.complete_messages(&messages, EXTRACTION_MAX_TOKENS, 0.0).await {
+            Ok(reply) => reply,
+            Err(e) => {
+                warn!("Memory extraction for session {} failed: {}", session_id, e);
+                return;
+            }
+        };
+
+        let known: HashSet<String> = self.list(user).into_iter().map(|m| m.text.to_lowercase()).collect();
+        let facts: Vec<String> = reply
+            .lines()
+            .map(|line| line.trim().trim_start_matches(['-', '*', '•']).trim())
+            .filter(|line| !line.is_empty() && !line.eq_ignore_ascii_case("NONE"))
+            .filter(|line| !known.contains(&line.to_lowercase()))
+            .map(str::to_string)
+            .collect();
+        if facts.is_empty() {
+            return;
+        }
+
+        let mut stored = Vec::with_capacity(facts.len());
+        for text in facts {
+            let embedding = self.embed(&text).await;
+            stored.push(StoredMemory {
+                memory: Memory { id: Uuid::new_v4(), text, session_id: Some(session_id), created_at: Utc::now() },
+                embedding,
+            });
+        }
+
+        let Ok(mut memories) = self.memories.lock() else { return };
+        let list = memories.entry(user.to_string()).or_default();
+        info!("Remembered {} new fact(s) about user {}", stored.len(), user);
+        list.extend(stored);
+        if list.len() > self.max_per_user {
+            let excess = list.len() - self.max_per_user;
+            list.drain(..excess);
+        }
+        self.save(&memories);
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Mutex, OnceLock};
+
+// Process-wide counters and gauges, rendered in the Prometheus text format on `/metrics`
+#[derive(Default)]
+pub struct Metrics {
+    counters: Mutex<BTreeMap<String, f64>>,
+    gauges: Mutex<BTreeMap<String, f64>>,
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+pub fn metrics() -> &'static Metrics {
+    METRICS.get_or_init(Metrics::default)
+}
+
+// Build the series name, e.g. `cache_hits_total{kind="exact"}`
+fn series(name: &str, labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return name.to_string();
+    }
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(key, value)| format!("{}=\"{}\"", key, value.replace('\\', "\\\\").replace('"', "\\\"")))
+        .collect();
+    format!("{}{{{}}}", name, labels.join(","))
+}
+
+impl Metrics {
+    pub fn increment(&self, name: &str, labels: &[(&str, &str)]) {
+        self.add(name, labels, 1.0);
+    }
+
+    pub fn add(&self, name: &str, labels: &[(&str, &str)], value: f64) {
+        if let Ok(mut counters) = self.counters.lock() {
+            *counters.entry(series(name, labels)).or_insert(0.0) += value;
+        }
+    }
+
+    pub fn set(&self, name: &str, labels: &[(&str, &str)], value: f64) {
+        if let Ok(mut gauges) = self.gauges.lock() {
+            gauges.insert(series(name, labels), value);
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        for (kind, values) in [("counter", &self.counters), ("gauge", &self.gauges)] {
+            let Ok(values) = values.lock() else { continue };
+            let mut last_name = "";
+            for (series, value) in values.iter() {
+                // Emit a TYPE line once per metric family
+                let name = series.split('{').next().unwrap_or(series);
+                if name != last_name {
+                    let _ = writeln!(output, "# TYPE {} {}", name, kind);
+                    last_name = name;
+                }
+                let _ = writeln!(output, "{} {}", series, value);
+            }
+        }
+        output
+    }
+}
@@ -0,0 +1,152 @@
+use std::time::{Duration, Instant};
+use futures::stream::{self, StreamExt};
+use log::info;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use super::{GenerationOptions, LlamaModel};
+
+// Prompts cycled through by a benchmark that doesn't set its own; each request also gets
+// a unique suffix so backends can't answer from a prefix cache
+const SYNTHETIC_PROMPTS: &[&str] = &[
+    "Explain how a hash map handles collisions.",
+    "Write a short story about a lighthouse keeper who finds a message in a bottle.",
+    "Summarize the causes and consequences of the French Revolution.",
+    "Describe the differences between TCP and UDP, with an example use for each.",
+    "List ten ideas for a weekend project that teaches electronics to a beginner.",
+];
+// Errors quoted in a report; the rest are only counted
+const MAX_REPORTED_ERRORS: usize = 5;
+
+// Shape of a benchmark run
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    pub requests: usize,
+    pub concurrency: usize,
+    pub max_tokens: usize,
+    pub prompt: Option<String>,
+}
+
+// Distribution of a duration over the successful requests, in milliseconds
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct Percentiles {
+    pub p50: u64,
+    pub p95: u64,
+    pub max: u64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BenchReport {
+    pub model: String,
+    pub requests: usize,
+    pub concurrency: usize,
+    pub max_tokens: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub duration_ms: u64,
+    pub requests_per_sec: f64,
+    // Completion tokens generated per second of wall time, across all requests
+    pub tokens_per_sec: f64,
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    // Time from sending a request to receiving its full answer, queue wait included
+    pub latency_ms: Percentiles,
+    // Time spent waiting for a free generation slot (MAX_CONCURRENT_REQUESTS)
+    pub queue_wait_ms: Percentiles,
+    pub errors: Vec<String>,
+}
+
+fn percentiles(mut samples: Vec<Duration>) -> Percentiles {
+    if samples.is_empty() {
+        return Percentiles::default();
+    }
+    samples.sort();
+    // Nearest-rank percentile
+    let at = |p: f64| {
+        let rank = ((p * samples.len() as f64).ceil() as usize).clamp(1, samples.len());
+        samples[rank - 1].as_millis() as u64
+    };
+    Percentiles {
+        p50: at(0.50),
+        p95: at(0.95),
+        max: at(1.0),
+    }
+}
+
+// Fire `requests` generations at a model, at most `concurrency` at a time, through the
+// same path as chat requests (concurrency limit, replica selection, usage accounting).
+// The response cache is bypassed so every request reaches a backend.
+pub async fn run(model: &LlamaModel, config: &BenchConfig) -> BenchReport {
+    info!(
+        "Benchmarking model {}: {} requests, concurrency {}, max_tokens {}",
+        model.name(), config.requests, config.concurrency, config.max_tokens
+    );
+    let mut options = GenerationOptions::new(config.max_tokens);
+    options.use_cache = false;
+
+    let started = Instant::now();
+    let outcomes: Vec<_> = stream::iter(0..config.requests)
+        .map(|i| {
+            let prompt = match &config.prompt {
+                Some(prompt) => prompt.clone(),
+                None => format!("{} (request {})", SYNTHETIC_PROMPTS[i % SYNTHETIC_PROMPTS.len()], i + 1),
+            };
+            let options = &options;
+            async move {
+                let sent = Instant::now();
+                let result = model.generate_response(&prompt, &[], options).await;
+                (result, sent.elapsed())
+            }
+        })
+        .buffer_unordered(config.concurrency.max(1))
+        .collect()
+        .await;
+    let duration = started.elapsed();
+
+    let mut latencies = Vec::new();
+    let mut queue_waits = Vec::new();
+    let mut prompt_tokens = 0;
+    let mut completion_tokens = 0;
+    let mut errors = Vec::new();
+    let mut failed = 0;
+    for (result, latency) in outcomes {
+        match result {
+            Ok(completion) => {
+                latencies.push(latency);
+                queue_waits.push(completion.queue_wait);
+                prompt_tokens += completion.prompt_tokens;
+                completion_tokens += completion.completion_tokens;
+            }
+            Err(e) => {
+                failed += 1;
+                if errors.len() < MAX_REPORTED_ERRORS {
+                    errors.push(e.to_string());
+                }
+            }
+        }
+    }
+
+    let seconds = duration.as_secs_f64().max(f64::EPSILON);
+    let report = BenchReport {
+        model: model.name().to_string(),
+        requests: config.requests,
+        concurrency: config.concurrency,
+        max_tokens: config.max_tokens,
+        succeeded: latencies.len(),
+        failed,
+        duration_ms: duration.as_millis() as u64,
+        requests_per_sec: latencies.len() as f64 / seconds,
+        tokens_per_sec: completion_tokens as f64 / seconds,
+        prompt_tokens,
+        completion_tokens,
+        latency_ms: percentiles(latencies),
+        queue_wait_ms: percentiles(queue_waits),
+        errors,
+    };
+    info!(
+        "Benchmark of model {} finished: {}/{} succeeded, {:.1} tokens/s, p50 {} ms, p95 {} ms",
+        report.model, report.succeeded, report.requests, report.tokens_per_sec,
+        report.latency_ms.p50, report.latency_ms.p95
+    );
+    report
+}
@@ -0,0 +1,332 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::env;
+use std::hash::{Hash, Hasher};
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use lru::LruCache;
+use reqwest::Client;
+use serde_json::{json, Value};
+use log::{info, debug, warn};
+
+use crate::metrics::metrics;
+use crate::web::request_id;
+
+// Default time a cached response stays valid
+const DEFAULT_CACHE_TTL_SECS: u64 = 3600;
+// Default cosine similarity above which a cached answer is reused in semantic mode
+const DEFAULT_SEMANTIC_THRESHOLD: f32 = 0.95;
+
+/// Environment variables for configuring the response cache:
+///
+/// - `RESPONSE_CACHE_SIZE`: Maximum number of cached responses, 0 disables the cache (default: 0)
+/// - `RESPONSE_CACHE_TTL_SECS`: Seconds a cached response stays valid (default: 3600)
+/// - `RESPONSE_CACHE_MODE`: "exact" to match identical requests only, or "semantic" to also
+///   reuse answers to similar prompts (default: "exact")
+/// - `SEMANTIC_CACHE_THRESHOLD`: Minimum cosine similarity for a semantic hit (default: 0.95)
+/// - `EMBEDDING_SERVER_URL`: OpenAI-compatible server used to embed prompts in semantic mode
+///   (default: `MISTRAL_SERVER_URL`)
+/// - `EMBEDDING_MODEL`: Model name sent to the embedding endpoint (default: "default")
+
+struct CachedResponse {
+    response: String,
+    // User whose request produced the response, for data deletion requests
+    user: Option<String>,
+    inserted_at: Instant,
+}
+
+// A cached answer indexed by the embedding of its prompt
+struct SemanticEntry {
+    model: String,
+    // Fingerprint of the request around the prompt; see `ResponseCache::context_key`
+    context: u64,
+    embedding: Vec<f32>,
+    response: String,
+    user: Option<String>,
+    inserted_at: Instant,
+}
+
+// Similarity index over prompt embeddings, evicting the oldest entries first
+struct SemanticIndex {
+    entries: Mutex<VecDeque<SemanticEntry>>,
+    capacity: usize,
+    threshold: f32,
+    embedding_url: String,
+    embedding_model: String,
+}
+
+// LRU cache of generated responses keyed on the full upstream payload,
+// optionally backed by a semantic index for near-duplicate prompts
+pub struct ResponseCache {
+    entries: Mutex<LruCache<u64, CachedResponse>>,
+    semantic: Option<SemanticIndex>,
+    ttl: Duration,
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+// Embed text with an OpenAI-compatible `/v1/embeddings` endpoint
+pub async fn request_embedding(client: &Client, url: &str, model: &str, text: &str) -> Result<Vec<f32>> {
+    let request = client.post(format!("{}/v1/embeddings", url));
+    let response: Value = request_id::forward(request)
+        .json(&json!({
+            "model": model,
+            "input": text,
+        }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    response
+        .get("data")
+        .and_then(|data| data.get(0))
+        .and_then(|item| item.get("embedding"))
+        .and_then(|embedding| embedding.as_array())
+        .map(|values| values.iter().filter_map(|v| v.as_f64()).map(|v| v as f32).collect())
+        .ok_or_else(|| anyhow::anyhow!("Embedding response has no data[0].embedding"))
+}
+
+impl ResponseCache {
+    // Build the cache from the environment, or None when caching is disabled
+    pub fn from_env() -> Option<Self> {
+        let size = env::var("RESPONSE_CACHE_SIZE")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .and_then(NonZeroUsize::new)?;
+        let ttl = env::var("RESPONSE_CACHE_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_CACHE_TTL_SECS);
+
+        let semantic = match env::var("RESPONSE_CACHE_MODE").as_deref() {
+            Ok("semantic") => {
+                let threshold = env::var("SEMANTIC_CACHE_THRESHOLD")
+                    .ok()
+                    .and_then(|v| v.parse::<f32>().ok())
+                    .unwrap_or(DEFAULT_SEMANTIC_THRESHOLD);
+                let embedding_url = env::var("EMBEDDING_SERVER_URL")
+                    .or_else(|_| env::var("MISTRAL_SERVER_URL"))
+                    .unwrap_or_else(|_| "http://localhost:8081".to_string());
+                info!("Semantic response cache enabled: threshold {}, embeddings from {}", threshold, embedding_url);
+                Some(SemanticIndex {
+                    entries: Mutex::new(VecDeque::new()),
+                    capacity: size.get(),
+                    threshold,
+                    // MISTRAL_SERVER_URL may list several replicas; any of them can embed
+                    embedding_url: embedding_url.split(',').next().unwrap_or_default().trim().trim_end_matches('/').to_string(),
+                    embedding_model: env::var("EMBEDDING_MODEL").unwrap_or_else(|_| "default".to_string()),
+                })
+            }
+            Ok("exact") | Err(_) => None,
+            Ok(other) => {
+                warn!("Unknown RESPONSE_CACHE_MODE {}, falling back to exact matching", other);
+                None
+            }
+        };
+
+        info!("Response cache enabled: {} entries, TTL {}s", size, ttl);
+        Some(Self {
+            entries: Mutex::new(LruCache::new(size)),
+            semantic,
+            ttl: Duration::from_secs(ttl),
+        })
+    }
+
+    pub fn is_semantic(&self) -> bool {
+        self.semantic.is_some()
+    }
+
+    // The payload holds the model, messages and sampling parameters, so it identifies the request
+    pub fn key(payload: &Value) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        payload.to_string().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    // Fingerprint of everything in a request but its final prompt: system prompt, history,
+    // injected context and sampling parameters, plus the user. Semantic hits must share it, so a
+    // similar prompt is only answered from the cache in the same conversation state, for the
+    // same user, and never with another conversation's answer to a follow-up.
+    pub fn context_key(payload: &Value, prompt: &str, user: Option<&str>) -> u64 {
+        let mut context = payload.clone();
+        let last = context
+            .get_mut("messages")
+            .and_then(Value::as_array_mut)
+            .and_then(|messages| messages.last_mut());
+        if let Some(last) = last {
+            if let Some(content) = last.get("content").and_then(Value::as_str) {
+                let rest = content.strip_suffix(prompt).unwrap_or(content).to_string();
+                last["content"] = json!(rest);
+            }
+        }
+        // Backends without a chat endpoint get the conversation rendered into one prompt
+        if let Some(rendered) = context.get("prompt").and_then(Value::as_str) {
+            let rest = match rendered.rfind(prompt) {
+                Some(start) => format!("{}{}", &rendered[..start], &rendered[start + prompt.len()..]),
+                None => rendered.to_string(),
+            };
+            context["prompt"] = json!(rest);
+        }
+
+        let mut hasher = DefaultHasher::new();
+        context.to_string().hash(&mut hasher);
+        user.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    pub fn get(&self, key: u64) -> Option<String> {
+        let mut entries = self.entries.lock().ok()?;
+        let expired = match entries.get(&key) {
+            Some(entry) if entry.inserted_at.elapsed() < self.ttl => {
+                debug!("Response cache hit for key {:x}", key);
+                metrics().increment("response_cache_hits_total", &[("kind", "exact")]);
+                return Some(entry.response.clone());
+            }
+            Some(_) => true,
+            None => false,
+        };
+
+        if expired {
+            entries.pop(&key);
+        }
+        metrics().increment("response_cache_misses_total", &[("kind", "exact")]);
+        None
+    }
+
+    pub fn insert(&self, key: u64, response: String, user: Option<&str>) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.put(key, CachedResponse {
+                response,
+                user: user.map(str::to_string),
+                inserted_at: Instant::now(),
+            });
+        }
+    }
+
+    // Embed a prompt for the semantic index; None when semantic mode is off or embedding fails
+    pub async fn embed(&self, client: &Client, text: &str) -> Option<Vec<f32>> {
+        let index = self.semantic.as_ref()?;
+        match request_embedding(client, &index.embedding_url, &index.embedding_model, text).await {
+            Ok(embedding) => Some(embedding),
+            Err(e) => {
+                warn!("Failed to embed prompt for semantic cache: {}", e);
+                None
+            }
+        }
+    }
+
+    // Find the most similar cached prompt for the model and request context above the
+    // similarity threshold
+    pub fn get_similar(&self, model: &str, context: u64, embedding: &[f32]) -> Option<String> {
+        let index = self.semantic.as_ref()?;
+        let mut entries = index.entries.lock().ok()?;
+        entries.retain(|entry| entry.inserted_at.elapsed() < self.ttl);
+
+        let best = entries
+            .iter()
+            .filter(|entry| entry.model == model && entry.context == context)
+            .map(|entry| (cosine_similarity(&entry.embedding, embedding), entry))
+            .filter(|(score, _)| *score >= index.threshold)
+            .max_by(|(a, _), (b, _)| a.total_cmp(b));
+
+        match best {
+            Some((score, entry)) => {
+                debug!("Semantic cache hit for model {} with similarity {:.3}", model, score);
+                metrics().increment("response_cache_hits_total", &[("kind", "semantic")]);
+                Some(entry.response.clone())
+            }
+            None => {
+                metrics().increment("response_cache_misses_total", &[("kind", "semantic")]);
+                None
+            }
+        }
+    }
+
+    pub fn insert_similar(&self, model: &str, context: u64, embedding: Vec<f32>, response: String, user: Option<&str>) {
+        let Some(index) = &self.semantic else { return };
+        if let Ok(mut entries) = index.entries.lock() {
+            if entries.len() >= index.capacity {
+                entries.pop_front();
+            }
+            entries.push_back(SemanticEntry {
+                model: model.to_string(),
+                context,
+                embedding,
+                response,
+                user: user.map(str::to_string),
+                inserted_at: Instant::now(),
+            });
+        }
+    }
+
+    // Cached responses and prompt embeddings produced by a user's requests
+    pub fn export_user(&self, user: &str) -> Value {
+        let responses: Vec<String> = self.entries
+            .lock()
+            .map(|entries| {
+                entries
+                    .iter()
+                    .filter(|(_, entry)| entry.user.as_deref() == Some(user))
+                    .map(|(_, entry)| entry.response.clone())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let embeddings: Vec<Value> = self.semantic
+            .as_ref()
+            .and_then(|index| index.entries.lock().ok())
+            .map(|entries| {
+                entries
+                    .iter()
+                    .filter(|entry| entry.user.as_deref() == Some(user))
+                    .map(|entry| json!({
+                        "model": entry.model,
+                        "embedding": entry.embedding,
+                        "response": entry.response,
+                    }))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        json!({
+            "responses": responses,
+            "embeddings": embeddings,
+        })
+    }
+
+    // Drop everything cached for a user's requests, returning how many entries were removed
+    pub fn purge_user(&self, user: &str) -> usize {
+        let mut removed = 0;
+        if let Ok(mut entries) = self.entries.lock() {
+            let keys: Vec<u64> = entries
+                .iter()
+                .filter(|(_, entry)| entry.user.as_deref() == Some(user))
+                .map(|(key, _)| *key)
+                .collect();
+            for key in keys {
+                entries.pop(&key);
+                removed += 1;
+            }
+        }
+        if let Some(mut entries) = self.semantic.as_ref().and_then(|index| index.entries.lock().ok()) {
+            let before = entries.len();
+            entries.retain(|entry| entry.user.as_deref() != Some(user));
+            removed += before - entries.len();
+        }
+        removed
+    }
+}
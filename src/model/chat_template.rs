@@ -0,0 +1,107 @@
+use std::str::FromStr;
+
+use crate::web::models::{Message, Role};
+
+// Prompt formats of instruction-tuned models, for backends that only expose `/v1/completions`.
+// The rendered prompt ends where the assistant's reply should start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatTemplate {
+    // <|im_start|>role ... <|im_end|>, used by Qwen, Yi and many fine-tunes
+    ChatMl,
+    // [INST] <<SYS>> ... <</SYS>> ... [/INST]
+    Llama2,
+    // <|start_header_id|>role<|end_header_id|> ... <|eot_id|>
+    Llama3,
+    // [INST] ... [/INST], without a system role
+    Mistral,
+}
+
+impl FromStr for ChatTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "chatml" => Ok(Self::ChatMl),
+            "llama2" | "llama-2" => Ok(Self::Llama2),
+            "llama3" | "llama-3" => Ok(Self::Llama3),
+            "mistral" => Ok(Self::Mistral),
+            other => Err(anyhow::anyhow!("Unknown chat template: {}", other)),
+        }
+    }
+}
+
+fn role_name(role: Role) -> &'static str {
+    match role {
+        Role::System => "system",
+        Role::User => "user",
+        Role::Assistant => "assistant",
+    }
+}
+
+impl ChatTemplate {
+    // Sequences that end the assistant's turn; sent as stop sequences so the model doesn't
+    // carry on with the next user message
+    pub fn stop_sequences(&self) -> &'static [&'static str] {
+        match self {
+            Self::ChatMl => &["<|im_end|>", "<|im_start|>"],
+            Self::Llama2 | Self::Mistral => &["</s>", "[INST]"],
+            Self::Llama3 => &["<|eot_id|>", "<|start_header_id|>"],
+        }
+    }
+
+    // Render a conversation into a single completion prompt
+    pub fn render(&self, messages: &[Message]) -> String {
+        match self {
+            Self::ChatMl => {
+                let mut prompt: String = messages
+                    .iter()
+                    .map(|m| format!("<|im_start|>{}\n{}<|im_end|>\n", role_name(m.role), m.content))
+                    .collect();
+                prompt.push_str("<|im_start|>assistant\n");
+                prompt
+            }
+            Self::Llama3 => {
+                let mut prompt = String::from("<|begin_of_text|>");
+                for m in messages {
+                    prompt.push_str(&format!(
+                        "<|start_header_id|>{}<|end_header_id|>\n\n{}<|eot_id|>",
+                        role_name(m.role),
+                        m.content.trim()
+                    ));
+                }
+                prompt.push_str("<|start_header_id|>assistant<|end_header_id|>\n\n");
+                prompt
+            }
+            Self::Llama2 => Self::render_inst(messages, |system, user| {
+                format!("<<SYS>>\n{}\n<</SYS>>\n\n{}", system, user)
+            }),
+            // Mistral instruct has no system role; the system message opens the first instruction
+            Self::Mistral => Self::render_inst(messages, |system, user| format!("{}\n\n{}", system, user)),
+        }
+    }
+
+    // [INST]-style templates: each user message is wrapped in [INST] ... [/INST] and followed
+    // by the assistant's reply; `with_system` merges the system message into the first one
+    fn render_inst(messages: &[Message], with_system: impl Fn(&str, &str) -> String) -> String {
+        let mut system = messages
+            .iter()
+            .find(|m| m.role == Role::System)
+            .map(|m| m.content.trim().to_string());
+
+        let mut prompt = String::new();
+        for m in messages {
+            match m.role {
+                Role::System => {}
+                Role::User => {
+                    let content = match system.take() {
+                        Some(system) => with_system(&system, m.content.trim()),
+                        None => m.content.trim().to_string(),
+                    };
+                    prompt.push_str(&format!("<s>[INST] {} [/INST]", content));
+                }
+                Role::Assistant => prompt.push_str(&format!(" {}</s>", m.content.trim())),
+            }
+        }
+        prompt
+    }
+}
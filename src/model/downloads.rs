@@ -0,0 +1,395 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::{info, warn, error};
+use reqwest::header::{AUTHORIZATION, RANGE};
+use reqwest::{RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::http_client::http_client;
+use super::resources::{disk_space, DiskSpace, ResourceLimits};
+
+// Default directory GGUF files are downloaded into
+const DEFAULT_MODELS_DIR: &str = "models";
+// Default Hugging Face host
+const DEFAULT_HF_ENDPOINT: &str = "https://huggingface.co";
+// Suffix of files still being downloaded
+const PARTIAL_SUFFIX: &str = ".part";
+
+/// Environment variables for downloading GGUF models:
+///
+/// - `MODELS_DIR`: Directory downloaded models are stored in (default: "models")
+/// - `HF_TOKEN`: Hugging Face access token, for gated and private repositories (default: none)
+/// - `HF_ENDPOINT`: Hugging Face host, e.g. a mirror (default: "https://huggingface.co")
+///
+/// Files are written to `<MODELS_DIR>/<file>.part` and renamed once their SHA-256 matches;
+/// a download that is started again continues from the partial file.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DownloadStatus {
+    Downloading,
+    Verifying,
+    Completed,
+    Failed,
+}
+
+// Body of `POST /api/admin/models/downloads`
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct DownloadRequest {
+    // Hugging Face repository, e.g. "TheBloke/Mistral-7B-Instruct-v0.2-GGUF"
+    pub repo: String,
+    // GGUF file in the repository, e.g. "mistral-7b-instruct-v0.2.Q4_K_M.gguf"
+    pub filename: String,
+    // Branch, tag or commit (default: "main")
+    pub revision: Option<String>,
+    // Expected SHA-256; Hugging Face's own checksum of the file is used when omitted
+    pub sha256: Option<String>,
+}
+
+// Progress of a download as shown to admins
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Download {
+    pub id: Uuid,
+    pub repo: String,
+    pub filename: String,
+    pub revision: String,
+    pub status: DownloadStatus,
+    pub downloaded_bytes: u64,
+    pub total_bytes: Option<u64>,
+    // Where the file ends up
+    pub path: String,
+    pub sha256: Option<String>,
+    pub error: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+// A GGUF file in the models directory
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct LocalModelFile {
+    pub filename: String,
+    pub path: String,
+    pub size_bytes: u64,
+    pub modified_at: Option<DateTime<Utc>>,
+}
+
+// Disk use of the models directory, for the admin stats
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ModelsDiskUsage {
+    pub dir: String,
+    // Bytes taken by GGUF files in the directory
+    pub models_bytes: u64,
+    pub disk: Option<DiskSpace>,
+    pub min_free_bytes: u64,
+}
+
+pub struct DownloadManager {
+    dir: PathBuf,
+    endpoint: String,
+    token: Option<String>,
+    limits: ResourceLimits,
+    downloads: Mutex<HashMap<Uuid, Download>>,
+}
+
+impl DownloadRequest {
+    // Reject names that could escape the models directory or aren't GGUF files
+    pub fn validate(&self) -> Result<(), String> {
+        let parts: Vec<&str> = self.repo.split('/').collect();
+        if parts.len() != 2 || parts.iter().any(|part| part.is_empty() || *part == "." || *part == "..") {
+            return Err("repo must look like \"owner/name\"".to_string());
+        }
+        if !self.filename.ends_with(".gguf") {
+            return Err("filename must be a .gguf file".to_string());
+        }
+        if self.filename.starts_with('/') || self.filename.split('/').any(|part| part.is_empty() || part == "..") {
+            return Err("filename must be a path inside the repository".to_string());
+        }
+        if let Some(revision) = &self.revision {
+            if revision.is_empty() || revision.contains("..") {
+                return Err("revision must be a branch, tag or commit".to_string());
+            }
+        }
+        if let Some(sha256) = &self.sha256 {
+            if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err("sha256 must be 64 hexadecimal characters".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+// SHA-256 of a file, read off the async runtime
+async fn file_sha256(path: PathBuf) -> Result<String> {
+    tokio::task::spawn_blocking(move || {
+        let mut file = std::fs::File::open(&path)?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut file, &mut hasher)?;
+        Ok(hex::encode(hasher.finalize()))
+    })
+    .await?
+}
+
+impl DownloadManager {
+    pub fn from_env() -> Self {
+        Self {
+            dir: PathBuf::from(env::var("MODELS_DIR").unwrap_or_else(|_| DEFAULT_MODELS_DIR.to_string())),
+            endpoint: env::var("HF_ENDPOINT")
+                .unwrap_or_else(|_| DEFAULT_HF_ENDPOINT.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            token: env::var("HF_TOKEN").ok().filter(|token| !token.is_empty()),
+            limits: ResourceLimits::from_env(),
+            downloads: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Downloads started since the server came up, newest first
+    pub fn list(&self) -> Vec<Download> {
+        let mut downloads: Vec<Download> = self.downloads
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+            .cloned()
+            .collect();
+        downloads.sort_by(|a, b| b.started_at.cmp(&a.started_at));
+        downloads
+    }
+
+    // GGUF files in the models directory, whether downloaded here or copied in
+    pub async fn local_files(&self) -> Vec<LocalModelFile> {
+        let mut files = Vec::new();
+        let mut entries = match fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(_) => return files,
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let filename = entry.file_name().to_string_lossy().to_string();
+            if !filename.ends_with(".gguf") {
+                continue;
+            }
+            let Ok(metadata) = entry.metadata().await else { continue };
+            files.push(LocalModelFile {
+                path: entry.path().to_string_lossy().to_string(),
+                size_bytes: metadata.len(),
+                modified_at: metadata.modified().ok().map(DateTime::<Utc>::from),
+                filename,
+            });
+        }
+        files.sort_by(|a, b| a.filename.cmp(&b.filename));
+        files
+    }
+
+    pub async fn disk_usage(&self) -> ModelsDiskUsage {
+        ModelsDiskUsage {
+            dir: self.dir.to_string_lossy().to_string(),
+            models_bytes: self.local_files().await.iter().map(|file| file.size_bytes).sum(),
+            disk: disk_space(&self.dir),
+            min_free_bytes: self.limits.min_free_disk_bytes,
+        }
+    }
+
+    pub fn limits(&self) -> &ResourceLimits {
+        &self.limits
+    }
+
+    // Where a repository file is stored; files from nested repository folders land directly
+    // in the models directory
+    fn path_for(&self, filename: &str) -> PathBuf {
+        self.dir.join(filename.rsplit('/').next().unwrap_or(filename))
+    }
+
+    fn url(&self, repo: &str, revision: &str, filename: &str) -> String {
+        format!("{}/{}/resolve/{}/{}", self.endpoint, repo, revision, filename)
+    }
+
+    fn authorized(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.token {
+            Some(token) => request.header(AUTHORIZATION, format!("Bearer {}", token)),
+            None => request,
+        }
+    }
+
+    // Refuse a download that wouldn't fit on disk, or whose model wouldn't fit in the VRAM
+    // budget. Files whose size Hugging Face doesn't report are let through and checked again
+    // once the download starts.
+    pub async fn check_resources(&self, req: &DownloadRequest) -> Result<(), String> {
+        let url = self.url(&req.repo, req.revision.as_deref().unwrap_or("main"), &req.filename);
+        let response = match self.authorized(http_client().head(&url)).send().await {
+            Ok(response) if response.status().is_success() => response,
+            _ => return Ok(()),
+        };
+        // Hugging Face reports the size of LFS files separately from the redirect's body
+        let size = response.headers()
+            .get("x-linked-size")
+            .and_then(|size| size.to_str().ok())
+            .and_then(|size| size.parse::<u64>().ok())
+            .or_else(|| response.content_length().filter(|length| *length > 0));
+        let Some(size) = size else { return Ok(()) };
+
+        let partial = format!("{}{}", self.path_for(&req.filename).to_string_lossy(), PARTIAL_SUFFIX);
+        let downloaded = fs::metadata(&partial).await.map(|metadata| metadata.len()).unwrap_or(0);
+        self.limits.check_disk(&self.dir, size.saturating_sub(downloaded))?;
+        self.limits.check_vram(&req.filename, self.limits.estimate_vram(size))
+    }
+
+    // Start downloading a validated request in the background; fails when the same file is
+    // already being downloaded
+    pub fn start(self: &Arc<Self>, req: DownloadRequest) -> Result<Download, String> {
+        let path = self.path_for(&req.filename);
+
+        let download = Download {
+            id: Uuid::new_v4(),
+            revision: req.revision.clone().unwrap_or_else(|| "main".to_string()),
+            repo: req.repo,
+            filename: req.filename,
+            status: DownloadStatus::Downloading,
+            downloaded_bytes: 0,
+            total_bytes: None,
+            path: path.to_string_lossy().to_string(),
+            sha256: req.sha256.map(|sha256| sha256.to_lowercase()),
+            error: None,
+            started_at: Utc::now(),
+            finished_at: None,
+        };
+        {
+            let mut downloads = self.downloads.lock().unwrap_or_else(PoisonError::into_inner);
+            let busy = downloads.values().any(|other| {
+                other.path == download.path && matches!(other.status, DownloadStatus::Downloading | DownloadStatus::Verifying)
+            });
+            if busy {
+                return Err(format!("{} is already being downloaded", download.path));
+            }
+            downloads.insert(download.id, download.clone());
+        }
+
+        let manager = self.clone();
+        let id = download.id;
+        tokio::spawn(async move {
+            match manager.run(id).await {
+                Ok(()) => manager.update(id, |d| {
+                    d.status = DownloadStatus::Completed;
+                    d.finished_at = Some(Utc::now());
+                }),
+                Err(e) => {
+                    error!("Download {} failed: {:#}", id, e);
+                    manager.update(id, |d| {
+                        d.status = DownloadStatus::Failed;
+                        d.error = Some(format!("{:#}", e));
+                        d.finished_at = Some(Utc::now());
+                    });
+                }
+            }
+        });
+        Ok(download)
+    }
+
+    fn update(&self, id: Uuid, change: impl FnOnce(&mut Download)) {
+        if let Some(download) = self.downloads.lock().unwrap_or_else(PoisonError::into_inner).get_mut(&id) {
+            change(download);
+        }
+    }
+
+    fn get(&self, id: Uuid) -> Option<Download> {
+        self.downloads.lock().unwrap_or_else(PoisonError::into_inner).get(&id).cloned()
+    }
+
+    async fn run(&self, id: Uuid) -> Result<()> {
+        let download = self.get(id).context("Download disappeared")?;
+        let path = PathBuf::from(&download.path);
+        let partial = PathBuf::from(format!("{}{}", download.path, PARTIAL_SUFFIX));
+        fs::create_dir_all(&self.dir)
+            .await
+            .with_context(|| format!("Failed to create models directory {}", self.dir.display()))?;
+
+        // Continue where an earlier attempt stopped
+        let mut offset = fs::metadata(&partial).await.map(|metadata| metadata.len()).unwrap_or(0);
+        let url = self.url(&download.repo, &download.revision, &download.filename);
+        info!("Downloading {} to {} (resuming at byte {})", url, path.display(), offset);
+
+        let mut request = self.authorized(http_client().get(&url));
+        if offset > 0 {
+            request = request.header(RANGE, format!("bytes={}-", offset));
+        }
+        let mut response = request.send().await.context("Failed to reach Hugging Face")?;
+        let status = response.status();
+        if status == StatusCode::RANGE_NOT_SATISFIABLE {
+            // The partial file is already complete; verification decides whether it's usable
+            info!("Partial file {} is already complete", partial.display());
+        } else if !status.is_success() {
+            return Err(anyhow::anyhow!("Hugging Face answered {} for {}", status, url));
+        }
+
+        // Hugging Face reports the SHA-256 of LFS files as their linked ETag
+        let expected = download.sha256.clone().or_else(|| {
+            response.headers()
+                .get("x-linked-etag")
+                .and_then(|etag| etag.to_str().ok())
+                .map(|etag| etag.trim_matches('"').to_lowercase())
+                .filter(|etag| etag.len() == 64 && etag.chars().all(|c| c.is_ascii_hexdigit()))
+        });
+
+        if status.is_success() {
+            // A server ignoring the range sends the whole file again
+            if status != StatusCode::PARTIAL_CONTENT && offset > 0 {
+                warn!("Range request for {} not honoured; starting over", url);
+                offset = 0;
+            }
+            let total = response.content_length().map(|length| length + offset);
+            // Free space may have shrunk since the download was accepted
+            if let Some(remaining) = response.content_length() {
+                self.limits.check_disk(&self.dir, remaining).map_err(anyhow::Error::msg)?;
+            }
+            self.update(id, |d| {
+                d.downloaded_bytes = offset;
+                d.total_bytes = total;
+                d.sha256 = expected.clone();
+            });
+
+            let mut file = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .append(offset > 0)
+                .truncate(offset == 0)
+                .open(&partial)
+                .await
+                .with_context(|| format!("Failed to open {}", partial.display()))?;
+            let mut written = offset;
+            while let Some(chunk) = response.chunk().await.context("Download interrupted; start it again to resume")? {
+                file.write_all(&chunk).await?;
+                written += chunk.len() as u64;
+                self.update(id, |d| d.downloaded_bytes = written);
+            }
+            file.flush().await?;
+            if let Some(total) = total.filter(|total| written < *total) {
+                return Err(anyhow::anyhow!("Download ended at {} of {} bytes; start it again to resume", written, total));
+            }
+        }
+
+        self.update(id, |d| d.status = DownloadStatus::Verifying);
+        let actual = file_sha256(partial.clone()).await?;
+        match &expected {
+            Some(expected) if *expected != actual => {
+                // A corrupt partial file can't be resumed, so start from scratch next time
+                let _ = fs::remove_file(&partial).await;
+                return Err(anyhow::anyhow!("Checksum mismatch: expected {}, got {}", expected, actual));
+            }
+            Some(_) => {}
+            None => warn!("No checksum known for {}; kept without verification", download.filename),
+        }
+        fs::rename(&partial, &path)
+            .await
+            .with_context(|| format!("Failed to move {} into place", partial.display()))?;
+        self.update(id, |d| d.sha256 = Some(actual));
+        info!("Downloaded {} ({})", path.display(), download.filename);
+        Ok(())
+    }
+}
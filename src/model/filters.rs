@@ -0,0 +1,223 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use anyhow::{Context, Result};
+use log::info;
+use regex::Regex;
+use serde::Deserialize;
+
+// Default location of the response filter configuration
+const DEFAULT_RESPONSE_FILTERS_CONFIG: &str = "response_filters.toml";
+
+/// Environment variables for configuring response post-processing:
+///
+/// - `RESPONSE_FILTERS_CONFIG`: Path of the TOML filter chain (default: "response_filters.toml");
+///   answers are returned as generated when the file doesn't exist
+///
+/// Filters run in the order listed, on every chat answer before it is cached, stored in the
+/// session and returned. Plain completions and FIM are left untouched, as they are spliced
+/// into text the caller already has. `models` limits a filter to some registry models:
+///
+/// ```toml
+/// [[filters]]
+/// type = "strip_think"         # drop <think>...</think> blocks
+/// models = ["qwq-32b"]         # optional (default: every model)
+///
+/// [[filters]]
+/// type = "regex_replace"
+/// pattern = "(?m)^As an AI language model, "
+/// replacement = ""
+///
+/// [[filters]]
+/// type = "trim"
+///
+/// [[filters]]
+/// type = "max_length"
+/// max_chars = 4000
+/// suffix = "…"                 # optional, appended when the answer is cut
+/// ```
+
+// A step normalizing model output
+pub trait ResponseFilter: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn apply(&self, text: String) -> String;
+}
+
+fn default_think_open() -> String {
+    "<think>".to_string()
+}
+
+fn default_think_close() -> String {
+    "</think>".to_string()
+}
+
+// A filter as written in the config file
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum FilterKind {
+    StripThink {
+        #[serde(default = "default_think_open")]
+        open: String,
+        #[serde(default = "default_think_close")]
+        close: String,
+    },
+    Trim,
+    MaxLength {
+        max_chars: usize,
+        #[serde(default)]
+        suffix: String,
+    },
+    RegexReplace {
+        pattern: String,
+        #[serde(default)]
+        replacement: String,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct FilterEntry {
+    #[serde(flatten)]
+    kind: FilterKind,
+    #[serde(default)]
+    models: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct FiltersConfig {
+    #[serde(default)]
+    filters: Vec<FilterEntry>,
+}
+
+// Removes reasoning blocks. An answer that starts mid-block, because the chat template opened
+// it, loses everything up to the closing tag; an unclosed block runs to the end of the answer.
+pub struct StripThinkFilter {
+    open: String,
+    close: String,
+}
+
+impl ResponseFilter for StripThinkFilter {
+    fn name(&self) -> &str {
+        "strip_think"
+    }
+
+    fn apply(&self, text: String) -> String {
+        let mut text = match (text.find(&self.open), text.find(&self.close)) {
+            (open, Some(close)) if open.map_or(true, |open| close < open) => text[close + self.close.len()..].to_string(),
+            _ => text,
+        };
+        while let Some(start) = text.find(&self.open) {
+            let end = text[start..]
+                .find(&self.close)
+                .map_or(text.len(), |end| start + end + self.close.len());
+            text.replace_range(start..end, "");
+        }
+        text.trim_start().to_string()
+    }
+}
+
+pub struct TrimFilter;
+
+impl ResponseFilter for TrimFilter {
+    fn name(&self) -> &str {
+        "trim"
+    }
+
+    fn apply(&self, text: String) -> String {
+        text.trim().to_string()
+    }
+}
+
+// Cuts answers longer than `max_chars` characters, suffix included
+pub struct MaxLengthFilter {
+    max_chars: usize,
+    suffix: String,
+}
+
+impl ResponseFilter for MaxLengthFilter {
+    fn name(&self) -> &str {
+        "max_length"
+    }
+
+    fn apply(&self, text: String) -> String {
+        if text.chars().count() <= self.max_chars {
+            return text;
+        }
+        let keep = self.max_chars.saturating_sub(self.suffix.chars().count());
+        let mut cut: String = text.chars().take(keep).collect();
+        cut.push_str(&self.suffix);
+        cut
+    }
+}
+
+pub struct RegexReplaceFilter {
+    regex: Regex,
+    replacement: String,
+}
+
+impl ResponseFilter for RegexReplaceFilter {
+    fn name(&self) -> &str {
+        "regex_replace"
+    }
+
+    fn apply(&self, text: String) -> String {
+        self.regex.replace_all(&text, self.replacement.as_str()).into_owned()
+    }
+}
+
+struct ChainEntry {
+    filter: Box<dyn ResponseFilter>,
+    // Models the filter applies to; empty for every model
+    models: Vec<String>,
+}
+
+// Filters applied in order to the answers of chat generations
+pub struct ResponseFilterChain {
+    entries: Vec<ChainEntry>,
+}
+
+impl ResponseFilterChain {
+    pub fn load() -> Result<Self> {
+        let path = env::var("RESPONSE_FILTERS_CONFIG")
+            .unwrap_or_else(|_| DEFAULT_RESPONSE_FILTERS_CONFIG.to_string());
+        if !Path::new(&path).exists() {
+            return Ok(Self { entries: Vec::new() });
+        }
+
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read response filters {}", path))?;
+        let config: FiltersConfig = toml::from_str(&contents)
+            .with_context(|| format!("Invalid response filters {}", path))?;
+
+        let entries = config.filters
+            .into_iter()
+            .map(|entry| {
+                let filter: Box<dyn ResponseFilter> = match entry.kind {
+                    FilterKind::StripThink { open, close } => Box::new(StripThinkFilter { open, close }),
+                    FilterKind::Trim => Box::new(TrimFilter),
+                    FilterKind::MaxLength { max_chars, suffix } => Box::new(MaxLengthFilter { max_chars, suffix }),
+                    FilterKind::RegexReplace { pattern, replacement } => Box::new(RegexReplaceFilter {
+                        regex: Regex::new(&pattern)
+                            .with_context(|| format!("Invalid response filter pattern {}", pattern))?,
+                        replacement,
+                    }),
+                };
+                Ok(ChainEntry { filter, models: entry.models })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if !entries.is_empty() {
+            let names: Vec<&str> = entries.iter().map(|entry| entry.filter.name()).collect();
+            info!("Response filters enabled: {}", names.join(", "));
+        }
+        Ok(Self { entries })
+    }
+
+    // Run the filters that apply to `model` over an answer
+    pub fn apply(&self, model: &str, text: String) -> String {
+        self.entries
+            .iter()
+            .filter(|entry| entry.models.is_empty() || entry.models.iter().any(|m| m == model))
+            .fold(text, |text, entry| entry.filter.apply(text))
+    }
+}
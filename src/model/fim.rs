@@ -0,0 +1,48 @@
+use serde::Deserialize;
+
+// Special tokens a code model was trained with for fill-in-the-middle. The prompt is laid
+// out prefix-suffix-middle: `{prefix}<code before>{suffix}<code after>{middle}`, and the
+// model generates the code that goes between.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct FimTokens {
+    pub prefix: String,
+    pub suffix: String,
+    pub middle: String,
+}
+
+// How a registry entry declares its FIM tokens: a known model family or explicit tokens
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum FimConfig {
+    Preset(String),
+    Tokens(FimTokens),
+}
+
+fn tokens(prefix: &str, suffix: &str, middle: &str) -> FimTokens {
+    FimTokens {
+        prefix: prefix.to_string(),
+        suffix: suffix.to_string(),
+        middle: middle.to_string(),
+    }
+}
+
+impl FimConfig {
+    pub fn resolve(self) -> anyhow::Result<FimTokens> {
+        match self {
+            Self::Tokens(tokens) => Ok(tokens),
+            Self::Preset(name) => match name.trim().to_lowercase().as_str() {
+                "starcoder" => Ok(tokens("<fim_prefix>", "<fim_suffix>", "<fim_middle>")),
+                "codellama" => Ok(tokens("<PRE> ", " <SUF>", " <MID>")),
+                "deepseek" => Ok(tokens("<｜fim▁begin｜>", "<｜fim▁hole｜>", "<｜fim▁end｜>")),
+                "qwen" | "codegemma" => Ok(tokens("<|fim_prefix|>", "<|fim_suffix|>", "<|fim_middle|>")),
+                other => Err(anyhow::anyhow!("Unknown fill-in-the-middle preset: {}", other)),
+            },
+        }
+    }
+}
+
+impl FimTokens {
+    pub fn render(&self, prefix: &str, suffix: &str) -> String {
+        format!("{}{}{}{}{}", self.prefix, prefix, self.suffix, suffix, self.middle)
+    }
+}
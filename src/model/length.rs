@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+// Tokens of each preset when the registry doesn't tune them for a model
+const DEFAULT_SHORT_TOKENS: usize = 256;
+const DEFAULT_MEDIUM_TOKENS: usize = 768;
+const DEFAULT_LONG_TOKENS: usize = 2048;
+
+// How long a reply should be; sets both max_tokens and how the model is asked to answer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseLength {
+    Short,
+    Medium,
+    Long,
+    // As long as the model's max_tokens allows
+    Max,
+}
+
+impl ResponseLength {
+    // Added to the system message so the model aims for the length rather than getting cut off
+    pub fn instruction(self) -> &'static str {
+        match self {
+            ResponseLength::Short => "Keep your answer short: a few sentences at most, without preamble.",
+            ResponseLength::Medium => "Answer in moderate detail, covering the main points without padding.",
+            ResponseLength::Long => "Provide a detailed and comprehensive answer.",
+            ResponseLength::Max => "Provide an exhaustive, in-depth answer that covers every relevant aspect.",
+        }
+    }
+}
+
+// max_tokens of the presets for one model, as set in the registry's `length_presets`
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct LengthPresets {
+    pub short: Option<usize>,
+    pub medium: Option<usize>,
+    pub long: Option<usize>,
+}
+
+impl LengthPresets {
+    // max_tokens of a preset, before the model's limits are applied; None for `max`
+    pub fn tokens(&self, length: ResponseLength) -> Option<usize> {
+        match length {
+            ResponseLength::Short => Some(self.short.unwrap_or(DEFAULT_SHORT_TOKENS)),
+            ResponseLength::Medium => Some(self.medium.unwrap_or(DEFAULT_MEDIUM_TOKENS)),
+            ResponseLength::Long => Some(self.long.unwrap_or(DEFAULT_LONG_TOKENS)),
+            ResponseLength::Max => None,
+        }
+    }
+}
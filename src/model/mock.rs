@@ -0,0 +1,191 @@
+use std::env;
+use std::time::Duration;
+use actix_web::{web, App, HttpResponse, HttpServer};
+use log::info;
+use serde_json::{json, Value};
+
+// Defaults for the mock backend
+const DEFAULT_MOCK_BACKEND_ADDR: &str = "127.0.0.1:8089";
+const DEFAULT_MOCK_LATENCY_MS: u64 = 50;
+// Dimensions of the mock embeddings
+const MOCK_EMBEDDING_DIMENSIONS: usize = 32;
+
+/// Environment variables for running without a mistral.rs server:
+///
+/// - `MOCK_BACKEND`: Start a built-in mock backend and send all generations to it (default: false)
+/// - `MOCK_BACKEND_ADDR`: Address the mock backend listens on; port 0 picks a free port
+///   (default: "127.0.0.1:8089")
+/// - `MOCK_LATENCY_MS`: Delay before each mock response (default: 50)
+/// - `MOCK_RESPONSE`: Fixed reply to every generation; by default the mock echoes the last
+///   user message (chat) or the last line of the prompt (completions)
+///
+/// The mock speaks the subset of the OpenAI-compatible API the app uses (`/health`,
+/// `/v1/models`, `/v1/chat/completions`, `/v1/completions`, `/v1/embeddings` and
+/// `/tokenize`), with deterministic answers and one token per word, so the whole app can be
+/// exercised in development and tests.
+
+// Deterministic stand-in for a mistral.rs server
+#[derive(Debug, Clone)]
+pub struct MockBackend {
+    addr: String,
+    latency: Duration,
+    response: Option<String>,
+}
+
+impl MockBackend {
+    // Configure the mock from the environment, or None when it is disabled
+    pub fn from_env() -> Option<Self> {
+        let enabled = env::var("MOCK_BACKEND")
+            .ok()
+            .and_then(|v| v.parse::<bool>().ok())
+            .unwrap_or(false);
+        if !enabled {
+            return None;
+        }
+        let latency = env::var("MOCK_LATENCY_MS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_MOCK_LATENCY_MS);
+        Some(Self {
+            addr: env::var("MOCK_BACKEND_ADDR").unwrap_or_else(|_| DEFAULT_MOCK_BACKEND_ADDR.to_string()),
+            latency: Duration::from_millis(latency),
+            response: env::var("MOCK_RESPONSE").ok(),
+        })
+    }
+
+    // A mock on a free local port that answers straight away
+    #[cfg(test)]
+    pub fn ephemeral() -> Self {
+        Self {
+            addr: "127.0.0.1:0".to_string(),
+            latency: Duration::ZERO,
+            response: None,
+        }
+    }
+
+    // Start serving in the background and return the URL to use as the backend
+    pub fn start(self) -> std::io::Result<String> {
+        let addr = self.addr.clone();
+        let mock = web::Data::new(self);
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(mock.clone())
+                .route("/health", web::get().to(health))
+                .route("/v1/models", web::get().to(models))
+                .route("/v1/chat/completions", web::post().to(chat_completions))
+                .route("/v1/completions", web::post().to(completions))
+                .route("/v1/embeddings", web::post().to(embeddings))
+                .route("/tokenize", web::post().to(tokenize))
+        })
+        .workers(1)
+        .disable_signals()
+        .bind(&addr)?;
+        // The bound address, which differs from the configured one when port 0 was asked for
+        let url = match server.addrs().first() {
+            Some(bound) => format!("http://{}", bound),
+            None => format!("http://{}", addr),
+        };
+        actix_web::rt::spawn(server.run());
+        info!("Mock backend listening on {}", url);
+        Ok(url)
+    }
+
+    fn reply(&self, echoed: &str, max_tokens: Option<u64>) -> String {
+        let reply = self.response.clone().unwrap_or_else(|| format!("Echo: {}", echoed.trim()));
+        // Respect max_tokens, counting one token per word
+        match max_tokens {
+            Some(max) => reply.split_whitespace().take(max as usize).collect::<Vec<_>>().join(" "),
+            None => reply,
+        }
+    }
+}
+
+fn count_tokens(text: &str) -> usize {
+    text.split_whitespace().count()
+}
+
+fn usage(prompt_tokens: usize, completion_tokens: usize) -> Value {
+    json!({
+        "prompt_tokens": prompt_tokens,
+        "completion_tokens": completion_tokens,
+        "total_tokens": prompt_tokens + completion_tokens,
+    })
+}
+
+async fn health() -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "status": "ok" }))
+}
+
+// No context length is reported, so the configured window is kept
+async fn models() -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "object": "list",
+        "data": [{ "id": "mock", "object": "model", "owned_by": "mock" }],
+    }))
+}
+
+async fn chat_completions(mock: web::Data<MockBackend>, payload: web::Json<Value>) -> HttpResponse {
+    tokio::time::sleep(mock.latency).await;
+    let messages = payload["messages"].as_array().cloned().unwrap_or_default();
+    let contents = messages.iter().filter_map(|m| m["content"].as_str());
+    let last_user = messages
+        .iter()
+        .rev()
+        .find(|m| m["role"] == "user")
+        .and_then(|m| m["content"].as_str())
+        .unwrap_or_default();
+    let content = mock.reply(last_user, payload["max_tokens"].as_u64());
+
+    HttpResponse::Ok().json(json!({
+        "object": "chat.completion",
+        "model": payload["model"],
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": content },
+            "finish_reason": "stop",
+        }],
+        "usage": usage(contents.map(count_tokens).sum(), count_tokens(&content)),
+    }))
+}
+
+async fn completions(mock: web::Data<MockBackend>, payload: web::Json<Value>) -> HttpResponse {
+    tokio::time::sleep(mock.latency).await;
+    let prompt = payload["prompt"].as_str().unwrap_or_default();
+    let last_line = prompt.lines().rev().find(|line| !line.trim().is_empty()).unwrap_or_default();
+    let mut text = mock.reply(last_line, payload["max_tokens"].as_u64());
+    if payload["echo"].as_bool().unwrap_or(false) {
+        text = format!("{}{}", prompt, text);
+    }
+
+    HttpResponse::Ok().json(json!({
+        "object": "text_completion",
+        "model": payload["model"],
+        "choices": [{ "index": 0, "text": text, "finish_reason": "stop" }],
+        "usage": usage(count_tokens(prompt), count_tokens(&text)),
+    }))
+}
+
+// Letter frequencies, normalized, so similar texts get similar embeddings
+async fn embeddings(payload: web::Json<Value>) -> HttpResponse {
+    let input = payload["input"].as_str().unwrap_or_default();
+    let mut embedding = vec![0.0f32; MOCK_EMBEDDING_DIMENSIONS];
+    for byte in input.to_lowercase().bytes().filter(|b| b.is_ascii_alphanumeric()) {
+        embedding[byte as usize % MOCK_EMBEDDING_DIMENSIONS] += 1.0;
+    }
+    let norm = embedding.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        embedding.iter_mut().for_each(|v| *v /= norm);
+    }
+
+    HttpResponse::Ok().json(json!({
+        "object": "list",
+        "data": [{ "object": "embedding", "index": 0, "embedding": embedding }],
+        "usage": usage(count_tokens(input), 0),
+    }))
+}
+
+async fn tokenize(payload: web::Json<Value>) -> HttpResponse {
+    let text = payload["text"].as_str().unwrap_or_default();
+    let tokens: Vec<u32> = (0..count_tokens(text) as u32).collect();
+    HttpResponse::Ok().json(json!({ "tokens": tokens }))
+}
@@ -1,10 +1,47 @@
-use std::sync::Arc;
+pub mod bench;
+pub mod cache;
+pub mod chat_template;
+pub mod downloads;
+pub mod filters;
+pub mod fim;
+pub mod length;
+pub mod mock;
+pub mod pool;
+pub mod priority;
+pub mod protocol;
+pub mod registry;
+pub mod replay;
+pub mod resources;
+pub mod system_prompt;
+pub mod truncation;
+
+use std::collections::HashMap;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use anyhow::Result;
 use std::env;
 use reqwest::Client;
 use serde_json::{json, Value};
 use log::{info, debug, warn, error};
-use crate::web::models::{Message, Role};
+use crate::http_client::http_client;
+use crate::metrics::metrics;
+use crate::prompt_log::prompt_log;
+use crate::stats::stats;
+use crate::web::models::{Message, Role, ModelInfo};
+use crate::web::request_id;
+use cache::ResponseCache;
+use chat_template::ChatTemplate;
+use filters::ResponseFilterChain;
+use fim::FimTokens;
+use length::{LengthPresets, ResponseLength};
+use pool::{BackendPool, BackendStatus};
+use priority::{Priority, PriorityLimiter};
+use protocol::{BackendProtocol, ReasoningEffort, PROMPT_CACHE_FIELD, THINKING_BUDGET_FIELD};
+use registry::{ModelConfig, ModelRegistry, Pricing, SpeculativeConfig};
+use replay::replay;
+use system_prompt::PromptVariables;
+use truncation::TruncationStrategy;
 
 // Default constants for token limits
 const DEFAULT_MAX_CONTEXT_WINDOW: usize = 4096; // Default maximum context window size
@@ -12,10 +49,37 @@ const DEFAULT_SYSTEM_MESSAGE_RESERVE: usize = 200; // Default reserve tokens for
 const DEFAULT_RESPONSE_RESERVE: usize = 500; // Default reserve tokens for response
 const DEFAULT_MIN_TOKENS: usize = 100; // Default minimum tokens for response
 const DEFAULT_MAX_TOKENS: usize = 4096; // Default maximum tokens for response
+const DEFAULT_TEMPERATURE: f32 = 0.7; // Default sampling temperature
+const DEFAULT_TOP_P: f32 = 0.95; // Default top-p sampling parameter
+const DEFAULT_HEALTH_CHECK_INTERVAL_SECS: u64 = 10; // Default interval between backend health checks
+const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 4; // Default number of generations sent upstream at once
+const DEFAULT_WARMUP_TIMEOUT_SECS: u64 = 300; // Default time to keep retrying the warm-up request
+const WARMUP_RETRY_INTERVAL: Duration = Duration::from_secs(2); // Pause between warm-up attempts
+const DEFAULT_WAIT_FOR_BACKEND_TIMEOUT_SECS: u64 = 120; // Default time to wait for backends at startup
+const DEFAULT_WAIT_FOR_BACKEND_MAX_INTERVAL_SECS: u64 = 15; // Default cap on the pause between startup probes
+const WAIT_FOR_BACKEND_INITIAL_INTERVAL: Duration = Duration::from_secs(1); // First pause between startup probes
 
 /// Environment variables for configuring the LLM model:
 /// 
-/// - `MISTRAL_SERVER_URL`: URL of the mistral.rs server (default: "http://localhost:8081")
+/// - `MODEL_REGISTRY_PATH`: Path of the TOML model registry (default: "models.toml")
+/// - `MODEL_NAME`: Name of the implicit model when no registry file exists (default: "local-model")
+/// - `MISTRAL_SERVER_URL`: URL of the mistral.rs server, or a comma-separated list of replicas (default: "http://localhost:8081")
+/// - `LOAD_BALANCE_STRATEGY`: How requests are spread across replicas, "round_robin" or "least_in_flight" (default: "round_robin")
+/// - `HEALTH_CHECK_INTERVAL_SECS`: Seconds between backend health checks (default: 10)
+/// - `MAX_CONCURRENT_REQUESTS`: Generations in flight to the backends at once, across all models (default: 4)
+/// - `WARMUP`: Send each model a one-token request at startup, so the first user doesn't wait
+///   for the backend to load the model (default: false)
+/// - `WARMUP_TIMEOUT_SECS`: How long warm-up retries a backend that isn't ready yet (default: 300)
+/// - `WAIT_FOR_BACKEND`: Before serving, wait until every local model has a replica passing its
+///   health check, for container setups where the backend may start later (default: false)
+/// - `WAIT_FOR_BACKEND_TIMEOUT_SECS`: How long to wait before giving up and exiting (default: 120)
+/// - `WAIT_FOR_BACKEND_MAX_INTERVAL_SECS`: Longest pause between probes; pauses start at one
+///   second and double after each failed round (default: 15)
+/// - `PROMPT_CACHE_HINTS`: Keep the start of chat prompts identical across a session's turns,
+///   moving recalled memories next to the new message, and mark the cacheable prefix for
+///   backends that take explicit hints (Anthropic) (default: true)
+/// - `DETECT_CONTEXT_WINDOW`: Query the backend for its context length and use it instead of
+///   `MAX_CONTEXT_WINDOW` (default: true)
 /// - `MAX_CONTEXT_WINDOW`: Maximum context window size in tokens (default: 4096)
 /// - `SYSTEM_MESSAGE_RESERVE`: Tokens reserved for system message (default: 200)
 /// - `RESPONSE_RESERVE`: Tokens reserved for response (default: 500)
@@ -23,160 +87,607 @@ const DEFAULT_MAX_TOKENS: usize = 4096; // Default maximum tokens for response
 /// - `MAX_TOKENS`: Maximum tokens for response (default: 4096)
 /// - `TEMPERATURE`: Sampling temperature (default: 0.7)
 /// - `TOP_P`: Top-p sampling parameter (default: 0.95)
+/// - `PROMPT_PRICE_PER_1K`: Price of 1000 prompt tokens, for cost estimates (default: 0)
+/// - `COMPLETION_PRICE_PER_1K`: Price of 1000 completion tokens, for cost estimates (default: 0)
 /// 
 /// Note: All token-related values must be positive integers, and the following must hold:
 /// - MIN_TOKENS <= MAX_TOKENS
 /// - SYSTEM_MESSAGE_RESERVE + RESPONSE_RESERVE < MAX_CONTEXT_WINDOW
 /// - MAX_TOKENS <= MAX_CONTEXT_WINDOW
+/// 
+/// The token-related variables act as defaults for models in the registry that don't set
+/// their own limits (see `registry`).
+
+// Backend failures that callers report differently from other generation errors
+#[derive(Debug)]
+pub enum BackendError {
+    // No replica could be reached
+    Unreachable(String),
+    // The prompt doesn't fit in the model's context window
+    ContextOverflow(String),
+    // The backend asked us to slow down
+    RateLimited(String),
+    // Every replica's circuit breaker is open after repeated failures
+    CircuitOpen { model: String, retry_after_secs: u64 },
+}
+
+impl std::fmt::Display for BackendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackendError::Unreachable(msg) => write!(f, "Backend unreachable: {}", msg),
+            BackendError::ContextOverflow(msg) => write!(f, "Context window exceeded: {}", msg),
+            BackendError::RateLimited(msg) => write!(f, "Backend rate limited the request: {}", msg),
+            BackendError::CircuitOpen { model, retry_after_secs } => write!(
+                f, "Backends of model {} are failing; circuit breaker open, retry in {}s", model, retry_after_secs
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BackendError {}
+
+// Whether a backend error message complains about the prompt length
+fn is_context_overflow(message: &str) -> bool {
+    let message = message.to_lowercase();
+    [
+        "context length", "context window", "maximum context", "too many tokens", "exceeds the model",
+        "prompt is too long", "exceeds the maximum number of tokens",
+    ]
+        .iter()
+        .any(|needle| message.contains(needle))
+}
+
+fn prompt_cache_hints() -> bool {
+    env::var("PROMPT_CACHE_HINTS")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(true)
+}
+
+// Tags around the thinking of reasoning models that return it inline
+const THINK_OPEN: &str = "<think>";
+const THINK_CLOSE: &str = "</think>";
+
+// Separate a reasoning model's thinking from its answer. The thinking comes either in a
+// `reasoning_content` (or `reasoning`) field of the message, or as a <think> block leading
+// the content, whose opening tag is missing when the chat template already emitted it.
+fn split_reasoning(response_json: &Value, content: &str) -> (String, Option<String>) {
+    let message = &response_json["choices"][0]["message"];
+    let field = message["reasoning_content"].as_str().or_else(|| message["reasoning"].as_str());
+    if let Some(reasoning) = field.map(str::trim).filter(|reasoning| !reasoning.is_empty()) {
+        return (content.to_string(), Some(reasoning.to_string()));
+    }
+    
+    let Some(end) = content.find(THINK_CLOSE) else { return (content.to_string(), None) };
+    let head = content[..end].trim_start();
+    let reasoning = match head.strip_prefix(THINK_OPEN) {
+        Some(reasoning) => reasoning,
+        // Text before a <think> block is part of the answer, so leave it alone
+        None if head.contains(THINK_OPEN) => return (content.to_string(), None),
+        None => head,
+    };
+    let answer = content[end + THINK_CLOSE.len()..].trim_start().to_string();
+    let reasoning = reasoning.trim();
+    (answer, (!reasoning.is_empty()).then(|| reasoning.to_string()))
+}
+
+// Payload fields extra_body can't replace, as they carry the conversation itself
+const PROTECTED_PAYLOAD_FIELDS: [&str; 2] = ["messages", "prompt"];
+
+// Merge backend-specific parameters into an upstream payload, overriding what's there
+fn merge_extra_body(payload: &mut Value, extra_body: &Value) {
+    let (Some(payload), Some(extra_body)) = (payload.as_object_mut(), extra_body.as_object()) else { return };
+    for (key, value) in extra_body {
+        if !PROTECTED_PAYLOAD_FIELDS.contains(&key.as_str()) {
+            payload.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+// Generated text with the tokens it took; answers served from the cache cost none
+#[derive(Debug, Clone, Default)]
+pub struct Completion {
+    pub text: String,
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    // Prompt tokens the backend served from its prefix cache
+    pub cached_tokens: usize,
+    // Time spent waiting for a free generation slot
+    pub queue_wait: Duration,
+    // Thinking of a reasoning model, kept apart from `text`; not cached
+    pub reasoning: Option<String>,
+}
+
+// Tokens of a text; `tokens` is None when the backend couldn't be asked and the count is estimated
+#[derive(Debug, Clone)]
+pub struct Tokenization {
+    pub count: usize,
+    pub tokens: Option<Vec<u32>>,
+}
+
+// Per-request generation settings
+#[derive(Debug, Clone)]
+pub struct GenerationOptions {
+    pub max_tokens: usize,
+    pub use_cache: bool,
+    // Replaces the default system message when set
+    pub system_prompt: Option<String>,
+    // Appended to the system message, e.g. memories from earlier conversations
+    pub system_context: Option<String>,
+    // Few-shot exchanges sent after the system message, before the history
+    pub examples: Vec<Message>,
+    // JSON Schema the reply must follow, for backends that constrain their output to one
+    pub json_schema: Option<Value>,
+    // Override the TEMPERATURE / TOP_P defaults when set
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    // Caller-supplied user identifier, used for usage accounting
+    pub user: Option<String>,
+    // Backend-specific parameters for this request, applied over the model's own
+    pub extra_body: Option<Value>,
+    // How much a reasoning model thinks; either one replaces the model's own settings
+    pub reasoning_effort: Option<ReasoningEffort>,
+    pub thinking_budget_tokens: Option<usize>,
+    // Place in line when every generation slot is taken
+    pub priority: Priority,
+}
+
+impl GenerationOptions {
+    pub fn new(max_tokens: usize) -> Self {
+        Self {
+            max_tokens,
+            use_cache: true,
+            system_prompt: None,
+            system_context: None,
+            examples: Vec::new(),
+            json_schema: None,
+            temperature: None,
+            top_p: None,
+            user: None,
+            extra_body: None,
+            reasoning_effort: None,
+            thinking_budget_tokens: None,
+            priority: Priority::Normal,
+        }
+    }
+}
 
 // A wrapper for the mistral.rs server API
 pub struct LlamaModel {
-    server_url: String,
+    name: String,
+    backend_model: String,
+    protocol: BackendProtocol,
+    api_key: Option<String>,
+    safety_settings: Option<Value>,
+    pool: BackendPool,
     client: Client,
-    max_context_window: usize,
+    cache: Option<Arc<ResponseCache>>,
+    limiter: Arc<PriorityLimiter>,
+    filters: Arc<ResponseFilterChain>,
+    max_context_window: AtomicUsize,
     system_message_reserve: usize,
     response_reserve: usize,
     min_tokens: usize,
     max_tokens: usize,
+    truncation: TruncationStrategy,
+    pricing: Pricing,
+    temperature: f32,
+    top_p: f32,
+    stop: Vec<String>,
+    extra_body: Option<Value>,
+    reasoning_effort: Option<ReasoningEffort>,
+    thinking_budget_tokens: Option<usize>,
+    fold_system_prompt: bool,
+    chat_template: Option<ChatTemplate>,
+    fim: Option<FimTokens>,
+    speculative: Option<SpeculativeConfig>,
+    vram_mb: Option<u64>,
+    length_presets: LengthPresets,
+    // Drafted and accepted tokens reported by the backend, for the acceptance rate
+    draft_tokens: AtomicU64,
+    accepted_draft_tokens: AtomicU64,
 }
 
 impl LlamaModel {
-    pub async fn new() -> Result<Self> {
-        info!("Initializing connection to mistral.rs server");
+    pub fn new(
+        config: &ModelConfig,
+        client: Client,
+        cache: Option<Arc<ResponseCache>>,
+        limiter: Arc<PriorityLimiter>,
+        filters: Arc<ResponseFilterChain>,
+    ) -> Result<Self> {
+        info!("Initializing model {} on mistral.rs server", config.name);
         
-        // Get server URL from environment or use default
-        let server_url = env::var("MISTRAL_SERVER_URL")
-            .unwrap_or_else(|_| "http://localhost:8081".to_string());
-        
-        // Get token limits from environment or use defaults
-        let max_context_window = env::var("MAX_CONTEXT_WINDOW")
-            .ok()
-            .and_then(|v| v.parse::<usize>().ok())
-            .unwrap_or(DEFAULT_MAX_CONTEXT_WINDOW);
-            
-        let system_message_reserve = env::var("SYSTEM_MESSAGE_RESERVE")
-            .ok()
-            .and_then(|v| v.parse::<usize>().ok())
-            .unwrap_or(DEFAULT_SYSTEM_MESSAGE_RESERVE);
-            
-        let response_reserve = env::var("RESPONSE_RESERVE")
-            .ok()
-            .and_then(|v| v.parse::<usize>().ok())
-            .unwrap_or(DEFAULT_RESPONSE_RESERVE);
-            
-        let min_tokens = env::var("MIN_TOKENS")
-            .ok()
-            .and_then(|v| v.parse::<usize>().ok())
-            .unwrap_or(DEFAULT_MIN_TOKENS);
-            
-        let max_tokens = env::var("MAX_TOKENS")
-            .ok()
-            .and_then(|v| v.parse::<usize>().ok())
-            .unwrap_or(DEFAULT_MAX_TOKENS);
+        let ModelConfig {
+            max_context_window,
+            system_message_reserve,
+            response_reserve,
+            min_tokens,
+            max_tokens,
+            ..
+        } = *config;
         
         // Validate token limits
         if min_tokens > max_tokens {
-            error!("Invalid token limits: MIN_TOKENS ({}) > MAX_TOKENS ({})", min_tokens, max_tokens);
-            return Err(anyhow::anyhow!("Invalid token limits: MIN_TOKENS > MAX_TOKENS"));
+            error!("Invalid token limits for {}: MIN_TOKENS ({}) > MAX_TOKENS ({})", config.name, min_tokens, max_tokens);
+            return Err(anyhow::anyhow!("Invalid token limits for {}: MIN_TOKENS > MAX_TOKENS", config.name));
         }
         
         if max_tokens > max_context_window {
-            error!("Invalid token limits: MAX_TOKENS ({}) > MAX_CONTEXT_WINDOW ({})", max_tokens, max_context_window);
-            return Err(anyhow::anyhow!("Invalid token limits: MAX_TOKENS > MAX_CONTEXT_WINDOW"));
+            error!("Invalid token limits for {}: MAX_TOKENS ({}) > MAX_CONTEXT_WINDOW ({})", config.name, max_tokens, max_context_window);
+            return Err(anyhow::anyhow!("Invalid token limits for {}: MAX_TOKENS > MAX_CONTEXT_WINDOW", config.name));
         }
         
         let total_reserve = system_message_reserve + response_reserve;
         if total_reserve >= max_context_window {
-            error!("Invalid token limits: SYSTEM_MESSAGE_RESERVE ({}) + RESPONSE_RESERVE ({}) >= MAX_CONTEXT_WINDOW ({})", 
-                system_message_reserve, response_reserve, max_context_window);
-            return Err(anyhow::anyhow!("Invalid token limits: SYSTEM_MESSAGE_RESERVE + RESPONSE_RESERVE >= MAX_CONTEXT_WINDOW"));
+            error!("Invalid token limits for {}: SYSTEM_MESSAGE_RESERVE ({}) + RESPONSE_RESERVE ({}) >= MAX_CONTEXT_WINDOW ({})", 
+                config.name, system_message_reserve, response_reserve, max_context_window);
+            return Err(anyhow::anyhow!("Invalid token limits for {}: SYSTEM_MESSAGE_RESERVE + RESPONSE_RESERVE >= MAX_CONTEXT_WINDOW", config.name));
         }
         
         // Validate that we have enough space for at least one message
         let min_message_space = max_context_window - total_reserve;
         if min_message_space < 100 {
-            error!("Insufficient space for messages: MAX_CONTEXT_WINDOW ({}) - (SYSTEM_MESSAGE_RESERVE ({}) + RESPONSE_RESERVE ({})) = {} < 100", 
-                max_context_window, system_message_reserve, response_reserve, min_message_space);
-            return Err(anyhow::anyhow!("Insufficient space for messages: less than 100 tokens available after reserves"));
+            error!("Insufficient space for messages in {}: MAX_CONTEXT_WINDOW ({}) - (SYSTEM_MESSAGE_RESERVE ({}) + RESPONSE_RESERVE ({})) = {} < 100", 
+                config.name, max_context_window, system_message_reserve, response_reserve, min_message_space);
+            return Err(anyhow::anyhow!("Insufficient space for messages in {}: less than 100 tokens available after reserves", config.name));
         }
         
-        info!("Using mistral.rs server at: {}", server_url);
+        info!("Model {} using mistral.rs server(s) at: {} ({:?})", config.name, config.urls.join(", "), config.strategy);
         info!("Token limits - Context Window: {}, System Reserve: {}, Response Reserve: {}, Min Tokens: {}, Max Tokens: {}", 
             max_context_window, system_message_reserve, response_reserve, min_tokens, max_tokens);
         info!("Available space for messages: {} tokens", min_message_space);
+        if let Some(speculative) = &config.speculative {
+            info!("Model {} is decoded speculatively with draft model {} (gamma {}); the backend must be launched with the same draft model",
+                config.name, speculative.draft_model, speculative.gamma);
+        }
+        
+        let api_key = config.api_key_env
+            .as_deref()
+            .and_then(|var| env::var(var).ok())
+            .filter(|key| !key.is_empty());
+        if api_key.is_none() && !config.protocol.is_local() {
+            warn!("No API key for model {}; set {}", config.name, config.api_key_env.as_deref().unwrap_or("api_key_env"));
+        }
         
         Ok(Self {
-            server_url,
-            client: Client::new(),
-            max_context_window,
+            name: config.name.clone(),
+            backend_model: config.backend_model.clone(),
+            protocol: config.protocol,
+            api_key,
+            safety_settings: config.safety_settings.clone(),
+            pool: BackendPool::new(&config.urls, config.strategy),
+            client,
+            cache,
+            limiter,
+            filters,
+            max_context_window: AtomicUsize::new(max_context_window),
             system_message_reserve,
             response_reserve,
             min_tokens,
             max_tokens,
+            truncation: config.truncation,
+            pricing: config.pricing,
+            temperature: config.temperature,
+            top_p: config.top_p,
+            stop: config.stop.clone(),
+            extra_body: config.extra_body.clone(),
+            reasoning_effort: config.reasoning_effort,
+            thinking_budget_tokens: config.thinking_budget_tokens,
+            fold_system_prompt: config.fold_system_prompt,
+            chat_template: config.chat_template,
+            fim: config.fim.clone(),
+            speculative: config.speculative.clone(),
+            vram_mb: config.vram_mb,
+            length_presets: config.length_presets,
+            draft_tokens: AtomicU64::new(0),
+            accepted_draft_tokens: AtomicU64::new(0),
         })
     }
     
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    
+    pub fn context_window(&self) -> usize {
+        self.max_context_window.load(Ordering::SeqCst)
+    }
+    
+    // Ask the backend for the model's real context length and adopt it when it differs
+    pub async fn detect_context_window(&self) {
+        if !self.protocol.is_local() {
+            return;
+        }
+        let Some(backend) = self.pool.acquire() else {
+            warn!("Skipping context window detection for {}: no healthy backend", self.name);
+            return;
+        };
+        
+        let models: Value = match self.client.get(format!("{}/v1/models", backend.url()))
+            .send()
+            .await
+        {
+            Ok(response) if response.status().is_success() => match response.json().await {
+                Ok(json) => json,
+                Err(e) => {
+                    warn!("Failed to parse model list from {}: {}", backend.url(), e);
+                    return;
+                }
+            },
+            Ok(response) => {
+                warn!("Failed to query model list from {}: status {}", backend.url(), response.status());
+                return;
+            }
+            Err(e) => {
+                warn!("Failed to query model list from {}: {}", backend.url(), e);
+                return;
+            }
+        };
+        
+        let Some(entry) = Self::model_entry(&models, &self.backend_model) else {
+            info!("Backend {} lists no model named {}, keeping the context window of {}: {}",
+                backend.url(), self.backend_model, self.name, self.context_window());
+            return;
+        };
+        let Some(detected) = Self::context_length(entry) else {
+            info!("Backend {} doesn't report a context length for {}, keeping {}", 
+                backend.url(), self.name, self.context_window());
+            return;
+        };
+        
+        // Never shrink below what the reserves and max_tokens need
+        let minimum = (self.system_message_reserve + self.response_reserve + 100).max(self.max_tokens);
+        if detected < minimum {
+            warn!("Backend reports a context length of {} for {}, which is below the configured reserves/max tokens ({}); keeping {}", 
+                detected, self.name, minimum, self.context_window());
+            return;
+        }
+        
+        let configured = self.max_context_window.swap(detected, Ordering::SeqCst);
+        if configured != detected {
+            warn!("Configured context window for {} ({}) disagrees with backend ({}), using {}", 
+                self.name, configured, detected, detected);
+        } else {
+            info!("Context window for {} confirmed by backend: {}", self.name, detected);
+        }
+    }
+    
+    // The entry of an OpenAI-style `/v1/models` listing for the model. A listing of a single
+    // model is taken to describe it whatever its ID, but on a server with several models
+    // another model's entry would give the wrong window.
+    fn model_entry<'a>(models: &'a Value, backend_model: &str) -> Option<&'a Value> {
+        let entries = models.get("data")?.as_array()?;
+        entries
+            .iter()
+            .find(|m| m.get("id").and_then(|id| id.as_str()) == Some(backend_model))
+            .or_else(|| match entries.as_slice() {
+                [only] => Some(only),
+                _ => None,
+            })
+    }
+    
+    // Context length of a `/v1/models` entry; different servers report it under different keys
+    fn context_length(entry: &Value) -> Option<usize> {
+        ["max_model_len", "context_length", "max_context_length", "n_ctx"]
+            .iter()
+            .find_map(|key| entry.get(*key))
+            .or_else(|| entry.get("meta").and_then(|meta| meta.get("n_ctx_train")))
+            .and_then(|value| value.as_u64())
+            .map(|value| value as usize)
+    }
+    
+    pub fn backend_statuses(&self) -> Vec<BackendStatus> {
+        self.pool.statuses()
+    }
+    
+    // Summary of this model for the models listing endpoint
+    pub fn info(&self, is_default: bool) -> ModelInfo {
+        ModelInfo {
+            name: self.name.clone(),
+            context_window: self.context_window(),
+            max_tokens: self.max_tokens,
+            default: is_default,
+            speculative: self.speculative.clone(),
+            vram_mb: self.vram_mb,
+        }
+    }
+    
+    // Estimated VRAM of the loaded model in MiB, as configured in the registry
+    pub fn vram_mb(&self) -> Option<u64> {
+        self.vram_mb
+    }
+    
+    pub fn default_temperature(&self) -> f32 {
+        self.temperature
+    }
+    
+    pub fn default_top_p(&self) -> f32 {
+        self.top_p
+    }
+    
+    // Shape the messages and payload to this model's quirks before sending
+    fn prepare_payload(&self, mut messages: Vec<Message>, mut payload: Value) -> Value {
+        if self.fold_system_prompt && messages.first().is_some_and(|m| m.role == Role::System) {
+            let system = messages.remove(0).content;
+            match messages.iter_mut().find(|m| m.role == Role::User) {
+                Some(first_user) => first_user.content = format!("{}\n\n{}", system, first_user.content),
+                None => messages.insert(0, Message { role: Role::User, content: system }),
+            }
+        }
+        let mut stop = self.stop.clone();
+        match self.chat_template {
+            // Backends without a chat endpoint get the conversation rendered into one prompt
+            Some(template) => {
+                payload["prompt"] = json!(template.render(&messages));
+                stop.extend(template.stop_sequences().iter().map(|s| s.to_string()));
+            }
+            None => payload["messages"] = json!(messages),
+        }
+        if !stop.is_empty() {
+            payload["stop"] = json!(stop);
+        }
+        if let Some(extra_body) = &self.extra_body {
+            merge_extra_body(&mut payload, extra_body);
+        }
+        payload
+    }
+    
+    // Ask a reasoning model for the request's thinking effort, or the model's when the request sets none
+    fn apply_reasoning(&self, payload: &mut Value, options: &GenerationOptions) {
+        let (effort, budget) = if options.reasoning_effort.is_some() || options.thinking_budget_tokens.is_some() {
+            (options.reasoning_effort, options.thinking_budget_tokens)
+        } else {
+            (self.reasoning_effort, self.thinking_budget_tokens)
+        };
+        if let Some(effort) = effort {
+            payload["reasoning_effort"] = json!(effort);
+        }
+        if let Some(budget) = budget {
+            payload[THINKING_BUDGET_FIELD] = json!(budget);
+        }
+    }
+    
+    // Fill-in-the-middle prompt for the code around the cursor, or None when the model has no FIM tokens
+    pub fn fim_prompt(&self, prefix: &str, suffix: &str) -> Option<String> {
+        self.fim.as_ref().map(|fim| fim.render(prefix, suffix))
+    }
+    
+    // Estimated price of a generation, from the model's configured per-token prices
+    pub fn estimate_cost(&self, prompt_tokens: usize, completion_tokens: usize) -> f64 {
+        self.pricing.cost(prompt_tokens, completion_tokens)
+    }
+    
+    // Keep a requested max_tokens within this model's configured bounds
+    pub fn clamp_max_tokens(&self, max_tokens: usize) -> usize {
+        max_tokens.clamp(self.min_tokens, self.max_tokens)
+    }
+    
+    // max_tokens of a response length preset on this model
+    pub fn length_tokens(&self, length: ResponseLength) -> usize {
+        self.clamp_max_tokens(self.length_presets.tokens(length).unwrap_or(self.max_tokens))
+    }
+    
+    // Tokens a message without history may use, after the system message and response reserves
+    pub fn message_budget(&self) -> usize {
+        self.context_window().saturating_sub(self.system_message_reserve + self.response_reserve)
+    }
+    
     // Helper function to estimate token count (rough approximation)
-    fn estimate_tokens(text: &str) -> usize {
+    pub fn estimate_tokens(text: &str) -> usize {
         // Rough approximation: 1 token ≈ 4 characters
         // This is a simple estimation - in production you might want to use a proper tokenizer
         (text.len() / 4).max(1)
     }
 
-    pub async fn generate_response(&self, prompt: &str, max_tokens: usize, history: &[String]) -> Result<String> {
+    // Count the tokens of a text with the backend's tokenizer, falling back to the estimate
+    pub async fn tokenize(&self, text: &str) -> Tokenization {
+        let estimate = Tokenization { count: Self::estimate_tokens(text), tokens: None };
+        if !self.protocol.is_local() {
+            return estimate;
+        }
+        let Some(backend) = self.pool.acquire() else {
+            warn!("Estimating tokens for {}: no healthy backend", self.name);
+            return estimate;
+        };
+        
+        let request = self.client.post(format!("{}/tokenize", backend.url()));
+        let response = request_id::forward(request)
+            .json(&json!({
+                "model": self.backend_model,
+                "text": text,
+                "add_special_tokens": false,
+            }))
+            .send()
+            .await;
+        let tokens: Option<Vec<u32>> = match response {
+            Ok(response) if response.status().is_success() => response
+                .json::<Value>()
+                .await
+                .ok()
+                .and_then(|json| serde_json::from_value(json.get("tokens")?.clone()).ok()),
+            Ok(response) => {
+                debug!("Backend {} can't tokenize: status {}", backend.url(), response.status());
+                None
+            }
+            Err(e) => {
+                debug!("Backend {} can't tokenize: {}", backend.url(), e);
+                None
+            }
+        };
+        
+        match tokens {
+            Some(tokens) => Tokenization { count: tokens.len(), tokens: Some(tokens) },
+            None => estimate,
+        }
+    }
+
+    pub async fn generate_response(&self, prompt: &str, history: &[String], options: &GenerationOptions) -> Result<Completion> {
+        let result = self.generate(prompt, history, options).await;
+        if let Err(e) = &result {
+            stats().record_error(&self.name, &e.to_string());
+            metrics().increment("generation_errors_total", &[("model", self.name.as_str())]);
+        }
+        result
+    }
+    
+    async fn generate(&self, prompt: &str, history: &[String], options: &GenerationOptions) -> Result<Completion> {
+        let max_tokens = options.max_tokens;
         info!("Generating response for prompt with max_tokens: {}", max_tokens);
         debug!("Prompt: {}", prompt);
         
-        // Read configuration from environment
-        let temperature = env::var("TEMPERATURE").ok().and_then(|v| v.parse::<f32>().ok()).unwrap_or(0.7);
-        let top_p = env::var("TOP_P").ok().and_then(|v| v.parse::<f32>().ok()).unwrap_or(0.95);
+        // Read configuration from the request, falling back to the environment
+        let temperature = options.temperature.unwrap_or_else(|| self.default_temperature());
+        let top_p = options.top_p.unwrap_or_else(|| self.default_top_p());
         
         // Adjust max_tokens to be within configured bounds
-        let adjusted_max_tokens = if max_tokens < self.min_tokens {
-            info!("Increasing max_tokens from {} to minimum of {}", max_tokens, self.min_tokens);
-            self.min_tokens
-        } else if max_tokens > self.max_tokens {
-            info!("Capping max_tokens from {} to maximum of {}", max_tokens, self.max_tokens);
-            self.max_tokens
-        } else {
-            max_tokens
-        };
+        let adjusted_max_tokens = self.clamp_max_tokens(max_tokens);
+        if adjusted_max_tokens != max_tokens {
+            info!("Adjusted max_tokens from {} to {} (bounds {}..={})", 
+                max_tokens, adjusted_max_tokens, self.min_tokens, self.max_tokens);
+        }
         
         // Calculate available tokens for history
         let system_tokens = self.system_message_reserve;
         let response_tokens = self.response_reserve;
         let prompt_tokens = Self::estimate_tokens(prompt);
-        let available_history_tokens = self.max_context_window.saturating_sub(system_tokens + response_tokens + prompt_tokens);
+        // Few-shot examples are always sent, so they come out of the history's share
+        let example_tokens: usize = options.examples.iter().map(|m| Self::estimate_tokens(&m.content)).sum();
+        if system_tokens + response_tokens + example_tokens + prompt_tokens > self.context_window() {
+            return Err(BackendError::ContextOverflow(format!(
+                "the message and examples need about {} tokens but only {} are available",
+                prompt_tokens + example_tokens, self.context_window().saturating_sub(system_tokens + response_tokens)
+            )).into());
+        }
+        let available_history_tokens = self.context_window() - (system_tokens + response_tokens + example_tokens + prompt_tokens);
         
         // Create the message array starting with system message
+        let mut system_message = system_prompt::render(options.system_prompt.as_deref(), &PromptVariables {
+            user_name: options.user.as_deref(),
+            model_name: &self.name,
+            max_tokens: adjusted_max_tokens,
+        });
+        // Context that changes every turn goes with the new message when prefix caching is on,
+        // so the system message and history stay a prefix the backend has already processed
+        let cache_hints = prompt_cache_hints();
+        if let Some(context) = options.system_context.as_ref().filter(|_| !cache_hints) {
+            system_message.push_str("\n\n");
+            system_message.push_str(context);
+        }
         let mut messages = vec![
             Message {
                 role: Role::System,
-                content: format!("You are a helpful AI assistant. When responding to the user, please be thorough and detailed in your explanations. Aim to use close to the maximum token length of {} tokens when appropriate for the question.", adjusted_max_tokens),
+                content: system_message,
             }
         ];
+        messages.extend(options.examples.iter().cloned());
         
-        // Add conversation history with token limit
-        let mut total_history_tokens = 0;
-        let mut truncated_history = Vec::new();
-        
-        // Process history in reverse to keep most recent messages
-        for message in history.iter().rev() {
-            let message_tokens = Self::estimate_tokens(message);
-            
-            if total_history_tokens + message_tokens > available_history_tokens {
-                warn!("Conversation history truncated due to token limit. Available: {}, Needed: {}", 
-                    available_history_tokens, total_history_tokens + message_tokens);
-                break;
-            }
-            
-            total_history_tokens += message_tokens;
-            truncated_history.push(message.clone());
+        // Add conversation history with token limit, dropping messages as the strategy dictates
+        let truncated_history = self.truncation.apply(history, available_history_tokens, Self::estimate_tokens)?;
+        if truncated_history.len() < history.len() {
+            warn!("Conversation history truncated due to token limit ({:?}). Available: {}, kept {} of {} messages",
+                self.truncation, available_history_tokens, truncated_history.len(), history.len());
         }
         
-        // Reverse back to original order
-        truncated_history.reverse();
-        
         // Add truncated history to messages
         for message in truncated_history {
             let (role, content) = if message.starts_with("user: ") {
@@ -196,60 +707,566 @@ impl LlamaModel {
         // Add the current message
         messages.push(Message {
             role: Role::User,
-            content: prompt.to_string(),
+            content: match options.system_context.as_ref().filter(|_| cache_hints) {
+                Some(context) => format!("{}\n\n{}", context, prompt),
+                None => prompt.to_string(),
+            },
         });
         
+        let estimated_prompt_tokens: usize = messages
+            .iter()
+            .map(|m| Self::estimate_tokens(&m.content))
+            .sum();
+        
         // Create the request payload
-        let payload = json!({
-            "model": "local-model", // This is arbitrary for mistral.rs server
-            "messages": messages,
+        let mut payload = self.prepare_payload(messages, json!({
+            "model": self.backend_model,
             "temperature": temperature,
             "top_p": top_p,
             "max_tokens": adjusted_max_tokens
+        }));
+        self.apply_reasoning(&mut payload, options);
+        if cache_hints {
+            payload[PROMPT_CACHE_FIELD] = json!(true);
+        }
+        if let Some(schema) = &options.json_schema {
+            payload["response_format"] = json!({
+                "type": "json_schema",
+                "json_schema": { "name": "response", "schema": schema, "strict": true },
+            });
+        }
+        if let Some(extra_body) = &options.extra_body {
+            merge_extra_body(&mut payload, extra_body);
+        }
+        
+        // Serve repeated requests from the cache unless the caller bypasses it
+        let cache_key = ResponseCache::key(&payload);
+        if options.use_cache {
+            if let Some(cached) = self.cache.as_ref().and_then(|cache| cache.get(cache_key)) {
+                info!("Serving response for model {} from cache", self.name);
+                return Ok(Completion { text: cached, ..Default::default() });
+            }
+        }
+        
+        // In semantic mode, answers to sufficiently similar prompts in the same context are
+        // reused as well
+        let context_key = ResponseCache::context_key(&payload, prompt, options.user.as_deref());
+        let prompt_embedding = match &self.cache {
+            Some(cache) if options.use_cache && cache.is_semantic() => cache.embed(&self.client, prompt).await,
+            _ => None,
+        };
+        if let (Some(cache), Some(embedding)) = (&self.cache, &prompt_embedding) {
+            if let Some(cached) = cache.get_similar(&self.name, context_key, embedding) {
+                info!("Serving response for model {} from semantic cache", self.name);
+                return Ok(Completion { text: cached, ..Default::default() });
+            }
+        }
+        
+        info!("Sending request for model {} to mistral.rs server with max_tokens: {}", self.name, adjusted_max_tokens);
+        let (response_json, queue_wait) = self.send_request(self.chat_endpoint(), &payload, options.priority).await?;
+        
+        // Extract the generated text from the response
+        let content = Self::extract_content(&response_json)?;
+        
+        info!("Response length: {} characters", content.len());
+        
+        let (prompt_tokens, completion_tokens) =
+            self.record_usage(&payload, &response_json, content, estimated_prompt_tokens, options.user.as_deref());
+        
+        // Post-process the answer once, so cached and fresh answers match
+        let (content, reasoning) = split_reasoning(&response_json, content);
+        let content = self.filters.apply(&self.name, content);
+        
+        if let Some(cache) = &self.cache {
+            cache.insert(cache_key, content.clone(), options.user.as_deref());
+            if let Some(embedding) = prompt_embedding {
+                cache.insert_similar(&self.name, context_key, embedding, content.clone(), options.user.as_deref());
+            }
+        }
+        
+        Ok(Completion {
+            text: content,
+            prompt_tokens,
+            completion_tokens,
+            cached_tokens: self.cached_tokens(&response_json),
+            queue_wait,
+            reasoning,
+        })
+    }
+    
+    // Run a fixed list of messages without history, truncation or caching.
+    // Used for auxiliary calls such as classification.
+    pub async fn complete_messages(&self, messages: &[Message], max_tokens: usize, temperature: f32) -> Result<String> {
+        let payload = self.prepare_payload(messages.to_vec(), json!({
+            "model": self.backend_model,
+            "temperature": temperature,
+            "max_tokens": self.clamp_max_tokens(max_tokens),
+        }));
+        let (response_json, _) = self.send_request(self.chat_endpoint(), &payload, Priority::Normal).await?;
+        Ok(Self::extract_content(&response_json)?.to_string())
+    }
+    
+    // Plain text completion of a prompt, without chat formatting, history or caching.
+    // `stop` adds to the model's own stop sequences; with `echo` the reply starts with the prompt.
+    pub async fn complete_prompt(&self, prompt: &str, options: &GenerationOptions, stop: &[String], echo: bool) -> Result<Completion> {
+        let result = self.complete_prompt_inner(prompt, options, stop, echo).await;
+        if let Err(e) = &result {
+            stats().record_error(&self.name, &e.to_string());
+            metrics().increment("generation_errors_total", &[("model", self.name.as_str())]);
+        }
+        result
+    }
+    
+    async fn complete_prompt_inner(&self, prompt: &str, options: &GenerationOptions, stop: &[String], echo: bool) -> Result<Completion> {
+        let max_tokens = self.clamp_max_tokens(options.max_tokens);
+        let estimated_prompt_tokens = Self::estimate_tokens(prompt);
+        if estimated_prompt_tokens + max_tokens > self.context_window() {
+            return Err(BackendError::ContextOverflow(format!(
+                "the prompt needs about {} tokens but only {} are available after max_tokens",
+                estimated_prompt_tokens, self.context_window().saturating_sub(max_tokens)
+            )).into());
+        }
+        
+        let stop: Vec<&String> = self.stop.iter().chain(stop).collect();
+        let mut payload = json!({
+            "model": self.backend_model,
+            "prompt": prompt,
+            "temperature": options.temperature.unwrap_or_else(|| self.default_temperature()),
+            "top_p": options.top_p.unwrap_or_else(|| self.default_top_p()),
+            "max_tokens": max_tokens,
+            "echo": echo,
         });
+        if !stop.is_empty() {
+            payload["stop"] = json!(stop);
+        }
+        for extra_body in [&self.extra_body, &options.extra_body].into_iter().flatten() {
+            merge_extra_body(&mut payload, extra_body);
+        }
+        
+        info!("Sending completion request for model {} with max_tokens: {}", self.name, max_tokens);
+        let (response_json, queue_wait) = self.send_request("v1/completions", &payload, options.priority).await?;
+        let content = Self::extract_content(&response_json)?;
+        let (prompt_tokens, completion_tokens) =
+            self.record_usage(&payload, &response_json, content, estimated_prompt_tokens, options.user.as_deref());
+        
+        Ok(Completion {
+            text: content.to_string(),
+            prompt_tokens,
+            completion_tokens,
+            cached_tokens: self.cached_tokens(&response_json),
+            queue_wait,
+            reasoning: None,
+        })
+    }
+    
+    // Account a finished generation in the stats, metrics and prompt log; returns the
+    // prompt and completion tokens, preferring the backend's counts to our estimates
+    fn record_usage(
+        &self,
+        payload: &Value,
+        response_json: &Value,
+        content: &str,
+        estimated_prompt_tokens: usize,
+        user: Option<&str>,
+    ) -> (usize, usize) {
+        let usage = response_json.get("usage");
+        let prompt_tokens = usage
+            .and_then(|u| u.get("prompt_tokens"))
+            .and_then(|t| t.as_u64())
+            .map(|t| t as usize)
+            .unwrap_or(estimated_prompt_tokens);
+        let completion_tokens = usage
+            .and_then(|u| u.get("completion_tokens"))
+            .and_then(|t| t.as_u64())
+            .map(|t| t as usize)
+            .unwrap_or_else(|| Self::estimate_tokens(content));
+        stats().record_completion(user, prompt_tokens, completion_tokens);
+        metrics().add("prompt_tokens_total", &[("model", self.name.as_str())], prompt_tokens as f64);
+        metrics().add("completion_tokens_total", &[("model", self.name.as_str())], completion_tokens as f64);
+        self.record_speculation(response_json);
+        
+        if prompt_log().sample() {
+            // Plain prompts, including those rendered with a chat template, are logged as they were sent
+            let messages = match payload.get("prompt") {
+                Some(prompt) => vec![json!({ "role": "prompt", "content": prompt })],
+                None => payload["messages"].as_array().cloned().unwrap_or_default(),
+            };
+            prompt_log().record(&self.name, user, &messages, content, prompt_tokens, completion_tokens);
+        }
+        (prompt_tokens, completion_tokens)
+    }
+    
+    // Prompt tokens the backend reused from its cache: OpenAI-style `prompt_tokens_details`,
+    // into which the other protocols are translated, or llama.cpp's `timings`
+    fn cached_tokens(&self, response_json: &Value) -> usize {
+        let cached = response_json["usage"]["prompt_tokens_details"]["cached_tokens"]
+            .as_u64()
+            .or_else(|| response_json["timings"]["cache_n"].as_u64())
+            .unwrap_or(0);
+        if cached > 0 {
+            metrics().add("cached_prompt_tokens_total", &[("model", self.name.as_str())], cached as f64);
+        }
+        cached as usize
+    }
+    
+    // Count drafted and accepted tokens when the backend reports them, as llama.cpp does in
+    // `timings`; the acceptance rate is the share of drafted tokens the model kept so far
+    fn record_speculation(&self, response_json: &Value) {
+        let timings = &response_json["timings"];
+        let (Some(drafted), Some(accepted)) = (timings["draft_n"].as_u64(), timings["draft_n_accepted"].as_u64()) else {
+            return;
+        };
+        if drafted == 0 {
+            return;
+        }
+        let labels = [("model", self.name.as_str())];
+        metrics().add("speculative_draft_tokens_total", &labels, drafted as f64);
+        metrics().add("speculative_accepted_tokens_total", &labels, accepted as f64);
+        let drafted = self.draft_tokens.fetch_add(drafted, Ordering::SeqCst) + drafted;
+        let accepted = self.accepted_draft_tokens.fetch_add(accepted, Ordering::SeqCst) + accepted;
+        metrics().set("speculative_acceptance_rate", &labels, accepted as f64 / drafted as f64);
+    }
+    
+    // Endpoint serving chat: models with a chat template go to the plain completions endpoint
+    fn chat_endpoint(&self) -> &'static str {
+        if self.chat_template.is_some() { "v1/completions" } else { "v1/chat/completions" }
+    }
+    
+    // Send a completion payload to a healthy replica and return the parsed response, along
+    // with the time the request waited for a generation slot
+    async fn send_request(&self, endpoint: &str, payload: &Value, priority: Priority) -> Result<(Value, Duration)> {
+        // Recorded responses stand in for the backend entirely
+        if replay().is_replaying() {
+            return Ok((replay().lookup(endpoint, payload)?, Duration::ZERO));
+        }
+        
+        // Wait for a slot so bursts (e.g. batches) don't overwhelm the backends
+        let queued_at = Instant::now();
+        let _permit = self.limiter.acquire(priority).await;
+        let queue_wait = queued_at.elapsed();
+        metrics().add("queue_wait_seconds_total", &[("priority", priority.as_str())], queue_wait.as_secs_f64());
         
-        info!("Sending request to mistral.rs server with max_tokens: {}", adjusted_max_tokens);
         debug!("Payload: {}", payload);
         
-        // Send the request to the server
-        let response = self.client.post(&format!("{}/v1/chat/completions", self.server_url))
-            .json(&payload)
+        // Pick a replica to serve this request
+        let Some(mut backend) = self.pool.acquire() else {
+            // Fail fast while the breakers give the backends time to recover
+            if let Some(wait) = self.pool.circuit_retry_after() {
+                return Err(BackendError::CircuitOpen {
+                    model: self.name.clone(),
+                    retry_after_secs: wait.as_secs().max(1),
+                }.into());
+            }
+            return Err(BackendError::Unreachable(format!("No healthy backend available for model {}", self.name)).into());
+        };
+        debug!("Using backend {}", backend.url());
+        
+        // Send the request to the server, in the API it speaks
+        let upstream = self.protocol.request(
+            backend.url(),
+            endpoint,
+            payload,
+            self.api_key.as_deref(),
+            self.safety_settings.as_ref(),
+        )?;
+        let mut request = self.client.post(upstream.url);
+        for (name, value) in &upstream.headers {
+            request = request.header(*name, value);
+        }
+        let response = match request_id::forward(request)
+            .json(&upstream.body)
             .send()
-            .await?;
+            .await
+        {
+            Ok(response) => response,
+            Err(e) => {
+                backend.record_failure();
+                // Unreachable replicas are taken out of rotation until they pass a health check
+                if e.is_connect() || e.is_timeout() {
+                    backend.mark_unhealthy();
+                    return Err(BackendError::Unreachable(format!("{}: {}", backend.url(), e)).into());
+                }
+                return Err(e.into());
+            }
+        };
         
-        if !response.status().is_success() {
+        let status = response.status();
+        if !status.is_success() {
+            // Overload and bad requests don't mean the backend is broken
+            if status.is_server_error() && status.as_u16() != 529 {
+                backend.record_failure();
+            } else {
+                backend.record_success();
+            }
             let error_text = response.text().await?;
+            // Anthropic answers 529 when it is overloaded
+            if status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.as_u16() == 529 {
+                return Err(BackendError::RateLimited(error_text).into());
+            }
+            if status.is_client_error() && is_context_overflow(&error_text) {
+                return Err(BackendError::ContextOverflow(error_text).into());
+            }
             return Err(anyhow::anyhow!("API request failed: {}", error_text));
         }
         
+        backend.record_success();
+        
         // Parse the response
-        let response_json: Value = response.json().await?;
+        let response_json: Value = self.protocol.response(response.json().await?)?;
         debug!("Response JSON: {}", response_json);
-        
-        // Extract the generated text from the response
-        let content = response_json
+        replay().record(endpoint, payload, &response_json);
+        Ok((response_json, queue_wait))
+    }
+    
+    // Extract the generated text from a chat completion or plain completion response
+    fn extract_content(response_json: &Value) -> Result<&str> {
+        let choice = response_json
             .get("choices")
-            .and_then(|choices| choices.get(0))
+            .and_then(|choices| choices.get(0));
+        choice
             .and_then(|choice| choice.get("message"))
             .and_then(|message| message.get("content"))
+            .or_else(|| choice.and_then(|choice| choice.get("text")))
             .and_then(|content| content.as_str())
-            .ok_or_else(|| anyhow::anyhow!("Failed to extract content from response"))?;
-        
-        info!("Response length: {} characters", content.len());
-        Ok(content.to_string())
+            .ok_or_else(|| anyhow::anyhow!("Failed to extract content from response"))
     }
 }
 
-// Singleton instance for the model
+// Registry of the configured models
 pub struct ModelManager {
-    pub model: Arc<LlamaModel>,
+    models: Vec<Arc<LlamaModel>>,
+    // Starts as the registry's default; an admin can activate another registered model
+    default_model: RwLock<String>,
+    cache: Option<Arc<ResponseCache>>,
+    limiter: Arc<PriorityLimiter>,
+    max_concurrent: usize,
 }
 
 impl ModelManager {
     pub async fn new() -> Result<Self> {
-        let model = LlamaModel::new().await?;
+        let registry = ModelRegistry::load()?;
+        let client = http_client().clone();
+        let cache = ResponseCache::from_env().map(Arc::new);
+        let max_concurrent = env::var("MAX_CONCURRENT_REQUESTS")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_MAX_CONCURRENT_REQUESTS)
+            .max(1);
+        let limiter = PriorityLimiter::new(max_concurrent);
+        let filters = Arc::new(ResponseFilterChain::load()?);
+        
+        let models = registry.models
+            .iter()
+            .map(|config| {
+                LlamaModel::new(config, client.clone(), cache.clone(), limiter.clone(), filters.clone()).map(Arc::new)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        
+        info!("Registered {} model(s), default: {}", models.len(), registry.default_model);
+        
         Ok(Self {
-            model: Arc::new(model),
+            models,
+            default_model: RwLock::new(registry.default_model),
+            cache,
+            limiter,
+            max_concurrent,
         })
     }
-} 
\ No newline at end of file
+    
+    // Look up a model by name, or the default model when no name is given
+    pub fn get(&self, name: Option<&str>) -> Option<Arc<LlamaModel>> {
+        let default_model;
+        let name = match name {
+            Some(name) => name,
+            None => {
+                default_model = self.default_model();
+                &default_model
+            }
+        };
+        self.models.iter().find(|m| m.name == name).cloned()
+    }
+    
+    // Response cache shared by all models, if enabled
+    pub fn cache(&self) -> Option<&ResponseCache> {
+        self.cache.as_deref()
+    }
+    
+    pub fn default_model(&self) -> String {
+        self.default_model.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+    
+    // Serve requests that don't name a model with another registered model, returning the
+    // previous default. Requests already running finish on the model they started with.
+    pub fn activate(&self, name: &str) -> Option<String> {
+        self.models.iter().find(|m| m.name == name)?;
+        let mut default_model = self.default_model.write().unwrap_or_else(PoisonError::into_inner);
+        let previous = std::mem::replace(&mut *default_model, name.to_string());
+        info!("Default model switched from {} to {}", previous, name);
+        Some(previous)
+    }
+    
+    pub fn models(&self) -> &[Arc<LlamaModel>] {
+        &self.models
+    }
+    
+    pub fn max_concurrent(&self) -> usize {
+        self.max_concurrent
+    }
+    
+    // Generations currently holding a concurrency slot
+    pub fn in_flight(&self) -> usize {
+        self.max_concurrent.saturating_sub(self.limiter.available_permits())
+    }
+    
+    // Generations waiting for a concurrency slot, per priority
+    pub fn waiting(&self) -> HashMap<Priority, usize> {
+        self.limiter.waiting()
+    }
+    
+    // Detect the context window of every model whose backend reports one
+    pub async fn detect_context_windows(&self) {
+        let enabled = env::var("DETECT_CONTEXT_WINDOW")
+            .ok()
+            .and_then(|v| v.parse::<bool>().ok())
+            .unwrap_or(true);
+        if !enabled {
+            return;
+        }
+        
+        for model in &self.models {
+            model.detect_context_window().await;
+        }
+    }
+    
+    // Probe the backends of local models with exponential backoff until each model has a
+    // healthy replica, failing once the deadline passes
+    pub async fn wait_for_backends(&self) -> Result<()> {
+        let enabled = env::var("WAIT_FOR_BACKEND")
+            .ok()
+            .and_then(|v| v.parse::<bool>().ok())
+            .unwrap_or(false);
+        if !enabled {
+            return Ok(());
+        }
+        let timeout = Duration::from_secs(
+            env::var("WAIT_FOR_BACKEND_TIMEOUT_SECS")
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(DEFAULT_WAIT_FOR_BACKEND_TIMEOUT_SECS)
+        );
+        let max_interval = Duration::from_secs(
+            env::var("WAIT_FOR_BACKEND_MAX_INTERVAL_SECS")
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(DEFAULT_WAIT_FOR_BACKEND_MAX_INTERVAL_SECS)
+                .max(1)
+        );
+        
+        let started = Instant::now();
+        let mut interval = WAIT_FOR_BACKEND_INITIAL_INTERVAL;
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            // Cloud APIs have no health endpoint to probe
+            let mut waiting = Vec::new();
+            for model in self.models.iter().filter(|model| model.protocol.is_local()) {
+                model.pool.check_health(&model.client).await;
+                if !model.backend_statuses().iter().any(|backend| backend.healthy) {
+                    waiting.push(model.name.as_str());
+                }
+            }
+            if waiting.is_empty() {
+                if attempts > 1 {
+                    info!("Backends ready after {:.1}s ({} attempt(s))", started.elapsed().as_secs_f64(), attempts);
+                }
+                return Ok(());
+            }
+            
+            let elapsed = started.elapsed();
+            if elapsed >= timeout {
+                return Err(anyhow::anyhow!(
+                    "No backend of model(s) {} became reachable within {}s",
+                    waiting.join(", "), timeout.as_secs()
+                ));
+            }
+            let pause = interval.min(timeout - elapsed);
+            info!("Waiting for backend of model(s) {}; retrying in {}s", waiting.join(", "), pause.as_secs_f64().ceil());
+            tokio::time::sleep(pause).await;
+            interval = (interval * 2).min(max_interval);
+        }
+    }
+    
+    // Send each model a one-token request before serving traffic, retrying while its backend
+    // is still starting, and log how long the model took to answer
+    pub async fn warm_up(&self) {
+        let enabled = env::var("WARMUP")
+            .ok()
+            .and_then(|v| v.parse::<bool>().ok())
+            .unwrap_or(false);
+        if !enabled {
+            return;
+        }
+        let timeout = Duration::from_secs(
+            env::var("WARMUP_TIMEOUT_SECS")
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(DEFAULT_WARMUP_TIMEOUT_SECS)
+        );
+        
+        for model in &self.models {
+            let messages = vec![Message { role: Role::User, content: "Hi".to_string() }];
+            let payload = model.prepare_payload(messages, json!({
+                "model": model.backend_model,
+                "temperature": 0.0,
+                "max_tokens": 1,
+            }));
+            
+            let started = Instant::now();
+            let mut attempts = 0;
+            loop {
+                attempts += 1;
+                match model.send_request(model.chat_endpoint(), &payload, Priority::Normal).await {
+                    Ok(_) => {
+                        info!("Warmed up model {} in {:.1}s ({} attempt(s))", model.name, started.elapsed().as_secs_f64(), attempts);
+                        break;
+                    }
+                    Err(e) if started.elapsed() < timeout => {
+                        debug!("Warm-up of model {} failed, retrying: {}", model.name, e);
+                        tokio::time::sleep(WARMUP_RETRY_INTERVAL).await;
+                    }
+                    Err(e) => {
+                        warn!("Giving up warming up model {} after {}s: {}", model.name, timeout.as_secs(), e);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    
+    // Periodically probe every backend so failed replicas are re-added once they recover
+    pub fn start_health_checks(&self) {
+        let interval = env::var("HEALTH_CHECK_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_HEALTH_CHECK_INTERVAL_SECS);
+        let detect_context = env::var("DETECT_CONTEXT_WINDOW")
+            .ok()
+            .and_then(|v| v.parse::<bool>().ok())
+            .unwrap_or(true);
+        let models = self.models.clone();
+        
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(Duration::from_secs(interval.max(1)));
+            loop {
+                ticker.tick().await;
+                // Cloud APIs have no health endpoint to probe
+                for model in models.iter().filter(|model| model.protocol.is_local()) {
+                    // A backend coming back may have been restarted with a different model
+                    if model.pool.check_health(&model.client).await && detect_context {
+                        model.detect_context_window().await;
+                    }
+                }
+            }
+        });
+    }
+}
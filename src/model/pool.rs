@@ -0,0 +1,335 @@
+use std::env;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use log::{info, warn};
+
+use crate::metrics::metrics;
+
+// Timeout for a single backend health probe
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+// Defaults for the per-backend circuit breaker
+const DEFAULT_CIRCUIT_BREAKER_THRESHOLD: u32 = 5;
+const DEFAULT_CIRCUIT_BREAKER_COOLDOWN_SECS: u64 = 30;
+
+/// Environment variables for the circuit breaker around each backend replica:
+///
+/// - `CIRCUIT_BREAKER_THRESHOLD`: Consecutive failed requests (unreachable, timed out or 5xx)
+///   that open a replica's breaker, 0 to disable the breaker (default: 5)
+/// - `CIRCUIT_BREAKER_COOLDOWN_SECS`: Seconds an open breaker rejects requests before letting a
+///   single probe request through (default: 30)
+///
+/// A successful probe closes the breaker; a failed one opens it for another cooldown. While
+/// every replica of a model is open, requests fail at once with `circuit_open` instead of
+/// waiting for the backend to time out.
+
+// How requests are spread across the replicas of a model
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BalanceStrategy {
+    #[default]
+    RoundRobin,
+    LeastInFlight,
+}
+
+impl FromStr for BalanceStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "round_robin" => Ok(Self::RoundRobin),
+            "least_in_flight" => Ok(Self::LeastInFlight),
+            other => Err(anyhow::anyhow!("Unknown load balancing strategy: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BreakerConfig {
+    // 0 disables the breaker
+    threshold: u32,
+    cooldown: Duration,
+}
+
+impl BreakerConfig {
+    fn from_env() -> Self {
+        Self {
+            threshold: env::var("CIRCUIT_BREAKER_THRESHOLD")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_CIRCUIT_BREAKER_THRESHOLD),
+            cooldown: Duration::from_secs(
+                env::var("CIRCUIT_BREAKER_COOLDOWN_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(DEFAULT_CIRCUIT_BREAKER_COOLDOWN_SECS),
+            ),
+        }
+    }
+}
+
+struct Breaker {
+    state: CircuitState,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    // Whether the single half-open probe is in flight
+    probing: bool,
+}
+
+impl Breaker {
+    // Whether a request may be sent now, without claiming anything
+    fn admits(&self, cooldown: Duration) -> bool {
+        match self.state {
+            CircuitState::Closed => true,
+            CircuitState::Open => self.opened_at.map_or(true, |opened| opened.elapsed() >= cooldown),
+            CircuitState::HalfOpen => !self.probing,
+        }
+    }
+
+    // Time until an open breaker lets a probe through
+    fn retry_after(&self, cooldown: Duration) -> Option<Duration> {
+        match self.state {
+            CircuitState::Open => self.opened_at.map(|opened| cooldown.saturating_sub(opened.elapsed())),
+            CircuitState::HalfOpen => Some(Duration::ZERO),
+            CircuitState::Closed => None,
+        }
+    }
+}
+
+// A single mistral.rs replica
+struct Backend {
+    url: String,
+    healthy: AtomicBool,
+    in_flight: AtomicUsize,
+    breaker: Mutex<Breaker>,
+}
+
+impl Backend {
+    fn breaker(&self) -> std::sync::MutexGuard<'_, Breaker> {
+        self.breaker.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn report_circuit(&self, state: CircuitState) {
+        let labels = [("backend", self.url.as_str())];
+        metrics().set("circuit_breaker_open", &labels, if state == CircuitState::Closed { 0.0 } else { 1.0 });
+    }
+}
+
+// Health and load of a backend, as reported by the health endpoint
+#[derive(Debug, Serialize)]
+pub struct BackendStatus {
+    pub url: String,
+    pub healthy: bool,
+    pub in_flight: usize,
+    pub circuit: CircuitState,
+    pub consecutive_failures: u32,
+}
+
+// Handle on a selected backend; counts as in-flight until dropped
+pub struct BackendGuard {
+    backend: Arc<Backend>,
+    config: BreakerConfig,
+    // Whether this request is the probe of a half-open breaker
+    probe: bool,
+}
+
+impl BackendGuard {
+    pub fn url(&self) -> &str {
+        &self.backend.url
+    }
+
+    // The backend answered; closes its breaker
+    pub fn record_success(&mut self) {
+        let mut breaker = self.backend.breaker();
+        breaker.consecutive_failures = 0;
+        if breaker.state != CircuitState::Closed {
+            info!("Circuit breaker for backend {} closed", self.backend.url);
+            breaker.state = CircuitState::Closed;
+            breaker.opened_at = None;
+            self.backend.report_circuit(CircuitState::Closed);
+        }
+        breaker.probing = false;
+        self.probe = false;
+    }
+
+    // The backend failed or timed out; opens its breaker after too many failures in a row,
+    // or at once when the failed request was the half-open probe
+    pub fn record_failure(&mut self) {
+        if self.config.threshold == 0 {
+            return;
+        }
+        let mut breaker = self.backend.breaker();
+        breaker.consecutive_failures = breaker.consecutive_failures.saturating_add(1);
+        let trip = breaker.state == CircuitState::HalfOpen
+            || (breaker.state == CircuitState::Closed && breaker.consecutive_failures >= self.config.threshold);
+        if trip {
+            warn!(
+                "Circuit breaker for backend {} opened after {} consecutive failure(s); retrying in {}s",
+                self.backend.url, breaker.consecutive_failures, self.config.cooldown.as_secs()
+            );
+            breaker.state = CircuitState::Open;
+            breaker.opened_at = Some(Instant::now());
+            metrics().increment("circuit_breaker_trips_total", &[("backend", self.backend.url.as_str())]);
+            self.backend.report_circuit(CircuitState::Open);
+        }
+        breaker.probing = false;
+        self.probe = false;
+    }
+
+    // Take the backend out of rotation until the next successful health check
+    pub fn mark_unhealthy(&self) {
+        if self.backend.healthy.swap(false, Ordering::SeqCst) {
+            warn!("Backend {} marked unhealthy after a failed request", self.backend.url);
+        }
+    }
+}
+
+impl Drop for BackendGuard {
+    fn drop(&mut self) {
+        self.backend.in_flight.fetch_sub(1, Ordering::SeqCst);
+        // A probe that ended without an outcome, e.g. a cancelled request, lets the next one try
+        if self.probe {
+            self.backend.breaker().probing = false;
+        }
+    }
+}
+
+// The replicas serving one logical model
+pub struct BackendPool {
+    backends: Vec<Arc<Backend>>,
+    strategy: BalanceStrategy,
+    next: AtomicUsize,
+    breaker: BreakerConfig,
+}
+
+impl BackendPool {
+    pub fn new(urls: &[String], strategy: BalanceStrategy) -> Self {
+        let backends = urls
+            .iter()
+            .map(|url| Arc::new(Backend {
+                url: url.clone(),
+                healthy: AtomicBool::new(true),
+                in_flight: AtomicUsize::new(0),
+                breaker: Mutex::new(Breaker {
+                    state: CircuitState::Closed,
+                    consecutive_failures: 0,
+                    opened_at: None,
+                    probing: false,
+                }),
+            }))
+            .collect();
+
+        Self {
+            backends,
+            strategy,
+            next: AtomicUsize::new(0),
+            breaker: BreakerConfig::from_env(),
+        }
+    }
+
+    // Pick a healthy backend according to the balancing strategy
+    pub fn acquire(&self) -> Option<BackendGuard> {
+        let count = self.backends.len();
+        if count == 0 {
+            return None;
+        }
+
+        // Start scanning from a rotating offset so ties are spread evenly
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % count;
+        let mut healthy = (0..count)
+            .map(|i| &self.backends[(start + i) % count])
+            .filter(|b| b.healthy.load(Ordering::SeqCst) && b.breaker().admits(self.breaker.cooldown));
+
+        let backend = match self.strategy {
+            BalanceStrategy::RoundRobin => healthy.next(),
+            BalanceStrategy::LeastInFlight => healthy.min_by_key(|b| b.in_flight.load(Ordering::SeqCst)),
+        }?;
+
+        // An open breaker whose cooldown is over lets this request through as its probe
+        let probe = {
+            let mut breaker = backend.breaker();
+            match breaker.state {
+                CircuitState::Closed => false,
+                _ if breaker.probing => return None,
+                _ => {
+                    if breaker.state == CircuitState::Open {
+                        info!("Circuit breaker for backend {} half-open, sending a probe request", backend.url);
+                        breaker.state = CircuitState::HalfOpen;
+                        backend.report_circuit(CircuitState::HalfOpen);
+                    }
+                    breaker.probing = true;
+                    true
+                }
+            }
+        };
+
+        backend.in_flight.fetch_add(1, Ordering::SeqCst);
+        Some(BackendGuard {
+            backend: backend.clone(),
+            config: self.breaker,
+            probe,
+        })
+    }
+
+    // After `acquire` found no replica: how long until an open breaker lets a probe through,
+    // or None when no breaker is open and the replicas are simply down
+    pub fn circuit_retry_after(&self) -> Option<Duration> {
+        self.backends
+            .iter()
+            .filter_map(|b| b.breaker().retry_after(self.breaker.cooldown))
+            .min()
+    }
+
+    // Probe every backend and update its health, re-adding recovered nodes to rotation.
+    // Returns true when at least one backend recovered.
+    pub async fn check_health(&self, client: &Client) -> bool {
+        let mut recovered = false;
+        for backend in &self.backends {
+            let healthy = match client
+                .get(format!("{}/health", backend.url))
+                .timeout(HEALTH_CHECK_TIMEOUT)
+                .send()
+                .await
+            {
+                Ok(response) => response.status().is_success(),
+                Err(_) => false,
+            };
+
+            let was_healthy = backend.healthy.swap(healthy, Ordering::SeqCst);
+            if healthy && !was_healthy {
+                info!("Backend {} is healthy again, returning it to rotation", backend.url);
+                recovered = true;
+            } else if !healthy && was_healthy {
+                warn!("Backend {} failed its health check, removing it from rotation", backend.url);
+            }
+        }
+        recovered
+    }
+
+    pub fn statuses(&self) -> Vec<BackendStatus> {
+        self.backends
+            .iter()
+            .map(|b| {
+                let breaker = b.breaker();
+                BackendStatus {
+                    url: b.url.clone(),
+                    healthy: b.healthy.load(Ordering::SeqCst),
+                    in_flight: b.in_flight.load(Ordering::SeqCst),
+                    circuit: breaker.state,
+                    consecutive_failures: breaker.consecutive_failures,
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,176 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::env;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+/// Environment variables for request prioritization:
+///
+/// - `PRIORITY_KEYS`: Comma-separated `key=priority` pairs giving callers a fixed tier, e.g.
+///   "k3y-dashboard=high,k3y-reports=low". A request presenting a key as
+///   `Authorization: Bearer <key>` (or as the Basic auth password) gets its tier in place of
+///   the endpoint's own priority (default: none)
+///
+/// When every generation slot (`MAX_CONCURRENT_REQUESTS`) is taken, waiting requests get the
+/// next free slot by priority, then in arrival order. Interactive endpoints (chat, completions,
+/// fill-in-the-middle) are `high`, batches, async jobs and scheduled prompts are `low`, and
+/// everything else is `normal`. Requests already running are never interrupted.
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        }
+    }
+}
+
+impl std::str::FromStr for Priority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "low" => Ok(Priority::Low),
+            "normal" => Ok(Priority::Normal),
+            "high" => Ok(Priority::High),
+            other => Err(format!("Unknown priority: {}", other)),
+        }
+    }
+}
+
+static PRIORITY_KEYS: OnceLock<Vec<(String, Priority)>> = OnceLock::new();
+
+// API keys and the tier each one grants
+pub fn priority_keys() -> &'static [(String, Priority)] {
+    PRIORITY_KEYS.get_or_init(|| {
+        env::var("PRIORITY_KEYS")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|pair| {
+                let (key, priority) = pair.rsplit_once('=')?;
+                let key = key.trim();
+                if key.is_empty() {
+                    return None;
+                }
+                Some((key.to_string(), priority.parse().ok()?))
+            })
+            .collect()
+    })
+}
+
+// Highest priority first, then oldest first
+type WaiterKey = (Reverse<Priority>, u64);
+
+struct LimiterState {
+    available: usize,
+    next_seq: u64,
+    waiting: BTreeMap<WaiterKey, oneshot::Sender<()>>,
+}
+
+// Counting semaphore handing free slots to the highest-priority waiter
+pub struct PriorityLimiter {
+    state: Mutex<LimiterState>,
+}
+
+// A generation slot, returned to the limiter when dropped
+pub struct PriorityPermit {
+    limiter: Arc<PriorityLimiter>,
+}
+
+impl Drop for PriorityPermit {
+    fn drop(&mut self) {
+        self.limiter.release();
+    }
+}
+
+// A queued acquire; leaving the queue before its slot was used gives the slot back
+struct Waiting {
+    limiter: Arc<PriorityLimiter>,
+    key: WaiterKey,
+    granted: bool,
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        if self.granted {
+            return;
+        }
+        let removed = self.limiter.lock().waiting.remove(&self.key).is_some();
+        if !removed {
+            // The slot was handed over just as the caller gave up
+            self.limiter.release();
+        }
+    }
+}
+
+impl PriorityLimiter {
+    pub fn new(permits: usize) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(LimiterState {
+                available: permits,
+                next_seq: 0,
+                waiting: BTreeMap::new(),
+            }),
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LimiterState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn available_permits(&self) -> usize {
+        self.lock().available
+    }
+
+    // Requests waiting for a slot, per priority
+    pub fn waiting(&self) -> HashMap<Priority, usize> {
+        let mut counts = HashMap::new();
+        for (Reverse(priority), _) in self.lock().waiting.keys() {
+            *counts.entry(*priority).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    pub async fn acquire(self: &Arc<Self>, priority: Priority) -> PriorityPermit {
+        let (key, receiver) = {
+            let mut state = self.lock();
+            if state.available > 0 {
+                state.available -= 1;
+                return PriorityPermit { limiter: self.clone() };
+            }
+            let key = (Reverse(priority), state.next_seq);
+            state.next_seq += 1;
+            let (sender, receiver) = oneshot::channel();
+            state.waiting.insert(key, sender);
+            (key, receiver)
+        };
+
+        let mut waiting = Waiting { limiter: self.clone(), key, granted: false };
+        // The sender only goes away by handing over a slot
+        let _ = receiver.await;
+        waiting.granted = true;
+        PriorityPermit { limiter: self.clone() }
+    }
+
+    // Hand a freed slot to the next waiter, or put it back
+    fn release(&self) {
+        let mut state = self.lock();
+        while let Some((_, sender)) = state.waiting.pop_first() {
+            if sender.send(()).is_ok() {
+                return;
+            }
+        }
+        state.available += 1;
+    }
+}
@@ -0,0 +1,415 @@
+use std::collections::BTreeMap;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use utoipa::ToSchema;
+
+// Version of the Anthropic Messages API the requests are written against
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+// Anthropic requires max_tokens; used when a payload doesn't set it
+const ANTHROPIC_DEFAULT_MAX_TOKENS: u64 = 1024;
+// Smallest thinking budget Anthropic accepts
+const ANTHROPIC_MIN_THINKING_BUDGET: u64 = 1024;
+// Payload field carrying the thinking budget to the protocol translation; OpenAI-compatible
+// backends take `reasoning_effort` instead, so it is never sent upstream
+pub const THINKING_BUDGET_FIELD: &str = "thinking_budget_tokens";
+// Payload field asking the translation to mark the prompt prefix as cacheable; backends that
+// cache prefixes on their own don't need it, so it isn't sent upstream either
+pub const PROMPT_CACHE_FIELD: &str = "prompt_cache_hint";
+
+// API a model's backend speaks. Payloads are built in the OpenAI format throughout and
+// translated just before they are sent, and responses are translated back, so caching,
+// usage accounting and replay work the same for every backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendProtocol {
+    // OpenAI-compatible `/v1/chat/completions` and `/v1/completions`, as served by mistral.rs
+    #[default]
+    OpenAi,
+    // Anthropic's `/v1/messages`
+    Anthropic,
+    // Google's Gemini `generateContent`
+    Gemini,
+}
+
+// How hard a reasoning model thinks before answering. OpenAI-compatible backends get it as
+// `reasoning_effort`; Anthropic and Gemini take a thinking budget, which each level maps to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningEffort {
+    Low,
+    Medium,
+    High,
+}
+
+impl ReasoningEffort {
+    // Thinking tokens a level stands for
+    pub fn budget_tokens(self) -> u64 {
+        match self {
+            ReasoningEffort::Low => 1024,
+            ReasoningEffort::Medium => 4096,
+            ReasoningEffort::High => 16384,
+        }
+    }
+
+    // Closest level for a thinking budget, for backends that only take a level
+    pub fn from_budget(tokens: u64) -> Self {
+        if tokens <= ReasoningEffort::Low.budget_tokens() {
+            ReasoningEffort::Low
+        } else if tokens <= ReasoningEffort::Medium.budget_tokens() {
+            ReasoningEffort::Medium
+        } else {
+            ReasoningEffort::High
+        }
+    }
+}
+
+// Thinking budget requested by a payload, explicitly or through its reasoning effort
+fn thinking_budget(payload: &Value) -> Option<u64> {
+    payload[THINKING_BUDGET_FIELD].as_u64().or_else(|| {
+        ReasoningEffort::deserialize(&payload["reasoning_effort"])
+            .ok()
+            .map(ReasoningEffort::budget_tokens)
+    })
+}
+
+// A request ready to send upstream
+pub struct UpstreamRequest {
+    pub url: String,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Value,
+}
+
+impl BackendProtocol {
+    // Base URL used when a model doesn't set one
+    pub fn default_url(self) -> Option<&'static str> {
+        match self {
+            BackendProtocol::OpenAi => None,
+            BackendProtocol::Anthropic => Some("https://api.anthropic.com"),
+            BackendProtocol::Gemini => Some("https://generativelanguage.googleapis.com"),
+        }
+    }
+
+    // Environment variable holding the API key when a model doesn't name one
+    pub fn default_api_key_env(self) -> Option<&'static str> {
+        match self {
+            BackendProtocol::OpenAi => None,
+            BackendProtocol::Anthropic => Some("ANTHROPIC_API_KEY"),
+            BackendProtocol::Gemini => Some("GEMINI_API_KEY"),
+        }
+    }
+
+    // Whether the backend serves our `/health`, `/v1/models` and `/tokenize` probes
+    pub fn is_local(self) -> bool {
+        self == BackendProtocol::OpenAi
+    }
+
+    // Translate an OpenAI-style payload for `endpoint` into this backend's request.
+    // `safety_settings` is the Gemini `safetySettings` array, when the model sets any.
+    pub fn request(
+        self,
+        base_url: &str,
+        endpoint: &str,
+        payload: &Value,
+        api_key: Option<&str>,
+        safety_settings: Option<&Value>,
+    ) -> Result<UpstreamRequest> {
+        match self {
+            BackendProtocol::OpenAi => Ok(UpstreamRequest {
+                url: format!("{}/{}", base_url, endpoint),
+                headers: api_key
+                    .map(|key| vec![("Authorization", format!("Bearer {}", key))])
+                    .unwrap_or_default(),
+                body: openai_request(payload),
+            }),
+            BackendProtocol::Anthropic => {
+                if endpoint != "v1/chat/completions" {
+                    return Err(anyhow::anyhow!("Anthropic backends only support chat, not {}", endpoint));
+                }
+                let mut headers = vec![("anthropic-version", ANTHROPIC_VERSION.to_string())];
+                if let Some(key) = api_key {
+                    headers.push(("x-api-key", key.to_string()));
+                }
+                Ok(UpstreamRequest {
+                    url: format!("{}/v1/messages", base_url),
+                    headers,
+                    body: anthropic_request(payload),
+                })
+            }
+            BackendProtocol::Gemini => {
+                if endpoint != "v1/chat/completions" {
+                    return Err(anyhow::anyhow!("Gemini backends only support chat, not {}", endpoint));
+                }
+                let model = payload["model"].as_str().unwrap_or_default();
+                Ok(UpstreamRequest {
+                    url: format!("{}/v1beta/models/{}:generateContent", base_url, model),
+                    headers: api_key
+                        .map(|key| vec![("x-goog-api-key", key.to_string())])
+                        .unwrap_or_default(),
+                    body: gemini_request(payload, safety_settings),
+                })
+            }
+        }
+    }
+
+    // Translate this backend's response into an OpenAI-style chat completion
+    pub fn response(self, response: Value) -> Result<Value> {
+        match self {
+            BackendProtocol::OpenAi => Ok(response),
+            BackendProtocol::Anthropic => anthropic_response(&response),
+            BackendProtocol::Gemini => gemini_response(&response),
+        }
+    }
+}
+
+// The thinking budget only travels as a reasoning effort, and prefix caching is automatic
+fn openai_request(payload: &Value) -> Value {
+    let mut body = payload.clone();
+    if let Some(body) = body.as_object_mut() {
+        body.remove(PROMPT_CACHE_FIELD);
+        if let Some(budget) = body.remove(THINKING_BUDGET_FIELD).and_then(|budget| budget.as_u64()) {
+            body.entry("reasoning_effort")
+                .or_insert_with(|| json!(ReasoningEffort::from_budget(budget)));
+        }
+    }
+    body
+}
+
+// System messages become the `system` field, and consecutive messages of the same role are
+// merged, as the Messages API expects user and assistant turns to alternate
+fn anthropic_request(payload: &Value) -> Value {
+    let mut system = Vec::new();
+    let mut messages: Vec<Value> = Vec::new();
+    for message in payload["messages"].as_array().into_iter().flatten() {
+        let role = message["role"].as_str().unwrap_or("user");
+        let text = message["content"].as_str().unwrap_or_default();
+        if role == "system" {
+            system.push(text);
+            continue;
+        }
+        let block = json!({ "type": "text", "text": text });
+        match messages.last_mut() {
+            Some(last) if last["role"] == role => {
+                if let Some(content) = last["content"].as_array_mut() {
+                    content.push(block);
+                }
+            }
+            _ => messages.push(json!({ "role": role, "content": [block] })),
+        }
+    }
+
+    // Thinking counts against max_tokens, so the budget comes on top of the answer's tokens
+    let budget = thinking_budget(payload).map(|budget| budget.max(ANTHROPIC_MIN_THINKING_BUDGET));
+    let max_tokens = payload["max_tokens"].as_u64().unwrap_or(ANTHROPIC_DEFAULT_MAX_TOKENS);
+
+    // Breakpoints on the system prompt and on the block before the new message, which ends the
+    // conversation so far: the next turn repeats everything up to it and reads it from the cache
+    let cache = payload[PROMPT_CACHE_FIELD] == true;
+    if cache {
+        let previous_block = messages
+            .iter_mut()
+            .rev()
+            .filter_map(|message| message["content"].as_array_mut())
+            .flat_map(|content| content.iter_mut().rev())
+            .nth(1);
+        if let Some(block) = previous_block {
+            block["cache_control"] = json!({ "type": "ephemeral" });
+        }
+    }
+
+    let mut request = Map::new();
+    request.insert("model".to_string(), payload["model"].clone());
+    request.insert("messages".to_string(), json!(messages));
+    request.insert("max_tokens".to_string(), json!(max_tokens + budget.unwrap_or(0)));
+    if !system.is_empty() {
+        let mut block = json!({ "type": "text", "text": system.join("\n\n") });
+        if cache {
+            block["cache_control"] = json!({ "type": "ephemeral" });
+        }
+        request.insert("system".to_string(), json!([block]));
+    }
+    if let Some(budget) = budget {
+        request.insert("thinking".to_string(), json!({ "type": "enabled", "budget_tokens": budget }));
+    }
+    // Anthropic's temperature range is 0 to 1, and recent models reject top_p alongside it.
+    // Thinking only works with the default temperature.
+    if let Some(temperature) = payload["temperature"].as_f64().filter(|_| budget.is_none()) {
+        request.insert("temperature".to_string(), json!(temperature.clamp(0.0, 1.0)));
+    }
+    if let Some(stop) = payload["stop"].as_array() {
+        let stop: Vec<&Value> = stop
+            .iter()
+            .filter(|s| s.as_str().is_some_and(|s| !s.trim().is_empty()))
+            .collect();
+        if !stop.is_empty() {
+            request.insert("stop_sequences".to_string(), json!(stop));
+        }
+    }
+    Value::Object(request)
+}
+
+fn anthropic_response(response: &Value) -> Result<Value> {
+    let blocks = response["content"]
+        .as_array()
+        .ok_or_else(|| anyhow::anyhow!("Failed to extract content from Anthropic response"))?;
+    let text: String = blocks
+        .iter()
+        .filter(|block| block["type"] == "text")
+        .filter_map(|block| block["text"].as_str())
+        .collect();
+    // Extended thinking arrives in its own blocks
+    let reasoning: String = blocks
+        .iter()
+        .filter(|block| block["type"] == "thinking")
+        .filter_map(|block| block["thinking"].as_str())
+        .collect();
+    let finish_reason = match response["stop_reason"].as_str() {
+        Some("max_tokens") => "length",
+        _ => "stop",
+    };
+    Ok(json!({
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": text, "reasoning_content": reasoning },
+            "finish_reason": finish_reason,
+        }],
+        // Anthropic counts cache reads and writes apart from the other input tokens
+        "usage": {
+            "prompt_tokens": ["input_tokens", "cache_read_input_tokens", "cache_creation_input_tokens"]
+                .iter()
+                .filter_map(|field| response["usage"][field].as_u64())
+                .sum::<u64>(),
+            "completion_tokens": response["usage"]["output_tokens"],
+            "prompt_tokens_details": { "cached_tokens": response["usage"]["cache_read_input_tokens"].as_u64().unwrap_or(0) },
+        },
+    }))
+}
+
+// Build Gemini's `safetySettings` from a registry entry's `safety_settings`, which maps
+// harm categories (`harassment`, `hate_speech`, `sexually_explicit`, `dangerous_content`,
+// `civic_integrity`) to thresholds (`block_none`, `block_only_high`, `block_medium_and_above`,
+// `block_low_and_above`, `off`)
+pub fn gemini_safety_settings(settings: &BTreeMap<String, String>) -> Result<Value> {
+    let settings = settings
+        .iter()
+        .map(|(category, threshold)| {
+            let category = match category.as_str() {
+                "harassment" => "HARM_CATEGORY_HARASSMENT",
+                "hate_speech" => "HARM_CATEGORY_HATE_SPEECH",
+                "sexually_explicit" => "HARM_CATEGORY_SEXUALLY_EXPLICIT",
+                "dangerous_content" => "HARM_CATEGORY_DANGEROUS_CONTENT",
+                "civic_integrity" => "HARM_CATEGORY_CIVIC_INTEGRITY",
+                other => return Err(anyhow::anyhow!("Unknown safety category: {}", other)),
+            };
+            let threshold = match threshold.as_str() {
+                "block_none" => "BLOCK_NONE",
+                "block_only_high" => "BLOCK_ONLY_HIGH",
+                "block_medium_and_above" => "BLOCK_MEDIUM_AND_ABOVE",
+                "block_low_and_above" => "BLOCK_LOW_AND_ABOVE",
+                "off" => "OFF",
+                other => return Err(anyhow::anyhow!("Unknown safety threshold: {}", other)),
+            };
+            Ok(json!({ "category": category, "threshold": threshold }))
+        })
+        .collect::<Result<Vec<Value>>>()?;
+    Ok(json!(settings))
+}
+
+// Gemini calls the assistant "model" and takes system messages as `systemInstruction`
+fn gemini_request(payload: &Value, safety_settings: Option<&Value>) -> Value {
+    let mut system = Vec::new();
+    let mut contents: Vec<Value> = Vec::new();
+    for message in payload["messages"].as_array().into_iter().flatten() {
+        let text = message["content"].as_str().unwrap_or_default();
+        let role = match message["role"].as_str() {
+            Some("system") => {
+                system.push(text);
+                continue;
+            }
+            Some("assistant") => "model",
+            _ => "user",
+        };
+        let part = json!({ "text": text });
+        match contents.last_mut() {
+            Some(last) if last["role"] == role => {
+                if let Some(parts) = last["parts"].as_array_mut() {
+                    parts.push(part);
+                }
+            }
+            _ => contents.push(json!({ "role": role, "parts": [part] })),
+        }
+    }
+
+    let mut generation_config = Map::new();
+    if let Some(temperature) = payload["temperature"].as_f64() {
+        generation_config.insert("temperature".to_string(), json!(temperature));
+    }
+    if let Some(top_p) = payload["top_p"].as_f64() {
+        generation_config.insert("topP".to_string(), json!(top_p));
+    }
+    if let Some(max_tokens) = payload["max_tokens"].as_u64() {
+        generation_config.insert("maxOutputTokens".to_string(), json!(max_tokens));
+    }
+    if let Some(stop) = payload["stop"].as_array() {
+        // Gemini accepts at most five stop sequences
+        generation_config.insert("stopSequences".to_string(), json!(stop.iter().take(5).collect::<Vec<_>>()));
+    }
+    // Structured output: the OpenAI `response_format` schema becomes Gemini's JSON response schema
+    if let Some(schema) = payload["response_format"]["json_schema"].get("schema") {
+        generation_config.insert("responseMimeType".to_string(), json!("application/json"));
+        generation_config.insert("responseJsonSchema".to_string(), schema.clone());
+    }
+    if let Some(budget) = thinking_budget(payload) {
+        generation_config.insert(
+            "thinkingConfig".to_string(),
+            json!({ "thinkingBudget": budget, "includeThoughts": true }),
+        );
+    }
+
+    let mut request = Map::new();
+    request.insert("contents".to_string(), json!(contents));
+    request.insert("generationConfig".to_string(), Value::Object(generation_config));
+    if !system.is_empty() {
+        request.insert("systemInstruction".to_string(), json!({ "parts": [{ "text": system.join("\n\n") }] }));
+    }
+    if let Some(safety_settings) = safety_settings {
+        request.insert("safetySettings".to_string(), safety_settings.clone());
+    }
+    Value::Object(request)
+}
+
+fn gemini_response(response: &Value) -> Result<Value> {
+    if let Some(reason) = response["promptFeedback"]["blockReason"].as_str() {
+        return Err(anyhow::anyhow!("Gemini blocked the prompt: {}", reason));
+    }
+    let candidate = &response["candidates"][0];
+    let finish_reason = candidate["finishReason"].as_str().unwrap_or("STOP");
+    let parts = candidate["content"]["parts"].as_array();
+    if parts.is_none() && finish_reason != "STOP" && finish_reason != "MAX_TOKENS" {
+        return Err(anyhow::anyhow!("Gemini returned no answer: {}", finish_reason));
+    }
+    let parts = parts.ok_or_else(|| anyhow::anyhow!("Failed to extract content from Gemini response"))?;
+    // Thought summaries are parts flagged with `thought`
+    let text: String = parts
+        .iter()
+        .filter(|part| part["thought"] != true)
+        .filter_map(|part| part["text"].as_str())
+        .collect();
+    let reasoning: String = parts
+        .iter()
+        .filter(|part| part["thought"] == true)
+        .filter_map(|part| part["text"].as_str())
+        .collect();
+    Ok(json!({
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": text, "reasoning_content": reasoning },
+            "finish_reason": if finish_reason == "MAX_TOKENS" { "length" } else { "stop" },
+        }],
+        "usage": {
+            "prompt_tokens": response["usageMetadata"]["promptTokenCount"],
+            "completion_tokens": response["usageMetadata"]["candidatesTokenCount"],
+            "prompt_tokens_details": { "cached_tokens": response["usageMetadata"]["cachedContentTokenCount"].as_u64().unwrap_or(0) },
+        },
+    }))
+}
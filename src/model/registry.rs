@@ -0,0 +1,410 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use log::{info, warn};
+use utoipa::ToSchema;
+
+use super::chat_template::ChatTemplate;
+use super::fim::{FimConfig, FimTokens};
+use super::length::LengthPresets;
+use super::pool::BalanceStrategy;
+use super::protocol::{gemini_safety_settings, BackendProtocol, ReasoningEffort};
+use super::truncation::TruncationStrategy;
+use super::{
+    DEFAULT_MAX_CONTEXT_WINDOW, DEFAULT_SYSTEM_MESSAGE_RESERVE, DEFAULT_RESPONSE_RESERVE,
+    DEFAULT_MIN_TOKENS, DEFAULT_MAX_TOKENS, DEFAULT_TEMPERATURE, DEFAULT_TOP_P,
+};
+
+// Default location of the model registry file
+const DEFAULT_REGISTRY_PATH: &str = "models.toml";
+// Name used for the implicit model when no registry file exists
+const DEFAULT_MODEL_NAME: &str = "local-model";
+// Default mistral.rs server URL
+const DEFAULT_SERVER_URL: &str = "http://localhost:8081";
+// Default number of tokens the draft model proposes per step, as in mistral.rs's examples
+const DEFAULT_SPECULATIVE_GAMMA: usize = 32;
+
+/// Model registry configuration.
+///
+/// The registry is read from the TOML file at `MODEL_REGISTRY_PATH` (default: "models.toml").
+/// Each `[[models]]` entry maps a name to a backend URL and its token limits:
+///
+/// ```toml
+/// [[models]]
+/// name = "llama3-8b"
+/// url = "http://localhost:8081"
+/// backend_model = "llama3"      # optional, sent as "model" in the upstream payload
+/// protocol = "openai"           # optional, "openai" (default), "anthropic" or "gemini": API the backend speaks
+/// api_key_env = "LLM_API_KEY"   # optional, environment variable holding the backend's API key
+///                               # (default: ANTHROPIC_API_KEY or GEMINI_API_KEY for those protocols)
+/// safety_settings = { harassment = "block_only_high" }  # optional, "gemini" only: category = threshold
+/// urls = ["http://localhost:8082"]  # optional extra replicas serving the same model
+/// strategy = "least_in_flight"  # optional, "round_robin" (default) or "least_in_flight"
+/// max_context_window = 8192     # optional, falls back to MAX_CONTEXT_WINDOW
+/// max_tokens = 2048             # optional, falls back to MAX_TOKENS
+/// history_truncation = "pairwise"  # optional, falls back to HISTORY_TRUNCATION
+/// prompt_price_per_1k = 0.5     # optional, falls back to PROMPT_PRICE_PER_1K
+/// completion_price_per_1k = 1.5 # optional, falls back to COMPLETION_PRICE_PER_1K
+/// temperature = 0.2             # optional, falls back to TEMPERATURE
+/// top_p = 0.9                   # optional, falls back to TOP_P
+/// stop = ["<|endoftext|>"]      # optional, sent with every generation
+/// extra_body = { min_p = 0.05 } # optional, backend-specific parameters added to every payload
+/// reasoning_effort = "low"      # optional, "low", "medium" or "high", for reasoning models
+/// thinking_budget_tokens = 2048 # optional, thinking tokens, for backends that take a budget
+/// fold_system_prompt = true     # optional, for chat templates without a system role
+/// chat_template = "chatml"      # optional, "chatml", "llama2", "llama3" or "mistral": format
+///                               # prompts ourselves and use the backend's /v1/completions
+/// fim = "starcoder"             # optional, fill-in-the-middle tokens: "starcoder", "codellama",
+///                               # "deepseek", "qwen", "codegemma" or { prefix, suffix, middle }
+/// speculative = { draft_model = "llama3.2-1b", gamma = 16 }  # optional, draft model the backend
+///                               # runs speculative decoding with (gamma default: 32)
+/// vram_mb = 6144                # optional, estimated VRAM of the loaded model, checked against
+///                               # VRAM_BUDGET_MB before the model is activated
+/// length_presets = { short = 128, long = 3072 }  # optional, max_tokens of the `length` presets
+///                               # (default: short 256, medium 768, long 2048; max: max_tokens)
+/// default = true                # optional, model used when a request doesn't pick one
+/// ```
+///
+/// Cloud models can be mixed in with local ones. `anthropic` and `gemini` models need no
+/// `url`, and `backend_model` names the cloud model:
+///
+/// ```toml
+/// [[models]]
+/// name = "claude"
+/// protocol = "anthropic"
+/// backend_model = "claude-sonnet-4-5"
+/// max_context_window = 200000
+/// max_tokens = 8192
+///
+/// [[models]]
+/// name = "gemini"
+/// protocol = "gemini"
+/// backend_model = "gemini-2.5-flash"
+/// safety_settings = { dangerous_content = "block_medium_and_above", harassment = "block_none" }
+/// ```
+///
+/// Speculative decoding happens inside the backend: mistral.rs loads the draft model at startup
+/// from the `[speculative]` section of its TOML selector, so `speculative` has to match how the
+/// backend was launched. It is reported by `GET /api/models` and labels the acceptance metrics.
+///
+/// Any limit or sampling default omitted in the file falls back to the corresponding
+/// environment variable and then to the built-in default. When the file doesn't exist, a single model named
+/// `MODEL_NAME` (default: "local-model") is registered at `MISTRAL_SERVER_URL`, which may
+/// hold a comma-separated list of replicas.
+
+// Raw registry file layout
+#[derive(Debug, Deserialize)]
+struct RegistryFile {
+    #[serde(default)]
+    models: Vec<ModelEntry>,
+}
+
+// A single model entry as written in the registry file
+#[derive(Debug, Deserialize)]
+struct ModelEntry {
+    name: String,
+    url: Option<String>,
+    #[serde(default)]
+    urls: Vec<String>,
+    #[serde(default)]
+    strategy: BalanceStrategy,
+    backend_model: Option<String>,
+    #[serde(default)]
+    protocol: BackendProtocol,
+    api_key_env: Option<String>,
+    #[serde(default)]
+    safety_settings: BTreeMap<String, String>,
+    max_context_window: Option<usize>,
+    system_message_reserve: Option<usize>,
+    response_reserve: Option<usize>,
+    min_tokens: Option<usize>,
+    max_tokens: Option<usize>,
+    history_truncation: Option<String>,
+    prompt_price_per_1k: Option<f64>,
+    completion_price_per_1k: Option<f64>,
+    temperature: Option<f32>,
+    top_p: Option<f32>,
+    #[serde(default)]
+    stop: Vec<String>,
+    extra_body: Option<toml::Table>,
+    reasoning_effort: Option<ReasoningEffort>,
+    thinking_budget_tokens: Option<usize>,
+    #[serde(default)]
+    fold_system_prompt: bool,
+    chat_template: Option<String>,
+    fim: Option<FimConfig>,
+    speculative: Option<SpeculativeConfig>,
+    vram_mb: Option<u64>,
+    #[serde(default)]
+    length_presets: LengthPresets,
+    #[serde(default)]
+    default: bool,
+}
+
+fn default_speculative_gamma() -> usize {
+    DEFAULT_SPECULATIVE_GAMMA
+}
+
+// Draft model a backend uses for speculative decoding
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SpeculativeConfig {
+    pub draft_model: String,
+    // Tokens the draft model proposes before the target model verifies them
+    #[serde(default = "default_speculative_gamma")]
+    pub gamma: usize,
+}
+
+// Fully resolved configuration for one model
+#[derive(Debug, Clone)]
+pub struct ModelConfig {
+    pub name: String,
+    pub urls: Vec<String>,
+    pub strategy: BalanceStrategy,
+    pub backend_model: String,
+    pub protocol: BackendProtocol,
+    // Environment variable holding the backend's API key
+    pub api_key_env: Option<String>,
+    // Gemini `safetySettings`, when the entry sets any
+    pub safety_settings: Option<Value>,
+    pub max_context_window: usize,
+    pub system_message_reserve: usize,
+    pub response_reserve: usize,
+    pub min_tokens: usize,
+    pub max_tokens: usize,
+    pub truncation: TruncationStrategy,
+    pub pricing: Pricing,
+    // Sampling defaults of this model; requests, sessions and assistants override them
+    pub temperature: f32,
+    pub top_p: f32,
+    // Stop sequences sent with every generation
+    pub stop: Vec<String>,
+    // Backend-specific parameters added to every payload
+    pub extra_body: Option<Value>,
+    // Default thinking effort of a reasoning model; requests override it
+    pub reasoning_effort: Option<ReasoningEffort>,
+    pub thinking_budget_tokens: Option<usize>,
+    // Merge the system message into the first user message, for templates without a system role
+    pub fold_system_prompt: bool,
+    // Prompt format for backends without a chat endpoint; None uses `/v1/chat/completions`
+    pub chat_template: Option<ChatTemplate>,
+    // Fill-in-the-middle tokens; None when the model doesn't support FIM
+    pub fim: Option<FimTokens>,
+    // Speculative decoding set up on the backend, if any
+    pub speculative: Option<SpeculativeConfig>,
+    // Estimated VRAM of the loaded model, in MiB
+    pub vram_mb: Option<u64>,
+    // max_tokens of the response length presets
+    pub length_presets: LengthPresets,
+}
+
+// Price of 1000 tokens, in whatever currency the deployment tracks spend in
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Pricing {
+    pub prompt_per_1k: f64,
+    pub completion_per_1k: f64,
+}
+
+impl Pricing {
+    pub fn cost(&self, prompt_tokens: usize, completion_tokens: usize) -> f64 {
+        (prompt_tokens as f64 * self.prompt_per_1k + completion_tokens as f64 * self.completion_per_1k) / 1000.0
+    }
+}
+
+pub struct ModelRegistry {
+    pub models: Vec<ModelConfig>,
+    pub default_model: String,
+}
+
+// Read an environment variable and parse it, falling back to a default
+fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|v| v.parse::<T>().ok())
+        .unwrap_or(default)
+}
+
+impl ModelEntry {
+    fn resolve(self) -> Result<ModelConfig> {
+        // Merge the single `url` with any additional replicas
+        let urls: Vec<String> = self.url
+            .into_iter()
+            .chain(self.urls)
+            .map(|url| url.trim().trim_end_matches('/').to_string())
+            .filter(|url| !url.is_empty())
+            .collect();
+        let urls = match (urls.is_empty(), self.protocol.default_url()) {
+            (true, Some(url)) => vec![url.to_string()],
+            _ => urls,
+        };
+
+        if urls.is_empty() {
+            return Err(anyhow::anyhow!("Model {} must define `url` or `urls`", self.name));
+        }
+
+        if self.protocol != BackendProtocol::OpenAi && (self.chat_template.is_some() || self.fim.is_some()) {
+            return Err(anyhow::anyhow!("Model {}: chat_template and fim need an OpenAI-compatible backend", self.name));
+        }
+
+        if let Some(speculative) = &self.speculative {
+            if !self.protocol.is_local() {
+                return Err(anyhow::anyhow!("Model {}: speculative decoding needs a local backend", self.name));
+            }
+            if speculative.draft_model.trim().is_empty() || speculative.gamma == 0 {
+                return Err(anyhow::anyhow!("Model {}: speculative needs a draft_model and a gamma above 0", self.name));
+            }
+        }
+
+        if self.protocol != BackendProtocol::Gemini && !self.safety_settings.is_empty() {
+            return Err(anyhow::anyhow!("Model {}: safety_settings only apply to the gemini protocol", self.name));
+        }
+        let safety_settings = if self.safety_settings.is_empty() {
+            None
+        } else {
+            Some(gemini_safety_settings(&self.safety_settings)
+                .with_context(|| format!("Invalid safety_settings for model {}", self.name))?)
+        };
+
+        Ok(ModelConfig {
+            safety_settings,
+            protocol: self.protocol,
+            api_key_env: self.api_key_env.or_else(|| self.protocol.default_api_key_env().map(str::to_string)),
+            backend_model: self.backend_model.unwrap_or_else(|| self.name.clone()),
+            name: self.name,
+            urls,
+            strategy: self.strategy,
+            max_context_window: self.max_context_window
+                .unwrap_or_else(|| env_or("MAX_CONTEXT_WINDOW", DEFAULT_MAX_CONTEXT_WINDOW)),
+            system_message_reserve: self.system_message_reserve
+                .unwrap_or_else(|| env_or("SYSTEM_MESSAGE_RESERVE", DEFAULT_SYSTEM_MESSAGE_RESERVE)),
+            response_reserve: self.response_reserve
+                .unwrap_or_else(|| env_or("RESPONSE_RESERVE", DEFAULT_RESPONSE_RESERVE)),
+            min_tokens: self.min_tokens
+                .unwrap_or_else(|| env_or("MIN_TOKENS", DEFAULT_MIN_TOKENS)),
+            max_tokens: self.max_tokens
+                .unwrap_or_else(|| env_or("MAX_TOKENS", DEFAULT_MAX_TOKENS)),
+            truncation: match self.history_truncation {
+                Some(strategy) => strategy.parse()?,
+                None => env_or("HISTORY_TRUNCATION", TruncationStrategy::DropOldest),
+            },
+            pricing: Pricing {
+                prompt_per_1k: self.prompt_price_per_1k
+                    .unwrap_or_else(|| env_or("PROMPT_PRICE_PER_1K", 0.0)),
+                completion_per_1k: self.completion_price_per_1k
+                    .unwrap_or_else(|| env_or("COMPLETION_PRICE_PER_1K", 0.0)),
+            },
+            temperature: self.temperature
+                .unwrap_or_else(|| env_or("TEMPERATURE", DEFAULT_TEMPERATURE)),
+            top_p: self.top_p
+                .unwrap_or_else(|| env_or("TOP_P", DEFAULT_TOP_P)),
+            stop: self.stop,
+            extra_body: self.extra_body.map(serde_json::to_value).transpose()?,
+            reasoning_effort: self.reasoning_effort,
+            thinking_budget_tokens: self.thinking_budget_tokens,
+            fold_system_prompt: self.fold_system_prompt,
+            chat_template: self.chat_template.map(|template| template.parse()).transpose()?,
+            fim: self.fim.map(FimConfig::resolve).transpose()?,
+            speculative: self.speculative,
+            vram_mb: self.vram_mb,
+            length_presets: self.length_presets,
+        })
+    }
+}
+
+impl ModelRegistry {
+    pub fn load() -> Result<Self> {
+        let path = env::var("MODEL_REGISTRY_PATH")
+            .unwrap_or_else(|_| DEFAULT_REGISTRY_PATH.to_string());
+
+        if !Path::new(&path).exists() {
+            info!("No model registry found at {}, using single model from environment", path);
+            return Self::from_env();
+        }
+
+        info!("Loading model registry from {}", path);
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read model registry {}", path))?;
+        Self::parse(&contents)
+            .with_context(|| format!("Invalid model registry {}", path))
+    }
+
+    // Build a registry containing the single model described by environment variables
+    fn from_env() -> Result<Self> {
+        let server_urls = env::var("MISTRAL_SERVER_URL").unwrap_or_else(|_| DEFAULT_SERVER_URL.to_string());
+        let entry = ModelEntry {
+            name: env::var("MODEL_NAME").unwrap_or_else(|_| DEFAULT_MODEL_NAME.to_string()),
+            url: None,
+            urls: server_urls.split(',').map(str::to_string).collect(),
+            strategy: env_or("LOAD_BALANCE_STRATEGY", BalanceStrategy::RoundRobin),
+            backend_model: None,
+            protocol: BackendProtocol::OpenAi,
+            api_key_env: None,
+            safety_settings: BTreeMap::new(),
+            max_context_window: None,
+            system_message_reserve: None,
+            response_reserve: None,
+            min_tokens: None,
+            max_tokens: None,
+            history_truncation: None,
+            prompt_price_per_1k: None,
+            completion_price_per_1k: None,
+            temperature: None,
+            top_p: None,
+            stop: Vec::new(),
+            extra_body: None,
+            reasoning_effort: None,
+            thinking_budget_tokens: None,
+            fold_system_prompt: false,
+            chat_template: None,
+            fim: None,
+            speculative: None,
+            vram_mb: None,
+            length_presets: LengthPresets::default(),
+            default: true,
+        };
+        let config = entry.resolve()?;
+        Ok(Self {
+            default_model: config.name.clone(),
+            models: vec![config],
+        })
+    }
+
+    fn parse(contents: &str) -> Result<Self> {
+        let file: RegistryFile = toml::from_str(contents)?;
+
+        if file.models.is_empty() {
+            return Err(anyhow::anyhow!("Model registry must define at least one [[models]] entry"));
+        }
+
+        let mut default_model = None;
+        let mut models: Vec<ModelConfig> = Vec::with_capacity(file.models.len());
+
+        for entry in file.models {
+            if models.iter().any(|m| m.name == entry.name) {
+                return Err(anyhow::anyhow!("Duplicate model name in registry: {}", entry.name));
+            }
+
+            if entry.default {
+                if let Some(existing) = &default_model {
+                    warn!("Multiple default models in registry, keeping {} over {}", existing, entry.name);
+                } else {
+                    default_model = Some(entry.name.clone());
+                }
+            }
+
+            models.push(entry.resolve()?);
+        }
+
+        // Fall back to the first listed model when none is marked as default
+        let default_model = default_model.unwrap_or_else(|| models[0].name.clone());
+
+        Ok(Self {
+            models,
+            default_model,
+        })
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use anyhow::Result;
+use log::{info, warn, error};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+// Default file holding recorded upstream exchanges
+const DEFAULT_REPLAY_PATH: &str = "replay.jsonl";
+
+/// Environment variables for recording and replaying backend traffic:
+///
+/// - `REPLAY_MODE`: "off", "record" to append every generation request sent upstream and its
+///   response to `REPLAY_PATH`, or "replay" to answer generation requests from that file
+///   without calling any backend (default: "off")
+/// - `REPLAY_PATH`: JSON lines file of recorded exchanges (default: "replay.jsonl")
+///
+/// Requests are matched by a SHA-256 hash of the endpoint and the full payload (model,
+/// messages or prompt and sampling parameters), so a replay is only exact when the same
+/// requests are made with the same settings. Unrecorded requests fail in replay mode.
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReplayMode {
+    #[default]
+    Off,
+    Record,
+    Replay,
+}
+
+impl FromStr for ReplayMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "off" | "" => Ok(Self::Off),
+            "record" => Ok(Self::Record),
+            "replay" => Ok(Self::Replay),
+            other => Err(anyhow::anyhow!("Unknown replay mode: {}", other)),
+        }
+    }
+}
+
+// One upstream request and the backend's response
+#[derive(Debug, Serialize, Deserialize)]
+struct Exchange {
+    key: String,
+    endpoint: String,
+    request: Value,
+    response: Value,
+}
+
+// Recorded backend exchanges, for reproducible demos and offline test runs
+pub struct Replay {
+    mode: ReplayMode,
+    path: String,
+    // Responses by request hash, loaded in replay mode
+    responses: HashMap<String, Value>,
+    // Opened lazily on the first recorded exchange
+    file: Mutex<Option<File>>,
+}
+
+static REPLAY: OnceLock<Replay> = OnceLock::new();
+
+pub fn replay() -> &'static Replay {
+    REPLAY.get_or_init(|| {
+        let mode = env::var("REPLAY_MODE")
+            .ok()
+            .and_then(|v| match v.parse::<ReplayMode>() {
+                Ok(mode) => Some(mode),
+                Err(e) => {
+                    warn!("{}, recording and replay disabled", e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+        let path = env::var("REPLAY_PATH").unwrap_or_else(|_| DEFAULT_REPLAY_PATH.to_string());
+
+        let mut responses = HashMap::new();
+        match mode {
+            ReplayMode::Replay => match Replay::read(&path) {
+                Ok(exchanges) => {
+                    for exchange in exchanges {
+                        responses.insert(exchange.key, exchange.response);
+                    }
+                    info!("Replaying {} recorded backend response(s) from {}", responses.len(), path);
+                }
+                Err(e) => error!("Failed to read recorded exchanges from {}: {}", path, e),
+            },
+            ReplayMode::Record => info!("Recording backend exchanges to {}", path),
+            ReplayMode::Off => {}
+        }
+
+        Replay {
+            mode,
+            path,
+            responses,
+            file: Mutex::new(None),
+        }
+    })
+}
+
+// Hash identifying a request; serde_json orders object keys, so equal payloads hash equally
+fn request_key(endpoint: &str, payload: &Value) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(endpoint.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(payload.to_string().as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+impl Replay {
+    fn read(path: &str) -> Result<Vec<Exchange>> {
+        let file = File::open(path)?;
+        let mut exchanges = Vec::new();
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(exchange) => exchanges.push(exchange),
+                Err(e) => warn!("Skipping malformed line {} of {}: {}", number + 1, path, e),
+            }
+        }
+        Ok(exchanges)
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.mode == ReplayMode::Replay
+    }
+
+    // The recorded response to a request, when replaying
+    pub fn lookup(&self, endpoint: &str, payload: &Value) -> Result<Value> {
+        let key = request_key(endpoint, payload);
+        self.responses
+            .get(&key)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("No recorded response for this request (hash {})", key))
+    }
+
+    // Append an exchange to the recording, when recording
+    pub fn record(&self, endpoint: &str, payload: &Value, response: &Value) {
+        if self.mode != ReplayMode::Record {
+            return;
+        }
+        let exchange = Exchange {
+            key: request_key(endpoint, payload),
+            endpoint: endpoint.to_string(),
+            request: payload.clone(),
+            response: response.clone(),
+        };
+        let Ok(mut file) = self.file.lock() else { return };
+        if file.is_none() {
+            match OpenOptions::new().create(true).append(true).open(&self.path) {
+                Ok(opened) => *file = Some(opened),
+                Err(e) => {
+                    error!("Failed to open replay recording {}: {}", self.path, e);
+                    return;
+                }
+            }
+        }
+        let Some(handle) = file.as_mut() else { return };
+        let result = serde_json::to_string(&exchange)
+            .map_err(anyhow::Error::from)
+            .and_then(|line| Ok(writeln!(handle, "{}", line)?));
+        if let Err(e) = result {
+            error!("Failed to record backend exchange: {}", e);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::env;
+use std::path::Path;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+// Default disk space left free after a download, in MiB
+const DEFAULT_MIN_FREE_DISK_MB: u64 = 1024;
+// Default VRAM assumed on top of a GGUF file's weights for the KV cache and activations, in MiB
+const DEFAULT_VRAM_OVERHEAD_MB: u64 = 1024;
+const MIB: u64 = 1024 * 1024;
+
+/// Environment variables for the disk and VRAM guardrails of model management:
+///
+/// - `MIN_FREE_DISK_MB`: Disk space a download must leave free in `MODELS_DIR` (default: 1024)
+/// - `VRAM_BUDGET_MB`: VRAM of the GPU the backends run on; models estimated to need more are
+///   refused (default: unchecked)
+/// - `VRAM_OVERHEAD_MB`: VRAM estimated on top of a GGUF file's size for the KV cache and
+///   activations, when the registry gives no `vram_mb` (default: 1024)
+///
+/// The backends run in their own processes, so VRAM can't be measured here; estimates come from
+/// the registry's `vram_mb` or from the size of the GGUF file.
+
+// Free and total space of the file system holding a directory
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub struct DiskSpace {
+    pub available_bytes: u64,
+    pub total_bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ResourceLimits {
+    pub min_free_disk_bytes: u64,
+    pub vram_budget_bytes: Option<u64>,
+    pub vram_overhead_bytes: u64,
+}
+
+fn env_mb(name: &str) -> Option<u64> {
+    env::var(name).ok().and_then(|v| v.parse::<u64>().ok())
+}
+
+// Space on the file system of `dir`, or of its nearest existing parent before it is created
+pub fn disk_space(dir: &Path) -> Option<DiskSpace> {
+    let existing = dir.ancestors().find(|path| path.exists()).unwrap_or(Path::new("."));
+    Some(DiskSpace {
+        available_bytes: fs2::available_space(existing).ok()?,
+        total_bytes: fs2::total_space(existing).ok()?,
+    })
+}
+
+fn format_mib(bytes: u64) -> String {
+    format!("{} MiB", bytes.div_ceil(MIB))
+}
+
+impl ResourceLimits {
+    pub fn from_env() -> Self {
+        Self {
+            min_free_disk_bytes: env_mb("MIN_FREE_DISK_MB").unwrap_or(DEFAULT_MIN_FREE_DISK_MB) * MIB,
+            vram_budget_bytes: env_mb("VRAM_BUDGET_MB").filter(|mb| *mb > 0).map(|mb| mb * MIB),
+            vram_overhead_bytes: env_mb("VRAM_OVERHEAD_MB").unwrap_or(DEFAULT_VRAM_OVERHEAD_MB) * MIB,
+        }
+    }
+
+    // VRAM a GGUF file of this size is expected to need once loaded
+    pub fn estimate_vram(&self, file_bytes: u64) -> u64 {
+        file_bytes + self.vram_overhead_bytes
+    }
+
+    // Refuse writing `bytes` more into `dir` when it would eat into the reserved free space
+    pub fn check_disk(&self, dir: &Path, bytes: u64) -> Result<(), String> {
+        let Some(space) = disk_space(dir) else { return Ok(()) };
+        if space.available_bytes.saturating_sub(bytes) < self.min_free_disk_bytes {
+            return Err(format!(
+                "Not enough disk space in {}: {} needed, {} available and {} must stay free",
+                dir.display(),
+                format_mib(bytes),
+                format_mib(space.available_bytes),
+                format_mib(self.min_free_disk_bytes),
+            ));
+        }
+        Ok(())
+    }
+
+    // Refuse a model estimated to need more VRAM than the configured budget
+    pub fn check_vram(&self, model: &str, estimated_bytes: u64) -> Result<(), String> {
+        match self.vram_budget_bytes {
+            Some(budget) if estimated_bytes > budget => Err(format!(
+                "{} needs an estimated {} of VRAM, more than the {} budget",
+                model,
+                format_mib(estimated_bytes),
+                format_mib(budget),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use std::env;
+use std::fs;
+use std::sync::OnceLock;
+use chrono::Utc;
+use log::{info, warn};
+use tera::{Context, Tera};
+
+// System message used when neither the request, its assistant nor the environment gives one
+const DEFAULT_SYSTEM_PROMPT: &str = "You are a helpful AI assistant. When responding to the user, please be thorough and detailed in your explanations. Aim to use close to the maximum token length of {{ max_tokens }} tokens when appropriate for the question.";
+
+/// Environment variables for the system message:
+///
+/// - `SYSTEM_PROMPT`: Tera template of the default system message (default: a generic helpful
+///   assistant asked to use close to `max_tokens`)
+/// - `SYSTEM_PROMPT_PATH`: File holding the template instead, for longer prompts; it wins over
+///   `SYSTEM_PROMPT` (default: none)
+///
+/// The template, and the system prompts of assistants, are rendered for every request with:
+/// `today` (e.g. "2024-05-01"), `now` (RFC 3339, UTC), `user_name` (the request's `user`, empty
+/// when not given), `model_name` and `max_tokens` (the response limit after clamping). A prompt
+/// that fails to render is sent as written.
+
+// Values a system prompt template can refer to
+pub struct PromptVariables<'a> {
+    pub user_name: Option<&'a str>,
+    pub model_name: &'a str,
+    pub max_tokens: usize,
+}
+
+static DEFAULT_TEMPLATE: OnceLock<String> = OnceLock::new();
+
+fn default_template() -> &'static str {
+    DEFAULT_TEMPLATE.get_or_init(|| {
+        if let Some(path) = env::var("SYSTEM_PROMPT_PATH").ok().filter(|p| !p.is_empty()) {
+            match fs::read_to_string(&path) {
+                Ok(template) => {
+                    info!("Loaded the system prompt template from {}", path);
+                    return template;
+                }
+                Err(e) => warn!("Failed to read SYSTEM_PROMPT_PATH {}: {}; using the default", path, e),
+            }
+        }
+        env::var("SYSTEM_PROMPT")
+            .ok()
+            .filter(|p| !p.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_SYSTEM_PROMPT.to_string())
+    })
+}
+
+// The system message for a request: `template` (e.g. an assistant's prompt) or the configured
+// default, with its variables filled in
+pub fn render(template: Option<&str>, vars: &PromptVariables) -> String {
+    let template = template.unwrap_or_else(|| default_template());
+    // Plain prompts are the common case and can't fail
+    if !template.contains("{{") && !template.contains("{%") {
+        return template.to_string();
+    }
+
+    let now = Utc::now();
+    let mut context = Context::new();
+    context.insert("today", &now.format("%Y-%m-%d").to_string());
+    context.insert("now", &now.to_rfc3339());
+    context.insert("user_name", vars.user_name.unwrap_or_default());
+    context.insert("model_name", vars.model_name);
+    context.insert("max_tokens", &vars.max_tokens);
+
+    match Tera::one_off(template, &context, false) {
+        Ok(rendered) => rendered,
+        Err(e) => {
+            warn!("Failed to render the system prompt template: {}", e);
+            template.to_string()
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use std::env;
+use std::ops::Range;
+use std::str::FromStr;
+
+use super::BackendError;
+
+// Turns kept from the start and the end of a conversation by `drop-middle`
+const DEFAULT_HISTORY_KEEP_FIRST: usize = 1;
+const DEFAULT_HISTORY_KEEP_LAST: usize = 3;
+
+/// Environment variables for trimming conversation history to the context window:
+///
+/// - `HISTORY_TRUNCATION`: Which messages go first when the history doesn't fit, one of
+///   "drop-oldest", "drop-middle" or "pairwise" (default: "drop-oldest")
+/// - `HISTORY_KEEP_FIRST`: Turns `drop-middle` keeps from the start of the conversation (default: 1)
+/// - `HISTORY_KEEP_LAST`: Turns `drop-middle` keeps from the end of the conversation (default: 3)
+///
+/// A turn is a user message together with the assistant replies that follow it. Whatever
+/// the strategy, the latest turn is always sent, along with the exchange before it when the
+/// latest turn is a message still awaiting its reply; older history is dropped first.
+
+// How conversation history is cut down to fit the context window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TruncationStrategy {
+    // Keep the newest messages, dropping from the start; may split a turn
+    #[default]
+    DropOldest,
+    // Keep the first and last turns, then fill the budget with the newest of the rest
+    DropMiddle { keep_first: usize, keep_last: usize },
+    // Keep the newest whole turns, so a reply never loses its question
+    Pairwise,
+}
+
+impl FromStr for TruncationStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "drop-oldest" => Ok(Self::DropOldest),
+            "drop-middle" => Ok(Self::DropMiddle {
+                keep_first: env_usize("HISTORY_KEEP_FIRST", DEFAULT_HISTORY_KEEP_FIRST),
+                keep_last: env_usize("HISTORY_KEEP_LAST", DEFAULT_HISTORY_KEEP_LAST),
+            }),
+            "pairwise" => Ok(Self::Pairwise),
+            other => Err(anyhow::anyhow!("Unknown history truncation strategy: {}", other)),
+        }
+    }
+}
+
+fn env_usize(name: &str, default: usize) -> usize {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(default)
+}
+
+// Split history entries ("user: ..." / "assistant: ...") into turns, each starting at a user message
+fn turns(history: &[String]) -> Vec<Range<usize>> {
+    let mut turns: Vec<Range<usize>> = Vec::new();
+    for (i, entry) in history.iter().enumerate() {
+        match turns.last_mut() {
+            Some(turn) if !entry.starts_with("user: ") => turn.end = i + 1,
+            _ => turns.push(i..i + 1),
+        }
+    }
+    turns
+}
+
+impl TruncationStrategy {
+    // Pick the history entries that fit in `budget` tokens, in their original order.
+    // Fails when even the latest turn doesn't fit.
+    pub fn apply<'a>(
+        &self,
+        history: &'a [String],
+        budget: usize,
+        cost: impl Fn(&str) -> usize,
+    ) -> Result<Vec<&'a String>, BackendError> {
+        let cost_of = |entries: &[String]| -> usize { entries.iter().map(|entry| cost(entry)).sum() };
+        let turns = turns(history);
+        let Some(latest) = turns.last().cloned() else { return Ok(Vec::new()) };
+
+        let latest_cost = cost_of(&history[latest.clone()]);
+        if latest_cost > budget {
+            return Err(BackendError::ContextOverflow(format!(
+                "the latest message needs about {} tokens but only {} are available",
+                latest_cost, budget
+            )));
+        }
+        let mut pinned = latest;
+        let mut budget = budget - latest_cost;
+
+        // A message awaiting its reply is sent with the exchange it follows up on, if that fits
+        let awaiting_reply = pinned.len() == 1 && history[pinned.start].starts_with("user: ");
+        if awaiting_reply && turns.len() >= 2 {
+            let previous = turns[turns.len() - 2].clone();
+            let previous_cost = cost_of(&history[previous.clone()]);
+            if previous_cost <= budget {
+                budget -= previous_cost;
+                pinned.start = previous.start;
+            }
+        }
+
+        let mut kept = self.select(&history[..pinned.start], budget, &cost);
+        kept.extend(&history[pinned]);
+        Ok(kept)
+    }
+
+    // Apply the strategy itself to the history before the pinned turns
+    fn select<'a>(&self, history: &'a [String], budget: usize, cost: &impl Fn(&str) -> usize) -> Vec<&'a String> {
+        // Groups that are kept or dropped as a whole
+        let groups = match self {
+            Self::DropOldest => (0..history.len()).map(|i| i..i + 1).collect(),
+            Self::DropMiddle { .. } | Self::Pairwise => turns(history),
+        };
+        let costs: Vec<usize> = groups
+            .iter()
+            .map(|group| history[group.clone()].iter().map(|entry| cost(entry)).sum())
+            .collect();
+
+        // Groups are considered in phases; each phase stops at the first group that doesn't fit
+        let newest_first: Vec<usize> = (0..groups.len()).rev().collect();
+        let phases = match *self {
+            Self::DropOldest | Self::Pairwise => vec![newest_first],
+            Self::DropMiddle { keep_first, keep_last } => {
+                let last = newest_first.iter().copied().take(keep_last).collect();
+                let first = (0..groups.len().min(keep_first)).collect();
+                vec![last, first, newest_first]
+            }
+        };
+
+        let mut kept = vec![false; groups.len()];
+        let mut used = 0;
+        for phase in phases {
+            for group in phase {
+                if kept[group] {
+                    continue;
+                }
+                if used + costs[group] > budget {
+                    break;
+                }
+                used += costs[group];
+                kept[group] = true;
+            }
+        }
+
+        groups
+            .into_iter()
+            .zip(kept)
+            .filter(|(_, kept)| *kept)
+            .flat_map(|(group, _)| &history[group])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STRATEGIES: [TruncationStrategy; 3] = [
+        TruncationStrategy::DropOldest,
+        TruncationStrategy::DropMiddle { keep_first: 1, keep_last: 3 },
+        TruncationStrategy::Pairwise,
+    ];
+
+    // One token per word keeps the budgets below easy to follow
+    fn words(entry: &str) -> usize {
+        entry.split_whitespace().count()
+    }
+
+    fn history(entries: &[&str]) -> Vec<String> {
+        entries.iter().map(|entry| entry.to_string()).collect()
+    }
+
+    // A conversation whose last message awaits its reply. Costs: 3 + 2, 5 + 6, then 4.
+    fn follow_up() -> Vec<String> {
+        history(&[
+            "user: hello there",
+            "assistant: hi",
+            "user: tell me about rust",
+            "assistant: rust is a systems language",
+            "user: and its tooling?",
+        ])
+    }
+
+    #[test]
+    fn newest_turn_is_always_kept() {
+        let history = history(&[
+            "user: one two three",
+            "assistant: four five six",
+            "user: seven eight",
+            "assistant: nine ten",
+            "user: latest question",
+            "assistant: latest answer",
+        ]);
+        for strategy in STRATEGIES {
+            for budget in [6, 9, 100] {
+                let kept = strategy.apply(&history, budget, words).unwrap();
+                assert_eq!(kept[kept.len() - 2..], [&history[4], &history[5]], "{:?} with budget {}", strategy, budget);
+            }
+        }
+    }
+
+    #[test]
+    fn awaiting_message_pins_previous_exchange_when_it_fits() {
+        let history = follow_up();
+        for strategy in STRATEGIES {
+            let kept = strategy.apply(&history, 15, words).unwrap();
+            assert_eq!(kept, [&history[2], &history[3], &history[4]], "{:?}", strategy);
+        }
+    }
+
+    #[test]
+    fn awaiting_message_does_not_pin_previous_exchange_that_does_not_fit() {
+        let history = follow_up();
+        for strategy in STRATEGIES {
+            let kept = strategy.apply(&history, 14, words).unwrap();
+            assert!(!kept.contains(&&history[2]), "{:?} kept {:?}", strategy, kept);
+            assert_eq!(kept.last(), Some(&&history[4]), "{:?}", strategy);
+        }
+        // Whole turns only: the reply can't be kept without its question
+        let kept = TruncationStrategy::Pairwise.apply(&history, 14, words).unwrap();
+        assert_eq!(kept, [&history[4]]);
+    }
+
+    #[test]
+    fn newest_message_over_budget_is_a_context_overflow() {
+        let history = history(&["user: short", "assistant: reply", "user: a question far too long for the budget"]);
+        for strategy in STRATEGIES {
+            let result = strategy.apply(&history, 5, words);
+            assert!(matches!(result, Err(BackendError::ContextOverflow(_))), "{:?}", strategy);
+        }
+    }
+
+    #[test]
+    fn empty_history_keeps_nothing() {
+        for strategy in STRATEGIES {
+            assert!(strategy.apply(&[], 0, words).unwrap().is_empty());
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::env;
+use std::time::Duration;
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{both_stages, ModerationAction, Moderator, Stage, Violation};
+use crate::http_client::http_client;
+
+// Timeout for a single moderation API call
+const EXTERNAL_MODERATION_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Deserialize)]
+pub struct ExternalConfig {
+    // Endpoint speaking the OpenAI moderation format
+    pub url: String,
+    // Environment variable holding the API key sent as a bearer token
+    pub api_key_env: Option<String>,
+    pub action: ModerationAction,
+    #[serde(default = "both_stages")]
+    pub stages: Vec<Stage>,
+}
+
+// Screens text with an external moderation API returning
+// `{"results": [{"flagged": bool, "categories": {"<name>": bool}}]}`
+pub struct ExternalModerator {
+    client: Client,
+    url: String,
+    api_key: Option<String>,
+    action: ModerationAction,
+    stages: Vec<Stage>,
+}
+
+impl ExternalModerator {
+    pub fn new(config: ExternalConfig) -> Self {
+        Self {
+            client: http_client().clone(),
+            url: config.url,
+            api_key: config.api_key_env.and_then(|name| env::var(name).ok()),
+            action: config.action,
+            stages: config.stages,
+        }
+    }
+}
+
+#[async_trait]
+impl Moderator for ExternalModerator {
+    fn name(&self) -> &str {
+        "external"
+    }
+
+    async fn check(&self, stage: Stage, text: &str) -> Result<Vec<Violation>> {
+        if !self.stages.contains(&stage) {
+            return Ok(Vec::new());
+        }
+
+        let mut request = self.client
+            .post(&self.url)
+            .timeout(EXTERNAL_MODERATION_TIMEOUT)
+            .json(&json!({ "input": text }));
+        if let Some(key) = &self.api_key {
+            request = request.bearer_auth(key);
+        }
+        let response: Value = request.send().await?.error_for_status()?.json().await?;
+
+        let Some(result) = response.get("results").and_then(|r| r.get(0)) else {
+            return Err(anyhow::anyhow!("Moderation response has no results"));
+        };
+        if !result.get("flagged").and_then(|f| f.as_bool()).unwrap_or(false) {
+            return Ok(Vec::new());
+        }
+
+        // Report each flagged category, or the bare flag when none are listed
+        let mut rules: Vec<String> = result
+            .get("categories")
+            .and_then(|c| c.as_object())
+            .map(|categories| {
+                categories
+                    .iter()
+                    .filter(|(_, flagged)| flagged.as_bool().unwrap_or(false))
+                    .map(|(name, _)| format!("external:{}", name))
+                    .collect()
+            })
+            .unwrap_or_default();
+        if rules.is_empty() {
+            rules.push("external:flagged".to_string());
+        }
+
+        Ok(rules
+            .into_iter()
+            .map(|rule| Violation {
+                rule,
+                action: self.action,
+                spans: Vec::new(),
+            })
+            .collect())
+    }
+}
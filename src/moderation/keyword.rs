@@ -0,0 +1,87 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use regex::Regex;
+use serde::Deserialize;
+
+use super::{both_stages, ModerationAction, Moderator, Stage, Violation};
+
+// A named set of keywords and regular expressions
+#[derive(Debug, Deserialize)]
+pub struct KeywordRule {
+    pub name: String,
+    // Whole words matched case-insensitively
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    pub action: ModerationAction,
+    #[serde(default = "both_stages")]
+    pub stages: Vec<Stage>,
+}
+
+struct CompiledRule {
+    name: String,
+    regexes: Vec<Regex>,
+    action: ModerationAction,
+    stages: Vec<Stage>,
+}
+
+// Screens text against keyword and regex rules
+pub struct KeywordModerator {
+    rules: Vec<CompiledRule>,
+}
+
+impl KeywordModerator {
+    pub fn new(rules: Vec<KeywordRule>) -> Result<Self> {
+        let rules = rules
+            .into_iter()
+            .map(|rule| {
+                let keywords = rule.keywords
+                    .iter()
+                    .map(|keyword| format!(r"(?i)\b{}\b", regex::escape(keyword)));
+                let regexes = rule.patterns
+                    .iter()
+                    .cloned()
+                    .chain(keywords)
+                    .map(|pattern| Regex::new(&pattern)
+                        .with_context(|| format!("Invalid pattern in moderation rule {}", rule.name)))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(CompiledRule {
+                    name: rule.name,
+                    regexes,
+                    action: rule.action,
+                    stages: rule.stages,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { rules })
+    }
+}
+
+#[async_trait]
+impl Moderator for KeywordModerator {
+    fn name(&self) -> &str {
+        "keyword"
+    }
+
+    async fn check(&self, stage: Stage, text: &str) -> Result<Vec<Violation>> {
+        let violations = self.rules
+            .iter()
+            .filter(|rule| rule.stages.contains(&stage))
+            .filter_map(|rule| {
+                let spans: Vec<(usize, usize)> = rule.regexes
+                    .iter()
+                    .flat_map(|regex| regex.find_iter(text).map(|m| (m.start(), m.end())))
+                    .collect();
+                (!spans.is_empty()).then(|| Violation {
+                    rule: rule.name.clone(),
+                    action: rule.action,
+                    spans,
+                })
+            })
+            .collect();
+
+        Ok(violations)
+    }
+}
@@ -0,0 +1,91 @@
+use std::sync::Arc;
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use super::{both_stages, ModerationAction, Moderator, Stage, Violation};
+use crate::model::LlamaModel;
+use crate::web::models::{Message, Role};
+
+// Llama Guard verdicts are short ("safe" / "unsafe\nS1,S10")
+const LLAMA_GUARD_MAX_TOKENS: usize = 20;
+
+#[derive(Debug, Deserialize)]
+pub struct LlamaGuardConfig {
+    // Registry model serving Llama Guard
+    pub model: String,
+    pub action: ModerationAction,
+    #[serde(default = "both_stages")]
+    pub stages: Vec<Stage>,
+}
+
+// Classifies text with a Llama Guard model served by the backend; the backend
+// applies the model's chat template, which carries the safety taxonomy
+pub struct LlamaGuardModerator {
+    model: Arc<LlamaModel>,
+    action: ModerationAction,
+    stages: Vec<Stage>,
+}
+
+impl LlamaGuardModerator {
+    pub fn new(model: Arc<LlamaModel>, config: LlamaGuardConfig) -> Self {
+        Self {
+            model,
+            action: config.action,
+            stages: config.stages,
+        }
+    }
+}
+
+// Parse "unsafe\nS1,S10" into the violated categories; None when the text is safe
+fn parse_verdict(verdict: &str) -> Option<Vec<String>> {
+    let mut lines = verdict.trim().lines();
+    if lines.next()?.trim() != "unsafe" {
+        return None;
+    }
+
+    let categories: Vec<String> = lines
+        .next()
+        .map(|line| line.split(',').map(|c| c.trim().to_string()).filter(|c| !c.is_empty()).collect())
+        .unwrap_or_default();
+    Some(categories)
+}
+
+#[async_trait]
+impl Moderator for LlamaGuardModerator {
+    fn name(&self) -> &str {
+        "llama_guard"
+    }
+
+    async fn check(&self, stage: Stage, text: &str) -> Result<Vec<Violation>> {
+        if !self.stages.contains(&stage) {
+            return Ok(Vec::new());
+        }
+
+        // Llama Guard judges the last turn, so output is presented as an assistant reply
+        let role = match stage {
+            Stage::Input => Role::User,
+            Stage::Output => Role::Assistant,
+        };
+        let mut messages = Vec::new();
+        if stage == Stage::Output {
+            messages.push(Message { role: Role::User, content: String::new() });
+        }
+        messages.push(Message { role, content: text.to_string() });
+
+        let verdict = self.model.complete_messages(&messages, LLAMA_GUARD_MAX_TOKENS, 0.0).await?;
+        let Some(categories) = parse_verdict(&verdict) else {
+            return Ok(Vec::new());
+        };
+
+        let rules = if categories.is_empty() { vec!["unsafe".to_string()] } else { categories };
+        Ok(rules
+            .into_iter()
+            .map(|category| Violation {
+                rule: format!("llama_guard:{}", category),
+                action: self.action,
+                spans: Vec::new(),
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,297 @@
+pub mod external;
+pub mod keyword;
+pub mod llama_guard;
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+use log::{info, warn, error};
+
+use crate::metrics::metrics;
+use crate::model::ModelManager;
+use external::{ExternalConfig, ExternalModerator};
+use keyword::{KeywordModerator, KeywordRule};
+use llama_guard::{LlamaGuardConfig, LlamaGuardModerator};
+
+// Default location of the moderation configuration
+const DEFAULT_MODERATION_CONFIG: &str = "moderation.toml";
+// Default file receiving audit records of triggered rules
+const DEFAULT_MODERATION_AUDIT_PATH: &str = "moderation_audit.jsonl";
+// Replacement text for redacted content
+const REDACTED: &str = "[REDACTED]";
+
+/// Environment variables for configuring content moderation:
+///
+/// - `MODERATION_CONFIG`: Path of the TOML moderation config (default: "moderation.toml");
+///   moderation is off when the file doesn't exist
+/// - `MODERATION_AUDIT_PATH`: JSON lines file recording every triggered rule
+///   (default: "moderation_audit.jsonl")
+///
+/// ```toml
+/// [[keyword_rules]]
+/// name = "credentials"
+/// patterns = ["(?i)password\\s*[:=]"]
+/// action = "redact"            # "block", "redact" or "log"
+/// stages = ["input"]           # default: both "input" and "output"
+///
+/// [llama_guard]
+/// model = "llama-guard"        # registry model running Llama Guard
+/// action = "block"
+///
+/// [external]
+/// url = "https://moderation.example.com/v1/moderations"
+/// api_key_env = "MODERATION_API_KEY"
+/// action = "log"
+/// ```
+
+// Which side of the conversation is being screened
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    Input,
+    Output,
+}
+
+pub(crate) fn both_stages() -> Vec<Stage> {
+    vec![Stage::Input, Stage::Output]
+}
+
+// What happens when a rule triggers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationAction {
+    Block,
+    Redact,
+    #[serde(alias = "log_only")]
+    Log,
+}
+
+// A triggered rule
+#[derive(Debug, Clone)]
+pub struct Violation {
+    pub rule: String,
+    pub action: ModerationAction,
+    // Byte ranges of the offending text; empty when the whole text is flagged
+    pub spans: Vec<(usize, usize)>,
+}
+
+#[async_trait]
+pub trait Moderator: Send + Sync {
+    fn name(&self) -> &str;
+
+    // Return the rules the text violates at this stage
+    async fn check(&self, stage: Stage, text: &str) -> Result<Vec<Violation>>;
+}
+
+// Outcome of screening a piece of text
+pub enum Screened {
+    // The text to use, possibly with redactions applied
+    Allowed(String),
+    // Names of the rules that blocked the text
+    Blocked(Vec<String>),
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ModerationConfig {
+    #[serde(default)]
+    keyword_rules: Vec<KeywordRule>,
+    llama_guard: Option<LlamaGuardConfig>,
+    external: Option<ExternalConfig>,
+}
+
+// Moderators applied in order to user input and model output
+pub struct ModerationPipeline {
+    moderators: Vec<Box<dyn Moderator>>,
+    audit_path: String,
+}
+
+// Replace the given byte ranges, or the whole text when there are none
+fn redact(text: &str, spans: &[(usize, usize)]) -> String {
+    if spans.is_empty() {
+        return REDACTED.to_string();
+    }
+
+    let mut spans = spans.to_vec();
+    spans.sort_unstable();
+    let mut output = String::with_capacity(text.len());
+    let mut cursor = 0;
+    for (start, end) in spans {
+        // Overlapping spans were already covered by the previous replacement
+        if start < cursor {
+            cursor = cursor.max(end);
+            continue;
+        }
+        output.push_str(&text[cursor..start]);
+        output.push_str(REDACTED);
+        cursor = end;
+    }
+    output.push_str(&text[cursor..]);
+    output
+}
+
+impl ModerationPipeline {
+    pub fn load(models: &ModelManager) -> Result<Self> {
+        let path = env::var("MODERATION_CONFIG")
+            .unwrap_or_else(|_| DEFAULT_MODERATION_CONFIG.to_string());
+        let audit_path = env::var("MODERATION_AUDIT_PATH")
+            .unwrap_or_else(|_| DEFAULT_MODERATION_AUDIT_PATH.to_string());
+
+        let config: ModerationConfig = if Path::new(&path).exists() {
+            let contents = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read moderation config {}", path))?;
+            toml::from_str(&contents)
+                .with_context(|| format!("Invalid moderation config {}", path))?
+        } else {
+            info!("No moderation config found at {}, moderation disabled", path);
+            ModerationConfig::default()
+        };
+
+        let mut moderators: Vec<Box<dyn Moderator>> = Vec::new();
+        if !config.keyword_rules.is_empty() {
+            moderators.push(Box::new(KeywordModerator::new(config.keyword_rules)?));
+        }
+        if let Some(guard) = config.llama_guard {
+            let model = models.get(Some(guard.model.as_str()))
+                .ok_or_else(|| anyhow::anyhow!("Unknown Llama Guard model: {}", guard.model))?;
+            moderators.push(Box::new(LlamaGuardModerator::new(model, guard)));
+        }
+        if let Some(external) = config.external {
+            moderators.push(Box::new(ExternalModerator::new(external)));
+        }
+
+        if !moderators.is_empty() {
+            let names: Vec<&str> = moderators.iter().map(|m| m.name()).collect();
+            info!("Content moderation enabled: {}", names.join(", "));
+        }
+
+        Ok(Self {
+            moderators,
+            audit_path,
+        })
+    }
+
+    // Run every moderator over the text, applying redactions as they trigger
+    pub async fn screen(&self, stage: Stage, text: &str, session_id: Uuid) -> Screened {
+        let mut text = text.to_string();
+
+        for moderator in &self.moderators {
+            // A failing moderator shouldn't take the chat down with it
+            let violations = match moderator.check(stage, &text).await {
+                Ok(violations) => violations,
+                Err(e) => {
+                    warn!("Moderator {} failed: {:#}", moderator.name(), e);
+                    continue;
+                }
+            };
+
+            for violation in &violations {
+                self.audit(session_id, stage, moderator.name(), violation);
+            }
+
+            let blocked: Vec<String> = violations
+                .iter()
+                .filter(|v| v.action == ModerationAction::Block)
+                .map(|v| v.rule.clone())
+                .collect();
+            if !blocked.is_empty() {
+                return Screened::Blocked(blocked);
+            }
+
+            let redactions: Vec<&Violation> = violations
+                .iter()
+                .filter(|v| v.action == ModerationAction::Redact)
+                .collect();
+            if redactions.iter().any(|v| v.spans.is_empty()) {
+                text = REDACTED.to_string();
+            } else if !redactions.is_empty() {
+                let spans: Vec<(usize, usize)> = redactions.iter().flat_map(|v| v.spans.iter().copied()).collect();
+                text = redact(&text, &spans);
+            }
+        }
+
+        Screened::Allowed(text)
+    }
+
+    // Record a triggered rule in the audit log and metrics
+    fn audit(&self, session_id: Uuid, stage: Stage, moderator: &str, violation: &Violation) {
+        warn!("Moderation rule {} ({}) triggered on {:?} of session {}: {:?}",
+            violation.rule, moderator, stage, session_id, violation.action);
+
+        let action = match violation.action {
+            ModerationAction::Block => "block",
+            ModerationAction::Redact => "redact",
+            ModerationAction::Log => "log",
+        };
+        metrics().increment("moderation_hits_total", &[("rule", violation.rule.as_str()), ("action", action)]);
+
+        let record = json!({
+            "at": Utc::now(),
+            "session_id": session_id,
+            "stage": stage,
+            "moderator": moderator,
+            "rule": violation.rule,
+            "action": violation.action,
+        });
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.audit_path)
+            .and_then(|mut file| writeln!(file, "{}", record));
+        if let Err(e) = result {
+            error!("Failed to write moderation audit record to {}: {}", self.audit_path, e);
+        }
+    }
+
+    // Audit records of the given sessions
+    pub fn audit_records_for(&self, sessions: &[Uuid]) -> Result<Vec<Value>> {
+        Ok(self.read_audit()?
+            .into_iter()
+            .filter(|record| Self::record_in(record, sessions))
+            .collect())
+    }
+
+    // Rewrite the audit log without the given sessions' records, returning how many were removed
+    pub fn purge_audit_records(&self, sessions: &[Uuid]) -> Result<usize> {
+        let records = self.read_audit()?;
+        let before = records.len();
+        let kept: Vec<Value> = records.into_iter().filter(|record| !Self::record_in(record, sessions)).collect();
+        let removed = before - kept.len();
+        if removed == 0 {
+            return Ok(0);
+        }
+
+        let tmp_path = format!("{}.tmp", self.audit_path);
+        let mut contents = String::new();
+        for record in &kept {
+            contents.push_str(&record.to_string());
+            contents.push('\n');
+        }
+        fs::write(&tmp_path, contents)?;
+        fs::rename(&tmp_path, &self.audit_path)?;
+        Ok(removed)
+    }
+
+    fn record_in(record: &Value, sessions: &[Uuid]) -> bool {
+        record
+            .get("session_id")
+            .and_then(|id| id.as_str())
+            .and_then(|id| id.parse::<Uuid>().ok())
+            .is_some_and(|id| sessions.contains(&id))
+    }
+
+    fn read_audit(&self) -> Result<Vec<Value>> {
+        let contents = match fs::read_to_string(&self.audit_path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(contents.lines().filter_map(|line| serde_json::from_str(line).ok()).collect())
+    }
+}
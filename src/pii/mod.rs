@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use anyhow::{Context, Result};
+use log::info;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+
+use crate::prompt_log::Redactor;
+
+// Default location of the custom PII patterns
+const DEFAULT_PII_PATTERNS_PATH: &str = "pii_patterns.toml";
+
+const EMAIL_PATTERN: &str = r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}";
+const CREDIT_CARD_PATTERN: &str = r"\b(?:\d[ -]?){12,18}\d\b";
+const PHONE_PATTERN: &str = r"(?:\+\d{1,3}[\s.-]?)?\(?\d{3}\)?[\s.-]?\d{3}[\s.-]?\d{4}\b";
+
+/// Environment variables for configuring PII redaction:
+///
+/// - `PII_REDACTION`: "off" (default), "mask" to replace detected values with a
+///   placeholder such as `[EMAIL]`, or "tokenize" to replace them with numbered
+///   tokens such as `[EMAIL_1]` that are swapped back in the reply
+/// - `PII_PATTERNS_PATH`: TOML file with additional patterns (default: "pii_patterns.toml")
+///
+/// ```toml
+/// [[patterns]]
+/// name = "employee_id"
+/// regex = "EMP-\\d{6}"
+/// ```
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PiiMode {
+    Off,
+    Mask,
+    Tokenize,
+}
+
+#[derive(Debug, Deserialize)]
+struct CustomPattern {
+    name: String,
+    regex: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct PatternFile {
+    #[serde(default)]
+    patterns: Vec<CustomPattern>,
+}
+
+struct Detector {
+    // Placeholder label, e.g. "EMAIL"
+    label: String,
+    regex: Regex,
+    // Reject matches failing a checksum (credit card numbers)
+    luhn: bool,
+}
+
+// Original values behind the tokens handed out for a session
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PiiVault {
+    values: HashMap<String, String>,
+}
+
+impl PiiVault {
+    // Reuse the token already assigned to a value so repeated mentions stay consistent
+    fn token_for(&mut self, label: &str, value: &str) -> String {
+        if let Some((token, _)) = self.values.iter().find(|(_, v)| v.as_str() == value) {
+            return token.clone();
+        }
+
+        let prefix = format!("[{}_", label);
+        let next = self.values.keys().filter(|t| t.starts_with(&prefix)).count() + 1;
+        let token = format!("{}{}]", prefix, next);
+        self.values.insert(token.clone(), value.to_string());
+        token
+    }
+}
+
+// Luhn checksum over the digits of a candidate card number
+fn luhn_valid(candidate: &str) -> bool {
+    let digits: Vec<u32> = candidate.chars().filter_map(|c| c.to_digit(10)).collect();
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| {
+            if i % 2 == 1 {
+                let doubled = d * 2;
+                if doubled > 9 { doubled - 9 } else { doubled }
+            } else {
+                d
+            }
+        })
+        .sum();
+    sum % 10 == 0
+}
+
+// Detects and masks personal data in user messages
+pub struct PiiFilter {
+    mode: PiiMode,
+    detectors: Vec<Detector>,
+}
+
+impl PiiFilter {
+    pub fn from_env() -> Result<Self> {
+        let mode = match env::var("PII_REDACTION").unwrap_or_default().to_lowercase().as_str() {
+            "" | "off" => PiiMode::Off,
+            "mask" => PiiMode::Mask,
+            "tokenize" => PiiMode::Tokenize,
+            other => return Err(anyhow::anyhow!("Invalid PII_REDACTION mode: {}", other)),
+        };
+        if mode == PiiMode::Off {
+            return Ok(Self { mode, detectors: Vec::new() });
+        }
+
+        let detectors = Self::load_detectors()?;
+        info!("PII redaction enabled ({:?}) with {} patterns", mode, detectors.len());
+        Ok(Self { mode, detectors })
+    }
+
+    // A filter that masks with every pattern, regardless of PII_REDACTION
+    pub fn masking() -> Result<Self> {
+        Ok(Self { mode: PiiMode::Mask, detectors: Self::load_detectors()? })
+    }
+
+    // Built-in detectors followed by the custom patterns file, if any
+    fn load_detectors() -> Result<Vec<Detector>> {
+        // Card numbers go first so their digit groups aren't taken for phone numbers
+        let mut detectors = vec![
+            Detector { label: "EMAIL".to_string(), regex: Regex::new(EMAIL_PATTERN)?, luhn: false },
+            Detector { label: "CREDIT_CARD".to_string(), regex: Regex::new(CREDIT_CARD_PATTERN)?, luhn: true },
+            Detector { label: "PHONE".to_string(), regex: Regex::new(PHONE_PATTERN)?, luhn: false },
+        ];
+
+        let path = env::var("PII_PATTERNS_PATH")
+            .unwrap_or_else(|_| DEFAULT_PII_PATTERNS_PATH.to_string());
+        if Path::new(&path).exists() {
+            let contents = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read PII patterns {}", path))?;
+            let file: PatternFile = toml::from_str(&contents)
+                .with_context(|| format!("Invalid PII patterns {}", path))?;
+            for pattern in file.patterns {
+                let regex = Regex::new(&pattern.regex)
+                    .with_context(|| format!("Invalid PII pattern {}", pattern.name))?;
+                detectors.push(Detector { label: pattern.name.to_uppercase(), regex, luhn: false });
+            }
+        }
+
+        Ok(detectors)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.mode != PiiMode::Off
+    }
+
+    // Replace detected values with placeholders, recording tokens in the vault
+    pub fn redact(&self, text: &str, vault: &mut PiiVault) -> String {
+        let mut text = text.to_string();
+
+        for detector in &self.detectors {
+            text = detector.regex
+                .replace_all(&text, |caps: &Captures| {
+                    let value = &caps[0];
+                    if detector.luhn && !luhn_valid(value) {
+                        return value.to_string();
+                    }
+                    match self.mode {
+                        PiiMode::Tokenize => vault.token_for(&detector.label, value),
+                        _ => format!("[{}]", detector.label),
+                    }
+                })
+                .into_owned();
+        }
+
+        text
+    }
+
+    // Swap tokens in a reply back to the values they stand for
+    pub fn restore(&self, text: &str, vault: &PiiVault) -> String {
+        if self.mode != PiiMode::Tokenize {
+            return text.to_string();
+        }
+
+        vault.values
+            .iter()
+            .fold(text.to_string(), |text, (token, value)| text.replace(token.as_str(), value))
+    }
+}
+
+impl Redactor for PiiFilter {
+    fn scrub(&self, text: &str) -> String {
+        self.redact(text, &mut PiiVault::default())
+    }
+}
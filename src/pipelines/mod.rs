@@ -0,0 +1,329 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use anyhow::{Context as _, Result};
+use futures::future::try_join_all;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use tera::{Context, Tera};
+use utoipa::ToSchema;
+
+use crate::extract;
+use crate::model::{GenerationOptions, ModelManager};
+use crate::prompts::is_valid_name;
+
+// Default directory holding the pipelines
+const DEFAULT_PIPELINES_DIR: &str = "pipelines";
+// Most steps a pipeline may have
+const MAX_PIPELINE_STEPS: usize = 32;
+// Tokens a generating step uses when it doesn't set max_tokens
+const DEFAULT_STEP_MAX_TOKENS: usize = 512;
+// Tokens an extract or classify step's JSON reply may use
+const STRUCTURED_STEP_MAX_TOKENS: usize = 1024;
+const EXTRACT_MAX_KEYWORDS: usize = 10;
+
+/// Environment variables for configuring pipelines:
+///
+/// - `PIPELINES_DIR`: Directory of pipelines, one `<name>.toml` file each (default: "pipelines")
+///
+/// A pipeline is a graph of steps. Each step renders a Tera template from the run's `input`
+/// variables and the outputs of the steps it `needs`, available as `steps.<id>`. Steps whose
+/// needs are met run concurrently.
+///
+/// ```toml
+/// description = "Triage a support ticket and draft a reply"
+///
+/// [[steps]]
+/// id = "facts"
+/// kind = "generate"       # "template", "generate", "extract" or "classify"
+/// template = "List the facts stated in this ticket:\n\n{{ input.ticket }}"
+/// max_tokens = 300        # optional, like model, system_prompt and temperature
+///
+/// [[steps]]
+/// id = "meta"
+/// kind = "extract"        # title, keywords, entities and sentiment of the rendered text
+/// needs = ["facts"]
+/// template = "{{ steps.facts }}"
+///
+/// [[steps]]
+/// id = "reply"
+/// kind = "generate"
+/// needs = ["facts", "meta"]
+/// template = "Write a {{ steps.meta.sentiment }}-aware reply to a customer, based on:\n{{ steps.facts }}"
+/// ```
+///
+/// `template` steps only render their template. `classify` steps choose among `labels`,
+/// keeping the best `top_k` (default 1). Generating steps use the model named in the run
+/// request unless they set `model`.
+
+// What a step does with its rendered template
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum StepKind {
+    Template,
+    Generate,
+    Extract,
+    Classify,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PipelineStep {
+    pub id: String,
+    pub kind: StepKind,
+    // Steps whose outputs this one uses; they run first
+    #[serde(default)]
+    pub needs: Vec<String>,
+    pub template: String,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub max_tokens: Option<usize>,
+    // Labels of a classify step
+    #[serde(default)]
+    pub labels: Vec<String>,
+    #[serde(default)]
+    pub top_k: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Pipeline {
+    #[serde(skip_deserializing)]
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub steps: Vec<PipelineStep>,
+}
+
+// Output of a step and what producing it took
+#[derive(Debug, Clone)]
+pub struct StepResult {
+    pub id: String,
+    pub kind: StepKind,
+    // A string, or the JSON object of an extract or classify step
+    pub output: Value,
+    // Model that ran the step; None for template steps
+    pub model: Option<String>,
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    pub cached_tokens: usize,
+    pub duration_ms: u64,
+}
+
+// Pipelines read from disk
+pub struct PipelineStore {
+    pipelines: BTreeMap<String, Pipeline>,
+}
+
+impl Pipeline {
+    // Steps grouped into stages: each stage only needs steps of earlier ones
+    fn stages(&self) -> Result<Vec<Vec<&PipelineStep>>> {
+        let mut done: HashSet<&str> = HashSet::new();
+        let mut pending: Vec<&PipelineStep> = self.steps.iter().collect();
+        let mut stages = Vec::new();
+        while !pending.is_empty() {
+            let (ready, waiting): (Vec<_>, Vec<_>) = pending
+                .into_iter()
+                .partition(|step| step.needs.iter().all(|need| done.contains(need.as_str())));
+            if ready.is_empty() {
+                let ids = waiting.iter().map(|step| step.id.as_str()).collect::<Vec<_>>().join(", ");
+                anyhow::bail!("Steps {} need each other", ids);
+            }
+            done.extend(ready.iter().map(|step| step.id.as_str()));
+            stages.push(ready);
+            pending = waiting;
+        }
+        Ok(stages)
+    }
+
+    fn validate(&self, models: &ModelManager) -> Result<()> {
+        if self.steps.is_empty() {
+            anyhow::bail!("Pipeline has no steps");
+        }
+        if self.steps.len() > MAX_PIPELINE_STEPS {
+            anyhow::bail!("Pipeline has more than {} steps", MAX_PIPELINE_STEPS);
+        }
+        let ids: HashSet<&str> = self.steps.iter().map(|step| step.id.as_str()).collect();
+        if ids.len() != self.steps.len() {
+            anyhow::bail!("Step ids must be unique");
+        }
+        for step in &self.steps {
+            if !is_valid_name(&step.id) || step.id.contains('-') {
+                anyhow::bail!("Step id {} may only contain letters, digits and '_'", step.id);
+            }
+            if let Some(need) = step.needs.iter().find(|need| !ids.contains(need.as_str())) {
+                anyhow::bail!("Step {} needs unknown step {}", step.id, need);
+            }
+            Tera::default()
+                .add_raw_template(&step.id, &step.template)
+                .with_context(|| format!("Invalid template in step {}", step.id))?;
+            if let Some(model) = &step.model {
+                if models.get(Some(model)).is_none() {
+                    anyhow::bail!("Step {}: unknown model {}", step.id, model);
+                }
+            }
+            if step.kind == StepKind::Classify {
+                if step.labels.len() < 2 {
+                    anyhow::bail!("Step {}: classify needs at least two labels", step.id);
+                }
+                if step.top_k.is_some_and(|k| k == 0 || k > step.labels.len()) {
+                    anyhow::bail!("Step {}: top_k must be between 1 and the number of labels", step.id);
+                }
+            }
+        }
+        self.stages().map(|_| ())
+    }
+
+    // Run every step, in stages, and return their results in the pipeline's order. `model` runs
+    // the steps that don't name one, with `options` as the base of every request.
+    pub async fn run(
+        &self,
+        models: &ModelManager,
+        model: Option<&str>,
+        input: &Map<String, Value>,
+        options: &GenerationOptions,
+    ) -> Result<Vec<StepResult>> {
+        let mut outputs: Map<String, Value> = Map::new();
+        let mut results: HashMap<String, StepResult> = HashMap::new();
+
+        for stage in self.stages()? {
+            let context = Context::from_serialize(json!({ "input": input, "steps": outputs }))?;
+            let finished = try_join_all(stage.into_iter().map(|step| {
+                let context = &context;
+                async move {
+                    run_step(step, context, models, model, options)
+                        .await
+                        .with_context(|| format!("Step {} failed", step.id))
+                }
+            }))
+            .await?;
+            for result in finished {
+                outputs.insert(result.id.clone(), result.output.clone());
+                results.insert(result.id.clone(), result);
+            }
+        }
+
+        Ok(self.steps.iter().filter_map(|step| results.remove(&step.id)).collect())
+    }
+}
+
+async fn run_step(
+    step: &PipelineStep,
+    context: &Context,
+    models: &ModelManager,
+    model: Option<&str>,
+    options: &GenerationOptions,
+) -> Result<StepResult> {
+    let started = Instant::now();
+    let text = Tera::one_off(&step.template, context, false)?;
+    let mut result = StepResult {
+        id: step.id.clone(),
+        kind: step.kind,
+        output: Value::Null,
+        model: None,
+        prompt_tokens: 0,
+        completion_tokens: 0,
+        cached_tokens: 0,
+        duration_ms: 0,
+    };
+    if step.kind == StepKind::Template {
+        result.output = Value::String(text);
+        return Ok(result);
+    }
+
+    let name = step.model.as_deref().or(model);
+    let llm = models.get(name)
+        .ok_or_else(|| anyhow::anyhow!("Unknown model: {}", name.unwrap_or_default()))?;
+    let mut options = options.clone();
+    options.system_prompt = step.system_prompt.clone();
+    options.temperature = step.temperature;
+    options.max_tokens = step.max_tokens.unwrap_or(DEFAULT_STEP_MAX_TOKENS);
+    let labels: Vec<String> = step.labels.iter().map(|label| label.trim().to_string()).collect();
+    let top_k = step.top_k.unwrap_or(1);
+    let prompt = match step.kind {
+        StepKind::Extract => {
+            options.json_schema = Some(extract::metadata_schema());
+            options.temperature = Some(step.temperature.unwrap_or(0.0));
+            options.max_tokens = step.max_tokens.unwrap_or(STRUCTURED_STEP_MAX_TOKENS);
+            extract::metadata_prompt(&text, EXTRACT_MAX_KEYWORDS)
+        }
+        StepKind::Classify => {
+            options.json_schema = Some(extract::classification_schema(&labels, top_k));
+            options.temperature = Some(step.temperature.unwrap_or(0.0));
+            options.max_tokens = step.max_tokens.unwrap_or(STRUCTURED_STEP_MAX_TOKENS);
+            extract::classification_prompt(&text, &labels, top_k)
+        }
+        _ => text,
+    };
+
+    let completion = llm.generate_response(&prompt, &[], &options).await?;
+    result.output = match step.kind {
+        StepKind::Extract => serde_json::to_value(extract::parse_reply::<extract::Metadata>(&completion.text)?)?,
+        StepKind::Classify => {
+            let scores = extract::parse_classification(&completion.text, &labels, top_k)?;
+            json!({ "label": scores[0].label, "confidence": scores[0].confidence, "labels": scores })
+        }
+        _ => Value::String(completion.text.trim().to_string()),
+    };
+    result.model = Some(llm.name().to_string());
+    result.prompt_tokens = completion.prompt_tokens;
+    result.completion_tokens = completion.completion_tokens;
+    result.cached_tokens = completion.cached_tokens;
+    result.duration_ms = started.elapsed().as_millis() as u64;
+    Ok(result)
+}
+
+impl PipelineStore {
+    pub fn load(models: &ModelManager) -> Self {
+        let dir = PathBuf::from(env::var("PIPELINES_DIR").unwrap_or_else(|_| DEFAULT_PIPELINES_DIR.to_string()));
+
+        let mut pipelines = BTreeMap::new();
+        match fs::read_dir(&dir) {
+            Ok(entries) => {
+                for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+                    if path.extension().and_then(|ext| ext.to_str()) != Some("toml") {
+                        continue;
+                    }
+                    match Self::read_pipeline(&path, models) {
+                        Ok(pipeline) => {
+                            pipelines.insert(pipeline.name.clone(), pipeline);
+                        }
+                        Err(e) => warn!("Skipping pipeline {}: {:#}", path.display(), e),
+                    }
+                }
+                info!("Loaded {} pipeline(s) from {}", pipelines.len(), dir.display());
+            }
+            Err(_) => info!("No pipelines directory at {}", dir.display()),
+        }
+
+        Self { pipelines }
+    }
+
+    fn read_pipeline(path: &Path, models: &ModelManager) -> Result<Pipeline> {
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .filter(|stem| is_valid_name(stem))
+            .ok_or_else(|| anyhow::anyhow!("Invalid pipeline file name"))?
+            .to_string();
+        let contents = fs::read_to_string(path)?;
+        let mut pipeline: Pipeline = toml::from_str(&contents)?;
+        pipeline.validate(models)?;
+        pipeline.name = name;
+        Ok(pipeline)
+    }
+
+    pub fn list(&self) -> Vec<Pipeline> {
+        self.pipelines.values().cloned().collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Pipeline> {
+        self.pipelines.get(name)
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+use actix_web::web::Data;
+use chrono::{DateTime, Duration, Utc};
+use log::{info, error};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::audit::audit;
+use crate::prompt_log::prompt_log;
+use crate::stats::stats;
+use crate::AppState;
+
+// Default delay between a confirmed deletion request and the purge
+const DEFAULT_DELETION_GRACE_SECS: i64 = 86400;
+// Time a deletion request waits for its confirmation
+const CONFIRMATION_TTL_SECS: i64 = 600;
+// How often scheduled deletions are checked
+const PURGE_INTERVAL_SECS: u64 = 60;
+
+/// Environment variables for configuring user data deletion:
+///
+/// - `DELETION_GRACE_SECS`: Seconds between confirming a deletion and purging the data,
+///   during which it can still be cancelled (default: 86400)
+
+struct PendingConfirmation {
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+// Two-step deletion requests and the purges they schedule. Scheduled
+// deletions are kept in memory and do not survive a restart.
+pub struct PrivacyManager {
+    grace: Duration,
+    confirmations: Mutex<HashMap<String, PendingConfirmation>>,
+    scheduled: Mutex<HashMap<String, DateTime<Utc>>>,
+}
+
+impl PrivacyManager {
+    pub fn from_env() -> Self {
+        let grace = env::var("DELETION_GRACE_SECS")
+            .ok()
+            .and_then(|v| v.parse::<i64>().ok())
+            .unwrap_or(DEFAULT_DELETION_GRACE_SECS)
+            .max(0);
+        Self {
+            grace: Duration::seconds(grace),
+            confirmations: Mutex::new(HashMap::new()),
+            scheduled: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Start a deletion request, returning the token that confirms it and its expiry
+    pub fn request_deletion(&self, user: &str) -> (String, DateTime<Utc>) {
+        let token = Uuid::new_v4().simple().to_string();
+        let expires_at = Utc::now() + Duration::seconds(CONFIRMATION_TTL_SECS);
+        if let Ok(mut confirmations) = self.confirmations.lock() {
+            confirmations.insert(user.to_string(), PendingConfirmation { token: token.clone(), expires_at });
+        }
+        (token, expires_at)
+    }
+
+    // Schedule the purge if the token matches an unexpired request, returning when it will run
+    pub fn confirm_deletion(&self, user: &str, token: &str) -> Option<DateTime<Utc>> {
+        let mut confirmations = self.confirmations.lock().ok()?;
+        let pending = confirmations.get(user)?;
+        if pending.token != token || pending.expires_at < Utc::now() {
+            return None;
+        }
+        confirmations.remove(user);
+
+        let delete_at = Utc::now() + self.grace;
+        self.scheduled.lock().ok()?.insert(user.to_string(), delete_at);
+        info!("Scheduled deletion of data for user {} at {}", user, delete_at);
+        Some(delete_at)
+    }
+
+    pub fn cancel_deletion(&self, user: &str) -> bool {
+        let cancelled = self.scheduled
+            .lock()
+            .map(|mut scheduled| scheduled.remove(user).is_some())
+            .unwrap_or(false);
+        if cancelled {
+            info!("Cancelled deletion of data for user {}", user);
+        }
+        cancelled
+    }
+
+    pub fn scheduled_deletion(&self, user: &str) -> Option<DateTime<Utc>> {
+        self.scheduled.lock().ok()?.get(user).copied()
+    }
+
+    // Remove and return the users whose grace period has passed
+    fn take_due(&self) -> Vec<String> {
+        let Ok(mut scheduled) = self.scheduled.lock() else { return Vec::new() };
+        let now = Utc::now();
+        let due: Vec<String> = scheduled
+            .iter()
+            .filter(|(_, delete_at)| **delete_at <= now)
+            .map(|(user, _)| user.clone())
+            .collect();
+        for user in &due {
+            scheduled.remove(user);
+        }
+        due
+    }
+}
+
+// Periodically purge users whose deletion grace period has passed
+pub fn start_purger(data: Data<AppState>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(std::time::Duration::from_secs(PURGE_INTERVAL_SECS));
+        loop {
+            ticker.tick().await;
+            for user in data.privacy.take_due() {
+                let removed = purge_user(&data, &user).await;
+                info!("Purged data for user {}: {}", user, removed);
+            }
+        }
+    });
+}
+
+// Everything stored about a user
+pub async fn export_user(data: &AppState, user: &str) -> Value {
+    let user_sessions = data.sessions.for_user(user).await;
+    let session_ids: Vec<Uuid> = user_sessions.iter().map(|(id, _)| *id).collect();
+    let sessions: Vec<Value> = user_sessions
+        .iter()
+        .map(|(id, session)| json!({
+            "session_id": id,
+            "assistant": session.assistant,
+            "settings": session.settings,
+            "tags": session.tags,
+            "pinned": session.pinned,
+            "archived": session.archived,
+            "history": session.history,
+        }))
+        .collect();
+
+    let audit_entries = audit().entries_for_user(user).unwrap_or_else(|e| {
+        error!("Failed to read audit log for export: {}", e);
+        Vec::new()
+    });
+    let moderation = data.moderation.audit_records_for(&session_ids).unwrap_or_else(|e| {
+        error!("Failed to read moderation audit log for export: {}", e);
+        Vec::new()
+    });
+    let prompts = prompt_log().records_for_user(user).unwrap_or_else(|e| {
+        error!("Failed to read prompt log for export: {}", e);
+        Vec::new()
+    });
+
+    json!({
+        "user": user,
+        "exported_at": Utc::now(),
+        "scheduled_deletion": data.privacy.scheduled_deletion(user),
+        "sessions": sessions,
+        "jobs": data.jobs.for_user(user),
+        "cache": data.model.cache().map(|cache| cache.export_user(user)),
+        "usage": stats().usage_for(user),
+        "audit": audit_entries,
+        "moderation": moderation,
+        "prompt_log": prompts,
+        "memories": data.memory.list(user),
+    })
+}
+
+// Delete everything stored about a user, returning what was removed from each store
+pub async fn purge_user(data: &AppState, user: &str) -> Value {
+    let session_ids: Vec<Uuid> = data.sessions.for_user(user).await.into_iter().map(|(id, _)| id).collect();
+    for id in &session_ids {
+        data.sessions.remove(id);
+    }
+
+    let moderation = data.moderation.purge_audit_records(&session_ids).unwrap_or_else(|e| {
+        error!("Failed to purge moderation audit records for user {}: {}", user, e);
+        0
+    });
+    let audit_entries = audit().purge_user(user).unwrap_or_else(|e| {
+        error!("Failed to purge audit entries for user {}: {}", user, e);
+        0
+    });
+    let prompt_records = prompt_log().purge_user(user).unwrap_or_else(|e| {
+        error!("Failed to purge prompt log records for user {}: {}", user, e);
+        0
+    });
+
+    json!({
+        "sessions": session_ids.len(),
+        "jobs": data.jobs.purge_user(user),
+        "cache_entries": data.model.cache().map_or(0, |cache| cache.purge_user(user)),
+        "idempotent_responses": data.idempotency.purge_user(user),
+        "usage": stats().forget_user(user),
+        "audit_entries": audit_entries,
+        "moderation_records": moderation,
+        "prompt_log_records": prompt_records,
+        "memories": data.memory.purge_user(user),
+    })
+}
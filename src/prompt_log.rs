@@ -0,0 +1,241 @@
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::sync::{Mutex, OnceLock, RwLock};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use log::{info, error};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::web::request_id;
+
+// Default file receiving logged prompts and completions
+const DEFAULT_PROMPT_LOG_PATH: &str = "prompt_log.jsonl";
+
+/// Environment variables for configuring prompt and completion logging:
+///
+/// - `PROMPT_LOG`: Record full prompts and completions at startup, "true" or "false"
+///   (default: false). Can be switched at runtime via `PUT /api/admin/prompt-log`.
+/// - `PROMPT_LOG_PATH`: JSON lines file receiving the records (default: "prompt_log.jsonl")
+/// - `PROMPT_LOG_SAMPLE_RATE`: Fraction of generations recorded, between 0 and 1 (default: 1)
+/// - `PROMPT_LOG_REDACT_PII`: Mask e-mail addresses, card and phone numbers and the custom
+///   PII patterns in records, whatever `PII_REDACTION` is set to (default: true)
+
+// Scrubs text before it is written to the prompt log
+pub trait Redactor: Send + Sync {
+    fn scrub(&self, text: &str) -> String;
+}
+
+// Runtime-adjustable logging switches
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+pub struct PromptLogSettings {
+    pub enabled: bool,
+    // Fraction of generations recorded, between 0 and 1
+    pub sample_rate: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct PromptRecord<'a> {
+    at: DateTime<Utc>,
+    request_id: Option<String>,
+    model: &'a str,
+    user: Option<&'a str>,
+    messages: Vec<Value>,
+    completion: String,
+    prompt_tokens: usize,
+    completion_tokens: usize,
+}
+
+// Sampled record of full prompts and completions, for debugging answer quality
+pub struct PromptLog {
+    path: String,
+    redact_pii: bool,
+    settings: RwLock<PromptLogSettings>,
+    redactors: RwLock<Vec<Box<dyn Redactor>>>,
+    // Opened lazily on the first write
+    file: Mutex<Option<File>>,
+}
+
+static PROMPT_LOG: OnceLock<PromptLog> = OnceLock::new();
+
+pub fn prompt_log() -> &'static PromptLog {
+    PROMPT_LOG.get_or_init(|| {
+        let enabled = env::var("PROMPT_LOG")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        let sample_rate = env::var("PROMPT_LOG_SAMPLE_RATE")
+            .ok()
+            .and_then(|v| v.parse::<f64>().ok())
+            .unwrap_or(1.0)
+            .clamp(0.0, 1.0);
+        let log = PromptLog {
+            path: env::var("PROMPT_LOG_PATH").unwrap_or_else(|_| DEFAULT_PROMPT_LOG_PATH.to_string()),
+            redact_pii: env::var("PROMPT_LOG_REDACT_PII")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(true),
+            settings: RwLock::new(PromptLogSettings { enabled, sample_rate }),
+            redactors: RwLock::new(Vec::new()),
+            file: Mutex::new(None),
+        };
+        if enabled {
+            info!("Logging {:.0}% of prompts and completions to {}", sample_rate * 100.0, log.path);
+        }
+        log
+    })
+}
+
+impl PromptLog {
+    pub fn settings(&self) -> PromptLogSettings {
+        self.settings.read().map(|s| *s).unwrap_or(PromptLogSettings { enabled: false, sample_rate: 0.0 })
+    }
+
+    pub fn update_settings(&self, settings: PromptLogSettings) -> PromptLogSettings {
+        let settings = PromptLogSettings {
+            enabled: settings.enabled,
+            sample_rate: settings.sample_rate.clamp(0.0, 1.0),
+        };
+        if let Ok(mut current) = self.settings.write() {
+            *current = settings;
+        }
+        info!("Prompt logging {} (sample rate {})", if settings.enabled { "enabled" } else { "disabled" }, settings.sample_rate);
+        settings
+    }
+
+    // Whether the PII patterns should be registered as a redaction hook
+    pub fn redacts_pii(&self) -> bool {
+        self.redact_pii
+    }
+
+    // Register a redaction hook; hooks run in registration order
+    pub fn add_redactor(&self, redactor: Box<dyn Redactor>) {
+        if let Ok(mut redactors) = self.redactors.write() {
+            redactors.push(redactor);
+        }
+    }
+
+    // Decide whether the next generation is recorded
+    pub fn sample(&self) -> bool {
+        let settings = self.settings();
+        settings.enabled && settings.sample_rate > 0.0 && rand::random::<f64>() < settings.sample_rate
+    }
+
+    fn scrub(&self, text: &str) -> String {
+        match self.redactors.read() {
+            Ok(redactors) => redactors.iter().fold(text.to_string(), |text, r| r.scrub(&text)),
+            Err(_) => text.to_string(),
+        }
+    }
+
+    // Record the messages sent to a model and its reply; callers check `sample` first
+    pub fn record(
+        &self,
+        model: &str,
+        user: Option<&str>,
+        messages: &[Value],
+        completion: &str,
+        prompt_tokens: usize,
+        completion_tokens: usize,
+    ) {
+        let messages = messages
+            .iter()
+            .map(|message| {
+                let mut message = message.clone();
+                if let Some(content) = message.get("content").and_then(|c| c.as_str()) {
+                    message["content"] = Value::String(self.scrub(content));
+                }
+                message
+            })
+            .collect();
+        let record = PromptRecord {
+            at: Utc::now(),
+            request_id: request_id::current(),
+            model,
+            user,
+            messages,
+            completion: self.scrub(completion),
+            prompt_tokens,
+            completion_tokens,
+        };
+
+        if let Err(e) = self.append(&record) {
+            error!("Failed to write prompt log record to {}: {}", self.path, e);
+        }
+    }
+
+    fn append(&self, record: &PromptRecord) -> Result<()> {
+        let line = serde_json::to_string(record)?;
+        let mut file = self.file.lock().map_err(|_| anyhow::anyhow!("prompt log lock poisoned"))?;
+        if file.is_none() {
+            *file = Some(OpenOptions::new().create(true).append(true).open(&self.path)?);
+        }
+        if let Some(file) = file.as_mut() {
+            writeln!(file, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    // Every record logged for a user, oldest first
+    pub fn records_for_user(&self, user: &str) -> Result<Vec<Value>> {
+        let mut records = Vec::new();
+        self.scan(|record| {
+            if record_user(&record) == Some(user) {
+                records.push(record);
+            }
+        })?;
+        Ok(records)
+    }
+
+    // Rewrite the log without a user's records, returning how many were removed
+    pub fn purge_user(&self, user: &str) -> Result<usize> {
+        // Hold the writer lock so no record is appended to the file being replaced
+        let mut file = self.file.lock().map_err(|_| anyhow::anyhow!("prompt log lock poisoned"))?;
+        let mut kept = Vec::new();
+        let mut removed = 0;
+        self.scan(|record| {
+            if record_user(&record) == Some(user) {
+                removed += 1;
+            } else {
+                kept.push(record);
+            }
+        })?;
+        if removed == 0 {
+            return Ok(0);
+        }
+
+        let tmp_path = format!("{}.tmp", self.path);
+        {
+            let mut tmp = File::create(&tmp_path)?;
+            for record in &kept {
+                writeln!(tmp, "{}", serde_json::to_string(record)?)?;
+            }
+            tmp.sync_all()?;
+        }
+        fs::rename(&tmp_path, &self.path)?;
+        // The old handle points at the replaced file
+        *file = None;
+        Ok(removed)
+    }
+
+    fn scan(&self, mut f: impl FnMut(Value)) -> Result<()> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            // Skip a partially written last line rather than failing the read
+            if let Ok(record) = serde_json::from_str::<Value>(&line) {
+                f(record);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn record_user(record: &Value) -> Option<&str> {
+    record.get("user").and_then(|u| u.as_str())
+}
@@ -0,0 +1,198 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{OnceLock, RwLock};
+use anyhow::{Context as _, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use serde_json::{Map, Value};
+use tera::{Context, Tera};
+use log::{info, warn};
+
+// Default directory holding the prompt templates
+const DEFAULT_PROMPTS_DIR: &str = "prompts";
+
+/// Environment variables for configuring the prompt template library:
+///
+/// - `PROMPTS_DIR`: Directory of prompt templates, one `<name>.toml` file each (default: "prompts")
+///
+/// A template file holds the Tera source and an optional description:
+///
+/// ```toml
+/// description = "Summarize a piece of text"
+/// template = "Summarize the following text in {{ sentences | default(value=3) }} sentences:\n\n{{ text }}"
+/// ```
+///
+/// Templates can't read the environment: Tera's `get_env` is refused when a template is stored
+/// and fails when one is rendered.
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PromptTemplate {
+    #[serde(skip_deserializing)]
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub template: String,
+}
+
+// Named prompt templates persisted as files in the prompts directory
+pub struct PromptLibrary {
+    dir: PathBuf,
+    templates: RwLock<BTreeMap<String, PromptTemplate>>,
+}
+
+// Template names double as file names, so keep them to a safe character set
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// Tera without `get_env`, so a template can't copy secrets such as ADMIN_TOKEN into a prompt
+fn sandboxed() -> Tera {
+    let mut tera = Tera::default();
+    tera.register_function("get_env", |_: &HashMap<String, Value>| -> tera::Result<Value> {
+        Err(tera::Error::msg("get_env is not available in prompt templates"))
+    });
+    tera
+}
+
+static GET_ENV_CALL: OnceLock<Regex> = OnceLock::new();
+
+// Check that a template parses and doesn't read the environment before it's stored
+fn validate(name: &str, template: &str) -> Result<()> {
+    let get_env = GET_ENV_CALL.get_or_init(|| Regex::new(r"\bget_env\s*\(").expect("valid regex"));
+    if get_env.is_match(template) {
+        return Err(anyhow::anyhow!("Templates may not call get_env"));
+    }
+    let mut tera = sandboxed();
+    tera.add_raw_template(name, template)?;
+    Ok(())
+}
+
+impl PromptLibrary {
+    pub fn load() -> Self {
+        let dir = PathBuf::from(env::var("PROMPTS_DIR").unwrap_or_else(|_| DEFAULT_PROMPTS_DIR.to_string()));
+        let mut templates = BTreeMap::new();
+
+        match fs::read_dir(&dir) {
+            Ok(entries) => {
+                for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+                    if path.extension().and_then(|ext| ext.to_str()) != Some("toml") {
+                        continue;
+                    }
+                    match Self::read_template(&path) {
+                        Ok(template) => {
+                            templates.insert(template.name.clone(), template);
+                        }
+                        Err(e) => warn!("Skipping prompt template {}: {:#}", path.display(), e),
+                    }
+                }
+                info!("Loaded {} prompt template(s) from {}", templates.len(), dir.display());
+            }
+            Err(_) => info!("No prompt templates directory at {}", dir.display()),
+        }
+
+        Self {
+            dir,
+            templates: RwLock::new(templates),
+        }
+    }
+
+    fn read_template(path: &Path) -> Result<PromptTemplate> {
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .filter(|stem| is_valid_name(stem))
+            .ok_or_else(|| anyhow::anyhow!("Invalid template file name"))?
+            .to_string();
+        let contents = fs::read_to_string(path)?;
+        let mut template: PromptTemplate = toml::from_str(&contents)?;
+        validate(&name, &template.template)?;
+        template.name = name;
+        Ok(template)
+    }
+
+    pub fn list(&self) -> Vec<PromptTemplate> {
+        self.templates
+            .read()
+            .map(|templates| templates.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<PromptTemplate> {
+        self.templates.read().ok()?.get(name).cloned()
+    }
+
+    // Create or replace a template and persist it to disk.
+    // Returns true when an existing template was replaced.
+    pub fn save(&self, name: &str, template: String, description: Option<String>) -> Result<bool> {
+        if !is_valid_name(name) {
+            return Err(anyhow::anyhow!("Template names may only contain letters, digits, '-' and '_'"));
+        }
+        validate(name, &template).context("Invalid template")?;
+
+        let prompt = PromptTemplate {
+            name: name.to_string(),
+            description,
+            template,
+        };
+
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.dir.join(format!("{}.toml", name)), toml::to_string(&prompt)?)?;
+
+        let mut templates = self.templates
+            .write()
+            .map_err(|_| anyhow::anyhow!("Prompt library lock poisoned"))?;
+        Ok(templates.insert(name.to_string(), prompt).is_some())
+    }
+
+    // Returns false when no template had that name
+    pub fn delete(&self, name: &str) -> Result<bool> {
+        let mut templates = self.templates
+            .write()
+            .map_err(|_| anyhow::anyhow!("Prompt library lock poisoned"))?;
+        if templates.remove(name).is_none() {
+            return Ok(false);
+        }
+
+        let path = self.dir.join(format!("{}.toml", name));
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(true)
+    }
+
+    // Render a named template with the supplied variables
+    pub fn render(&self, name: &str, vars: &Map<String, Value>) -> Result<String> {
+        let prompt = self.get(name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown prompt template: {}", name))?;
+
+        let mut tera = sandboxed();
+        tera.add_raw_template(name, &prompt.template)?;
+        let context = Context::from_serialize(vars)?;
+        tera.render(name, &context)
+            .with_context(|| format!("Failed to render prompt template {}", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template_reading_the_environment_is_rejected() {
+        assert!(validate("leak", "{{ get_env(name=\"ADMIN_TOKEN\") }}").is_err());
+        assert!(validate("leak", "{% set token = get_env ( name=\"ADMIN_TOKEN\" ) %}{{ token }}").is_err());
+        assert!(validate("summarize", "Summarize: {{ text }}").is_ok());
+    }
+
+    #[test]
+    fn get_env_fails_when_rendered() {
+        let mut tera = sandboxed();
+        tera.add_raw_template("leak", "{{ get_env(name=\"PATH\") }}").unwrap();
+        assert!(tera.render("leak", &Context::new()).is_err());
+    }
+}
@@ -0,0 +1,17 @@
+use anyhow::Result;
+
+use crate::model::{Completion, GenerationOptions, LlamaModel};
+
+// Sent after the draft, which the model sees as its own previous answer
+const REFINE_PROMPT: &str = "Review your previous answer as a demanding editor would. Check it for \
+    factual errors, gaps in reasoning, missed parts of the question and unclear wording. Then write \
+    the improved final answer. Reply with the final answer only, without the critique or any mention \
+    of a previous draft.";
+
+// Second pass of draft/refine mode: have the model critique its draft to the last message of
+// `history` and rewrite it. The draft's thinking isn't sent, only its answer.
+pub async fn refine(model: &LlamaModel, history: &[String], draft: &Completion, options: &GenerationOptions) -> Result<Completion> {
+    let mut history = history.to_vec();
+    history.push(format!("assistant: {}", draft.text));
+    model.generate_response(REFINE_PROMPT, &history, options).await
+}
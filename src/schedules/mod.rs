@@ -0,0 +1,340 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use actix_web::web::Data;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use log::{info, warn, error};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::jobs::webhook::WebhookSender;
+use crate::model::GenerationOptions;
+use crate::model::priority::Priority;
+use crate::prompts::is_valid_name;
+use crate::web::limits::max_session_history;
+use crate::AppState;
+
+// Default directory holding the scheduled prompts
+const DEFAULT_SCHEDULES_DIR: &str = "schedules";
+// How often the scheduler checks for due prompts
+const SCHEDULER_TICK: Duration = Duration::from_secs(1);
+// Answer length when neither the schedule nor its assistant sets max_tokens
+const DEFAULT_SCHEDULE_MAX_TOKENS: usize = 512;
+
+/// Environment variables for configuring scheduled prompts:
+///
+/// - `SCHEDULES_DIR`: Directory of scheduled prompts, one `<name>.toml` file each (default: "schedules")
+///
+/// A schedule runs a prompt whenever its cron expression fires. The expression has five
+/// fields (minute, hour, day of month, month, day of week) or six with leading seconds, and
+/// is evaluated in UTC. The answer is appended to `session_id`, whose history the prompt also
+/// sees, and/or posted to `webhook_url` as a `schedule.completed` or `schedule.failed` event:
+///
+/// ```toml
+/// description = "Morning briefing"
+/// cron = "0 8 * * Mon-Fri"
+/// prompt = "Summarize what we discussed yesterday and suggest three priorities for today."
+/// assistant = "coding-helper"     # optional system prompt and defaults
+/// model = "mistral"               # optional (default: the assistant's, then the default model)
+/// max_tokens = 400                # optional
+/// session_id = "3f0c5a4e-7d1b-4c55-9a51-0f7cf2a8b6de"
+/// webhook_url = "https://example.com/hooks/briefing"
+/// enabled = true                  # optional (default: true)
+/// ```
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Schedule {
+    #[serde(skip_deserializing)]
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub cron: String,
+    pub prompt: String,
+    #[serde(default)]
+    pub assistant: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub max_tokens: Option<usize>,
+    // Session the prompt runs in and the answer is appended to
+    #[serde(default)]
+    pub session_id: Option<Uuid>,
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+// What happened on a schedule's runs since startup
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct ScheduleState {
+    pub next_run_at: Option<DateTime<Utc>>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub runs: u64,
+    pub failures: u64,
+}
+
+// A schedule with its run state, as returned by the API
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ScheduleInfo {
+    #[serde(flatten)]
+    pub schedule: Schedule,
+    pub state: ScheduleState,
+}
+
+// Parse a five-field (no seconds) or six/seven-field cron expression
+pub fn parse_cron(expression: &str) -> Result<cron::Schedule> {
+    let expression = expression.trim();
+    let expression = if expression.split_whitespace().count() == 5 {
+        format!("0 {}", expression)
+    } else {
+        expression.to_string()
+    };
+    cron::Schedule::from_str(&expression).map_err(|e| anyhow::anyhow!("Invalid cron expression: {}", e))
+}
+
+// Scheduled prompts persisted as files in the schedules directory
+pub struct ScheduleStore {
+    dir: PathBuf,
+    schedules: RwLock<BTreeMap<String, Schedule>>,
+    states: RwLock<HashMap<String, ScheduleState>>,
+    webhooks: Arc<WebhookSender>,
+}
+
+impl ScheduleStore {
+    pub fn load() -> Self {
+        let dir = PathBuf::from(env::var("SCHEDULES_DIR").unwrap_or_else(|_| DEFAULT_SCHEDULES_DIR.to_string()));
+        let mut schedules = BTreeMap::new();
+
+        match fs::read_dir(&dir) {
+            Ok(entries) => {
+                for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+                    if path.extension().and_then(|ext| ext.to_str()) != Some("toml") {
+                        continue;
+                    }
+                    match Self::read_schedule(&path) {
+                        Ok(schedule) => {
+                            schedules.insert(schedule.name.clone(), schedule);
+                        }
+                        Err(e) => warn!("Skipping scheduled prompt {}: {:#}", path.display(), e),
+                    }
+                }
+                info!("Loaded {} scheduled prompt(s) from {}", schedules.len(), dir.display());
+            }
+            Err(_) => info!("No scheduled prompts directory at {}", dir.display()),
+        }
+
+        Self {
+            dir,
+            schedules: RwLock::new(schedules),
+            states: RwLock::new(HashMap::new()),
+            webhooks: Arc::new(WebhookSender::from_env()),
+        }
+    }
+
+    fn read_schedule(path: &Path) -> Result<Schedule> {
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .filter(|stem| is_valid_name(stem))
+            .ok_or_else(|| anyhow::anyhow!("Invalid schedule file name"))?
+            .to_string();
+        let contents = fs::read_to_string(path)?;
+        let mut schedule: Schedule = toml::from_str(&contents)?;
+        parse_cron(&schedule.cron)?;
+        schedule.name = name;
+        Ok(schedule)
+    }
+
+    fn state(&self, schedule: &Schedule) -> ScheduleState {
+        let mut state = self.states
+            .read()
+            .ok()
+            .and_then(|states| states.get(&schedule.name).cloned())
+            .unwrap_or_default();
+        state.next_run_at = schedule
+            .enabled
+            .then(|| parse_cron(&schedule.cron).ok()?.upcoming(Utc).next())
+            .flatten();
+        state
+    }
+
+    pub fn list(&self) -> Vec<ScheduleInfo> {
+        let schedules: Vec<Schedule> = self.schedules
+            .read()
+            .map(|schedules| schedules.values().cloned().collect())
+            .unwrap_or_default();
+        schedules
+            .into_iter()
+            .map(|schedule| ScheduleInfo { state: self.state(&schedule), schedule })
+            .collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<ScheduleInfo> {
+        let schedule = self.schedules.read().ok()?.get(name).cloned()?;
+        Some(ScheduleInfo { state: self.state(&schedule), schedule })
+    }
+
+    // Create or replace a schedule and persist it to disk.
+    // Returns true when an existing schedule was replaced.
+    pub fn save(&self, mut schedule: Schedule, name: &str) -> Result<bool> {
+        if !is_valid_name(name) {
+            return Err(anyhow::anyhow!("Schedule names may only contain letters, digits, '-' and '_'"));
+        }
+        parse_cron(&schedule.cron)?;
+        schedule.name = name.to_string();
+
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.dir.join(format!("{}.toml", name)), toml::to_string(&schedule)?)?;
+
+        let mut schedules = self.schedules
+            .write()
+            .map_err(|_| anyhow::anyhow!("Schedule store lock poisoned"))?;
+        Ok(schedules.insert(name.to_string(), schedule).is_some())
+    }
+
+    // Returns false when no schedule had that name
+    pub fn delete(&self, name: &str) -> Result<bool> {
+        let mut schedules = self.schedules
+            .write()
+            .map_err(|_| anyhow::anyhow!("Schedule store lock poisoned"))?;
+        if schedules.remove(name).is_none() {
+            return Ok(false);
+        }
+        if let Ok(mut states) = self.states.write() {
+            states.remove(name);
+        }
+
+        let path = self.dir.join(format!("{}.toml", name));
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(true)
+    }
+
+    fn record(&self, name: &str, result: &Result<String>) {
+        let Ok(mut states) = self.states.write() else { return };
+        let state = states.entry(name.to_string()).or_default();
+        state.last_run_at = Some(Utc::now());
+        state.runs += 1;
+        match result {
+            Ok(_) => state.last_error = None,
+            Err(e) => {
+                state.failures += 1;
+                state.last_error = Some(format!("{:#}", e));
+            }
+        }
+    }
+}
+
+// Run due schedules in the background. Each tick fires the schedules with a run time
+// between the previous tick and now, so a run is never repeated or skipped while the
+// server is up; runs missed while it was down are not caught up.
+pub fn start_scheduler(data: Data<AppState>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(SCHEDULER_TICK);
+        let mut last_tick = Utc::now();
+        loop {
+            ticker.tick().await;
+            let now = Utc::now();
+            for info in data.schedules.list() {
+                let schedule = info.schedule;
+                if !schedule.enabled {
+                    continue;
+                }
+                let Ok(cron) = parse_cron(&schedule.cron) else { continue };
+                if cron.after(&last_tick).next().is_some_and(|at| at <= now) {
+                    let data = data.clone();
+                    tokio::spawn(async move { run_schedule(&data, schedule).await });
+                }
+            }
+            last_tick = now;
+        }
+    });
+}
+
+// Run a schedule's prompt once and deliver the answer
+pub async fn run_schedule(data: &AppState, schedule: Schedule) -> Result<String> {
+    info!("Running scheduled prompt {}", schedule.name);
+    let result = generate(data, &schedule).await;
+    match &result {
+        Ok(_) => info!("Scheduled prompt {} completed", schedule.name),
+        Err(e) => error!("Scheduled prompt {} failed: {:#}", schedule.name, e),
+    }
+    data.schedules.record(&schedule.name, &result);
+
+    if let Some(url) = &schedule.webhook_url {
+        let (event, outcome) = match &result {
+            Ok(response) => ("schedule.completed", json!({ "response": response })),
+            Err(e) => ("schedule.failed", json!({ "error": format!("{:#}", e) })),
+        };
+        data.schedules.webhooks.deliver_event(url.clone(), event, json!({
+            "schedule": schedule.name,
+            "session_id": schedule.session_id,
+            "ran_at": Utc::now(),
+            "result": outcome,
+        }));
+    }
+    result
+}
+
+async fn generate(data: &AppState, schedule: &Schedule) -> Result<String> {
+    let assistant = match &schedule.assistant {
+        Some(name) => Some(data.assistants.get(name).ok_or_else(|| anyhow::anyhow!("Unknown assistant: {}", name))?),
+        None => None,
+    };
+    let model_name = schedule.model
+        .as_deref()
+        .or_else(|| assistant.as_ref().and_then(|a| a.model.as_deref()));
+    let model = data.model
+        .get(model_name)
+        .ok_or_else(|| anyhow::anyhow!("Unknown model: {}", model_name.unwrap_or_default()))?;
+
+    let max_tokens = schedule.max_tokens
+        .or_else(|| assistant.as_ref().and_then(|a| a.max_tokens))
+        .unwrap_or(DEFAULT_SCHEDULE_MAX_TOKENS);
+    let mut options = GenerationOptions::new(max_tokens);
+    // A scheduled prompt is usually meant to produce something new each time
+    options.use_cache = false;
+    // Nobody is waiting on a scheduled prompt, so interactive requests go first
+    options.priority = Priority::Low;
+    if let Some(assistant) = &assistant {
+        options.system_prompt = Some(assistant.system_prompt.clone());
+        options.examples = assistant.example_messages();
+        options.temperature = assistant.temperature;
+        options.top_p = assistant.top_p;
+    }
+
+    let prompt = match &assistant {
+        Some(assistant) => assistant.with_post_amble(&schedule.prompt),
+        None => schedule.prompt.clone(),
+    };
+    let Some(session_id) = schedule.session_id else {
+        return Ok(model.generate_response(&prompt, &[], &options).await?.text);
+    };
+
+    // Take the session's turn like a chat request, so the answer lands after any reply in progress
+    let _turn = data.sessions.begin_turn(session_id).await;
+    let session = data.sessions.get_or_create(session_id);
+    let history = {
+        let mut session = session.lock().await;
+        options.user = session.user.clone();
+        session.push_history(format!("user: {}", schedule.prompt), max_session_history());
+        session.history.clone()
+    };
+    let response = model.generate_response(&prompt, &history, &options).await?.text;
+    session.lock().await.push_history(format!("assistant: {}", response), max_session_history());
+    Ok(response)
+}
@@ -0,0 +1,235 @@
+pub mod search;
+pub mod snapshot;
+pub mod trash;
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, PoisonError, RwLock};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::pii::PiiVault;
+
+// Longest tag accepted, in characters, and the most tags a session can carry
+pub const MAX_TAG_CHARS: usize = 32;
+pub const MAX_TAGS_PER_SESSION: usize = 20;
+
+// Generation settings persisted for a session; unset fields fall back to the
+// assistant profile and then to the global defaults
+#[derive(Debug, Default, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SessionSettings {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<usize>,
+    pub model: Option<String>,
+    // Language of replies: "auto" for the language of each message, or a language
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+// Conversation state kept for each chat session
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    // Messages prefixed with their role, e.g. "user: ..." / "assistant: ..."
+    pub history: Vec<String>,
+    // Assistant profile selected for this session
+    pub assistant: Option<String>,
+    // User named by the session's chat requests, for data export and deletion
+    pub user: Option<String>,
+    pub settings: SessionSettings,
+    // Values behind the PII tokens used in this session's history
+    pub pii_vault: PiiVault,
+    // Messages added since facts were last extracted into long-term memory
+    pub messages_since_memory: usize,
+    // Labels for organizing conversations, e.g. "work" or "drafts"
+    pub tags: BTreeSet<String>,
+    // Document collections searched for context on every message
+    pub collections: Vec<Uuid>,
+    // When the last message was added
+    pub updated_at: Option<DateTime<Utc>>,
+    // Pinned sessions are listed first
+    pub pinned: bool,
+    // Archived sessions are left out of the session list unless asked for; nothing is deleted
+    pub archived: bool,
+    // Anonymous browser that started the session, see `web::identity`
+    pub owner: Option<Uuid>,
+    // Counts the changes made to the session, for its ETag
+    pub version: u64,
+    // When the session was moved to the trash; trashed sessions are hidden everywhere but
+    // the trash until they are restored or purged
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+// Overview of a session for the session list
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SessionSummary {
+    pub session_id: Uuid,
+    pub user: Option<String>,
+    pub assistant: Option<String>,
+    pub tags: BTreeSet<String>,
+    pub messages_total: usize,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub pinned: bool,
+    pub archived: bool,
+    // Set for sessions in the trash, with the time they will be purged
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub purge_at: Option<DateTime<Utc>>,
+}
+
+// Tags are compared case-insensitively; None when the tag is empty or too long
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag.trim().to_lowercase();
+    (!tag.is_empty() && tag.chars().count() <= MAX_TAG_CHARS).then_some(tag)
+}
+
+// Sessions by ID. The map's lock is only held to look up, add or remove a session and never
+// across an await; each session has its own async lock, so a slow request only holds up its
+// own session.
+#[derive(Default)]
+pub struct SessionStore {
+    sessions: RwLock<HashMap<Uuid, Arc<Mutex<Session>>>>,
+    // Held for the whole of a chat turn, separately from the session so reads aren't blocked
+    turns: RwLock<HashMap<Uuid, Arc<Mutex<()>>>>,
+}
+
+impl SessionStore {
+    pub fn len(&self) -> usize {
+        self.sessions.read().map(|sessions| sessions.len()).unwrap_or(0)
+    }
+
+    pub fn get(&self, id: &Uuid) -> Option<Arc<Mutex<Session>>> {
+        self.sessions.read().ok()?.get(id).cloned()
+    }
+
+    // The session with this ID, created empty if it doesn't exist yet
+    pub fn get_or_create(&self, id: Uuid) -> Arc<Mutex<Session>> {
+        self.sessions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(id)
+            .or_default()
+            .clone()
+    }
+
+    pub fn remove(&self, id: &Uuid) {
+        if let Ok(mut sessions) = self.sessions.write() {
+            sessions.remove(id);
+        }
+        if let Ok(mut turns) = self.turns.write() {
+            turns.remove(id);
+        }
+    }
+
+    // Wait until no other chat turn of the session is in progress. Tokio's mutex is fair, so
+    // waiting turns proceed in the order they arrived.
+    pub async fn begin_turn(&self, id: Uuid) -> OwnedMutexGuard<()> {
+        let turn = {
+            let mut turns = self.turns.write().unwrap_or_else(PoisonError::into_inner);
+            // Forget the locks of finished turns: only the map holds them once no turn has or
+            // awaits them, and handles are only cloned under this write lock. Turns are taken
+            // before the session is checked, so without this every unknown ID would stay.
+            turns.retain(|_, turn| Arc::strong_count(turn) > 1);
+            turns.entry(id).or_default().clone()
+        };
+        turn.lock_owned().await
+    }
+
+    // Handles to every session, taken so the map isn't locked while sessions are awaited
+    fn entries(&self) -> Vec<(Uuid, Arc<Mutex<Session>>)> {
+        self.sessions
+            .read()
+            .map(|sessions| sessions.iter().map(|(id, session)| (*id, session.clone())).collect())
+            .unwrap_or_default()
+    }
+
+    // Summaries of the sessions matching `filter`, pinned ones first, then most recently active first
+    pub async fn list(&self, filter: impl Fn(&Session) -> bool) -> Vec<SessionSummary> {
+        let mut summaries = Vec::new();
+        for (id, session) in self.entries() {
+            let session = session.lock().await;
+            if filter(&session) {
+                summaries.push(session.summary(id));
+            }
+        }
+        summaries.sort_by(|a, b| {
+            b.pinned.cmp(&a.pinned)
+                .then(b.updated_at.cmp(&a.updated_at))
+                .then(a.session_id.cmp(&b.session_id))
+        });
+        summaries
+    }
+
+    // Copies of the sessions whose requests named this user
+    pub async fn for_user(&self, user: &str) -> Vec<(Uuid, Session)> {
+        let mut found = Vec::new();
+        for (id, session) in self.entries() {
+            let session = session.lock().await;
+            if session.user.as_deref() == Some(user) {
+                found.push((id, session.clone()));
+            }
+        }
+        found
+    }
+}
+
+impl Session {
+    pub fn summary(&self, id: Uuid) -> SessionSummary {
+        SessionSummary {
+            session_id: id,
+            user: self.user.clone(),
+            assistant: self.assistant.clone(),
+            tags: self.tags.clone(),
+            messages_total: self.history.len(),
+            updated_at: self.updated_at,
+            pinned: self.pinned,
+            archived: self.archived,
+            deleted_at: self.deleted_at,
+            purge_at: self.deleted_at.map(trash::purge_at),
+        }
+    }
+
+    // Record a change, so requests that read the session before it fail their If-Match check
+    pub fn touch(&mut self) {
+        self.version += 1;
+    }
+
+    // Append a history entry, dropping the oldest ones beyond `max`
+    pub fn push_history(&mut self, entry: String, max: usize) {
+        self.touch();
+        self.history.push(entry);
+        self.updated_at = Some(Utc::now());
+        if self.history.len() > max {
+            let excess = self.history.len() - max;
+            self.history.drain(..excess);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn finished_turns_are_forgotten() {
+        let store = SessionStore::default();
+        for _ in 0..10 {
+            drop(store.begin_turn(Uuid::new_v4()).await);
+        }
+        let _turn = store.begin_turn(Uuid::new_v4()).await;
+        assert_eq!(store.turns.read().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn turns_in_progress_are_kept() {
+        let store = SessionStore::default();
+        let id = Uuid::new_v4();
+        let _first = store.begin_turn(id).await;
+        drop(store.begin_turn(Uuid::new_v4()).await);
+        assert!(store.turns.read().unwrap().contains_key(&id));
+    }
+}
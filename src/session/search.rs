@@ -0,0 +1,136 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use super::Session;
+
+// Characters of context shown before the first match in a message; the snippet is twice as long after it
+const SNIPPET_CONTEXT_CHARS: usize = 60;
+// Matching messages returned per session
+const MAX_MATCHES_PER_SESSION: usize = 5;
+
+// Span of a search term inside a snippet, in characters
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Highlight {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MessageMatch {
+    // Position of the message in the session history
+    pub index: usize,
+    pub role: String,
+    pub snippet: String,
+    pub highlights: Vec<Highlight>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SessionMatch {
+    pub session_id: Uuid,
+    pub assistant: Option<String>,
+    // Occurrences of the search terms across the session
+    pub score: usize,
+    pub matches: Vec<MessageMatch>,
+}
+
+// Case-insensitive comparison key, one char per input char so offsets line up
+fn fold(text: &str) -> Vec<char> {
+    text.chars().map(|c| c.to_lowercase().next().unwrap_or(c)).collect()
+}
+
+// Character offsets of every occurrence of `term` in `text`
+fn occurrences(text: &[char], term: &[char]) -> Vec<usize> {
+    if term.is_empty() || term.len() > text.len() {
+        return Vec::new();
+    }
+    (0..=text.len() - term.len())
+        .filter(|&i| text[i..i + term.len()] == *term)
+        .collect()
+}
+
+// Find the messages of the given sessions (those of one user) containing every term of
+// the query, best-scoring sessions first. `restore` turns a stored message back into the
+// text the user saw (PII tokens swapped back in).
+pub fn search(
+    sessions: &[(Uuid, Session)],
+    query: &str,
+    limit: usize,
+    restore: impl Fn(&Session, &str) -> String,
+) -> Vec<SessionMatch> {
+    let terms: Vec<Vec<char>> = query.split_whitespace().map(fold).collect();
+    if terms.is_empty() {
+        return Vec::new();
+    }
+
+    let mut results: Vec<SessionMatch> = sessions
+        .iter()
+        .filter_map(|(id, session)| {
+            let mut score = 0;
+            let mut matches = Vec::new();
+            for (index, entry) in session.history.iter().enumerate() {
+                let (role, text) = entry.split_once(": ").unwrap_or(("", entry.as_str()));
+                let text = restore(session, text);
+                let chars: Vec<char> = text.chars().collect();
+                let folded = fold(&text);
+
+                // Every term must appear in the message
+                let starts: Vec<Vec<usize>> = terms.iter().map(|term| occurrences(&folded, term)).collect();
+                if starts.iter().any(Vec::is_empty) {
+                    continue;
+                }
+                let hits: Vec<(usize, usize)> = terms
+                    .iter()
+                    .zip(&starts)
+                    .flat_map(|(term, starts)| starts.iter().map(move |&start| (start, start + term.len())))
+                    .collect();
+                score += hits.len();
+
+                if matches.len() < MAX_MATCHES_PER_SESSION {
+                    matches.push(snippet(index, role, &chars, hits));
+                }
+            }
+            (!matches.is_empty()).then(|| SessionMatch {
+                session_id: *id,
+                assistant: session.assistant.clone(),
+                score,
+                matches,
+            })
+        })
+        .collect();
+
+    results.sort_by(|a, b| b.score.cmp(&a.score).then(a.session_id.cmp(&b.session_id)));
+    results.truncate(limit);
+    results
+}
+
+// Cut a window around the first hit and translate the hits into it
+fn snippet(index: usize, role: &str, chars: &[char], mut hits: Vec<(usize, usize)>) -> MessageMatch {
+    hits.sort_unstable();
+    let first = hits.first().map_or(0, |(start, _)| *start);
+    let from = first.saturating_sub(SNIPPET_CONTEXT_CHARS);
+    let to = (first + SNIPPET_CONTEXT_CHARS * 2).min(chars.len());
+
+    let mut snippet: String = chars[from..to].iter().collect();
+    let mut offset = 0;
+    if from > 0 {
+        snippet.insert(0, '…');
+        offset = 1;
+    }
+    if to < chars.len() {
+        snippet.push('…');
+    }
+
+    let highlights = hits
+        .into_iter()
+        .filter(|(start, end)| *start >= from && *end <= to)
+        .map(|(start, end)| Highlight { start: start - from + offset, end: end - from + offset })
+        .collect();
+
+    MessageMatch {
+        index,
+        role: role.to_string(),
+        snippet,
+        highlights,
+    }
+}
@@ -0,0 +1,52 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{Session, SessionStore};
+
+// Format of the archive; bumped when a change would make older servers misread it
+pub const STATE_ARCHIVE_VERSION: u32 = 1;
+
+// Every session of an instance with all its metadata, for moving a running server to
+// another host. PII vaults travel along, so archives need the same care as the server itself.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StateArchive {
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub sessions: Vec<SessionRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub session_id: Uuid,
+    #[serde(flatten)]
+    pub session: Session,
+}
+
+impl SessionStore {
+    // Copy every session. Each one is read under its own lock, so a turn in progress is
+    // captured either entirely or not at all.
+    pub async fn export_state(&self) -> StateArchive {
+        let mut sessions = Vec::new();
+        for (session_id, session) in self.entries() {
+            sessions.push(SessionRecord { session_id, session: session.lock().await.clone() });
+        }
+        sessions.sort_by_key(|record| record.session_id);
+        StateArchive { version: STATE_ARCHIVE_VERSION, exported_at: Utc::now(), sessions }
+    }
+
+    // Load archived sessions, replacing those with the same ID. With `replace`, sessions
+    // missing from the archive are dropped first. Returns the number of sessions loaded.
+    pub async fn import_state(&self, archive: StateArchive, replace: bool) -> usize {
+        if replace {
+            for (id, _) in self.entries() {
+                self.remove(&id);
+            }
+        }
+        let count = archive.sessions.len();
+        for record in archive.sessions {
+            *self.get_or_create(record.session_id).lock().await = record.session;
+        }
+        count
+    }
+}
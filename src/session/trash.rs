@@ -0,0 +1,63 @@
+use std::env;
+use actix_web::web::Data;
+use chrono::{DateTime, Duration, Utc};
+use log::info;
+use uuid::Uuid;
+
+use super::SessionStore;
+use crate::AppState;
+
+// Default number of days a deleted session stays in the trash
+const DEFAULT_TRASH_RETENTION_DAYS: i64 = 30;
+// How often the trash is checked for sessions past their retention
+const TRASH_PURGE_INTERVAL_SECS: u64 = 3600;
+
+/// Environment variables for the session trash:
+///
+/// - `SESSION_TRASH_RETENTION_DAYS`: Days a deleted session can be restored before it is
+///   purged for good (default: 30)
+
+pub fn retention() -> Duration {
+    let days = env::var("SESSION_TRASH_RETENTION_DAYS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_TRASH_RETENTION_DAYS);
+    Duration::days(days.max(0))
+}
+
+// When a session deleted at `deleted_at` is purged
+pub fn purge_at(deleted_at: DateTime<Utc>) -> DateTime<Utc> {
+    deleted_at + retention()
+}
+
+impl SessionStore {
+    // Drop the sessions that have been in the trash longer than the retention.
+    // Returns the IDs of the sessions purged.
+    pub async fn purge_trash(&self) -> Vec<Uuid> {
+        let now = Utc::now();
+        let mut expired = Vec::new();
+        for (id, session) in self.entries() {
+            if session.lock().await.deleted_at.is_some_and(|deleted_at| purge_at(deleted_at) <= now) {
+                expired.push(id);
+            }
+        }
+        for id in &expired {
+            self.remove(id);
+        }
+        expired
+    }
+}
+
+// Periodically purge sessions whose time in the trash is up
+pub fn start_purger(data: Data<AppState>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(std::time::Duration::from_secs(TRASH_PURGE_INTERVAL_SECS));
+        loop {
+            ticker.tick().await;
+            let purged = data.sessions.purge_trash().await;
+            if !purged.is_empty() {
+                info!("Purged {} session(s) from the trash", purged.len());
+            }
+        }
+    });
+}
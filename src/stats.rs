@@ -0,0 +1,120 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+// Window over which tokens/sec is averaged
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(60);
+// Number of recent errors kept for the admin dashboard
+const MAX_RECENT_ERRORS: usize = 50;
+
+// User name recorded for requests that don't identify a user
+pub const ANONYMOUS_USER: &str = "anonymous";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorRecord {
+    pub at: DateTime<Utc>,
+    pub source: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UserUsage {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+#[derive(Default)]
+struct StatsInner {
+    // Completion token counts of recent generations, for throughput
+    completions: VecDeque<(Instant, usize)>,
+    errors: VecDeque<ErrorRecord>,
+    users: BTreeMap<String, UserUsage>,
+}
+
+// Live usage statistics shown on the admin dashboard
+pub struct Stats {
+    started_at: Instant,
+    inner: Mutex<StatsInner>,
+}
+
+static STATS: OnceLock<Stats> = OnceLock::new();
+
+pub fn stats() -> &'static Stats {
+    STATS.get_or_init(|| Stats {
+        started_at: Instant::now(),
+        inner: Mutex::new(StatsInner::default()),
+    })
+}
+
+impl Stats {
+    pub fn record_completion(&self, user: Option<&str>, prompt_tokens: usize, completion_tokens: usize) {
+        let Ok(mut inner) = self.inner.lock() else { return };
+        let now = Instant::now();
+        inner.completions.push_back((now, completion_tokens));
+        while inner.completions.front().is_some_and(|(at, _)| now.duration_since(*at) > THROUGHPUT_WINDOW) {
+            inner.completions.pop_front();
+        }
+
+        let usage = inner.users.entry(user.unwrap_or(ANONYMOUS_USER).to_string()).or_default();
+        usage.requests += 1;
+        usage.prompt_tokens += prompt_tokens as u64;
+        usage.completion_tokens += completion_tokens as u64;
+    }
+
+    pub fn record_error(&self, source: &str, message: &str) {
+        let Ok(mut inner) = self.inner.lock() else { return };
+        if inner.errors.len() >= MAX_RECENT_ERRORS {
+            inner.errors.pop_front();
+        }
+        inner.errors.push_back(ErrorRecord {
+            at: Utc::now(),
+            source: source.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    pub fn uptime_secs(&self) -> u64 {
+        self.started_at.elapsed().as_secs()
+    }
+
+    // Completion tokens generated per second over the last minute
+    pub fn tokens_per_sec(&self) -> f64 {
+        let Ok(inner) = self.inner.lock() else { return 0.0 };
+        let now = Instant::now();
+        let tokens: usize = inner.completions
+            .iter()
+            .filter(|(at, _)| now.duration_since(*at) <= THROUGHPUT_WINDOW)
+            .map(|(_, tokens)| tokens)
+            .sum();
+        tokens as f64 / THROUGHPUT_WINDOW.as_secs_f64()
+    }
+
+    // Most recent errors first
+    pub fn recent_errors(&self) -> Vec<ErrorRecord> {
+        self.inner
+            .lock()
+            .map(|inner| inner.errors.iter().rev().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn usage_by_user(&self) -> BTreeMap<String, UserUsage> {
+        self.inner
+            .lock()
+            .map(|inner| inner.users.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn usage_for(&self, user: &str) -> Option<UserUsage> {
+        self.inner.lock().ok()?.users.get(user).cloned()
+    }
+
+    pub fn forget_user(&self, user: &str) -> bool {
+        self.inner
+            .lock()
+            .map(|mut inner| inner.users.remove(user).is_some())
+            .unwrap_or(false)
+    }
+}
@@ -0,0 +1,126 @@
+use anyhow::Result;
+use futures::future::try_join_all;
+use log::info;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::chunker;
+use crate::model::{BackendError, GenerationOptions, LlamaModel};
+
+// Tokens of a chunk's budget left for the summarization instructions around it
+const INSTRUCTION_TOKENS: usize = 200;
+// Shortest and longest partial summary of a chunk, in tokens
+const MIN_PARTIAL_TOKENS: usize = 128;
+const MAX_PARTIAL_TOKENS: usize = 1024;
+// Rounds of summarizing partial summaries before giving up on a text
+const MAX_PASSES: usize = 4;
+
+// Shape of a summary
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SummaryStyle {
+    // A bulleted list of the key points
+    Bullets,
+    // One or a few paragraphs of prose
+    #[default]
+    Abstract,
+    // One or two sentences
+    #[serde(rename = "tldr", alias = "tl;dr")]
+    Tldr,
+}
+
+// A finished summary and what it took
+pub struct Summary {
+    pub text: String,
+    // Chunks the text was split into; 1 when it fit in one request
+    pub chunks: usize,
+    // Rounds of partial summaries before the final one
+    pub passes: usize,
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    pub cached_tokens: usize,
+}
+
+fn final_prompt(style: SummaryStyle, target_words: Option<usize>, text: &str, partial: bool) -> String {
+    let source = if partial {
+        "the following notes, which summarize a longer text part by part, in order"
+    } else {
+        "the following text"
+    };
+    let length = target_words.map(|words| format!(" of about {} words", words)).unwrap_or_default();
+    let instruction = match style {
+        SummaryStyle::Bullets => format!("Summarize {} as a bulleted list of its key points{}. Reply with the list only.", source, length),
+        SummaryStyle::Abstract => format!("Write a concise abstract{} of {}, in one or a few paragraphs. Reply with the abstract only.", length, source),
+        SummaryStyle::Tldr => format!("Write a TL;DR{} of {}, in one or two sentences. Reply with the TL;DR only.", length, source),
+    };
+    format!("{}\n\n{}", instruction, text)
+}
+
+fn partial_prompt(chunk: &str) -> String {
+    format!(
+        "Summarize this part of a longer text. Keep every key fact, name, number and conclusion, \
+        since the summary will be combined with those of the other parts. Reply with the summary only.\n\n{}",
+        chunk
+    )
+}
+
+// Summarize a text of any length: when it doesn't fit in one request, its chunks are summarized
+// separately and the summaries combined, repeating until they fit (map-reduce)
+pub async fn summarize(
+    model: &LlamaModel,
+    text: &str,
+    style: SummaryStyle,
+    target_words: Option<usize>,
+    options: &GenerationOptions,
+) -> Result<Summary> {
+    let budget = model.message_budget().saturating_sub(INSTRUCTION_TOKENS).max(1);
+    let mut summary = Summary {
+        text: String::new(),
+        chunks: 1,
+        passes: 0,
+        prompt_tokens: 0,
+        completion_tokens: 0,
+        cached_tokens: 0,
+    };
+
+    let mut text = text.to_string();
+    while LlamaModel::estimate_tokens(&text) > budget {
+        if summary.passes == MAX_PASSES {
+            return Err(BackendError::ContextOverflow(format!(
+                "the text is still too long for model {} after {} rounds of partial summaries",
+                model.name(), MAX_PASSES
+            )).into());
+        }
+        let chunks = chunker::chunk(&text, budget, LlamaModel::estimate_tokens);
+        if summary.passes == 0 {
+            summary.chunks = chunks.len();
+        }
+        info!("Summarizing {} chunk(s) for model {} (pass {})", chunks.len(), model.name(), summary.passes + 1);
+
+        // Partial summaries together must come out shorter than the text they summarize
+        let mut partial_options = options.clone();
+        partial_options.max_tokens = (budget / chunks.len()).clamp(MIN_PARTIAL_TOKENS, MAX_PARTIAL_TOKENS);
+        let partials = try_join_all(chunks.iter().map(|chunk| {
+            let prompt = partial_prompt(chunk);
+            let options = &partial_options;
+            async move { model.generate_response(&prompt, &[], options).await }
+        }))
+        .await?;
+
+        for partial in &partials {
+            summary.prompt_tokens += partial.prompt_tokens;
+            summary.completion_tokens += partial.completion_tokens;
+            summary.cached_tokens += partial.cached_tokens;
+        }
+        text = partials.into_iter().map(|partial| partial.text.trim().to_string()).collect::<Vec<_>>().join("\n\n");
+        summary.passes += 1;
+    }
+
+    let prompt = final_prompt(style, target_words, &text, summary.passes > 0);
+    let completion = model.generate_response(&prompt, &[], options).await?;
+    summary.prompt_tokens += completion.prompt_tokens;
+    summary.completion_tokens += completion.completion_tokens;
+    summary.cached_tokens += completion.cached_tokens;
+    summary.text = completion.text.trim().to_string();
+    Ok(summary)
+}
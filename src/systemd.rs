@@ -0,0 +1,91 @@
+use std::env;
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+use actix_web::web::Data;
+use log::{info, warn};
+use sd_notify::NotifyState;
+
+use crate::model::ModelManager;
+
+/// Integration with systemd, active only when systemd (or `systemfd` during development)
+/// provides the environment for it:
+///
+/// - Socket activation: a socket passed in through `LISTEN_FDS` is served instead of `HOST` /
+///   `PORT` / `UNIX_SOCKET`, so the unit's `.socket` owns the address and holds connections
+///   while the service restarts
+/// - `NOTIFY_SOCKET`: With `Type=notify`, readiness is reported once the server is listening,
+///   after waiting for backends and warm-up, and stopping when it shuts down
+/// - `WATCHDOG_USEC`: With `WatchdogSec=`, the watchdog is pinged at half the interval
+/// - `WATCHDOG_REQUIRES_BACKEND`: Only ping the watchdog while the default model has a healthy
+///   backend replica, so systemd restarts a server whose backend stays down; set to false to
+///   tie the watchdog to the process alone (default: true)
+
+// A listening socket inherited from systemd
+pub enum ActivatedListener {
+    Tcp(std::net::TcpListener),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixListener),
+}
+
+// The first socket passed by socket activation, if any
+pub fn activated_listener() -> io::Result<Option<ActivatedListener>> {
+    let mut fds = listenfd::ListenFd::from_env();
+    if fds.len() == 0 {
+        return Ok(None);
+    }
+    if fds.len() > 1 {
+        warn!("{} sockets passed by systemd; only the first is served", fds.len());
+    }
+    if let Ok(Some(listener)) = fds.take_tcp_listener(0) {
+        return Ok(Some(ActivatedListener::Tcp(listener)));
+    }
+    #[cfg(unix)]
+    if let Some(listener) = fds.take_unix_listener(0)? {
+        return Ok(Some(ActivatedListener::Unix(listener)));
+    }
+    Err(io::Error::new(io::ErrorKind::InvalidInput, "The socket passed by systemd is neither TCP nor a Unix stream socket"))
+}
+
+fn notify(state: &[NotifyState]) {
+    if let Err(e) = sd_notify::notify(false, state) {
+        warn!("Failed to notify systemd: {}", e);
+    }
+}
+
+// Run a bound server, telling systemd when it is ready and when it stops
+pub async fn serve(server: impl Future<Output = io::Result<()>>) -> io::Result<()> {
+    notify(&[NotifyState::Ready, NotifyState::Status("Serving requests")]);
+    let result = server.await;
+    notify(&[NotifyState::Stopping]);
+    result
+}
+
+// Ping systemd's watchdog for as long as the process, and optionally its backend, is healthy
+pub fn start_watchdog(models: Data<ModelManager>) {
+    let mut usec = 0;
+    if !sd_notify::watchdog_enabled(false, &mut usec) || usec == 0 {
+        return;
+    }
+    let requires_backend = env::var("WATCHDOG_REQUIRES_BACKEND")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(true);
+    let interval = Duration::from_micros(usec / 2);
+    info!("Pinging the systemd watchdog every {:.1}s", interval.as_secs_f64());
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let healthy = !requires_backend || models
+                .get(None)
+                .is_some_and(|model| model.backend_statuses().iter().any(|backend| backend.healthy));
+            if healthy {
+                notify(&[NotifyState::Watchdog]);
+            } else {
+                warn!("No healthy backend for the default model; withholding the watchdog ping");
+            }
+        }
+    });
+}
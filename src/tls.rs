@@ -0,0 +1,97 @@
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use anyhow::{Context, Result};
+use rustls::{Certificate, PrivateKey, ServerConfig};
+use rustls_pemfile::Item;
+
+// Default port for HTTPS when TLS is enabled
+const DEFAULT_HTTPS_PORT: u16 = 8443;
+
+/// Environment variables for terminating HTTPS in the app:
+///
+/// - `TLS_CERT_PATH`: PEM certificate chain; TLS is enabled when this and `TLS_KEY_PATH` are set
+/// - `TLS_KEY_PATH`: PEM private key (PKCS#8, RSA or EC)
+/// - `HTTPS_PORT`: Port serving HTTPS (default: 8443)
+/// - `TLS_REDIRECT_HTTP`: Keep the plain HTTP port open and redirect it to HTTPS (default: false)
+
+pub struct TlsSettings {
+    cert_path: String,
+    key_path: String,
+    pub port: u16,
+    pub redirect_http: bool,
+}
+
+impl TlsSettings {
+    // None when no certificate is configured
+    pub fn from_env() -> Option<Self> {
+        let cert_path = env::var("TLS_CERT_PATH").ok()?;
+        let key_path = env::var("TLS_KEY_PATH").ok()?;
+        let port = env::var("HTTPS_PORT")
+            .ok()
+            .and_then(|v| v.parse::<u16>().ok())
+            .unwrap_or(DEFAULT_HTTPS_PORT);
+        let redirect_http = env::var("TLS_REDIRECT_HTTP")
+            .ok()
+            .and_then(|v| v.parse::<bool>().ok())
+            .unwrap_or(false);
+
+        Some(Self {
+            cert_path,
+            key_path,
+            port,
+            redirect_http,
+        })
+    }
+
+    pub fn server_config(&self) -> Result<ServerConfig> {
+        let mut cert_reader = BufReader::new(File::open(&self.cert_path)
+            .with_context(|| format!("Failed to open TLS certificate {}", self.cert_path))?);
+        let certs: Vec<Certificate> = rustls_pemfile::certs(&mut cert_reader)
+            .with_context(|| format!("Invalid TLS certificate {}", self.cert_path))?
+            .into_iter()
+            .map(Certificate)
+            .collect();
+        if certs.is_empty() {
+            return Err(anyhow::anyhow!("No certificates found in {}", self.cert_path));
+        }
+
+        let mut key_reader = BufReader::new(File::open(&self.key_path)
+            .with_context(|| format!("Failed to open TLS key {}", self.key_path))?);
+        let key = rustls_pemfile::read_all(&mut key_reader)
+            .with_context(|| format!("Invalid TLS key {}", self.key_path))?
+            .into_iter()
+            .find_map(|item| match item {
+                Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key) => Some(PrivateKey(key)),
+                _ => None,
+            })
+            .ok_or_else(|| anyhow::anyhow!("No private key found in {}", self.key_path))?;
+
+        ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .context("Invalid TLS certificate or key")
+    }
+}
+
+// Redirect a plain HTTP request to the same path on the HTTPS port
+pub async fn redirect_to_https(req: HttpRequest, https_port: web::Data<u16>) -> HttpResponse {
+    let info = req.connection_info();
+    let host = info.host();
+    // Strip the port, keeping bracketed IPv6 hosts intact
+    let hostname = match host.rfind(':') {
+        Some(i) if !host[i..].contains(']') => &host[..i],
+        _ => host,
+    };
+    let port = match **https_port {
+        443 => String::new(),
+        port => format!(":{}", port),
+    };
+    let path = req.uri().path_and_query().map(|p| p.as_str()).unwrap_or("/");
+
+    HttpResponse::PermanentRedirect()
+        .insert_header((header::LOCATION, format!("https://{}{}{}", hostname, port, path)))
+        .finish()
+}
@@ -0,0 +1,99 @@
+use std::env;
+use std::path::Path;
+use actix_files::Files;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::middleware::DefaultHeaders;
+use actix_web::{web, HttpRequest, HttpResponse};
+use log::info;
+use rust_embed::RustEmbed;
+
+// Directory served under /static when assets are read from disk
+const DEFAULT_STATIC_DIR: &str = "./static";
+// Default lifetime of static assets in browser and proxy caches
+const DEFAULT_STATIC_CACHE_MAX_AGE_SECS: u64 = 3600;
+
+/// Environment variables for serving static assets:
+///
+/// - `STATIC_DIR`: Directory served under `/static` (default: "./static")
+/// - `EMBEDDED_ASSETS`: Serve the copy of `static/` compiled into the binary instead of
+///   `STATIC_DIR`, "true" or "false" (default: true only when `STATIC_DIR` doesn't exist)
+/// - `STATIC_FILES_LISTING`: Show directory listings under `/static`, "true" or "false" (default: false)
+/// - `STATIC_CACHE_MAX_AGE_SECS`: `max-age` of the Cache-Control header on static assets (default: 3600)
+
+// The static/ directory as it was at build time
+#[derive(RustEmbed)]
+#[folder = "static/"]
+struct EmbeddedAssets;
+
+fn env_flag(name: &str) -> Option<bool> {
+    env::var(name).ok().map(|v| v == "true" || v == "1")
+}
+
+fn cache_control() -> String {
+    let max_age = env::var("STATIC_CACHE_MAX_AGE_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_STATIC_CACHE_MAX_AGE_SECS);
+    format!("public, max-age={}", max_age)
+}
+
+// Log where static assets come from; called once at startup
+pub fn log_source() {
+    let dir = env::var("STATIC_DIR").unwrap_or_else(|_| DEFAULT_STATIC_DIR.to_string());
+    if use_embedded(&dir) {
+        info!("Serving embedded static assets");
+    } else {
+        info!("Serving static assets from {}", dir);
+    }
+}
+
+fn use_embedded(dir: &str) -> bool {
+    env_flag("EMBEDDED_ASSETS").unwrap_or_else(|| !Path::new(dir).is_dir())
+}
+
+// Register the /static scope, backed by the filesystem or by the embedded copy
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    let dir = env::var("STATIC_DIR").unwrap_or_else(|_| DEFAULT_STATIC_DIR.to_string());
+    let scope = web::scope("/static")
+        .wrap(DefaultHeaders::new().add((header::CACHE_CONTROL, cache_control())));
+
+    if use_embedded(&dir) {
+        cfg.service(scope.route("/{path:.*}", web::get().to(embedded_asset)));
+        return;
+    }
+
+    // ETag and Last-Modified are on by default and answer conditional requests with 304
+    let mut files = Files::new("", dir).use_etag(true).use_last_modified(true);
+    if env_flag("STATIC_FILES_LISTING").unwrap_or(false) {
+        files = files.show_files_listing();
+    }
+    cfg.service(scope.service(files));
+}
+
+// Serve a file compiled into the binary, with its content hash as the ETag
+async fn embedded_asset(req: HttpRequest, path: web::Path<String>) -> HttpResponse {
+    let Some(file) = EmbeddedAssets::get(&path) else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    let etag = format!("\"{}\"", hex::encode(file.metadata.sha256_hash()));
+    let not_modified = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*"));
+
+    let mut response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response.insert_header((header::ETAG, etag));
+    if not_modified {
+        return response.finish();
+    }
+    if let Ok(content_type) = HeaderValue::from_str(file.metadata.mimetype()) {
+        response.insert_header((header::CONTENT_TYPE, content_type));
+    }
+    response.body(file.data.into_owned())
+}
@@ -0,0 +1,61 @@
+use actix_web::HttpRequest;
+use base64::Engine;
+use std::env;
+
+use crate::model::priority::{priority_keys, Priority};
+use crate::web::error::{ApiError, ErrorCode};
+
+/// Environment variables for configuring admin access:
+///
+/// - `ADMIN_TOKEN`: Token required for `/admin` and `/api/admin/*`. It can be sent as
+///   `Authorization: Bearer <token>` or as the password of HTTP Basic auth (any user name),
+///   which lets browsers log in to the dashboard. Admin routes are disabled when unset.
+
+// Compare without short-circuiting so timing doesn't reveal the token
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// Extract the token from a Bearer or Basic authorization header
+fn presented_token(req: &HttpRequest) -> Option<String> {
+    let header = req.headers().get("Authorization")?.to_str().ok()?;
+
+    if let Some(token) = header.strip_prefix("Bearer ") {
+        return Some(token.trim().to_string());
+    }
+
+    let encoded = header.strip_prefix("Basic ")?;
+    let decoded = base64::engine::general_purpose::STANDARD.decode(encoded.trim()).ok()?;
+    let credentials = String::from_utf8(decoded).ok()?;
+    credentials.split_once(':').map(|(_, password)| password.to_string())
+}
+
+// Check admin credentials, returning the error to send when they're missing or wrong
+pub fn require_admin(req: &HttpRequest) -> Result<(), ApiError> {
+    let Some(expected) = env::var("ADMIN_TOKEN").ok().filter(|t| !t.is_empty()) else {
+        return Err(ApiError::new(
+            ErrorCode::Forbidden,
+            "Admin access is disabled; set ADMIN_TOKEN to enable it",
+        ));
+    };
+
+    match presented_token(req) {
+        Some(token) if constant_time_eq(token.as_bytes(), expected.as_bytes()) => Ok(()),
+        _ => Err(ApiError::new(ErrorCode::Unauthorized, "Admin credentials required")),
+    }
+}
+
+// Whether the request carries valid admin credentials
+pub fn is_admin(req: &HttpRequest) -> bool {
+    require_admin(req).is_ok()
+}
+
+// Priority of a request to an endpoint of the given priority: the tier of the API key it
+// presents, if any. The `user` field is never trusted for this, as any caller can set it.
+pub fn request_priority(req: &HttpRequest, endpoint: Priority) -> Priority {
+    let Some(token) = presented_token(req) else { return endpoint };
+    priority_keys()
+        .iter()
+        .find(|(key, _)| constant_time_eq(token.as_bytes(), key.as_bytes()))
+        .map_or(endpoint, |(_, priority)| *priority)
+}
@@ -0,0 +1,28 @@
+use std::env;
+use actix_web::dev::ServiceResponse;
+use actix_web::http::header::{self, HeaderValue};
+
+/// Environment variables for response compression:
+///
+/// - `RESPONSE_COMPRESSION`: Compress responses with gzip, brotli or zstd, whichever the
+///   client prefers in `Accept-Encoding`, "true" or "false" (default: true)
+
+pub fn enabled() -> bool {
+    env::var("RESPONSE_COMPRESSION")
+        .map(|v| v != "false" && v != "0")
+        .unwrap_or(true)
+}
+
+// Keep server-sent events out of the compressor, which would buffer them instead
+// of flushing each event. The compression middleware leaves alone any response
+// that already declares a Content-Encoding.
+pub fn exclude_event_streams<B>(res: &mut ServiceResponse<B>) {
+    let is_event_stream = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    if is_event_stream && !res.headers().contains_key(header::CONTENT_ENCODING) {
+        res.headers_mut().insert(header::CONTENT_ENCODING, HeaderValue::from_static("identity"));
+    }
+}
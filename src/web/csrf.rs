@@ -0,0 +1,118 @@
+use std::env;
+use actix_web::dev::ServiceRequest;
+use actix_web::http::Method;
+use actix_web::HttpMessage;
+
+use crate::web::error::{ApiError, ErrorCode};
+use crate::web::identity::{self, BrowserId, BROWSER_COOKIE};
+
+// Header the web UI sends the page's CSRF token in
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+/// Environment variables for cross-site request forgery protection:
+///
+/// - `CSRF_PROTECTION`: Require the page's CSRF token on state-changing requests that carry
+///   browser credentials; set to false for deployments serving only API clients (default: true)
+///
+/// Pages rendered by the server embed a token derived from the browser's signed identity cookie,
+/// which the web UI sends back in the `X-CSRF-Token` header. Only POST, PUT, PATCH and DELETE
+/// requests carrying something a browser sends on its own, the identity cookie or HTTP Basic
+/// credentials for the dashboard, are checked, and only when they come from a browser, which
+/// marks its requests with `Origin` or `Sec-Fetch-Site`. API clients using a Bearer token, no
+/// credentials at all, or a cookie jar holding the identity cookie are unaffected.
+
+pub fn enabled() -> bool {
+    env::var("CSRF_PROTECTION")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(true)
+}
+
+// Token a page served to this browser carries
+pub fn token(browser: BrowserId) -> String {
+    identity::signature("csrf", browser.0)
+}
+
+// Compare without short-circuiting so timing doesn't reveal the token
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// Credentials a browser attaches without the page asking for it
+fn has_ambient_credentials(req: &ServiceRequest) -> bool {
+    let basic_auth = req
+        .headers()
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(|h| h.starts_with("Basic "));
+    basic_auth || req.cookie(BROWSER_COOKIE).is_some()
+}
+
+// Browsers add these to every cross-site request, and to same-site ones other than plain GETs;
+// API clients don't send them, so a client keeping the cookie isn't asked for a page's token
+fn from_browser(req: &ServiceRequest) -> bool {
+    let headers = req.headers();
+    headers.contains_key("Origin") || headers.contains_key("Sec-Fetch-Site")
+}
+
+// Reject a state-changing browser request without the token of the page it came from
+pub fn check(req: &ServiceRequest) -> Result<(), ApiError> {
+    if !enabled() || matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE) {
+        return Ok(());
+    }
+    if !has_ambient_credentials(req) || !from_browser(req) {
+        return Ok(());
+    }
+
+    let expected = req.extensions().get::<BrowserId>().copied().map(token);
+    let presented = req.headers().get(CSRF_HEADER).and_then(|h| h.to_str().ok());
+    match (presented, expected) {
+        (Some(presented), Some(expected)) if constant_time_eq(presented.trim().as_bytes(), expected.as_bytes()) => Ok(()),
+        _ => Err(ApiError::new(ErrorCode::Forbidden, "Missing or invalid CSRF token; reload the page")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::cookie::Cookie;
+    use actix_web::test::TestRequest;
+    use uuid::Uuid;
+
+    // A POST carrying the identity cookie, as browsers and API clients with a cookie jar send it
+    fn with_cookie() -> TestRequest {
+        TestRequest::post()
+            .uri("/api/chat")
+            .cookie(Cookie::new(BROWSER_COOKIE, "signed-identity"))
+    }
+
+    fn check_with(req: TestRequest, browser: BrowserId) -> Result<(), ApiError> {
+        let req = req.to_srv_request();
+        req.extensions_mut().insert(browser);
+        check(&req)
+    }
+
+    #[test]
+    fn api_client_keeping_the_cookie_needs_no_token() {
+        let browser = BrowserId(Uuid::new_v4());
+        assert!(check_with(with_cookie(), browser).is_ok());
+    }
+
+    #[test]
+    fn browser_request_without_token_is_refused() {
+        let browser = BrowserId(Uuid::new_v4());
+        let cross_site = with_cookie().insert_header(("Origin", "https://evil.example"));
+        assert!(check_with(cross_site, browser).is_err());
+        let fetched = with_cookie().insert_header(("Sec-Fetch-Site", "same-origin"));
+        assert!(check_with(fetched, browser).is_err());
+    }
+
+    #[test]
+    fn browser_request_with_page_token_is_allowed() {
+        let browser = BrowserId(Uuid::new_v4());
+        let req = with_cookie()
+            .insert_header(("Origin", "http://localhost:8080"))
+            .insert_header((CSRF_HEADER, token(browser)));
+        assert!(check_with(req, browser).is_ok());
+    }
+}
@@ -0,0 +1,213 @@
+use std::fmt;
+use actix_web::http::{header, StatusCode};
+use actix_web::{HttpResponse, ResponseError};
+use serde::Serialize;
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::collections::fetch::FetchError;
+use crate::members::MembershipError;
+use crate::model::BackendError;
+use crate::web::request_id;
+
+// Machine-readable error codes returned in the `code` field of error responses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    InvalidRequest,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    Conflict,
+    ConfirmationRequired,
+    SessionModified,
+    PayloadTooLarge,
+    ContentBlocked,
+    OutputBlocked,
+    ContextOverflow,
+    RateLimited,
+    BackendUnreachable,
+    CircuitOpen,
+    Maintenance,
+    InsufficientResources,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::InvalidRequest => "invalid_request",
+            ErrorCode::Unauthorized => "unauthorized",
+            ErrorCode::Forbidden => "forbidden",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::Conflict => "conflict",
+            ErrorCode::ConfirmationRequired => "confirmation_required",
+            ErrorCode::SessionModified => "session_modified",
+            ErrorCode::PayloadTooLarge => "payload_too_large",
+            ErrorCode::ContentBlocked => "content_blocked",
+            ErrorCode::OutputBlocked => "output_blocked",
+            ErrorCode::ContextOverflow => "context_overflow",
+            ErrorCode::RateLimited => "rate_limited",
+            ErrorCode::BackendUnreachable => "backend_unreachable",
+            ErrorCode::CircuitOpen => "circuit_open",
+            ErrorCode::Maintenance => "maintenance",
+            ErrorCode::InsufficientResources => "insufficient_resources",
+            ErrorCode::Internal => "internal_error",
+        }
+    }
+
+    fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::InvalidRequest | ErrorCode::ContentBlocked | ErrorCode::ContextOverflow => StatusCode::BAD_REQUEST,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::Conflict | ErrorCode::ConfirmationRequired | ErrorCode::SessionModified => StatusCode::CONFLICT,
+            ErrorCode::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::OutputBlocked => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::BackendUnreachable | ErrorCode::CircuitOpen | ErrorCode::Maintenance => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::InsufficientResources => StatusCode::INSUFFICIENT_STORAGE,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+// JSON body of every error response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    // Human-readable description
+    pub error: String,
+    // Machine-readable code, e.g. "invalid_request" or "backend_unreachable"
+    pub code: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub details: Option<Value>,
+    // Same as the `X-Request-Id` response header
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+// Error returned by every API handler, rendered as an `ErrorBody`
+#[derive(Debug)]
+pub struct ApiError {
+    code: ErrorCode,
+    message: String,
+    details: Option<Value>,
+    // Seconds sent in a Retry-After header
+    retry_after: Option<u64>,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            details: None,
+            retry_after: None,
+        }
+    }
+
+    pub fn invalid_request(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::InvalidRequest, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::NotFound, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Internal, message)
+    }
+
+    // Attach structured context, e.g. the moderation rules that triggered
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    pub fn with_retry_after(mut self, secs: u64) -> Self {
+        self.retry_after = Some(secs);
+        self
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code.as_str(), self.message)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.code.status()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let body = ErrorBody {
+            error: self.message.clone(),
+            code: self.code.as_str(),
+            details: self.details.clone(),
+            request_id: request_id::current(),
+        };
+
+        let mut response = HttpResponse::build(self.status_code());
+        // Lets browsers prompt for the admin token
+        if self.code == ErrorCode::Unauthorized {
+            response.insert_header((header::WWW_AUTHENTICATE, "Basic realm=\"admin\""));
+        }
+        if let Some(secs) = self.retry_after {
+            response.insert_header((header::RETRY_AFTER, secs.to_string()));
+        }
+        response.json(body)
+    }
+}
+
+// Invitation failures, by whether the token or the invitee is at fault
+impl From<MembershipError> for ApiError {
+    fn from(e: MembershipError) -> Self {
+        let code = match e {
+            MembershipError::AlreadyMember => ErrorCode::Conflict,
+            MembershipError::InvalidToken => ErrorCode::NotFound,
+            MembershipError::Expired => ErrorCode::InvalidRequest,
+        };
+        Self::new(code, e.to_string())
+    }
+}
+
+// URL fetch failures; refusals are 403s, everything else is the caller's URL not working
+impl From<FetchError> for ApiError {
+    fn from(e: FetchError) -> Self {
+        let code = match e {
+            FetchError::Blocked(_) | FetchError::Disallowed(_) => ErrorCode::Forbidden,
+            FetchError::TooLarge(_) => ErrorCode::PayloadTooLarge,
+            FetchError::Invalid(_) | FetchError::Failed(_) => ErrorCode::InvalidRequest,
+        };
+        Self::new(code, e.to_string())
+    }
+}
+
+// Generation failures, classified by what went wrong upstream
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        let code = match e.downcast_ref::<BackendError>() {
+            Some(BackendError::Unreachable(_)) => ErrorCode::BackendUnreachable,
+            Some(BackendError::ContextOverflow(_)) => ErrorCode::ContextOverflow,
+            Some(BackendError::RateLimited(_)) => ErrorCode::RateLimited,
+            Some(BackendError::CircuitOpen { .. }) => ErrorCode::CircuitOpen,
+            None => ErrorCode::Internal,
+        };
+        let error = Self::new(code, format!("Failed to generate response: {}", e));
+        match e.downcast_ref::<BackendError>() {
+            Some(BackendError::CircuitOpen { retry_after_secs, .. }) => error.with_retry_after(*retry_after_secs),
+            _ => error,
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use actix_web::http::header;
+use actix_web::HttpRequest;
+use serde_json::json;
+
+use crate::session::Session;
+use crate::web::error::{ApiError, ErrorCode};
+
+// ETag of a session's current state; it changes with every change to the session
+pub fn etag(session: &Session) -> String {
+    format!("\"{}\"", session.version)
+}
+
+// Header carrying a session's ETag in responses
+pub fn header(session: &Session) -> (header::HeaderName, String) {
+    (header::ETAG, etag(session))
+}
+
+// Check the request's `If-Match` header against a session, None when it doesn't exist.
+// Requests without the header always pass; changes to a session someone else changed since
+// the caller read it fail with 409, carrying the current ETag.
+pub fn check_if_match(http_req: &HttpRequest, session: Option<&Session>) -> Result<(), ApiError> {
+    let Some(value) = http_req.headers().get(header::IF_MATCH) else { return Ok(()) };
+    let value = value
+        .to_str()
+        .map_err(|_| ApiError::invalid_request("If-Match must be visible ASCII"))?;
+    let Some(session) = session else {
+        return Err(ApiError::new(ErrorCode::SessionModified, "The session no longer exists"));
+    };
+
+    let current = etag(session);
+    // Weak tags compare equal to strong ones here: a session has no other representation
+    let matches = value
+        .split(',')
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == current);
+    if matches {
+        Ok(())
+    } else {
+        Err(ApiError::new(ErrorCode::SessionModified, "The session was changed by another request; reload it and try again")
+            .with_details(json!({ "etag": current })))
+    }
+}
@@ -1,99 +1,3605 @@
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{web, HttpRequest, HttpResponse, HttpResponseBuilder};
+use actix_web::http::header;
 use serde_json::json;
-use tera::Context;
+use tera::{Context, Tera};
 use uuid::Uuid;
 use log::{info, error};
 use std::env;
+use std::sync::Arc;
+use std::time::Instant;
 
-use crate::web::models::{ChatRequest, ChatResponse};
+use crate::assistants::{Assistant, FewShotExample, MAX_EXAMPLES};
+use crate::audit::{annotate, audit, AuditContext, AuditQuery};
+use crate::audit::usage::UsageQuery;
+use crate::collections::{self, Collection, Document, MAX_COLLECTION_NAME_CHARS, MAX_SESSION_COLLECTIONS};
+use crate::collections::fetch;
+use crate::collections::ingest::{self, DocumentFormat};
+use crate::consensus::{self, MAX_CONSENSUS_SAMPLES};
+use crate::eval::EvalRun;
+use crate::extract::{self, Metadata};
+use crate::guardrails::{Guarded, RuleContext};
+use crate::language;
+use crate::markdown;
+use crate::members::Member;
+use crate::memory::Memory;
+use crate::model::{Completion, GenerationOptions, LlamaModel};
+use crate::model::bench::{self, BenchConfig, BenchReport};
+use crate::model::downloads::DownloadRequest;
+use crate::model::priority::Priority;
+use crate::moderation::{Screened, Stage};
+use crate::pii::PiiVault;
+use crate::prompt_log::{prompt_log, PromptLogSettings};
+use crate::privacy;
+use crate::refine;
+use crate::schedules::{self, Schedule, ScheduleInfo};
+use crate::jobs::{webhook, Job};
+use crate::prompts::PromptTemplate;
+use crate::session::search::{self, SessionMatch};
+use crate::session::snapshot::{StateArchive, STATE_ARCHIVE_VERSION};
+use crate::session::trash;
+use crate::session::{normalize_tag, Session, SessionSettings, SessionSummary, MAX_TAGS_PER_SESSION, MAX_TAG_CHARS};
+use crate::stats::stats;
+use crate::summarize;
+use crate::web::auth::{is_admin, request_priority, require_admin};
+use crate::web::csrf;
+use crate::web::error::{ApiError, ErrorBody, ErrorCode};
+use crate::web::etag;
+use crate::web::idempotency::{idempotency_key, Claim};
+use crate::web::identity::{self, browser_id, can_access};
+use crate::web::limits::{check_document_length, check_message_length, max_session_history, read_upload, reasoning_in_history};
+use crate::web::mode::{ModeRequest, ModeState};
+use crate::web::models::{
+    ChatRequest, ChatResponse, AsyncGenerateRequest, BatchChatRequest, BatchItemResult,
+    PromptTemplateRequest, DeletionQuery, SearchQuery, TokenizeRequest, TokenizeResponse, Usage,
+    CompleteRequest, CompleteResponse, FimRequest, CompareRequest, CompareResult, EvalRequest, EvalRunsQuery,
+    TranslateRequest, TranslateResponse, SummarizeRequest, SummarizeResponse, ExtractRequest, ExtractResponse, ClassifyRequest, ClassifyResponse,
+    PipelineRunRequest, PipelineRunResponse, PipelineStepOutput, RefinePasses,
+    BenchRequest, PageQuery, SessionMessage, SessionPage, SessionListQuery, TagsRequest, SessionStatus, ExamplesRequest,
+    StateImportQuery, CollectionRequest, DocumentRequest, UploadQuery, UrlRequest, SessionCollectionsRequest,
+    InviteRequest, InviteResponse, AcceptInviteRequest, MemberRoleRequest,
+};
 use crate::AppState;
 
-// Index page handler
-pub async fn index(data: web::Data<AppState>) -> impl Responder {
-    let context = Context::new();
-    match data.tera.render("index.html", &context) {
-        Ok(html) => HttpResponse::Ok().content_type("text/html").body(html),
+// Tokens generated for a fill-in-the-middle request that doesn't set max_tokens
+const DEFAULT_FIM_MAX_TOKENS: usize = 128;
+// Prompt of a translation, unless the prompt library has a `translate` template
+const TRANSLATE_TEMPLATE: &str = "Translate the following text{% if source_lang %} from {{ source_lang }}{% endif %} to {{ target_lang }}. Keep its meaning, tone and formatting, including markdown and code. Reply with the translation only, without notes or quotes.\n\n{{ text }}";
+const TRANSLATE_SYSTEM_PROMPT: &str = "You are a professional translator.";
+// Translations should stay close to the source rather than be creative
+const TRANSLATE_TEMPERATURE: f32 = 0.2;
+// Fewest tokens a translation that doesn't set max_tokens may use
+const MIN_TRANSLATE_MAX_TOKENS: usize = 256;
+// Summaries should stick to the source
+const SUMMARIZE_TEMPERATURE: f32 = 0.3;
+const SUMMARIZE_SYSTEM_PROMPT: &str = "You are an expert at summarizing texts accurately and concisely.";
+// Longest summary that may be asked for, in words
+const MAX_SUMMARY_WORDS: usize = 2000;
+// Extraction and classification should be deterministic
+const EXTRACT_TEMPERATURE: f32 = 0.0;
+const EXTRACT_SYSTEM_PROMPT: &str = "You extract structured metadata from texts and reply with JSON only.";
+const EXTRACT_MAX_TOKENS: usize = 1024;
+// Most keywords asked of the model
+const EXTRACT_MAX_KEYWORDS: usize = 10;
+const CLASSIFY_SYSTEM_PROMPT: &str = "You classify texts into the labels you are given and reply with JSON only.";
+const CLASSIFY_MAX_TOKENS: usize = 512;
+// Most labels a classification may choose from, and longest label
+const MAX_CLASSIFY_LABELS: usize = 100;
+const MAX_LABEL_CHARS: usize = 200;
+// Shape of a benchmark that doesn't set its own, and the largest one accepted
+const DEFAULT_BENCH_REQUESTS: usize = 20;
+const DEFAULT_BENCH_CONCURRENCY: usize = 4;
+const DEFAULT_BENCH_MAX_TOKENS: usize = 128;
+const MAX_BENCH_REQUESTS: usize = 1000;
+const MAX_BENCH_CONCURRENCY: usize = 64;
+// Sessions returned by a search when no limit is given, and the most it may ask for
+const DEFAULT_SEARCH_LIMIT: usize = 20;
+const MAX_SEARCH_LIMIT: usize = 100;
+// Session messages returned per page when no limit is given, and the most a page may hold
+const DEFAULT_PAGE_LIMIT: usize = 50;
+const MAX_PAGE_LIMIT: usize = 200;
+
+// Render a page template; the templates may be swapped out by hot reloading
+fn render_page(data: &AppState, name: &str, context: &Context) -> Result<String, ApiError> {
+    let tera = data.tera
+        .read()
+        .map_err(|_| ApiError::internal("Template error"))?;
+    match tera.render(name, context) {
+        Ok(html) => Ok(html),
         Err(e) => {
             error!("Template error: {}", e);
-            HttpResponse::InternalServerError().body("Template error")
+            Err(ApiError::internal("Template error"))
+        }
+    }
+}
+
+// CSRF token for a page rendered for the requesting browser
+fn page_csrf_token(http_req: &HttpRequest) -> String {
+    browser_id(http_req).map(csrf::token).unwrap_or_default()
+}
+
+// Index page handler
+pub async fn index(http_req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let mut context = Context::new();
+    context.insert("mode", &data.mode.current());
+    context.insert("csrf_token", &page_csrf_token(&http_req));
+    let html = render_page(&data, "index.html", &context)?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+// Admin dashboard page
+pub async fn admin_page(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    
+    let mut context = Context::new();
+    context.insert("stats", &admin_stats_snapshot(&data).await);
+    context.insert("csrf_token", &page_csrf_token(&http_req));
+    let html = render_page(&data, "admin.html", &context)?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+// Live statistics backing the admin dashboard
+#[utoipa::path(
+    get, path = "/api/admin/stats", tag = "admin",
+    responses(
+        (status = 200, description = "Live statistics", body = Object),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn admin_stats(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    Ok(HttpResponse::Ok().json(admin_stats_snapshot(&data).await))
+}
+
+// Token usage over time, grouped by day, user or model
+#[utoipa::path(
+    get, path = "/api/admin/usage", tag = "admin",
+    params(UsageQuery),
+    responses(
+        (status = 200, description = "Usage per group, with daily points", body = Object),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn admin_usage(
+    http_req: HttpRequest,
+    query: web::Query<UsageQuery>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    
+    match audit().usage(&query) {
+        Ok(series) => Ok(HttpResponse::Ok().json(json!({
+            "from": query.from,
+            "to": query.to,
+            "group_by": query.group_by,
+            "series": series,
+        }))),
+        Err(e) => {
+            error!("Failed to read audit log: {}", e);
+            Err(ApiError::internal("Failed to read audit log"))
+        }
+    }
+}
+
+// Measure latency and throughput of a model under synthetic load
+#[utoipa::path(
+    post, path = "/api/admin/bench", tag = "admin",
+    request_body = BenchRequest,
+    responses(
+        (status = 200, description = "Benchmark report", body = BenchReport),
+        (status = 400, description = "Unknown model or out-of-range settings", body = ErrorBody),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn run_bench(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    req: web::Json<BenchRequest>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    
+    let model = data.model
+        .get(req.model.as_deref())
+        .ok_or_else(|| ApiError::invalid_request(format!("Unknown model: {}", req.model.as_deref().unwrap_or_default())))?;
+    let config = BenchConfig {
+        requests: req.requests.unwrap_or(DEFAULT_BENCH_REQUESTS),
+        concurrency: req.concurrency.unwrap_or(DEFAULT_BENCH_CONCURRENCY),
+        max_tokens: req.max_tokens.unwrap_or(DEFAULT_BENCH_MAX_TOKENS),
+        prompt: req.prompt.clone(),
+    };
+    if !(1..=MAX_BENCH_REQUESTS).contains(&config.requests) {
+        return Err(ApiError::invalid_request(format!("requests must be between 1 and {}", MAX_BENCH_REQUESTS)));
+    }
+    if !(1..=MAX_BENCH_CONCURRENCY).contains(&config.concurrency) {
+        return Err(ApiError::invalid_request(format!("concurrency must be between 1 and {}", MAX_BENCH_CONCURRENCY)));
+    }
+    if let Some(prompt) = &config.prompt {
+        check_message_length(prompt)?;
+    }
+    
+    Ok(HttpResponse::Ok().json(bench::run(&model, &config).await))
+}
+
+// List the evaluation suites
+#[utoipa::path(
+    get, path = "/api/admin/eval/suites", tag = "admin",
+    responses(
+        (status = 200, description = "Evaluation suites", body = Object),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn list_eval_suites(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    Ok(HttpResponse::Ok().json(json!({
+        "suites": data.evals.suites()
+    })))
+}
+
+// Run an evaluation suite against a model in the background
+#[utoipa::path(
+    post, path = "/api/admin/eval", tag = "admin",
+    request_body = EvalRequest,
+    responses(
+        (status = 202, description = "Run started; poll it by ID", body = EvalRun),
+        (status = 400, description = "Unknown model", body = ErrorBody),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 404, description = "Unknown suite", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn run_eval(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    req: web::Json<EvalRequest>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    
+    let suite = data.evals
+        .suite(&req.suite)
+        .cloned()
+        .ok_or_else(|| ApiError::not_found(format!("Unknown evaluation suite: {}", req.suite)))?;
+    let model = data.model
+        .get(req.model.as_deref())
+        .ok_or_else(|| ApiError::invalid_request(format!("Unknown model: {}", req.model.as_deref().unwrap_or_default())))?;
+    let judge = match &req.judge_model {
+        Some(name) => Some(data.model.get(Some(name)).ok_or_else(|| ApiError::invalid_request(format!("Unknown model: {}", name)))?),
+        None => None,
+    };
+    
+    let run = data.evals.start(suite, model, judge);
+    Ok(HttpResponse::Accepted().json(run))
+}
+
+// List evaluation runs, newest first
+#[utoipa::path(
+    get, path = "/api/admin/eval/runs", tag = "admin",
+    params(EvalRunsQuery),
+    responses(
+        (status = 200, description = "Evaluation runs", body = Object),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn list_eval_runs(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<EvalRunsQuery>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    Ok(HttpResponse::Ok().json(json!({
+        "runs": data.evals.runs(query.suite.as_deref(), query.model.as_deref())
+    })))
+}
+
+// Progress or results of an evaluation run
+#[utoipa::path(
+    get, path = "/api/admin/eval/runs/{id}", tag = "admin",
+    params(("id" = Uuid, Path, description = "Run ID")),
+    responses(
+        (status = 200, description = "Evaluation run", body = EvalRun),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 404, description = "Unknown run", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn get_eval_run(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    match data.evals.get_run(*path) {
+        Some(run) => Ok(HttpResponse::Ok().json(run)),
+        None => Err(ApiError::not_found("Evaluation run not found")),
+    }
+}
+
+// Query the audit log
+#[utoipa::path(
+    get, path = "/api/admin/audit", tag = "admin",
+    params(AuditQuery),
+    responses(
+        (status = 200, description = "Matching audit entries, newest first", body = Object),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn admin_audit(
+    http_req: HttpRequest,
+    query: web::Query<AuditQuery>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    
+    match audit().query(&query) {
+        Ok(entries) => Ok(HttpResponse::Ok().json(json!({ "entries": entries }))),
+        Err(e) => {
+            error!("Failed to read audit log: {}", e);
+            Err(ApiError::internal("Failed to read audit log"))
         }
     }
 }
 
-// Health check endpoint
-pub async fn health_check() -> impl Responder {
-    HttpResponse::Ok().json(json!({ "status": "ok" }))
+// Current prompt and completion logging switches
+#[utoipa::path(
+    get, path = "/api/admin/prompt-log", tag = "admin",
+    responses(
+        (status = 200, description = "Prompt logging settings", body = PromptLogSettings),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn get_prompt_log_settings(http_req: HttpRequest) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    Ok(HttpResponse::Ok().json(prompt_log().settings()))
+}
+
+// Switch prompt and completion logging on or off, or change its sample rate
+#[utoipa::path(
+    put, path = "/api/admin/prompt-log", tag = "admin",
+    request_body = PromptLogSettings,
+    responses(
+        (status = 200, description = "Settings now in effect", body = PromptLogSettings),
+        (status = 400, description = "Sample rate out of range", body = ErrorBody),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn put_prompt_log_settings(
+    http_req: HttpRequest,
+    req: web::Json<PromptLogSettings>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    
+    if !(0.0..=1.0).contains(&req.sample_rate) {
+        return Err(ApiError::invalid_request("sample_rate must be between 0 and 1"));
+    }
+    Ok(HttpResponse::Ok().json(prompt_log().update_settings(req.into_inner())))
+}
+
+// Make a registered model the default for requests that don't name one. Backends are
+// separate servers, so this switches between models they already serve rather than loading one.
+#[utoipa::path(
+    post, path = "/api/admin/models/{name}/activate", tag = "admin",
+    params(("name" = String, Path, description = "Registered model name")),
+    responses(
+        (status = 200, description = "New and previous default model", body = Object),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 404, description = "Unknown model", body = ErrorBody),
+        (status = 409, description = "None of the model's backends is healthy", body = ErrorBody),
+        (status = 507, description = "The model's estimated VRAM exceeds the budget", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn activate_model(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    
+    let name = path.into_inner();
+    let model = data.model.get(Some(&name)).ok_or_else(|| ApiError::not_found(format!("Unknown model: {}", name)))?;
+    if let Some(vram_mb) = model.vram_mb() {
+        data.downloads
+            .limits()
+            .check_vram(&name, vram_mb * 1024 * 1024)
+            .map_err(|e| ApiError::new(ErrorCode::InsufficientResources, e))?;
+    }
+    if !model.backend_statuses().iter().any(|backend| backend.healthy) {
+        return Err(ApiError::new(ErrorCode::Conflict, format!("No healthy backend serves model {}", name)));
+    }
+    let previous = data.model.activate(&name).ok_or_else(|| ApiError::not_found(format!("Unknown model: {}", name)))?;
+    Ok(HttpResponse::Ok().json(json!({
+        "default": name,
+        "previous": previous,
+    })))
+}
+
+// GGUF downloads started since startup and the model files already on disk
+#[utoipa::path(
+    get, path = "/api/admin/models/downloads", tag = "admin",
+    responses(
+        (status = 200, description = "Downloads and local GGUF files", body = Object),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn list_model_downloads(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    
+    Ok(HttpResponse::Ok().json(json!({
+        "downloads": data.downloads.list(),
+        "local": data.downloads.local_files().await,
+    })))
+}
+
+// Download a GGUF file from Hugging Face into the models directory in the background
+#[utoipa::path(
+    post, path = "/api/admin/models/downloads", tag = "admin",
+    request_body = DownloadRequest,
+    responses(
+        (status = 202, description = "Download started", body = Download),
+        (status = 400, description = "Invalid repository, file name or checksum", body = ErrorBody),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 409, description = "The file is already being downloaded", body = ErrorBody),
+        (status = 507, description = "Not enough disk space, or the model exceeds the VRAM budget", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn start_model_download(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    req: web::Json<DownloadRequest>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    
+    let req = req.into_inner();
+    req.validate().map_err(ApiError::invalid_request)?;
+    data.downloads
+        .check_resources(&req)
+        .await
+        .map_err(|e| ApiError::new(ErrorCode::InsufficientResources, e))?;
+    let download = data.downloads
+        .start(req)
+        .map_err(|e| ApiError::new(ErrorCode::Conflict, e))?;
+    info!("Started download {} of {}/{}", download.id, download.repo, download.filename);
+    Ok(HttpResponse::Accepted().json(download))
+}
+
+// Switch the server between healthy, degraded and maintenance mode
+#[utoipa::path(
+    post, path = "/api/admin/mode", tag = "admin",
+    request_body = ModeRequest,
+    responses(
+        (status = 200, description = "Mode now in effect", body = ModeState),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn set_mode(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    req: web::Json<ModeRequest>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    
+    let state = data.mode.set(req.into_inner());
+    info!("Server mode set to {:?}", state.mode);
+    Ok(HttpResponse::Ok().json(state))
+}
+
+// Dump every session with its metadata, to move the instance to another host
+#[utoipa::path(
+    get, path = "/api/admin/state/export", tag = "admin",
+    responses(
+        (status = 200, description = "Archive of all sessions", body = Object),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn export_state(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    
+    let archive = data.sessions.export_state().await;
+    info!("Exported {} sessions", archive.sessions.len());
+    let filename = format!("llama-state-{}.json", archive.exported_at.format("%Y%m%dT%H%M%SZ"));
+    Ok(HttpResponse::Ok()
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+        .json(archive))
+}
+
+// Load an archive written by the export endpoint, on this or another instance
+#[utoipa::path(
+    post, path = "/api/admin/state/import", tag = "admin",
+    params(StateImportQuery),
+    request_body = Object,
+    responses(
+        (status = 200, description = "Number of sessions loaded", body = Object),
+        (status = 400, description = "Invalid archive or unsupported archive version", body = ErrorBody),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 413, description = "Archive exceeds MAX_STATE_IMPORT_BYTES", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn import_state(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<StateImportQuery>,
+    archive: web::Json<StateArchive>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    
+    let archive = archive.into_inner();
+    if archive.version != STATE_ARCHIVE_VERSION {
+        return Err(ApiError::invalid_request(format!(
+            "Unsupported archive version {}; this server reads version {}", archive.version, STATE_ARCHIVE_VERSION
+        )));
+    }
+    let exported_at = archive.exported_at;
+    let imported = data.sessions.import_state(archive, query.replace).await;
+    info!("Imported {} sessions from an archive exported at {}", imported, exported_at);
+    Ok(HttpResponse::Ok().json(json!({
+        "imported_sessions": imported,
+        "replaced": query.replace,
+        "exported_at": exported_at,
+    })))
+}
+
+async fn admin_stats_snapshot(data: &AppState) -> serde_json::Value {
+    let active_sessions = data.sessions.len();
+    let backends: serde_json::Map<String, serde_json::Value> = data.model.models()
+        .iter()
+        .map(|m| (m.name().to_string(), json!(m.backend_statuses())))
+        .collect();
+    let vram_estimates: serde_json::Map<String, serde_json::Value> = data.model.models()
+        .iter()
+        .filter_map(|m| m.vram_mb().map(|mb| (m.name().to_string(), json!(mb * 1024 * 1024))))
+        .collect();
+    
+    json!({
+        "uptime_secs": stats().uptime_secs(),
+        "active_sessions": active_sessions,
+        "queue": {
+            "queued_jobs": data.jobs.queued(),
+            "in_flight_requests": data.model.in_flight(),
+            "max_concurrent_requests": data.model.max_concurrent(),
+            "waiting_by_priority": data.model.waiting(),
+        },
+        "backends": backends,
+        "resources": {
+            "models_dir": data.downloads.disk_usage().await,
+            "vram_budget_bytes": data.downloads.limits().vram_budget_bytes,
+            "vram_estimated_bytes": vram_estimates,
+        },
+        "tokens_per_sec": stats().tokens_per_sec(),
+        "recent_errors": stats().recent_errors(),
+        "usage_by_user": stats().usage_by_user(),
+    })
+}
+
+// Health check endpoint, including the health of every model backend
+pub async fn health_check(data: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let backends: serde_json::Map<String, serde_json::Value> = data.model.models()
+        .iter()
+        .map(|m| (m.name().to_string(), json!(m.backend_statuses())))
+        .collect();
+    
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "mode": data.mode.current(),
+        "backends": backends,
+    })))
+}
+
+// Prometheus metrics endpoint
+pub async fn metrics() -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(crate::metrics::metrics().render()))
+}
+
+// List the models available in the registry
+#[utoipa::path(
+    get, path = "/api/models", tag = "models",
+    responses((status = 200, description = "Registered models", body = Object))
+)]
+pub async fn list_models(data: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let default_model = data.model.default_model();
+    let models: Vec<_> = data.model.models()
+        .iter()
+        .map(|m| m.info(m.name() == default_model))
+        .collect();
+    
+    Ok(HttpResponse::Ok().json(json!({
+        "models": models,
+        "default": default_model,
+    })))
+}
+
+// Plain text completion of a prompt, for workloads the chat API can't express
+#[utoipa::path(
+    post, path = "/api/complete", tag = "chat",
+    request_body = CompleteRequest,
+    responses(
+        (status = 200, description = "Completion of the prompt", body = CompleteResponse),
+        (status = 400, description = "Invalid request or context overflow", body = ErrorBody),
+        (status = 413, description = "Prompt too long", body = ErrorBody),
+        (status = 429, description = "Backend rate limit", body = ErrorBody),
+        (status = 503, description = "Backend unreachable", body = ErrorBody),
+    )
+)]
+pub async fn complete(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    req: web::Json<CompleteRequest>,
+) -> Result<HttpResponse, ApiError> {
+    data.mode.ensure_available()?;
+    annotate(&http_req, AuditContext { user: req.user.clone(), ..Default::default() });
+    
+    if req.prompt.is_empty() {
+        return Err(ApiError::invalid_request("Prompt must not be empty"));
+    }
+    check_message_length(&req.prompt)?;
+    
+    let model = data.model
+        .get(req.model.as_deref())
+        .ok_or_else(|| ApiError::invalid_request(format!("Unknown model: {}", req.model.as_deref().unwrap_or_default())))?;
+    
+    let max_tokens = req.max_tokens.unwrap_or_else(|| {
+        env::var("MAX_TOKENS")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(512)
+    });
+    let mut options = GenerationOptions::new(max_tokens);
+    options.temperature = req.temperature;
+    options.top_p = req.top_p;
+    options.user = req.user.clone();
+    options.priority = request_priority(&http_req, Priority::High);
+    
+    let (text, usage) = run_completion(&http_req, &data, &model, &req.prompt, &options, &req.stop, req.echo).await?;
+    Ok(HttpResponse::Ok().json(CompleteResponse {
+        text,
+        model: model.name().to_string(),
+        usage,
+    }))
+}
+
+// Fill-in-the-middle code completion: the code that goes between `prefix` and `suffix`
+#[utoipa::path(
+    post, path = "/api/fim", tag = "chat",
+    request_body = FimRequest,
+    responses(
+        (status = 200, description = "Code to insert between prefix and suffix", body = CompleteResponse),
+        (status = 400, description = "Invalid request, model without FIM support or context overflow", body = ErrorBody),
+        (status = 413, description = "Code too long", body = ErrorBody),
+        (status = 429, description = "Backend rate limit", body = ErrorBody),
+        (status = 503, description = "Backend unreachable", body = ErrorBody),
+    )
+)]
+pub async fn fim(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    req: web::Json<FimRequest>,
+) -> Result<HttpResponse, ApiError> {
+    data.mode.ensure_available()?;
+    annotate(&http_req, AuditContext { user: req.user.clone(), ..Default::default() });
+    
+    let model = data.model
+        .get(req.model.as_deref())
+        .ok_or_else(|| ApiError::invalid_request(format!("Unknown model: {}", req.model.as_deref().unwrap_or_default())))?;
+    let prompt = model
+        .fim_prompt(&req.prefix, &req.suffix)
+        .ok_or_else(|| ApiError::invalid_request(format!("Model {} doesn't support fill-in-the-middle", model.name())))?;
+    check_message_length(&prompt)?;
+    
+    // Inline completions are short; editors ask for more explicitly
+    let mut options = GenerationOptions::new(req.max_tokens.unwrap_or(DEFAULT_FIM_MAX_TOKENS));
+    options.temperature = req.temperature;
+    options.user = req.user.clone();
+    options.priority = request_priority(&http_req, Priority::High);
+    
+    let (text, usage) = run_completion(&http_req, &data, &model, &prompt, &options, &req.stop, false).await?;
+    Ok(HttpResponse::Ok().json(CompleteResponse {
+        text,
+        model: model.name().to_string(),
+        usage,
+    }))
+}
+
+// Translate a text with a dedicated prompt, returning only the translation
+#[utoipa::path(
+    post, path = "/api/translate", tag = "chat",
+    request_body = TranslateRequest,
+    responses(
+        (status = 200, description = "The translation", body = TranslateResponse),
+        (status = 400, description = "Invalid request or context overflow", body = ErrorBody),
+        (status = 413, description = "Text too long", body = ErrorBody),
+        (status = 429, description = "Backend rate limit", body = ErrorBody),
+        (status = 503, description = "Backend unreachable", body = ErrorBody),
+    )
+)]
+pub async fn translate(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    req: web::Json<TranslateRequest>,
+) -> Result<HttpResponse, ApiError> {
+    data.mode.ensure_available()?;
+    annotate(&http_req, AuditContext { user: req.user.clone(), ..Default::default() });
+    
+    if req.text.trim().is_empty() {
+        return Err(ApiError::invalid_request("Text must not be empty"));
+    }
+    check_message_length(&req.text)?;
+    let valid_lang = |lang: &str| !lang.trim().is_empty() && lang.len() <= 64;
+    if !valid_lang(&req.target_lang) || req.source_lang.as_deref().is_some_and(|lang| !valid_lang(lang)) {
+        return Err(ApiError::invalid_request("Languages must be between 1 and 64 characters"));
+    }
+    
+    let model = data.model
+        .get(req.model.as_deref())
+        .ok_or_else(|| ApiError::invalid_request(format!("Unknown model: {}", req.model.as_deref().unwrap_or_default())))?;
+    
+    let source_lang = req.source_lang.clone()
+        .or_else(|| language::detect(&req.text).map(|lang| lang.code().to_string()));
+    let mut vars = serde_json::Map::new();
+    vars.insert("text".to_string(), json!(req.text));
+    vars.insert("source_lang".to_string(), json!(source_lang.as_deref().map(language::display_name)));
+    vars.insert("target_lang".to_string(), json!(language::display_name(&req.target_lang)));
+    let rendered = match data.prompts.get("translate") {
+        Some(_) => data.prompts.render("translate", &vars),
+        None => Context::from_serialize(&vars)
+            .and_then(|context| Tera::one_off(TRANSLATE_TEMPLATE, &context, false))
+            .map_err(anyhow::Error::from),
+    };
+    let prompt = rendered.map_err(|e| ApiError::internal(format!("Failed to render the translation prompt: {:#}", e)))?;
+    
+    // Translations run about as long as their source
+    let max_tokens = req.max_tokens.unwrap_or_else(|| (req.text.chars().count() / 2).max(MIN_TRANSLATE_MAX_TOKENS));
+    let mut options = GenerationOptions::new(max_tokens);
+    options.system_prompt = Some(TRANSLATE_SYSTEM_PROMPT.to_string());
+    options.temperature = Some(TRANSLATE_TEMPERATURE);
+    options.user = req.user.clone();
+    options.priority = request_priority(&http_req, Priority::Normal);
+    
+    let (translation, usage) = run_instruction(&http_req, &data, &model, &prompt, &options).await?;
+    Ok(HttpResponse::Ok().json(TranslateResponse {
+        translation: translation.trim().to_string(),
+        source_lang,
+        target_lang: req.target_lang.clone(),
+        model: model.name().to_string(),
+        usage,
+    }))
+}
+
+// Summarize a text of any length, in chunks when it doesn't fit in the context window
+#[utoipa::path(
+    post, path = "/api/summarize", tag = "chat",
+    request_body = SummarizeRequest,
+    responses(
+        (status = 200, description = "The summary", body = SummarizeResponse),
+        (status = 400, description = "Invalid request or text too long to summarize", body = ErrorBody),
+        (status = 413, description = "Text too long", body = ErrorBody),
+        (status = 429, description = "Backend rate limit", body = ErrorBody),
+        (status = 503, description = "Backend unreachable", body = ErrorBody),
+    )
+)]
+pub async fn summarize(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    req: web::Json<SummarizeRequest>,
+) -> Result<HttpResponse, ApiError> {
+    data.mode.ensure_available()?;
+    annotate(&http_req, AuditContext { user: req.user.clone(), ..Default::default() });
+    
+    if req.text.trim().is_empty() {
+        return Err(ApiError::invalid_request("Text must not be empty"));
+    }
+    check_document_length(&req.text)?;
+    if req.target_length.is_some_and(|words| words == 0 || words > MAX_SUMMARY_WORDS) {
+        return Err(ApiError::invalid_request(format!("target_length must be between 1 and {} words", MAX_SUMMARY_WORDS)));
+    }
+    
+    let model = data.model
+        .get(req.model.as_deref())
+        .ok_or_else(|| ApiError::invalid_request(format!("Unknown model: {}", req.model.as_deref().unwrap_or_default())))?;
+    
+    // About four tokens for every three words, with room to finish the last sentence
+    let max_tokens = match req.target_length {
+        Some(words) => words * 4 / 3 + 64,
+        None => env::var("MAX_TOKENS").ok().and_then(|s| s.parse::<usize>().ok()).unwrap_or(512),
+    };
+    let mut options = GenerationOptions::new(max_tokens);
+    options.system_prompt = Some(SUMMARIZE_SYSTEM_PROMPT.to_string());
+    options.temperature = Some(SUMMARIZE_TEMPERATURE);
+    options.user = req.user.clone();
+    options.priority = request_priority(&http_req, Priority::Normal);
+    
+    let mut vault = PiiVault::default();
+    let text = if data.pii.is_enabled() {
+        data.pii.redact(&req.text, &mut vault)
+    } else {
+        req.text.clone()
+    };
+    let summary = summarize::summarize(&model, &text, req.style, req.target_length, &options).await.map_err(|e| {
+        error!("Summarization failed: {}", e);
+        ApiError::from(e)
+    })?;
+    
+    let usage = Usage {
+        prompt_tokens: summary.prompt_tokens,
+        completion_tokens: summary.completion_tokens,
+        cached_tokens: summary.cached_tokens,
+        estimated_cost: model.estimate_cost(summary.prompt_tokens, summary.completion_tokens),
+    };
+    annotate(&http_req, AuditContext {
+        user: req.user.clone(),
+        model: Some(model.name().to_string()),
+        prompt_tokens: usage.prompt_tokens,
+        completion_tokens: usage.completion_tokens,
+        estimated_cost: usage.estimated_cost,
+        ..Default::default()
+    });
+    Ok(HttpResponse::Ok().json(SummarizeResponse {
+        summary: data.pii.restore(&summary.text, &vault),
+        style: req.style,
+        model: model.name().to_string(),
+        chunks: summary.chunks,
+        passes: summary.passes,
+        usage,
+    }))
+}
+
+// Extract a text's title, keywords, named entities and sentiment as structured JSON
+#[utoipa::path(
+    post, path = "/api/extract", tag = "chat",
+    request_body = ExtractRequest,
+    responses(
+        (status = 200, description = "The extracted metadata", body = ExtractResponse),
+        (status = 400, description = "Invalid request", body = ErrorBody),
+        (status = 413, description = "Text too long", body = ErrorBody),
+        (status = 429, description = "Backend rate limit", body = ErrorBody),
+        (status = 500, description = "The model's reply didn't match the schema", body = ErrorBody),
+        (status = 503, description = "Backend unreachable", body = ErrorBody),
+    )
+)]
+pub async fn extract(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    req: web::Json<ExtractRequest>,
+) -> Result<HttpResponse, ApiError> {
+    data.mode.ensure_available()?;
+    annotate(&http_req, AuditContext { user: req.user.clone(), ..Default::default() });
+    
+    if req.text.trim().is_empty() {
+        return Err(ApiError::invalid_request("Text must not be empty"));
+    }
+    check_message_length(&req.text)?;
+    
+    let model = data.model
+        .get(req.model.as_deref())
+        .ok_or_else(|| ApiError::invalid_request(format!("Unknown model: {}", req.model.as_deref().unwrap_or_default())))?;
+    
+    let mut options = GenerationOptions::new(EXTRACT_MAX_TOKENS);
+    options.system_prompt = Some(EXTRACT_SYSTEM_PROMPT.to_string());
+    options.temperature = Some(EXTRACT_TEMPERATURE);
+    options.json_schema = Some(extract::metadata_schema());
+    options.user = req.user.clone();
+    options.priority = request_priority(&http_req, Priority::Normal);
+    
+    let prompt = extract::metadata_prompt(&req.text, EXTRACT_MAX_KEYWORDS);
+    let (reply, usage) = run_instruction(&http_req, &data, &model, &prompt, &options).await?;
+    let metadata: Metadata = extract::parse_reply(&reply).map_err(|e| {
+        error!("Metadata extraction failed: {:#}", e);
+        ApiError::internal(format!("Metadata extraction failed: {:#}", e))
+    })?;
+    Ok(HttpResponse::Ok().json(ExtractResponse {
+        metadata,
+        model: model.name().to_string(),
+        usage,
+    }))
+}
+
+// Classify a text into the best one or top k of the given labels (zero-shot)
+#[utoipa::path(
+    post, path = "/api/classify", tag = "chat",
+    request_body = ClassifyRequest,
+    responses(
+        (status = 200, description = "The chosen labels, best first", body = ClassifyResponse),
+        (status = 400, description = "Invalid request", body = ErrorBody),
+        (status = 413, description = "Text too long", body = ErrorBody),
+        (status = 429, description = "Backend rate limit", body = ErrorBody),
+        (status = 500, description = "The model's reply didn't name any of the labels", body = ErrorBody),
+        (status = 503, description = "Backend unreachable", body = ErrorBody),
+    )
+)]
+pub async fn classify(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    req: web::Json<ClassifyRequest>,
+) -> Result<HttpResponse, ApiError> {
+    data.mode.ensure_available()?;
+    annotate(&http_req, AuditContext { user: req.user.clone(), ..Default::default() });
+    
+    if req.text.trim().is_empty() {
+        return Err(ApiError::invalid_request("Text must not be empty"));
+    }
+    check_message_length(&req.text)?;
+    let labels: Vec<String> = req.labels.iter().map(|label| label.trim().to_string()).collect();
+    if labels.len() < 2 || labels.len() > MAX_CLASSIFY_LABELS {
+        return Err(ApiError::invalid_request(format!("Give between 2 and {} labels", MAX_CLASSIFY_LABELS)));
+    }
+    if labels.iter().any(|label| label.is_empty() || label.chars().count() > MAX_LABEL_CHARS) {
+        return Err(ApiError::invalid_request(format!("Labels must be between 1 and {} characters", MAX_LABEL_CHARS)));
+    }
+    if labels.iter().enumerate().any(|(i, label)| labels[..i].iter().any(|other| other.eq_ignore_ascii_case(label))) {
+        return Err(ApiError::invalid_request("Labels must be unique"));
+    }
+    let top_k = req.top_k.unwrap_or(1);
+    if top_k == 0 || top_k > labels.len() {
+        return Err(ApiError::invalid_request("top_k must be between 1 and the number of labels"));
+    }
+    
+    let model = data.model
+        .get(req.model.as_deref())
+        .ok_or_else(|| ApiError::invalid_request(format!("Unknown model: {}", req.model.as_deref().unwrap_or_default())))?;
+    
+    let mut options = GenerationOptions::new(CLASSIFY_MAX_TOKENS);
+    options.system_prompt = Some(CLASSIFY_SYSTEM_PROMPT.to_string());
+    options.temperature = Some(EXTRACT_TEMPERATURE);
+    options.json_schema = Some(extract::classification_schema(&labels, top_k));
+    options.user = req.user.clone();
+    options.priority = request_priority(&http_req, Priority::Normal);
+    
+    let prompt = extract::classification_prompt(&req.text, &labels, top_k);
+    let (reply, usage) = run_instruction(&http_req, &data, &model, &prompt, &options).await?;
+    let scores = extract::parse_classification(&reply, &labels, top_k).map_err(|e| {
+        error!("Classification failed: {:#}", e);
+        ApiError::internal(format!("Classification failed: {:#}", e))
+    })?;
+    Ok(HttpResponse::Ok().json(ClassifyResponse {
+        label: scores[0].label.clone(),
+        confidence: scores[0].confidence,
+        labels: scores,
+        model: model.name().to_string(),
+        usage,
+    }))
+}
+
+// Answer a single instruction with the given options, outside any session, and record its
+// usage for the audit log. Personal data is masked for the backend and restored in the answer.
+async fn run_instruction(
+    http_req: &HttpRequest,
+    data: &AppState,
+    model: &LlamaModel,
+    prompt: &str,
+    options: &GenerationOptions,
+) -> Result<(String, Usage), ApiError> {
+    let mut vault = PiiVault::default();
+    let prompt = if data.pii.is_enabled() {
+        data.pii.redact(prompt, &mut vault)
+    } else {
+        prompt.to_string()
+    };
+    
+    let completion = model.generate_response(&prompt, &[], options).await.map_err(|e| {
+        error!("Model error: {}", e);
+        ApiError::from(e)
+    })?;
+    let usage = Usage {
+        prompt_tokens: completion.prompt_tokens,
+        completion_tokens: completion.completion_tokens,
+        cached_tokens: completion.cached_tokens,
+        estimated_cost: model.estimate_cost(completion.prompt_tokens, completion.completion_tokens),
+    };
+    annotate(http_req, AuditContext {
+        user: options.user.clone(),
+        model: Some(model.name().to_string()),
+        prompt_tokens: usage.prompt_tokens,
+        completion_tokens: usage.completion_tokens,
+        estimated_cost: usage.estimated_cost,
+        ..Default::default()
+    });
+    Ok((data.pii.restore(&completion.text, &vault), usage))
+}
+
+// Complete a plain prompt and record its usage for the audit log. Personal data is
+// masked for the backend and restored in the returned text.
+async fn run_completion(
+    http_req: &HttpRequest,
+    data: &AppState,
+    model: &LlamaModel,
+    prompt: &str,
+    options: &GenerationOptions,
+    stop: &[String],
+    echo: bool,
+) -> Result<(String, Usage), ApiError> {
+    let mut vault = PiiVault::default();
+    let prompt = if data.pii.is_enabled() {
+        data.pii.redact(prompt, &mut vault)
+    } else {
+        prompt.to_string()
+    };
+    
+    let completion = model.complete_prompt(&prompt, options, stop, echo).await.map_err(|e| {
+        error!("Model error: {}", e);
+        ApiError::from(e)
+    })?;
+    let usage = Usage {
+        prompt_tokens: completion.prompt_tokens,
+        completion_tokens: completion.completion_tokens,
+        cached_tokens: completion.cached_tokens,
+        estimated_cost: model.estimate_cost(completion.prompt_tokens, completion.completion_tokens),
+    };
+    annotate(http_req, AuditContext {
+        user: options.user.clone(),
+        model: Some(model.name().to_string()),
+        prompt_tokens: usage.prompt_tokens,
+        completion_tokens: usage.completion_tokens,
+        estimated_cost: usage.estimated_cost,
+        ..Default::default()
+    });
+    Ok((data.pii.restore(&completion.text, &vault), usage))
+}
+
+// Count the tokens of a text, e.g. to show how much of the context window is left
+#[utoipa::path(
+    post, path = "/api/tokenize", tag = "models",
+    request_body = TokenizeRequest,
+    responses(
+        (status = 200, description = "Token count, and the token IDs when requested", body = TokenizeResponse),
+        (status = 400, description = "Unknown model or text too long", body = ErrorBody),
+    )
+)]
+pub async fn tokenize(
+    data: web::Data<AppState>,
+    req: web::Json<TokenizeRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let model = data.model
+        .get(req.model.as_deref())
+        .ok_or_else(|| ApiError::invalid_request(format!("Unknown model: {}", req.model.as_deref().unwrap_or_default())))?;
+    check_message_length(&req.text)?;
+    
+    let tokenization = model.tokenize(&req.text).await;
+    Ok(HttpResponse::Ok().json(TokenizeResponse {
+        model: model.name().to_string(),
+        count: tokenization.count,
+        estimated: tokenization.tokens.is_none(),
+        tokens: tokenization.tokens.filter(|_| req.return_tokens),
+        context_window: model.context_window(),
+    }))
 }
 
 // Chat API endpoint
+#[utoipa::path(
+    post, path = "/api/chat", tag = "chat",
+    request_body = ChatRequest,
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Replay the stored response to retries with the same key"),
+        ("If-Match" = Option<String>, Header, description = "Only add the turn if the session still has this ETag"),
+    ),
+    responses(
+        (status = 200, description = "Model response", body = ChatResponse),
+        (status = 400, description = "Invalid request, blocked content or context overflow", body = ErrorBody),
+        (status = 409, description = "A request with the same Idempotency-Key is in progress, or the session changed since the If-Match ETag was read", body = ErrorBody),
+        (status = 413, description = "Message too long", body = ErrorBody),
+        (status = 422, description = "Response blocked by moderation", body = ErrorBody),
+        (status = 429, description = "Backend rate limit", body = ErrorBody),
+        (status = 503, description = "Backend unreachable", body = ErrorBody),
+    )
+)]
 pub async fn chat(
+    http_req: HttpRequest,
     data: web::Data<AppState>,
     req: web::Json<ChatRequest>,
-) -> impl Responder {
-    // Get default max tokens from environment or use 1000 as default
-    let default_max_tokens = env::var("MAX_TOKENS")
-        .ok()
-        .and_then(|s| s.parse::<usize>().ok())
-        .unwrap_or(512);
+) -> Result<HttpResponse, ApiError> {
+    let Some(key) = idempotency_key(&http_req)? else {
+        let response = respond_to_chat(Some(&http_req), &data, &req).await?;
+        return Ok(with_session_etag(&data, response.session_id, HttpResponse::Ok()).await.json(response));
+    };
     
-    // Use the requested max_tokens or default
-    let max_tokens = req.max_tokens.unwrap_or(default_max_tokens);
+    // Retries with the same key get the first response instead of adding another turn
+    if let Claim::Replay(response) = data.idempotency.claim(&key, &req)? {
+        annotate(&http_req, AuditContext {
+            user: req.user.clone(),
+            session_id: Some(response.session_id),
+            ..Default::default()
+        });
+        return Ok(HttpResponse::Ok()
+            .insert_header(("Idempotent-Replayed", "true"))
+            .json(response));
+    }
     
+    match respond_to_chat(Some(&http_req), &data, &req).await {
+        Ok(response) => {
+            data.idempotency.complete(&key, &response);
+            Ok(with_session_etag(&data, response.session_id, HttpResponse::Ok()).await.json(response))
+        }
+        Err(e) => {
+            data.idempotency.release(&key);
+            Err(e)
+        }
+    }
+}
+
+// Generate the reply to a chat message and record the turn in the session history.
+// Without an HTTP request (e.g. for chat integrations) nothing is attached for the audit log.
+pub(crate) async fn respond_to_chat(
+    http_req: Option<&HttpRequest>,
+    data: &AppState,
+    req: &ChatRequest,
+) -> Result<ChatResponse, ApiError> {
+    data.mode.ensure_available()?;
     let session_id = req.session_id.unwrap_or_else(Uuid::new_v4);
+    if let Some(http_req) = http_req {
+        annotate(http_req, AuditContext {
+            user: req.user.clone(),
+            session_id: Some(session_id),
+            ..Default::default()
+        });
+    }
+    if let Some(samples) = req.samples {
+        if !(2..=MAX_CONSENSUS_SAMPLES).contains(&samples) {
+            return Err(ApiError::invalid_request(format!("samples must be between 2 and {}", MAX_CONSENSUS_SAMPLES)));
+        }
+        if req.refine {
+            return Err(ApiError::invalid_request("samples and refine can't be combined"));
+        }
+    }
     
-    // Create a more specific prompt that encourages detailed responses
-    let enhanced_prompt = format!("{}\n\nPlease provide a detailed and comprehensive answer.", 
-                req.message);
+    // Requests for the same session are answered one at a time, in arrival order, so
+    // concurrent messages don't interleave in the history; other sessions are unaffected
+    let _turn = data.sessions.begin_turn(session_id).await;
+    
+    // Settings and assistant already stored for the session, if any
+    let (session_assistant, settings, mut pii_vault, session_collections) = match data.sessions.get(&session_id) {
+        Some(session) => {
+            let s = session.lock().await;
+            if let Some(http_req) = http_req {
+                if !can_access(http_req, &s) {
+                    return Err(ApiError::not_found("Session not found"));
+                }
+                etag::check_if_match(http_req, Some(&s))?;
+            }
+            if s.deleted_at.is_some() {
+                return Err(ApiError::not_found("Session is in the trash; restore it to continue the conversation"));
+            }
+            (s.assistant.clone(), s.settings.clone(), s.pii_vault.clone(), s.collections.clone())
+        }
+        None => {
+            if let Some(http_req) = http_req {
+                etag::check_if_match(http_req, None)?;
+            }
+            Default::default()
+        }
+    };
     
-    info!("Chat request from session {}: {} (max_tokens: {})", 
-          session_id, req.message, max_tokens);
+    // Use the assistant named in the request, or the one already selected for the session
+    let assistant_name = req.assistant.clone().or(session_assistant);
+    let assistant = match &assistant_name {
+        Some(name) => match data.assistants.get(name) {
+            Some(assistant) => Some(assistant),
+            None => {
+                return Err(ApiError::invalid_request(format!("Unknown assistant: {}", name)));
+            }
+        },
+        None => None,
+    };
     
-    // Add the new user message to history
-    let mut sessions = match data.sessions.lock() {
-        Ok(guard) => guard,
-        Err(e) => {
-            error!("Failed to lock sessions mutex: {}", e);
-            return HttpResponse::InternalServerError().json(json!({
-                "error": "Internal server error"
-            }));
+    // Get default max tokens from the session, the assistant, the environment, or use 512 as default
+    let default_max_tokens = settings.max_tokens
+        .or_else(|| assistant.as_ref().and_then(|a| a.max_tokens))
+        .unwrap_or_else(|| {
+            env::var("MAX_TOKENS")
+                .ok()
+                .and_then(|s| s.parse::<usize>().ok())
+                .unwrap_or(512)
+        });
+    
+    // Resolve the requested model, then the session's and the assistant's, falling back to the registry default
+    let model_name = req.model
+        .as_deref()
+        .or(settings.model.as_deref())
+        .or_else(|| assistant.as_ref().and_then(|a| a.model.as_deref()));
+    let model = match data.model.get(model_name) {
+        Some(model) => model,
+        None => {
+            return Err(ApiError::invalid_request(format!("Unknown model: {}", model_name.unwrap_or_default())));
+        }
+    };
+    
+    // Use the requested max_tokens, then the model's tokens for the requested length, or the default
+    let max_tokens = req.max_tokens
+        .or_else(|| req.length.map(|length| model.length_tokens(length)))
+        .unwrap_or(default_max_tokens);
+    
+    // Render the prompt template, if one was requested, into the user message
+    let message = match &req.template {
+        Some(template) => {
+            let mut vars = req.vars.clone();
+            vars.insert("message".to_string(), json!(req.message));
+            match data.prompts.render(template, &vars) {
+                Ok(rendered) => rendered,
+                Err(e) => {
+                    return Err(ApiError::invalid_request(format!("{:#}", e)));
+                }
+            }
+        }
+        None => req.message.clone(),
+    };
+    
+    if message.trim().is_empty() {
+        return Err(ApiError::invalid_request("Message must not be empty"));
+    }
+    check_message_length(&message)?;
+    if req.extra_body.as_ref().is_some_and(|extra| !extra.is_object()) {
+        return Err(ApiError::invalid_request("extra_body must be a JSON object"));
+    }
+    
+    // Mask personal data before the message is stored or sent to the backend
+    let message = if data.pii.is_enabled() {
+        data.pii.redact(&message, &mut pii_vault)
+    } else {
+        message
+    };
+    
+    // Screen the user message before it reaches the model or the session history
+    let message = match data.moderation.screen(Stage::Input, &message, session_id).await {
+        Screened::Allowed(message) => message,
+        Screened::Blocked(rules) => {
+            return Err(ApiError::new(ErrorCode::ContentBlocked, "Message was blocked by content moderation")
+                .with_details(json!({ "rules": rules })));
+        }
+    };
+    
+    let language_preference = language::preference(req.language.as_deref(), settings.language.as_deref())
+        .map_err(ApiError::invalid_request)?;
+    let detected_language = language::detect(&message);
+    
+    // Guardrail rules may rewrite the message or hand it to another model
+    let rule_context = RuleContext {
+        tools: assistant.as_ref().map(|a| a.tools.as_slice()).unwrap_or_default(),
+        confirmed: req.confirm,
+    };
+    let (message, model) = match data.guardrails.check(Stage::Input, &message, &rule_context) {
+        Guarded::Allowed { text, route: None } => (text, model),
+        Guarded::Allowed { text, route: Some(route) } => match data.model.get(Some(route.as_str())) {
+            Some(routed) => {
+                info!("Guardrails routed session {} from model {} to {}", session_id, model.name(), routed.name());
+                (text, routed)
+            }
+            None => return Err(ApiError::internal(format!("Guardrails route to unknown model {}", route))),
+        },
+        Guarded::Blocked { rules, message } => {
+            return Err(ApiError::new(ErrorCode::ContentBlocked, message).with_details(json!({ "rules": rules })));
+        }
+        Guarded::ConfirmationRequired { rules, message } => {
+            return Err(ApiError::new(ErrorCode::ConfirmationRequired, message).with_details(json!({ "rules": rules })));
         }
     };
     
-    let history = sessions.entry(session_id).or_insert_with(Vec::new);
+    let mut options = GenerationOptions::new(max_tokens);
+    options.use_cache = !req.bypass_cache;
+    options.user = req.user.clone();
+    options.priority = http_req.map_or(Priority::High, |http_req| request_priority(http_req, Priority::High));
+    options.extra_body = req.extra_body.clone();
+    options.reasoning_effort = req.reasoning_effort;
+    options.thinking_budget_tokens = req.thinking_budget_tokens;
+    if let Some(assistant) = &assistant {
+        options.system_prompt = Some(assistant.system_prompt.clone());
+        options.examples = assistant.example_messages();
+    }
+    // Memories are filed under the free-form `user` field, which any client can set, so only
+    // callers vouching for it with the admin token read or add to them. The chat integrations
+    // set the user themselves.
+    let vouched = match http_req {
+        Some(http_req) => is_admin(http_req),
+        None => true,
+    };
+    let memory_user = req.user.clone().filter(|_| vouched);
+    // Remind the model of what it learned about the user in earlier sessions
+    if let Some(user) = &memory_user {
+        options.system_context = data.memory.recall(user, &message).await;
+    }
+    // Ground the answer in the passages of the session's collections most relevant to the message
+    let passages = data.collections.retrieve(&session_collections, &message).await;
+    if let Some(context) = collections::context(&passages) {
+        add_system_context(&mut options, &context);
+    }
+    // Ask for the reply in the message's language, or the one the caller insists on
+    if let Some(instruction) = language_preference.and_then(|preference| language::instruction(preference, detected_language)) {
+        add_system_context(&mut options, &instruction);
+    }
+    // Ask for the requested length, so the answer fits in max_tokens instead of being cut off
+    if let Some(length) = req.length {
+        add_system_context(&mut options, length.instruction());
+    }
+    
+    // Resolve sampling settings up front so they can be echoed back
+    let effective_settings = SessionSettings {
+        temperature: Some(settings.temperature
+            .or_else(|| assistant.as_ref().and_then(|a| a.temperature))
+            .unwrap_or_else(|| model.default_temperature())),
+        top_p: Some(settings.top_p
+            .or_else(|| assistant.as_ref().and_then(|a| a.top_p))
+            .unwrap_or_else(|| model.default_top_p())),
+        max_tokens: Some(model.clamp_max_tokens(max_tokens)),
+        model: Some(model.name().to_string()),
+        language: req.language.clone().or_else(|| settings.language.clone()),
+    };
+    options.temperature = effective_settings.temperature;
+    options.top_p = effective_settings.top_p;
+    
+    // The assistant's post-amble goes to the model only; the history keeps the message as written
+    let prompt = match &assistant {
+        Some(assistant) => assistant.with_post_amble(&message),
+        None => message.clone(),
+    };
+    
+    info!("Chat request from session {} for model {}: {} (max_tokens: {})", 
+          session_id, model.name(), message, max_tokens);
+    
+    // Add the new user message to history
+    let session_lock = data.sessions.get_or_create(session_id);
+    let mut session = session_lock.lock().await;
+    
+    // Selecting an assistant in a request makes it the session's assistant
+    if req.assistant.is_some() {
+        session.assistant = assistant_name.clone();
+    }
+    if req.user.is_some() {
+        session.user = req.user.clone();
+    }
+    // A new session belongs to the browser that started it
+    if session.history.is_empty() && session.owner.is_none() {
+        session.owner = http_req.and_then(browser_id).map(|browser| browser.0);
+    }
     
-    // Add the new user message (original message, not enhanced)
-    history.push(format!("user: {}", req.message.clone()));
+    // Add the new user message
+    session.push_history(format!("user: {}", message), max_session_history());
+    session.pii_vault = pii_vault.clone();
     
     // Clone what we need for the future
-    let model = data.model.clone();
-    let history_clone = history.clone();
+    let history_clone = session.history.clone();
     
     // Release the lock before the async operation to avoid blocking
-    drop(sessions);
+    drop(session);
     
-    // Generate response
-    match model.model.generate_response(&enhanced_prompt, max_tokens, &history_clone).await {
-        Ok(response) => {
+    // Generate response; in refine mode the draft is critiqued and rewritten before anything is
+    // returned, and when sampling the answer most samples agree on is kept
+    let generated = match req.samples {
+        Some(samples) => consensus::sample(&model, &prompt, &history_clone, &options, samples)
+            .await
+            .map(|(completion, consensus)| (completion, None, Some(consensus))),
+        None => match model.generate_response(&prompt, &history_clone, &options).await {
+            Ok(draft) if req.refine => refine::refine(&model, &history_clone, &draft, &options)
+                .await
+                .map(|refined| (refined, Some(draft), None)),
+            result => result.map(|completion| (completion, None, None)),
+        },
+    };
+    match generated {
+        Ok((completion, draft, consensus)) => {
+            let usage_of = |completion: &Completion| Usage {
+                prompt_tokens: completion.prompt_tokens,
+                completion_tokens: completion.completion_tokens,
+                cached_tokens: completion.cached_tokens,
+                estimated_cost: model.estimate_cost(completion.prompt_tokens, completion.completion_tokens),
+            };
+            let passes = draft.as_ref().map(|draft| RefinePasses {
+                draft: usage_of(draft),
+                refine: usage_of(&completion),
+            });
+            let usage = match &passes {
+                Some(passes) => Usage {
+                    prompt_tokens: passes.draft.prompt_tokens + passes.refine.prompt_tokens,
+                    completion_tokens: passes.draft.completion_tokens + passes.refine.completion_tokens,
+                    cached_tokens: passes.draft.cached_tokens + passes.refine.cached_tokens,
+                    estimated_cost: passes.draft.estimated_cost + passes.refine.estimated_cost,
+                },
+                None => usage_of(&completion),
+            };
+            if let Some(http_req) = http_req {
+                annotate(http_req, AuditContext {
+                    user: req.user.clone(),
+                    session_id: Some(session_id),
+                    model: Some(model.name().to_string()),
+                    prompt_tokens: usage.prompt_tokens,
+                    completion_tokens: usage.completion_tokens,
+                    estimated_cost: usage.estimated_cost,
+                });
+            }
+            let response = completion.text;
+            
+            // Screen the reply before it is stored or returned
+            let response = match data.moderation.screen(Stage::Output, &response, session_id).await {
+                Screened::Allowed(response) => response,
+                Screened::Blocked(rules) => {
+                    return Err(ApiError::new(ErrorCode::OutputBlocked, "Response was blocked by content moderation")
+                        .with_details(json!({ "rules": rules })));
+                }
+            };
+            let response = match data.guardrails.check(Stage::Output, &response, &rule_context) {
+                Guarded::Allowed { text, .. } => text,
+                Guarded::Blocked { rules, message } | Guarded::ConfirmationRequired { rules, message } => {
+                    return Err(ApiError::new(ErrorCode::OutputBlocked, message).with_details(json!({ "rules": rules })));
+                }
+            };
+            // The thinking is shown to the caller too, so it is screened the same way
+            let reasoning = match completion.reasoning {
+                Some(reasoning) => match data.moderation.screen(Stage::Output, &reasoning, session_id).await {
+                    Screened::Allowed(reasoning) => Some(reasoning),
+                    Screened::Blocked(rules) => {
+                        return Err(ApiError::new(ErrorCode::OutputBlocked, "Response was blocked by content moderation")
+                            .with_details(json!({ "rules": rules })));
+                    }
+                },
+                None => None,
+            };
+            
             // Reacquire lock to update history
-            if let Ok(mut sessions) = data.sessions.lock() {
-                if let Some(history) = sessions.get_mut(&session_id) {
-                    history.push(format!("assistant: {}", response.clone()));
+            let mut session = session_lock.lock().await;
+            let entry = match &reasoning {
+                Some(reasoning) if reasoning_in_history() => format!("<think>\n{}\n</think>\n\n{}", reasoning, response),
+                _ => response.clone(),
+            };
+            session.push_history(format!("assistant: {}", entry), max_session_history());
+            if let Some(user) = memory_user.filter(|_| data.memory.is_enabled()) {
+                session.messages_since_memory += 2;
+                if session.messages_since_memory >= data.memory.extract_every() {
+                    session.messages_since_memory = 0;
+                    remember(data, &model, user, session_id, &session);
                 }
-            } else {
-                // Not critical if we fail to update history, just log it
-                error!("Failed to update session history");
             }
+            drop(session);
             
-            HttpResponse::Ok().json(ChatResponse {
-                response,
+            Ok(ChatResponse {
+                // History keeps the tokens; only the caller sees the original values
+                response: data.pii.restore(&response, &pii_vault),
+                reasoning: reasoning.map(|reasoning| data.pii.restore(&reasoning, &pii_vault)),
                 session_id,
+                model: model.name().to_string(),
+                assistant: assistant_name,
+                settings: effective_settings,
+                detected_language: detected_language.map(|lang| lang.code().to_string()),
+                usage,
+                passes,
+                consensus,
+                citations: collections::citations(&passages, &response),
             })
         }
         Err(e) => {
             error!("Model error: {}", e);
-            HttpResponse::InternalServerError().json(json!({
-                "error": format!("Failed to generate response: {}", e)
-            }))
+            Err(e.into())
         }
     }
-} 
\ No newline at end of file
+}
+
+// Append an instruction to the context sent after the system prompt
+fn add_system_context(options: &mut GenerationOptions, instruction: &str) {
+    options.system_context = Some(match options.system_context.take() {
+        Some(context) => format!("{}\n\n{}", context, instruction),
+        None => instruction.to_string(),
+    });
+}
+
+// Extract facts from a session's latest messages into long-term memory, in the background
+fn remember(data: &AppState, model: &Arc<LlamaModel>, user: String, session_id: Uuid, session: &Session) {
+    let start = session.history.len().saturating_sub(data.memory.extract_every());
+    // Memories outlive the session, so they hold the original values rather than PII tokens
+    let transcript: Vec<String> = session.history[start..]
+        .iter()
+        .map(|entry| data.pii.restore(entry, &session.pii_vault))
+        .collect();
+    let memory = data.memory.clone();
+    let model = model.clone();
+    tokio::spawn(async move {
+        memory.extract(&model, &user, session_id, &transcript).await;
+    });
+}
+
+// Batch chat endpoint: independent prompts, results returned in request order
+#[utoipa::path(
+    post, path = "/api/chat/batch", tag = "chat",
+    request_body = BatchChatRequest,
+    responses(
+        (status = 200, description = "One result per prompt, in request order", body = Object),
+        (status = 202, description = "Job IDs, when `async` is set", body = Object),
+        (status = 400, description = "Invalid request", body = ErrorBody),
+    )
+)]
+pub async fn chat_batch(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    req: web::Json<BatchChatRequest>,
+) -> Result<HttpResponse, ApiError> {
+    data.mode.ensure_available()?;
+    annotate(&http_req, AuditContext { user: req.user.clone(), ..Default::default() });
+    
+    let max_batch_size = env::var("MAX_BATCH_SIZE")
+        .ok()
+        .and_then(|s| s.parse::<usize>().ok())
+        .unwrap_or(32);
+    
+    if req.prompts.is_empty() || req.prompts.len() > max_batch_size {
+        return Err(ApiError::invalid_request(format!("A batch must contain between 1 and {} prompts", max_batch_size)));
+    }
+    for prompt in &req.prompts {
+        check_message_length(prompt)?;
+    }
+    
+    let max_tokens = req.max_tokens.unwrap_or_else(|| {
+        env::var("MAX_TOKENS")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(512)
+    });
+    
+    let model = match data.model.get(req.model.as_deref()) {
+        Some(model) => model,
+        None => {
+            return Err(ApiError::invalid_request(format!("Unknown model: {}", req.model.as_deref().unwrap_or_default())));
+        }
+    };
+    
+    info!("Batch request with {} prompts for model {}", req.prompts.len(), model.name());
+    let req = req.into_inner();
+    let mut options = GenerationOptions::new(max_tokens);
+    options.use_cache = !req.bypass_cache;
+    options.user = req.user.clone();
+    options.priority = request_priority(&http_req, Priority::Low);
+    
+    // Mask personal data in each prompt, keeping a vault per prompt for the reply
+    let prompts: Vec<(String, PiiVault)> = req.prompts
+        .iter()
+        .map(|prompt| {
+            let mut vault = PiiVault::default();
+            let prompt = if data.pii.is_enabled() { data.pii.redact(prompt, &mut vault) } else { prompt.clone() };
+            (prompt, vault)
+        })
+        .collect();
+    
+    if req.run_async {
+        let mut job_ids = Vec::with_capacity(prompts.len());
+        for (prompt, _) in prompts {
+            match data.jobs.submit(model.clone(), prompt, options.clone(), None) {
+                Ok(job_id) => job_ids.push(job_id),
+                Err(e) => {
+                    error!("Failed to queue batch job: {}", e);
+                    return Err(ApiError::internal("Failed to queue job"));
+                }
+            }
+        }
+        return Ok(HttpResponse::Accepted().json(json!({
+            "model": model.name(),
+            "jobs": job_ids,
+        })));
+    }
+    
+    // Run every prompt concurrently; the model's concurrency limiter bounds upstream load
+    let outcomes = futures::future::join_all(
+        prompts.iter().map(|(prompt, _)| model.generate_response(prompt, &[], &options))
+    )
+    .await;
+    
+    let mut usage = AuditContext {
+        user: req.user.clone(),
+        model: Some(model.name().to_string()),
+        ..Default::default()
+    };
+    let results: Vec<BatchItemResult> = outcomes
+        .into_iter()
+        .zip(&prompts)
+        .map(|(result, (_, vault))| match result {
+            Ok(completion) => {
+                usage.prompt_tokens += completion.prompt_tokens;
+                usage.completion_tokens += completion.completion_tokens;
+                usage.estimated_cost += model.estimate_cost(completion.prompt_tokens, completion.completion_tokens);
+                BatchItemResult { response: Some(data.pii.restore(&completion.text, vault)), error: None, code: None }
+            }
+            Err(e) => {
+                let error = ApiError::from(e);
+                BatchItemResult {
+                    response: None,
+                    error: Some(error.message().to_string()),
+                    code: Some(error.code().as_str().to_string()),
+                }
+            }
+        })
+        .collect();
+    annotate(&http_req, usage);
+    
+    Ok(HttpResponse::Ok().json(json!({
+        "model": model.name(),
+        "results": results,
+    })))
+}
+
+// Send the same message to several models at once and return every answer side by side
+#[utoipa::path(
+    post, path = "/api/compare", tag = "chat",
+    request_body = CompareRequest,
+    responses(
+        (status = 200, description = "One result per model, in request order", body = Object),
+        (status = 400, description = "Invalid request or unknown model", body = ErrorBody),
+        (status = 413, description = "Message too long", body = ErrorBody),
+    )
+)]
+pub async fn compare(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    req: web::Json<CompareRequest>,
+) -> Result<HttpResponse, ApiError> {
+    data.mode.ensure_available()?;
+    annotate(&http_req, AuditContext { user: req.user.clone(), ..Default::default() });
+    
+    if req.message.trim().is_empty() {
+        return Err(ApiError::invalid_request("Message must not be empty"));
+    }
+    check_message_length(&req.message)?;
+    
+    let mut names: Vec<&str> = Vec::with_capacity(req.models.len());
+    for name in &req.models {
+        if !names.contains(&name.as_str()) {
+            names.push(name);
+        }
+    }
+    if names.len() < 2 {
+        return Err(ApiError::invalid_request("Name at least two different models to compare"));
+    }
+    let models = names
+        .iter()
+        .map(|name| data.model.get(Some(name)).ok_or_else(|| ApiError::invalid_request(format!("Unknown model: {}", name))))
+        .collect::<Result<Vec<_>, _>>()?;
+    
+    let max_tokens = req.max_tokens.unwrap_or_else(|| {
+        env::var("MAX_TOKENS")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(512)
+    });
+    let mut options = GenerationOptions::new(max_tokens);
+    options.use_cache = req.use_cache;
+    options.temperature = req.temperature;
+    options.user = req.user.clone();
+    options.priority = request_priority(&http_req, Priority::Normal);
+    
+    let mut vault = PiiVault::default();
+    let message = if data.pii.is_enabled() {
+        data.pii.redact(&req.message, &mut vault)
+    } else {
+        req.message.clone()
+    };
+    
+    info!("Comparing {} models: {}", models.len(), names.join(", "));
+    let outcomes = futures::future::join_all(models.iter().map(|model| async {
+        let started = Instant::now();
+        let result = model.generate_response(&message, &[], &options).await;
+        (result, started.elapsed())
+    }))
+    .await;
+    
+    let mut usage = AuditContext { user: req.user.clone(), ..Default::default() };
+    let results: Vec<CompareResult> = models
+        .iter()
+        .zip(outcomes)
+        .map(|(model, (result, elapsed))| {
+            let latency_ms = elapsed.as_millis() as u64;
+            match result {
+                Ok(completion) => {
+                    let estimated_cost = model.estimate_cost(completion.prompt_tokens, completion.completion_tokens);
+                    usage.prompt_tokens += completion.prompt_tokens;
+                    usage.completion_tokens += completion.completion_tokens;
+                    usage.estimated_cost += estimated_cost;
+                    CompareResult {
+                        model: model.name().to_string(),
+                        response: Some(data.pii.restore(&completion.text, &vault)),
+                        error: None,
+                        code: None,
+                        latency_ms,
+                        tokens_per_sec: completion.completion_tokens as f64 / elapsed.as_secs_f64().max(0.001),
+                        usage: Usage {
+                            prompt_tokens: completion.prompt_tokens,
+                            completion_tokens: completion.completion_tokens,
+                            cached_tokens: completion.cached_tokens,
+                            estimated_cost,
+                        },
+                    }
+                }
+                Err(e) => {
+                    let error = ApiError::from(e);
+                    CompareResult {
+                        model: model.name().to_string(),
+                        response: None,
+                        error: Some(error.message().to_string()),
+                        code: Some(error.code().as_str().to_string()),
+                        latency_ms,
+                        tokens_per_sec: 0.0,
+                        usage: Usage::default(),
+                    }
+                }
+            }
+        })
+        .collect();
+    annotate(&http_req, usage);
+    
+    Ok(HttpResponse::Ok().json(json!({ "results": results })))
+}
+
+// Queue a generation in the background and return its job ID immediately
+#[utoipa::path(
+    post, path = "/api/generate/async", tag = "jobs",
+    request_body = AsyncGenerateRequest,
+    responses(
+        (status = 202, description = "Job queued", body = Object),
+        (status = 400, description = "Invalid request or blocked content", body = ErrorBody),
+        (status = 403, description = "callback_url resolves to an internal address", body = ErrorBody),
+    )
+)]
+pub async fn generate_async(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    req: web::Json<AsyncGenerateRequest>,
+) -> Result<HttpResponse, ApiError> {
+    data.mode.ensure_available()?;
+    annotate(&http_req, AuditContext { user: req.user.clone(), ..Default::default() });
+    
+    if req.message.trim().is_empty() {
+        return Err(ApiError::invalid_request("Message must not be empty"));
+    }
+    check_message_length(&req.message)?;
+    
+    let max_tokens = req.max_tokens.unwrap_or_else(|| {
+        env::var("MAX_TOKENS")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(512)
+    });
+    
+    let model = match data.model.get(req.model.as_deref()) {
+        Some(model) => model,
+        None => {
+            return Err(ApiError::invalid_request(format!("Unknown model: {}", req.model.as_deref().unwrap_or_default())));
+        }
+    };
+    
+    // Only plain HTTP(S) callbacks to public addresses are accepted
+    if let Some(url) = &req.callback_url {
+        webhook::check_url(url).await?;
+    }
+    
+    let req = req.into_inner();
+    let mut options = GenerationOptions::new(max_tokens);
+    options.use_cache = !req.bypass_cache;
+    options.user = req.user.clone();
+    options.priority = request_priority(&http_req, Priority::Low);
+    // Job results are kept as generated, so tokens in them are not swapped back
+    let message = if data.pii.is_enabled() {
+        data.pii.redact(&req.message, &mut PiiVault::default())
+    } else {
+        req.message
+    };
+    match data.jobs.submit(model, message, options, req.callback_url) {
+        Ok(job_id) => {
+            info!("Queued async job {}", job_id);
+            Ok(HttpResponse::Accepted().json(json!({
+                "job_id": job_id,
+                "status": "queued",
+            })))
+        }
+        Err(e) => {
+            error!("Failed to queue job: {}", e);
+            Err(ApiError::internal("Failed to queue job"))
+        }
+    }
+}
+
+// Poll the status and result of an async job
+#[utoipa::path(
+    get, path = "/api/jobs/{id}", tag = "jobs",
+    params(("id" = Uuid, Path, description = "Job ID")),
+    responses(
+        (status = 200, description = "Job status and result", body = Job),
+        (status = 404, description = "Unknown job", body = ErrorBody),
+    )
+)]
+pub async fn get_job(
+    data: web::Data<AppState>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    match data.jobs.get(&path) {
+        Some(job) => Ok(HttpResponse::Ok().json(job)),
+        None => Err(ApiError::not_found("Job not found")),
+    }
+}
+
+// List the prompt templates in the library
+#[utoipa::path(
+    get, path = "/api/prompts", tag = "prompts",
+    responses((status = 200, description = "Prompt templates", body = Object))
+)]
+pub async fn list_prompts(data: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok().json(json!({
+        "prompts": data.prompts.list()
+    })))
+}
+
+// Fetch a single prompt template
+#[utoipa::path(
+    get, path = "/api/prompts/{name}", tag = "prompts",
+    params(("name" = String, Path, description = "Template name")),
+    responses(
+        (status = 200, description = "Prompt template", body = PromptTemplate),
+        (status = 404, description = "Unknown template", body = ErrorBody),
+    )
+)]
+pub async fn get_prompt(
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    match data.prompts.get(&path) {
+        Some(prompt) => Ok(HttpResponse::Ok().json(prompt)),
+        None => Err(ApiError::not_found("Prompt template not found")),
+    }
+}
+
+// Create or replace a prompt template
+#[utoipa::path(
+    put, path = "/api/prompts/{name}", tag = "prompts",
+    params(("name" = String, Path, description = "Template name")),
+    request_body = PromptTemplateRequest,
+    responses(
+        (status = 200, description = "Template replaced", body = PromptTemplate),
+        (status = 201, description = "Template created", body = PromptTemplate),
+        (status = 400, description = "Template does not compile or reads the environment", body = ErrorBody),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn put_prompt(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+    req: web::Json<PromptTemplateRequest>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    let name = path.into_inner();
+    let req = req.into_inner();
+    
+    match data.prompts.save(&name, req.template, req.description) {
+        Ok(replaced) => {
+            info!("{} prompt template {}", if replaced { "Updated" } else { "Created" }, name);
+            let prompt = data.prompts.get(&name);
+            if replaced {
+                Ok(HttpResponse::Ok().json(prompt))
+            } else {
+                Ok(HttpResponse::Created().json(prompt))
+            }
+        }
+        Err(e) => {
+            error!("Failed to save prompt template {}: {:#}", name, e);
+            Err(ApiError::invalid_request(format!("{:#}", e)))
+        }
+    }
+}
+
+// Delete a prompt template
+#[utoipa::path(
+    delete, path = "/api/prompts/{name}", tag = "prompts",
+    params(("name" = String, Path, description = "Template name")),
+    responses(
+        (status = 204, description = "Template deleted"),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 404, description = "Unknown template", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn delete_prompt(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    match data.prompts.delete(&path) {
+        Ok(true) => Ok(HttpResponse::NoContent().finish()),
+        Ok(false) => Err(ApiError::not_found("Prompt template not found")),
+        Err(e) => {
+            error!("Failed to delete prompt template {}: {:#}", path, e);
+            Err(ApiError::internal("Failed to delete prompt template"))
+        }
+    }
+}
+
+// List the pipelines
+#[utoipa::path(
+    get, path = "/api/pipelines", tag = "pipelines",
+    responses((status = 200, description = "Pipelines", body = Object))
+)]
+pub async fn list_pipelines(data: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok().json(json!({
+        "pipelines": data.pipelines.list()
+    })))
+}
+
+// Run a pipeline's steps and return the output of each
+#[utoipa::path(
+    post, path = "/api/pipelines/{name}/run", tag = "pipelines",
+    params(("name" = String, Path, description = "Pipeline name")),
+    request_body = PipelineRunRequest,
+    responses(
+        (status = 200, description = "Outputs of the pipeline's steps", body = PipelineRunResponse),
+        (status = 400, description = "Invalid request", body = ErrorBody),
+        (status = 404, description = "Unknown pipeline", body = ErrorBody),
+        (status = 413, description = "Input too long", body = ErrorBody),
+        (status = 429, description = "Backend rate limit", body = ErrorBody),
+        (status = 500, description = "A step failed", body = ErrorBody),
+        (status = 503, description = "Backend unreachable", body = ErrorBody),
+    )
+)]
+pub async fn run_pipeline(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+    req: web::Json<PipelineRunRequest>,
+) -> Result<HttpResponse, ApiError> {
+    data.mode.ensure_available()?;
+    annotate(&http_req, AuditContext { user: req.user.clone(), ..Default::default() });
+    
+    let pipeline = data.pipelines
+        .get(&path)
+        .ok_or_else(|| ApiError::not_found("Pipeline not found"))?;
+    let model = data.model
+        .get(req.model.as_deref())
+        .ok_or_else(|| ApiError::invalid_request(format!("Unknown model: {}", req.model.as_deref().unwrap_or_default())))?;
+    
+    // Personal data in the input is masked for every step and restored in the outputs
+    let mut vault = PiiVault::default();
+    let mut input = serde_json::Map::new();
+    for (name, value) in &req.input {
+        let value = match value {
+            serde_json::Value::String(text) => {
+                check_message_length(text)?;
+                if data.pii.is_enabled() {
+                    json!(data.pii.redact(text, &mut vault))
+                } else {
+                    value.clone()
+                }
+            }
+            _ => value.clone(),
+        };
+        input.insert(name.clone(), value);
+    }
+    
+    let mut options = GenerationOptions::new(0);
+    options.user = req.user.clone();
+    options.priority = request_priority(&http_req, Priority::Normal);
+    let results = pipeline.run(&data.model, Some(model.name()), &input, &options).await.map_err(|e| {
+        error!("Pipeline {} failed: {:#}", pipeline.name, e);
+        let message = format!("Pipeline {} failed: {:#}", pipeline.name, e);
+        ApiError::new(ApiError::from(e).code(), message)
+    })?;
+    
+    let mut total = Usage::default();
+    let steps: Vec<PipelineStepOutput> = results
+        .into_iter()
+        .map(|result| {
+            let usage = result.model.as_deref().map(|name| {
+                let estimated_cost = data.model
+                    .get(Some(name))
+                    .map(|model| model.estimate_cost(result.prompt_tokens, result.completion_tokens))
+                    .unwrap_or_default();
+                Usage {
+                    prompt_tokens: result.prompt_tokens,
+                    completion_tokens: result.completion_tokens,
+                    cached_tokens: result.cached_tokens,
+                    estimated_cost,
+                }
+            });
+            if let Some(usage) = &usage {
+                total.prompt_tokens += usage.prompt_tokens;
+                total.completion_tokens += usage.completion_tokens;
+                total.cached_tokens += usage.cached_tokens;
+                total.estimated_cost += usage.estimated_cost;
+            }
+            PipelineStepOutput {
+                id: result.id,
+                kind: result.kind,
+                output: restore_value(&data, result.output, &vault),
+                model: result.model,
+                usage,
+                duration_ms: result.duration_ms,
+            }
+        })
+        .collect();
+    annotate(&http_req, AuditContext {
+        user: req.user.clone(),
+        model: Some(model.name().to_string()),
+        prompt_tokens: total.prompt_tokens,
+        completion_tokens: total.completion_tokens,
+        estimated_cost: total.estimated_cost,
+        ..Default::default()
+    });
+    Ok(HttpResponse::Ok().json(PipelineRunResponse {
+        pipeline: pipeline.name.clone(),
+        output: steps.last().map(|step| step.output.clone()).unwrap_or_default(),
+        steps,
+        usage: total,
+    }))
+}
+
+// Restore the personal data masked in every string of a step's output
+fn restore_value(data: &AppState, value: serde_json::Value, vault: &PiiVault) -> serde_json::Value {
+    match value {
+        serde_json::Value::String(text) => serde_json::Value::String(data.pii.restore(&text, vault)),
+        serde_json::Value::Array(items) => items.into_iter().map(|item| restore_value(data, item, vault)).collect(),
+        serde_json::Value::Object(fields) => fields
+            .into_iter()
+            .map(|(name, field)| (name, restore_value(data, field, vault)))
+            .collect(),
+        other => other,
+    }
+}
+
+// List the assistant profiles
+#[utoipa::path(
+    get, path = "/api/assistants", tag = "assistants",
+    responses((status = 200, description = "Assistant profiles", body = Object))
+)]
+pub async fn list_assistants(data: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok().json(json!({
+        "assistants": data.assistants.list()
+    })))
+}
+
+// Fetch a single assistant profile
+#[utoipa::path(
+    get, path = "/api/assistants/{name}", tag = "assistants",
+    params(("name" = String, Path, description = "Assistant name")),
+    responses(
+        (status = 200, description = "Assistant profile", body = Assistant),
+        (status = 404, description = "Unknown assistant", body = ErrorBody),
+    )
+)]
+pub async fn get_assistant(
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    match data.assistants.get(&path) {
+        Some(assistant) => Ok(HttpResponse::Ok().json(assistant)),
+        None => Err(ApiError::not_found("Assistant not found")),
+    }
+}
+
+// Create or replace an assistant profile
+#[utoipa::path(
+    put, path = "/api/assistants/{name}", tag = "assistants",
+    params(("name" = String, Path, description = "Assistant name")),
+    request_body = Assistant,
+    responses(
+        (status = 200, description = "Assistant replaced", body = Assistant),
+        (status = 201, description = "Assistant created", body = Assistant),
+        (status = 400, description = "Invalid profile", body = ErrorBody),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn put_assistant(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+    req: web::Json<Assistant>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    let name = path.into_inner();
+    
+    // Catch typos in the model name at save time rather than on the first chat
+    if let Some(model) = &req.model {
+        if data.model.get(Some(model.as_str())).is_none() {
+            return Err(ApiError::invalid_request(format!("Unknown model: {}", model)));
+        }
+    }
+    validate_examples(&req.examples)?;
+    
+    match data.assistants.save(req.into_inner(), &name) {
+        Ok(replaced) => {
+            info!("{} assistant {}", if replaced { "Updated" } else { "Created" }, name);
+            let assistant = data.assistants.get(&name);
+            if replaced {
+                Ok(HttpResponse::Ok().json(assistant))
+            } else {
+                Ok(HttpResponse::Created().json(assistant))
+            }
+        }
+        Err(e) => {
+            error!("Failed to save assistant {}: {:#}", name, e);
+            Err(ApiError::invalid_request(format!("{:#}", e)))
+        }
+    }
+}
+
+// Delete an assistant profile
+#[utoipa::path(
+    delete, path = "/api/assistants/{name}", tag = "assistants",
+    params(("name" = String, Path, description = "Assistant name")),
+    responses(
+        (status = 204, description = "Assistant deleted"),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 404, description = "Unknown assistant", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn delete_assistant(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    match data.assistants.delete(&path) {
+        Ok(true) => Ok(HttpResponse::NoContent().finish()),
+        Ok(false) => Err(ApiError::not_found("Assistant not found")),
+        Err(e) => {
+            error!("Failed to delete assistant {}: {:#}", path, e);
+            Err(ApiError::internal("Failed to delete assistant"))
+        }
+    }
+}
+
+fn validate_examples(examples: &[FewShotExample]) -> Result<(), ApiError> {
+    if examples.len() > MAX_EXAMPLES {
+        return Err(ApiError::invalid_request(format!("An assistant can have at most {} examples", MAX_EXAMPLES)));
+    }
+    examples.iter().try_for_each(|example| example.validate().map_err(ApiError::invalid_request))
+}
+
+// Replace an assistant's examples and persist the profile
+fn save_examples(data: &AppState, mut assistant: Assistant, examples: Vec<FewShotExample>) -> Result<Vec<FewShotExample>, ApiError> {
+    validate_examples(&examples)?;
+    let name = assistant.name.clone();
+    assistant.examples = examples;
+    let examples = assistant.examples.clone();
+    data.assistants.save(assistant, &name).map_err(|e| {
+        error!("Failed to save assistant {}: {:#}", name, e);
+        ApiError::internal("Failed to save assistant")
+    })?;
+    Ok(examples)
+}
+
+// List an assistant's few-shot examples in the order they are sent
+#[utoipa::path(
+    get, path = "/api/assistants/{name}/examples", tag = "assistants",
+    params(("name" = String, Path, description = "Assistant name")),
+    responses(
+        (status = 200, description = "Examples", body = ExamplesRequest),
+        (status = 404, description = "Unknown assistant", body = ErrorBody),
+    )
+)]
+pub async fn list_assistant_examples(
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let assistant = data.assistants.get(&path).ok_or_else(|| ApiError::not_found("Assistant not found"))?;
+    Ok(HttpResponse::Ok().json(json!({ "examples": assistant.examples })))
+}
+
+// Replace an assistant's few-shot examples
+#[utoipa::path(
+    put, path = "/api/assistants/{name}/examples", tag = "assistants",
+    params(("name" = String, Path, description = "Assistant name")),
+    request_body = ExamplesRequest,
+    responses(
+        (status = 200, description = "Examples replaced", body = ExamplesRequest),
+        (status = 400, description = "Invalid examples", body = ErrorBody),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 404, description = "Unknown assistant", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn put_assistant_examples(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+    req: web::Json<ExamplesRequest>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    let assistant = data.assistants.get(&path).ok_or_else(|| ApiError::not_found("Assistant not found"))?;
+    let examples = save_examples(&data, assistant, req.into_inner().examples)?;
+    info!("Set {} example(s) for assistant {}", examples.len(), path);
+    Ok(HttpResponse::Ok().json(json!({ "examples": examples })))
+}
+
+// Append a few-shot example to an assistant
+#[utoipa::path(
+    post, path = "/api/assistants/{name}/examples", tag = "assistants",
+    params(("name" = String, Path, description = "Assistant name")),
+    request_body = FewShotExample,
+    responses(
+        (status = 201, description = "Example added; all examples in order", body = ExamplesRequest),
+        (status = 400, description = "Invalid example or too many examples", body = ErrorBody),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 404, description = "Unknown assistant", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn add_assistant_example(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+    req: web::Json<FewShotExample>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    let assistant = data.assistants.get(&path).ok_or_else(|| ApiError::not_found("Assistant not found"))?;
+    let mut examples = assistant.examples.clone();
+    examples.push(req.into_inner());
+    let examples = save_examples(&data, assistant, examples)?;
+    Ok(HttpResponse::Created().json(json!({ "examples": examples })))
+}
+
+// Remove a few-shot example by its position
+#[utoipa::path(
+    delete, path = "/api/assistants/{name}/examples/{index}", tag = "assistants",
+    params(
+        ("name" = String, Path, description = "Assistant name"),
+        ("index" = usize, Path, description = "Position of the example, from 0"),
+    ),
+    responses(
+        (status = 204, description = "Example removed"),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 404, description = "Unknown assistant or example", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn delete_assistant_example(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<(String, usize)>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    let (name, index) = path.into_inner();
+    let assistant = data.assistants.get(&name).ok_or_else(|| ApiError::not_found("Assistant not found"))?;
+    if index >= assistant.examples.len() {
+        return Err(ApiError::not_found("Example not found"));
+    }
+    let mut examples = assistant.examples.clone();
+    examples.remove(index);
+    save_examples(&data, assistant, examples)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// List the scheduled prompts with their run state
+#[utoipa::path(
+    get, path = "/api/schedules", tag = "schedules",
+    responses(
+        (status = 200, description = "Scheduled prompts", body = Object),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn list_schedules(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    Ok(HttpResponse::Ok().json(json!({
+        "schedules": data.schedules.list()
+    })))
+}
+
+// Fetch a scheduled prompt
+#[utoipa::path(
+    get, path = "/api/schedules/{name}", tag = "schedules",
+    params(("name" = String, Path, description = "Schedule name")),
+    responses(
+        (status = 200, description = "Scheduled prompt with its run state", body = ScheduleInfo),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 404, description = "Unknown schedule", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn get_schedule(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    match data.schedules.get(&path) {
+        Some(schedule) => Ok(HttpResponse::Ok().json(schedule)),
+        None => Err(ApiError::not_found("Schedule not found")),
+    }
+}
+
+// Create or replace a scheduled prompt
+#[utoipa::path(
+    put, path = "/api/schedules/{name}", tag = "schedules",
+    params(("name" = String, Path, description = "Schedule name")),
+    request_body = Schedule,
+    responses(
+        (status = 200, description = "Schedule replaced", body = ScheduleInfo),
+        (status = 201, description = "Schedule created", body = ScheduleInfo),
+        (status = 400, description = "Invalid schedule", body = ErrorBody),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn put_schedule(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+    req: web::Json<Schedule>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    let name = path.into_inner();
+    
+    // Catch typos at save time rather than when the schedule first fires
+    if let Some(model) = &req.model {
+        if data.model.get(Some(model.as_str())).is_none() {
+            return Err(ApiError::invalid_request(format!("Unknown model: {}", model)));
+        }
+    }
+    if let Some(assistant) = &req.assistant {
+        if data.assistants.get(assistant).is_none() {
+            return Err(ApiError::invalid_request(format!("Unknown assistant: {}", assistant)));
+        }
+    }
+    if req.prompt.trim().is_empty() {
+        return Err(ApiError::invalid_request("Prompt must not be empty"));
+    }
+    check_message_length(&req.prompt)?;
+    
+    match data.schedules.save(req.into_inner(), &name) {
+        Ok(replaced) => {
+            info!("{} schedule {}", if replaced { "Updated" } else { "Created" }, name);
+            let schedule = data.schedules.get(&name);
+            if replaced {
+                Ok(HttpResponse::Ok().json(schedule))
+            } else {
+                Ok(HttpResponse::Created().json(schedule))
+            }
+        }
+        Err(e) => {
+            error!("Failed to save schedule {}: {:#}", name, e);
+            Err(ApiError::invalid_request(format!("{:#}", e)))
+        }
+    }
+}
+
+// Delete a scheduled prompt
+#[utoipa::path(
+    delete, path = "/api/schedules/{name}", tag = "schedules",
+    params(("name" = String, Path, description = "Schedule name")),
+    responses(
+        (status = 204, description = "Schedule deleted"),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 404, description = "Unknown schedule", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn delete_schedule(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    match data.schedules.delete(&path) {
+        Ok(true) => Ok(HttpResponse::NoContent().finish()),
+        Ok(false) => Err(ApiError::not_found("Schedule not found")),
+        Err(e) => {
+            error!("Failed to delete schedule {}: {:#}", path, e);
+            Err(ApiError::internal("Failed to delete schedule"))
+        }
+    }
+}
+
+// Run a scheduled prompt now, outside its schedule, and return the answer
+#[utoipa::path(
+    post, path = "/api/schedules/{name}/run", tag = "schedules",
+    params(("name" = String, Path, description = "Schedule name")),
+    responses(
+        (status = 200, description = "The answer, also delivered as configured", body = Object),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 404, description = "Unknown schedule", body = ErrorBody),
+        (status = 500, description = "Generation failed", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn run_schedule_now(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    let info = data.schedules.get(&path).ok_or_else(|| ApiError::not_found("Schedule not found"))?;
+    let response = schedules::run_schedule(&data, info.schedule).await?;
+    Ok(HttpResponse::Ok().json(json!({
+        "schedule": path.into_inner(),
+        "response": response,
+    })))
+}
+
+// List sessions, pinned first, then most recently active; archived ones only when asked for
+#[utoipa::path(
+    get, path = "/api/sessions", tag = "sessions",
+    params(SessionListQuery),
+    responses(
+        (status = 200, description = "Matching sessions", body = [SessionSummary]),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn list_sessions(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<SessionListQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let owner = session_list_owner(&http_req)?;
+    let tag = query.tag.as_deref().and_then(normalize_tag);
+    let summaries = data.sessions
+        .list(|session| {
+            let status = match query.status {
+                SessionStatus::Active => !session.archived,
+                SessionStatus::Archived => session.archived,
+                SessionStatus::All => true,
+            };
+            status
+                && session.deleted_at.is_none()
+                && owner.is_none_or(|owner| session.owner == Some(owner))
+                && query.user.as_ref().is_none_or(|user| session.user.as_ref() == Some(user))
+                && tag.as_ref().is_none_or(|tag| session.tags.contains(tag))
+                && query.pinned.is_none_or(|pinned| session.pinned == pinned)
+        })
+        .await;
+    Ok(HttpResponse::Ok().json(json!({
+        "sessions": summaries
+    })))
+}
+
+// Users aren't authenticated, so listing someone's conversations is an admin action; with
+// browser scoping, a browser may list the sessions it started. Returns the browser to list
+// the sessions of, None for an admin listing them all.
+fn session_list_owner(http_req: &HttpRequest) -> Result<Option<Uuid>, ApiError> {
+    match browser_id(http_req) {
+        Some(browser) if identity::scoping_enabled() && !is_admin(http_req) => Ok(Some(browser.0)),
+        _ => require_admin(http_req).map(|_| None),
+    }
+}
+
+// List the sessions in the trash, most recently active first
+#[utoipa::path(
+    get, path = "/api/sessions/trash", tag = "sessions",
+    responses(
+        (status = 200, description = "Deleted sessions, with the time each will be purged", body = [SessionSummary]),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn list_trash(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let owner = session_list_owner(&http_req)?;
+    let summaries = data.sessions
+        .list(|session| session.deleted_at.is_some() && owner.is_none_or(|owner| session.owner == Some(owner)))
+        .await;
+    Ok(HttpResponse::Ok().json(json!({
+        "sessions": summaries,
+        "retention_days": trash::retention().num_days(),
+    })))
+}
+
+// Move a session to the trash, from where it can be restored until it is purged
+#[utoipa::path(
+    delete, path = "/api/sessions/{id}", tag = "sessions",
+    params(("id" = Uuid, Path, description = "Session ID")),
+    responses(
+        (status = 200, description = "The deleted session, with the time it will be purged", body = SessionSummary),
+        (status = 404, description = "Unknown session", body = ErrorBody),
+        (status = 409, description = "The session changed since the If-Match ETag was read", body = ErrorBody),
+    )
+)]
+pub async fn delete_session(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let session_id = path.into_inner();
+    let session = accessible_session(&http_req, &data, session_id).await?;
+    let mut session = session.lock().await;
+    etag::check_if_match(&http_req, Some(&session))?;
+    session.deleted_at = Some(chrono::Utc::now());
+    session.touch();
+    info!("Moved session {} to the trash", session_id);
+    Ok(HttpResponse::Ok().insert_header(etag::header(&session)).json(session.summary(session_id)))
+}
+
+// Take a session out of the trash
+#[utoipa::path(
+    post, path = "/api/sessions/{id}/restore", tag = "sessions",
+    params(("id" = Uuid, Path, description = "Session ID")),
+    responses(
+        (status = 200, description = "The restored session", body = SessionSummary),
+        (status = 404, description = "Session not in the trash", body = ErrorBody),
+    )
+)]
+pub async fn restore_session(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let session_id = path.into_inner();
+    let session = trashed_session(&http_req, &data, session_id).await?;
+    let mut session = session.lock().await;
+    session.deleted_at = None;
+    session.touch();
+    info!("Restored session {} from the trash", session_id);
+    Ok(HttpResponse::Ok().insert_header(etag::header(&session)).json(session.summary(session_id)))
+}
+
+// Delete a session in the trash for good, without waiting for the retention to run out
+#[utoipa::path(
+    delete, path = "/api/sessions/trash/{id}", tag = "sessions",
+    params(("id" = Uuid, Path, description = "Session ID")),
+    responses(
+        (status = 204, description = "Session purged"),
+        (status = 404, description = "Session not in the trash", body = ErrorBody),
+    )
+)]
+pub async fn purge_session(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let session_id = path.into_inner();
+    trashed_session(&http_req, &data, session_id).await?;
+    data.sessions.remove(&session_id);
+    info!("Purged session {} from the trash", session_id);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// Attach tags to a session
+#[utoipa::path(
+    post, path = "/api/sessions/{id}/tags", tag = "sessions",
+    params(("id" = Uuid, Path, description = "Session ID")),
+    request_body = TagsRequest,
+    responses(
+        (status = 200, description = "The session's tags", body = Object),
+        (status = 400, description = "Invalid tag or too many tags", body = ErrorBody),
+        (status = 404, description = "Unknown session", body = ErrorBody),
+        (status = 409, description = "The session changed since the If-Match ETag was read", body = ErrorBody),
+    )
+)]
+pub async fn add_session_tags(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<Uuid>,
+    req: web::Json<TagsRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let tags = req.tags
+        .iter()
+        .map(|tag| normalize_tag(tag).ok_or_else(|| ApiError::invalid_request(
+            format!("Tags must be 1 to {} characters", MAX_TAG_CHARS)
+        )))
+        .collect::<Result<Vec<_>, _>>()?;
+    
+    let session = accessible_session(&http_req, &data, *path).await?;
+    let mut session = session.lock().await;
+    etag::check_if_match(&http_req, Some(&session))?;
+    let mut updated = session.tags.clone();
+    updated.extend(tags);
+    if updated.len() > MAX_TAGS_PER_SESSION {
+        return Err(ApiError::invalid_request(format!("A session can have at most {} tags", MAX_TAGS_PER_SESSION)));
+    }
+    session.tags = updated;
+    session.touch();
+    Ok(HttpResponse::Ok().insert_header(etag::header(&session)).json(json!({
+        "tags": session.tags
+    })))
+}
+
+// Remove a tag from a session
+#[utoipa::path(
+    delete, path = "/api/sessions/{id}/tags/{tag}", tag = "sessions",
+    params(
+        ("id" = Uuid, Path, description = "Session ID"),
+        ("tag" = String, Path, description = "Tag to remove"),
+    ),
+    responses(
+        (status = 200, description = "The session's remaining tags", body = Object),
+        (status = 404, description = "Unknown session", body = ErrorBody),
+        (status = 409, description = "The session changed since the If-Match ETag was read", body = ErrorBody),
+    )
+)]
+pub async fn delete_session_tag(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<(Uuid, String)>,
+) -> Result<HttpResponse, ApiError> {
+    let (session_id, tag) = path.into_inner();
+    let session = accessible_session(&http_req, &data, session_id).await?;
+    let mut session = session.lock().await;
+    etag::check_if_match(&http_req, Some(&session))?;
+    if normalize_tag(&tag).is_some_and(|tag| session.tags.remove(&tag)) {
+        session.touch();
+    }
+    Ok(HttpResponse::Ok().insert_header(etag::header(&session)).json(json!({
+        "tags": session.tags
+    })))
+}
+
+// Document collections, oldest first
+#[utoipa::path(
+    get, path = "/api/collections", tag = "collections",
+    responses((status = 200, description = "Collections and their documents", body = [Collection]))
+)]
+pub async fn list_collections(data: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(data.collections.list())
+}
+
+// Create an empty document collection
+#[utoipa::path(
+    post, path = "/api/collections", tag = "collections",
+    request_body = CollectionRequest,
+    responses(
+        (status = 201, description = "Collection created", body = Collection),
+        (status = 400, description = "Invalid name", body = ErrorBody),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn create_collection(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    req: web::Json<CollectionRequest>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    let req = req.into_inner();
+    let name = req.name.trim().to_string();
+    if name.is_empty() || name.chars().count() > MAX_COLLECTION_NAME_CHARS {
+        return Err(ApiError::invalid_request(format!("name must be 1 to {} characters", MAX_COLLECTION_NAME_CHARS)));
+    }
+    let collection = data.collections.create(name, req.description.filter(|d| !d.trim().is_empty()));
+    info!("Created collection {} ({})", collection.name, collection.id);
+    Ok(HttpResponse::Created().json(collection))
+}
+
+// A collection and its documents
+#[utoipa::path(
+    get, path = "/api/collections/{id}", tag = "collections",
+    params(("id" = Uuid, Path, description = "Collection ID")),
+    responses(
+        (status = 200, description = "The collection", body = Collection),
+        (status = 404, description = "Unknown collection", body = ErrorBody),
+    )
+)]
+pub async fn get_collection(
+    data: web::Data<AppState>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let collection = data.collections.get(*path).ok_or_else(|| ApiError::not_found("Collection not found"))?;
+    Ok(HttpResponse::Ok().json(collection))
+}
+
+// Delete a collection and its documents; sessions it was attached to stop searching it
+#[utoipa::path(
+    delete, path = "/api/collections/{id}", tag = "collections",
+    params(("id" = Uuid, Path, description = "Collection ID")),
+    responses(
+        (status = 204, description = "Collection deleted"),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 404, description = "Unknown collection", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn delete_collection(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    if data.collections.delete(*path) {
+        info!("Deleted collection {}", path);
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Err(ApiError::not_found("Collection not found"))
+    }
+}
+
+// Chunk and index a plain text document in a collection
+#[utoipa::path(
+    post, path = "/api/collections/{id}/documents", tag = "collections",
+    params(("id" = Uuid, Path, description = "Collection ID")),
+    request_body = DocumentRequest,
+    responses(
+        (status = 201, description = "Document added", body = Document),
+        (status = 400, description = "Missing name or text", body = ErrorBody),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 404, description = "Unknown collection", body = ErrorBody),
+        (status = 413, description = "Document longer than MAX_DOCUMENT_LENGTH", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn add_collection_document(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<Uuid>,
+    req: web::Json<DocumentRequest>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    let req = req.into_inner();
+    let name = req.name.trim().to_string();
+    if name.is_empty() {
+        return Err(ApiError::invalid_request("name must not be empty"));
+    }
+    if req.text.trim().is_empty() {
+        return Err(ApiError::invalid_request("text must not be empty"));
+    }
+    check_document_length(&req.text)?;
+    
+    let document = data.collections
+        .add_document(*path, name, DocumentFormat::Text, None, &req.text)
+        .await
+        .ok_or_else(|| ApiError::not_found("Collection not found"))?;
+    Ok(HttpResponse::Created().json(document))
+}
+
+// Extract, chunk and index an uploaded document file in a collection
+#[utoipa::path(
+    post, path = "/api/collections/{id}/files", tag = "collections",
+    params(("id" = Uuid, Path, description = "Collection ID"), UploadQuery),
+    request_body(content = Vec<u8>, description = "The file: PDF, DOCX, HTML, CSV, markdown or plain text", content_type = "application/octet-stream"),
+    responses(
+        (status = 201, description = "Document added", body = Document),
+        (status = 400, description = "Missing name, unknown format or unreadable file", body = ErrorBody),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 404, description = "Unknown collection", body = ErrorBody),
+        (status = 413, description = "File larger than MAX_UPLOAD_BYTES, or text longer than MAX_DOCUMENT_LENGTH", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn upload_collection_document(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<Uuid>,
+    query: web::Query<UploadQuery>,
+    payload: web::Payload,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    let name = query.name.trim().to_string();
+    if name.is_empty() {
+        return Err(ApiError::invalid_request("name must not be empty"));
+    }
+    if data.collections.get(*path).is_none() {
+        return Err(ApiError::not_found("Collection not found"));
+    }
+    let content_type = http_req.headers().get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok());
+    let format = query.format
+        .or_else(|| DocumentFormat::detect(content_type, &name))
+        .ok_or_else(|| ApiError::invalid_request("Unknown document format; name the file with its extension or pass format"))?;
+    let body = read_upload(payload).await?;
+    
+    // PDFs in particular take a while to parse, so extraction stays off the async workers
+    let text = tokio::task::spawn_blocking(move || ingest::extract(format, &body))
+        .await
+        .map_err(|e| ApiError::internal(format!("Document extraction failed: {}", e)))?
+        .map_err(|e| ApiError::invalid_request(format!("Could not read {}: {:#}", name, e)))?;
+    if text.trim().is_empty() {
+        return Err(ApiError::invalid_request(format!("No text found in {}", name)));
+    }
+    check_document_length(&text)?;
+    
+    let document = data.collections
+        .add_document(*path, name, format, None, &text)
+        .await
+        .ok_or_else(|| ApiError::not_found("Collection not found"))?;
+    Ok(HttpResponse::Created().json(document))
+}
+
+// Fetch a web page, or a document file on the web, and index its text in a collection
+#[utoipa::path(
+    post, path = "/api/collections/{id}/urls", tag = "collections",
+    params(("id" = Uuid, Path, description = "Collection ID")),
+    request_body = UrlRequest,
+    responses(
+        (status = 201, description = "Document added", body = Document),
+        (status = 400, description = "Invalid URL, failed fetch, unknown format or unreadable page", body = ErrorBody),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 403, description = "The URL resolves to an internal address, or robots.txt disallows it", body = ErrorBody),
+        (status = 404, description = "Unknown collection", body = ErrorBody),
+        (status = 413, description = "Page larger than URL_FETCH_MAX_BYTES, or text longer than MAX_DOCUMENT_LENGTH", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn add_collection_url(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<Uuid>,
+    req: web::Json<UrlRequest>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    let url = reqwest::Url::parse(req.url.trim())
+        .map_err(|e| ApiError::invalid_request(format!("url is not a valid URL: {}", e)))?;
+    if data.collections.get(*path).is_none() {
+        return Err(ApiError::not_found("Collection not found"));
+    }
+    let fetched = fetch::fetch(url).await?;
+    let format = DocumentFormat::detect(fetched.content_type.as_deref(), fetched.url.path())
+        .ok_or_else(|| ApiError::invalid_request(format!(
+            "Unknown document format of {} ({})",
+            fetched.url,
+            fetched.content_type.as_deref().unwrap_or("no Content-Type"),
+        )))?;
+    let url = fetched.url.to_string();
+    
+    let body = fetched.body;
+    let (text, title) = tokio::task::spawn_blocking(move || {
+        let title = (format == DocumentFormat::Html).then(|| ingest::html_title(&body)).flatten();
+        ingest::extract(format, &body).map(|text| (text, title))
+    })
+    .await
+    .map_err(|e| ApiError::internal(format!("Document extraction failed: {}", e)))?
+    .map_err(|e| ApiError::invalid_request(format!("Could not read {}: {:#}", url, e)))?;
+    if text.trim().is_empty() {
+        return Err(ApiError::invalid_request(format!("No text found at {}", url)));
+    }
+    check_document_length(&text)?;
+    
+    let name = req.name.as_deref()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .or(title)
+        .unwrap_or_else(|| url.clone());
+    let document = data.collections
+        .add_document(*path, name, format, Some(url), &text)
+        .await
+        .ok_or_else(|| ApiError::not_found("Collection not found"))?;
+    Ok(HttpResponse::Created().json(document))
+}
+
+// Remove a document from a collection
+#[utoipa::path(
+    delete, path = "/api/collections/{id}/documents/{document_id}", tag = "collections",
+    params(
+        ("id" = Uuid, Path, description = "Collection ID"),
+        ("document_id" = Uuid, Path, description = "Document ID"),
+    ),
+    responses(
+        (status = 204, description = "Document removed"),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 404, description = "Unknown collection or document", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn delete_collection_document(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<(Uuid, Uuid)>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    let (id, document_id) = path.into_inner();
+    if data.collections.delete_document(id, document_id) {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Err(ApiError::not_found("Document not found"))
+    }
+}
+
+// Collections a session searches, leaving out deleted ones
+fn attached_collections(data: &AppState, session: &Session) -> Vec<Collection> {
+    session.collections.iter().filter_map(|id| data.collections.get(*id)).collect()
+}
+
+// Collections attached to a session
+#[utoipa::path(
+    get, path = "/api/sessions/{id}/collections", tag = "sessions",
+    params(("id" = Uuid, Path, description = "Session ID")),
+    responses(
+        (status = 200, description = "The session's collections", body = Object),
+        (status = 404, description = "Unknown session", body = ErrorBody),
+    )
+)]
+pub async fn list_session_collections(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let session = accessible_session(&http_req, &data, *path).await?;
+    let session = session.lock().await;
+    Ok(HttpResponse::Ok().insert_header(etag::header(&session)).json(json!({
+        "collections": attached_collections(&data, &session)
+    })))
+}
+
+// Attach collections to a session, so its chat messages are answered from their documents
+#[utoipa::path(
+    post, path = "/api/sessions/{id}/collections", tag = "sessions",
+    params(("id" = Uuid, Path, description = "Session ID")),
+    request_body = SessionCollectionsRequest,
+    responses(
+        (status = 200, description = "The session's collections", body = Object),
+        (status = 400, description = "Unknown collection or too many collections", body = ErrorBody),
+        (status = 404, description = "Unknown session", body = ErrorBody),
+        (status = 409, description = "The session changed since the If-Match ETag was read", body = ErrorBody),
+    )
+)]
+pub async fn attach_session_collections(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<Uuid>,
+    req: web::Json<SessionCollectionsRequest>,
+) -> Result<HttpResponse, ApiError> {
+    if let Some(id) = req.collections.iter().find(|id| data.collections.get(**id).is_none()) {
+        return Err(ApiError::invalid_request(format!("Unknown collection: {}", id)));
+    }
+    
+    let session = accessible_session(&http_req, &data, *path).await?;
+    let mut session = session.lock().await;
+    etag::check_if_match(&http_req, Some(&session))?;
+    // Collections deleted since they were attached don't count against the limit
+    let mut updated: Vec<Uuid> = session.collections
+        .iter()
+        .copied()
+        .filter(|id| data.collections.get(*id).is_some())
+        .collect();
+    for id in &req.collections {
+        if !updated.contains(id) {
+            updated.push(*id);
+        }
+    }
+    if updated.len() > MAX_SESSION_COLLECTIONS {
+        return Err(ApiError::invalid_request(format!("A session can search at most {} collections", MAX_SESSION_COLLECTIONS)));
+    }
+    session.collections = updated;
+    session.touch();
+    Ok(HttpResponse::Ok().insert_header(etag::header(&session)).json(json!({
+        "collections": attached_collections(&data, &session)
+    })))
+}
+
+// Detach a collection from a session
+#[utoipa::path(
+    delete, path = "/api/sessions/{id}/collections/{collection_id}", tag = "sessions",
+    params(
+        ("id" = Uuid, Path, description = "Session ID"),
+        ("collection_id" = Uuid, Path, description = "Collection to detach"),
+    ),
+    responses(
+        (status = 200, description = "The session's remaining collections", body = Object),
+        (status = 404, description = "Unknown session, or the collection isn't attached", body = ErrorBody),
+        (status = 409, description = "The session changed since the If-Match ETag was read", body = ErrorBody),
+    )
+)]
+pub async fn detach_session_collection(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<(Uuid, Uuid)>,
+) -> Result<HttpResponse, ApiError> {
+    let (session_id, collection_id) = path.into_inner();
+    let session = accessible_session(&http_req, &data, session_id).await?;
+    let mut session = session.lock().await;
+    etag::check_if_match(&http_req, Some(&session))?;
+    if !session.collections.contains(&collection_id) {
+        return Err(ApiError::not_found("Collection is not attached to the session"));
+    }
+    session.collections.retain(|id| *id != collection_id);
+    session.touch();
+    Ok(HttpResponse::Ok().insert_header(etag::header(&session)).json(json!({
+        "collections": attached_collections(&data, &session)
+    })))
+}
+
+// Add the current ETag of a session to a response
+async fn with_session_etag(data: &AppState, session_id: Uuid, mut response: HttpResponseBuilder) -> HttpResponseBuilder {
+    if let Some(session) = data.sessions.get(&session_id) {
+        response.insert_header(etag::header(&*session.lock().await));
+    }
+    response
+}
+
+// Look up a session the caller may see; other browsers' sessions and sessions in the
+// trash look like unknown ones
+async fn accessible_session(
+    http_req: &HttpRequest,
+    data: &AppState,
+    session_id: Uuid,
+) -> Result<Arc<tokio::sync::Mutex<Session>>, ApiError> {
+    let session = data.sessions.get(&session_id).ok_or_else(|| ApiError::not_found("Session not found"))?;
+    {
+        let s = session.lock().await;
+        if !can_access(http_req, &s) || s.deleted_at.is_some() {
+            return Err(ApiError::not_found("Session not found"));
+        }
+    }
+    Ok(session)
+}
+
+// Look up a session of the caller's in the trash
+async fn trashed_session(
+    http_req: &HttpRequest,
+    data: &AppState,
+    session_id: Uuid,
+) -> Result<Arc<tokio::sync::Mutex<Session>>, ApiError> {
+    let session = data.sessions.get(&session_id).ok_or_else(|| ApiError::not_found("Session not found"))?;
+    {
+        let s = session.lock().await;
+        if !can_access(http_req, &s) || s.deleted_at.is_none() {
+            return Err(ApiError::not_found("Session not found in the trash"));
+        }
+    }
+    Ok(session)
+}
+
+// Set the pinned or archived flag of a session
+async fn set_session_flag(
+    http_req: &HttpRequest,
+    data: &AppState,
+    session_id: Uuid,
+    set: impl FnOnce(&mut Session),
+) -> Result<HttpResponse, ApiError> {
+    let session = accessible_session(http_req, data, session_id).await?;
+    let mut session = session.lock().await;
+    etag::check_if_match(http_req, Some(&session))?;
+    set(&mut session);
+    session.touch();
+    Ok(HttpResponse::Ok().insert_header(etag::header(&session)).json(session.summary(session_id)))
+}
+
+// Pin a session to the top of the session list
+#[utoipa::path(
+    put, path = "/api/sessions/{id}/pin", tag = "sessions",
+    params(("id" = Uuid, Path, description = "Session ID")),
+    responses(
+        (status = 200, description = "The updated session", body = SessionSummary),
+        (status = 404, description = "Unknown session", body = ErrorBody),
+        (status = 409, description = "The session changed since the If-Match ETag was read", body = ErrorBody),
+    )
+)]
+pub async fn pin_session(http_req: HttpRequest, data: web::Data<AppState>, path: web::Path<Uuid>) -> Result<HttpResponse, ApiError> {
+    set_session_flag(&http_req, &data, *path, |session| session.pinned = true).await
+}
+
+// Unpin a session
+#[utoipa::path(
+    delete, path = "/api/sessions/{id}/pin", tag = "sessions",
+    params(("id" = Uuid, Path, description = "Session ID")),
+    responses(
+        (status = 200, description = "The updated session", body = SessionSummary),
+        (status = 404, description = "Unknown session", body = ErrorBody),
+        (status = 409, description = "The session changed since the If-Match ETag was read", body = ErrorBody),
+    )
+)]
+pub async fn unpin_session(http_req: HttpRequest, data: web::Data<AppState>, path: web::Path<Uuid>) -> Result<HttpResponse, ApiError> {
+    set_session_flag(&http_req, &data, *path, |session| session.pinned = false).await
+}
+
+// Archive a session, hiding it from the default session list
+#[utoipa::path(
+    put, path = "/api/sessions/{id}/archive", tag = "sessions",
+    params(("id" = Uuid, Path, description = "Session ID")),
+    responses(
+        (status = 200, description = "The updated session", body = SessionSummary),
+        (status = 404, description = "Unknown session", body = ErrorBody),
+        (status = 409, description = "The session changed since the If-Match ETag was read", body = ErrorBody),
+    )
+)]
+pub async fn archive_session(http_req: HttpRequest, data: web::Data<AppState>, path: web::Path<Uuid>) -> Result<HttpResponse, ApiError> {
+    set_session_flag(&http_req, &data, *path, |session| session.archived = true).await
+}
+
+// Restore an archived session to the session list
+#[utoipa::path(
+    delete, path = "/api/sessions/{id}/archive", tag = "sessions",
+    params(("id" = Uuid, Path, description = "Session ID")),
+    responses(
+        (status = 200, description = "The updated session", body = SessionSummary),
+        (status = 404, description = "Unknown session", body = ErrorBody),
+        (status = 409, description = "The session changed since the If-Match ETag was read", body = ErrorBody),
+    )
+)]
+pub async fn unarchive_session(http_req: HttpRequest, data: web::Data<AppState>, path: web::Path<Uuid>) -> Result<HttpResponse, ApiError> {
+    set_session_flag(&http_req, &data, *path, |session| session.archived = false).await
+}
+
+// Page through the history of a session
+#[utoipa::path(
+    get, path = "/api/sessions/{id}", tag = "sessions",
+    params(("id" = Uuid, Path, description = "Session ID"), PageQuery),
+    responses(
+        (status = 200, description = "A page of the session's messages", body = SessionPage),
+        (status = 404, description = "Unknown session", body = ErrorBody),
+    )
+)]
+pub async fn get_session(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<Uuid>,
+    query: web::Query<PageQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let session_id = path.into_inner();
+    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    
+    // Only the requested page is copied out of the session store
+    let session = accessible_session(&http_req, &data, session_id).await?;
+    let (assistant, messages_total, page, pii_vault, etag) = {
+        let session = session.lock().await;
+        let page: Vec<String> = session.history.iter().skip(offset).take(limit).cloned().collect();
+        (session.assistant.clone(), session.history.len(), page, session.pii_vault.clone(), etag::header(&session))
+    };
+    
+    let messages = page
+        .iter()
+        .enumerate()
+        .filter_map(|(i, entry)| {
+            let (role, text) = entry.split_once(": ")?;
+            Some(SessionMessage {
+                index: offset + i,
+                role: role.to_string(),
+                content: data.pii.restore(text, &pii_vault),
+            })
+        })
+        .collect();
+    
+    Ok(HttpResponse::Ok().insert_header(etag).json(SessionPage {
+        session_id,
+        assistant,
+        messages_total,
+        offset,
+        limit,
+        messages,
+    }))
+}
+
+// Fetch the generation settings persisted for a session
+#[utoipa::path(
+    get, path = "/api/sessions/{id}/settings", tag = "sessions",
+    params(("id" = Uuid, Path, description = "Session ID")),
+    responses(
+        (status = 200, description = "Session settings", body = SessionSettings),
+        (status = 404, description = "Unknown session", body = ErrorBody),
+    )
+)]
+pub async fn get_session_settings(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let session = accessible_session(&http_req, &data, *path).await?;
+    let session = session.lock().await;
+    Ok(HttpResponse::Ok().insert_header(etag::header(&session)).json(&session.settings))
+}
+
+// Persist generation settings used for every message of a session
+#[utoipa::path(
+    put, path = "/api/sessions/{id}/settings", tag = "sessions",
+    params(("id" = Uuid, Path, description = "Session ID")),
+    request_body = SessionSettings,
+    responses(
+        (status = 200, description = "Settings saved", body = SessionSettings),
+        (status = 400, description = "Invalid settings", body = ErrorBody),
+        (status = 409, description = "The session changed since the If-Match ETag was read", body = ErrorBody),
+    )
+)]
+pub async fn put_session_settings(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<Uuid>,
+    req: web::Json<SessionSettings>,
+) -> Result<HttpResponse, ApiError> {
+    let session_id = path.into_inner();
+    let settings = req.into_inner();
+    
+    if let Some(model) = &settings.model {
+        if data.model.get(Some(model.as_str())).is_none() {
+            return Err(ApiError::invalid_request(format!("Unknown model: {}", model)));
+        }
+    }
+    if settings.temperature.is_some_and(|t| !(0.0..=2.0).contains(&t)) {
+        return Err(ApiError::invalid_request("temperature must be between 0 and 2"));
+    }
+    if settings.top_p.is_some_and(|p| p <= 0.0 || p > 1.0) {
+        return Err(ApiError::invalid_request("top_p must be greater than 0 and at most 1"));
+    }
+    if settings.max_tokens == Some(0) {
+        return Err(ApiError::invalid_request("max_tokens must be positive"));
+    }
+    if let Some(value) = &settings.language {
+        language::parse(value).map_err(ApiError::invalid_request)?;
+    }
+    
+    // Settings may be configured before the first message of a session, which then belongs
+    // to the browser configuring it
+    let session = data.sessions.get_or_create(session_id);
+    let mut session = session.lock().await;
+    if !can_access(&http_req, &session) || session.deleted_at.is_some() {
+        return Err(ApiError::not_found("Session not found"));
+    }
+    etag::check_if_match(&http_req, Some(&session))?;
+    if session.history.is_empty() && session.owner.is_none() {
+        session.owner = browser_id(&http_req).map(|browser| browser.0);
+    }
+    session.settings = settings.clone();
+    session.touch();
+    let etag = etag::header(&session);
+    drop(session);
+    info!("Updated settings for session {}", session_id);
+    Ok(HttpResponse::Ok().insert_header(etag).json(settings))
+}
+
+// The conversation of a session as a standalone HTML page, with the model's
+// markdown answers rendered
+#[utoipa::path(
+    get, path = "/api/sessions/{id}/export", tag = "sessions",
+    params(("id" = Uuid, Path, description = "Session ID")),
+    responses(
+        (status = 200, description = "HTML page of the conversation", content_type = "text/html", body = String),
+        (status = 404, description = "Unknown session", body = ErrorBody),
+    )
+)]
+pub async fn export_session(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    let session_id = path.into_inner();
+    let session = accessible_session(&http_req, &data, session_id).await?;
+    let (history, pii_vault) = {
+        let session = session.lock().await;
+        (session.history.clone(), session.pii_vault.clone())
+    };
+    
+    // User messages are shown as typed; only the model's answers are markdown
+    let messages: Vec<serde_json::Value> = history
+        .iter()
+        .filter_map(|entry| {
+            let (role, text) = entry.split_once(": ")?;
+            let text = data.pii.restore(text, &pii_vault);
+            Some(match role {
+                "assistant" => json!({ "role": role, "html": markdown::render(&text) }),
+                _ => json!({ "role": role, "text": text }),
+            })
+        })
+        .collect();
+    
+    let mut context = Context::new();
+    context.insert("session_id", &session_id);
+    context.insert("exported_at", &chrono::Utc::now().format("%Y-%m-%d %H:%M UTC").to_string());
+    context.insert("messages", &messages);
+    context.insert("highlight_css", &markdown::highlight_css());
+    let html = render_page(&data, "conversation.html", &context)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/html")
+        .insert_header(("Content-Disposition", format!("inline; filename=\"conversation-{}.html\"", session_id)))
+        .body(html))
+}
+
+// Full-text search across a user's conversations
+#[utoipa::path(
+    get, path = "/api/search", tag = "sessions",
+    params(SearchQuery),
+    responses(
+        (status = 200, description = "Matching sessions, best first, with message snippets", body = [SessionMatch]),
+        (status = 400, description = "Empty query", body = ErrorBody),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn search_sessions(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<SearchQuery>,
+) -> Result<HttpResponse, ApiError> {
+    // Users aren't authenticated, so searching someone's conversations is an admin action
+    require_admin(&http_req)?;
+    
+    if query.q.trim().is_empty() {
+        return Err(ApiError::invalid_request("Query must not be empty"));
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT).clamp(1, MAX_SEARCH_LIMIT);
+    
+    let mut sessions = data.sessions.for_user(&query.user).await;
+    sessions.retain(|(_, session)| session.deleted_at.is_none());
+    let results = search::search(&sessions, &query.q, limit, |session, text| {
+        data.pii.restore(text, &session.pii_vault)
+    });
+    
+    Ok(HttpResponse::Ok().json(json!({
+        "query": query.q,
+        "results": results,
+    })))
+}
+
+// Facts remembered about a user across sessions
+#[utoipa::path(
+    get, path = "/api/users/{id}/memories", tag = "privacy",
+    params(("id" = String, Path, description = "User identifier")),
+    responses(
+        (status = 200, description = "The user's memories, oldest first", body = [Memory]),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn list_memories(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    // Users aren't authenticated, so reading someone's memories is an admin action
+    require_admin(&http_req)?;
+    Ok(HttpResponse::Ok().json(data.memory.list(&path)))
+}
+
+// Forget a single remembered fact
+#[utoipa::path(
+    delete, path = "/api/users/{id}/memories/{memory_id}", tag = "privacy",
+    params(
+        ("id" = String, Path, description = "User identifier"),
+        ("memory_id" = Uuid, Path, description = "Memory ID"),
+    ),
+    responses(
+        (status = 204, description = "Memory deleted"),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 404, description = "Memory not found", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn delete_memory(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<(String, Uuid)>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    
+    let (user, memory_id) = path.into_inner();
+    if data.memory.delete(&user, memory_id) {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Err(ApiError::not_found("Memory not found"))
+    }
+}
+
+// Longest email address accepted for an invitation
+const MAX_EMAIL_CHARS: usize = 254;
+
+// Workspace members, and the invitations still waiting to be accepted
+#[utoipa::path(
+    get, path = "/api/admin/members", tag = "members",
+    responses(
+        (status = 200, description = "Members and pending invitations, as `members` and `invites`", body = Object),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn list_members(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    let (members, invites) = data.members.list();
+    Ok(HttpResponse::Ok().json(json!({
+        "members": members,
+        "invites": invites,
+    })))
+}
+
+// Invite someone by email. The token in the response is what they accept the invitation
+// with; it is only returned here, for the admin to send on.
+#[utoipa::path(
+    post, path = "/api/admin/members/invites", tag = "members",
+    request_body = InviteRequest,
+    responses(
+        (status = 201, description = "Invitation created", body = InviteResponse),
+        (status = 400, description = "Invalid email", body = ErrorBody),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 409, description = "The email belongs to a member already", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn create_invite(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    req: web::Json<InviteRequest>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    
+    let email = req.email.trim();
+    let valid = email.chars().count() <= MAX_EMAIL_CHARS
+        && email.split_once('@').is_some_and(|(local, domain)| !local.is_empty() && domain.contains('.'))
+        && !email.contains(char::is_whitespace);
+    if !valid {
+        return Err(ApiError::invalid_request("email must be a valid email address"));
+    }
+    let (invite, token) = data.members.invite(email, req.role)?;
+    Ok(HttpResponse::Created().json(InviteResponse { invite, token }))
+}
+
+// Withdraw a pending invitation
+#[utoipa::path(
+    delete, path = "/api/admin/members/invites/{id}", tag = "members",
+    params(("id" = Uuid, Path, description = "Invitation ID")),
+    responses(
+        (status = 204, description = "Invitation withdrawn"),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 404, description = "Invitation not found", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn revoke_invite(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    if data.members.revoke(*path) {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Err(ApiError::not_found("Invitation not found"))
+    }
+}
+
+// Join the workspace. The invitation token is the credential, so no admin token is needed.
+#[utoipa::path(
+    post, path = "/api/members/accept", tag = "members",
+    request_body = AcceptInviteRequest,
+    responses(
+        (status = 201, description = "Invitation accepted", body = Member),
+        (status = 400, description = "The invitation has expired", body = ErrorBody),
+        (status = 404, description = "Unknown invitation token", body = ErrorBody),
+        (status = 409, description = "The email belongs to a member already", body = ErrorBody),
+    )
+)]
+pub async fn accept_invite(
+    data: web::Data<AppState>,
+    req: web::Json<AcceptInviteRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let member = data.members.accept(&req.token)?;
+    Ok(HttpResponse::Created().json(member))
+}
+
+// Change a member's role
+#[utoipa::path(
+    put, path = "/api/admin/members/{id}/role", tag = "members",
+    params(("id" = Uuid, Path, description = "Member ID")),
+    request_body = MemberRoleRequest,
+    responses(
+        (status = 200, description = "The member with its new role", body = Member),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 404, description = "Member not found", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn set_member_role(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<Uuid>,
+    req: web::Json<MemberRoleRequest>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    let member = data.members
+        .set_role(*path, req.role)
+        .ok_or_else(|| ApiError::not_found("Member not found"))?;
+    Ok(HttpResponse::Ok().json(member))
+}
+
+// Remove a member from the workspace
+#[utoipa::path(
+    delete, path = "/api/admin/members/{id}", tag = "members",
+    params(("id" = Uuid, Path, description = "Member ID")),
+    responses(
+        (status = 204, description = "Member removed"),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 404, description = "Member not found", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn remove_member(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    if data.members.remove(*path) {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Err(ApiError::not_found("Member not found"))
+    }
+}
+
+// Dump everything stored about a user
+#[utoipa::path(
+    get, path = "/api/users/{id}/data/export", tag = "privacy",
+    params(("id" = String, Path, description = "User identifier")),
+    responses(
+        (status = 200, description = "Everything stored about the user", body = Object),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn export_user_data(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    Ok(HttpResponse::Ok().json(privacy::export_user(&data, &path).await))
+}
+
+// Delete a user's data in two steps: the first request returns a confirmation
+// token, repeating it with `?confirm=<token>` schedules the purge after the grace period
+#[utoipa::path(
+    delete, path = "/api/users/{id}/data", tag = "privacy",
+    params(("id" = String, Path, description = "User identifier"), DeletionQuery),
+    responses(
+        (status = 202, description = "Confirmation token, or the scheduled deletion time", body = Object),
+        (status = 400, description = "Invalid confirmation token", body = ErrorBody),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn delete_user_data(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+    query: web::Query<DeletionQuery>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    
+    let user = path.into_inner();
+    match &query.confirm {
+        None => {
+            let (token, expires_at) = data.privacy.request_deletion(&user);
+            Ok(HttpResponse::Accepted().json(json!({
+                "status": "confirmation_required",
+                "confirmation_token": token,
+                "expires_at": expires_at,
+            })))
+        }
+        Some(token) => match data.privacy.confirm_deletion(&user, token) {
+            Some(delete_at) => Ok(HttpResponse::Accepted().json(json!({
+                "status": "scheduled",
+                "delete_at": delete_at,
+            }))),
+            None => Err(ApiError::invalid_request("Invalid or expired confirmation token")),
+        },
+    }
+}
+
+// Cancel a scheduled deletion during its grace period
+#[utoipa::path(
+    post, path = "/api/users/{id}/data/cancel-deletion", tag = "privacy",
+    params(("id" = String, Path, description = "User identifier")),
+    responses(
+        (status = 200, description = "Deletion cancelled", body = Object),
+        (status = 404, description = "No deletion scheduled", body = ErrorBody),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn cancel_user_data_deletion(
+    http_req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    require_admin(&http_req)?;
+    
+    if data.privacy.cancel_deletion(&path) {
+        Ok(HttpResponse::Ok().json(json!({ "status": "cancelled" })))
+    } else {
+        Err(ApiError::not_found(format!("No deletion scheduled for user {}", path.as_str())))
+    }
+}
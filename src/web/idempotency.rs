@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use actix_web::HttpRequest;
+use sha2::{Digest, Sha256};
+
+use crate::web::error::{ApiError, ErrorCode};
+use crate::web::models::{ChatRequest, ChatResponse};
+
+// Default time a response stays replayable
+const DEFAULT_IDEMPOTENCY_WINDOW_SECS: u64 = 86400;
+// Longest accepted key
+const MAX_KEY_LENGTH: usize = 255;
+
+/// Environment variables for idempotent chat requests:
+///
+/// - `IDEMPOTENCY_WINDOW_SECS`: Seconds a response to a request carrying an
+///   `Idempotency-Key` header is kept and replayed to retries (default: 86400)
+
+enum Slot {
+    // The first request with this key is still being handled
+    InFlight,
+    Done(ChatResponse),
+}
+
+struct Entry {
+    fingerprint: String,
+    user: Option<String>,
+    slot: Slot,
+    stored_at: Instant,
+}
+
+// What to do with a request carrying an idempotency key
+pub enum Claim {
+    // First use of the key; the caller must `complete` or `release` it
+    New,
+    // A retry of a request that already succeeded
+    Replay(ChatResponse),
+}
+
+// Chat responses keyed by the client's `Idempotency-Key`
+pub struct IdempotencyStore {
+    window: Duration,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl IdempotencyStore {
+    pub fn from_env() -> Self {
+        let window = env::var("IDEMPOTENCY_WINDOW_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_IDEMPOTENCY_WINDOW_SECS);
+        Self {
+            window: Duration::from_secs(window),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Reserve the key for this request, or return the response it already produced
+    pub fn claim(&self, key: &str, req: &ChatRequest) -> Result<Claim, ApiError> {
+        let fingerprint = fingerprint(req);
+        let mut entries = self.entries
+            .lock()
+            .map_err(|_| ApiError::internal("Internal server error"))?;
+        entries.retain(|_, entry| entry.stored_at.elapsed() < self.window);
+
+        match entries.get(key) {
+            Some(entry) if entry.fingerprint != fingerprint => Err(ApiError::invalid_request(
+                "Idempotency-Key was already used for a different request",
+            )),
+            Some(Entry { slot: Slot::InFlight, .. }) => Err(ApiError::new(
+                ErrorCode::Conflict,
+                "A request with this Idempotency-Key is still being processed",
+            )),
+            Some(Entry { slot: Slot::Done(response), .. }) => Ok(Claim::Replay(response.clone())),
+            None => {
+                entries.insert(key.to_string(), Entry {
+                    fingerprint,
+                    user: req.user.clone(),
+                    slot: Slot::InFlight,
+                    stored_at: Instant::now(),
+                });
+                Ok(Claim::New)
+            }
+        }
+    }
+
+    // Keep the response to replay for the rest of the window
+    pub fn complete(&self, key: &str, response: &ChatResponse) {
+        if let Ok(mut entries) = self.entries.lock() {
+            if let Some(entry) = entries.get_mut(key) {
+                entry.slot = Slot::Done(response.clone());
+                entry.stored_at = Instant::now();
+            }
+        }
+    }
+
+    // Forget a key whose request failed, so a retry runs it again
+    pub fn release(&self, key: &str) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.remove(key);
+        }
+    }
+
+    pub fn purge_user(&self, user: &str) -> usize {
+        let Ok(mut entries) = self.entries.lock() else { return 0 };
+        let before = entries.len();
+        entries.retain(|_, entry| entry.user.as_deref() != Some(user));
+        before - entries.len()
+    }
+}
+
+// The `Idempotency-Key` header, if the request has one
+pub fn idempotency_key(req: &HttpRequest) -> Result<Option<String>, ApiError> {
+    let Some(value) = req.headers().get("Idempotency-Key") else { return Ok(None) };
+    let key = value
+        .to_str()
+        .map(str::trim)
+        .map_err(|_| ApiError::invalid_request("Idempotency-Key must be visible ASCII"))?;
+    if key.is_empty() || key.len() > MAX_KEY_LENGTH {
+        return Err(ApiError::invalid_request(format!(
+            "Idempotency-Key must be between 1 and {} characters",
+            MAX_KEY_LENGTH
+        )));
+    }
+    Ok(Some(key.to_string()))
+}
+
+// Hash of the request body, to catch a key reused for a different request
+fn fingerprint(req: &ChatRequest) -> String {
+    let body = serde_json::to_vec(req).unwrap_or_default();
+    hex::encode(Sha256::digest(&body))
+}
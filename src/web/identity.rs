@@ -0,0 +1,121 @@
+use std::env;
+use std::sync::OnceLock;
+use actix_web::cookie::{time::Duration as CookieDuration, Cookie, SameSite};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::{HttpMessage, HttpRequest};
+use hmac::{Hmac, Mac};
+use log::{warn, error};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::session::Session;
+use crate::web::auth::is_admin;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Cookie tying a browser to the sessions it started
+pub const BROWSER_COOKIE: &str = "llama_browser";
+// How long a browser keeps its identity without visiting
+const BROWSER_COOKIE_MAX_AGE_DAYS: i64 = 365;
+
+/// Environment variables for anonymous browser identities:
+///
+/// - `SESSION_COOKIE_SECRET`: Key signing the browser cookie; without it a random key is used
+///   and browsers get new identities whenever the server restarts (default: random)
+/// - `SCOPE_SESSIONS_TO_BROWSER`: Only let a browser see and continue the sessions it started,
+///   and list them without an admin token (default: false)
+///
+/// Every client without a valid cookie gets a new random browser ID in a signed, HttpOnly
+/// cookie, and sessions record the browser that started them. API clients that don't keep
+/// cookies get a new identity on every request, so with scoping on they need a cookie jar, or
+/// the admin token, which sees every session. Keeping the cookie doesn't subject them to CSRF
+/// checks, which only apply to requests from a browser.
+
+// Anonymous identity of the browser a request came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BrowserId(pub Uuid);
+
+static SECRET: OnceLock<Vec<u8>> = OnceLock::new();
+
+fn secret() -> &'static [u8] {
+    SECRET.get_or_init(|| match env::var("SESSION_COOKIE_SECRET") {
+        Ok(secret) if !secret.is_empty() => secret.into_bytes(),
+        _ => {
+            warn!("SESSION_COOKIE_SECRET is not set; browser identities won't survive a restart");
+            (0..32).map(|_| rand::random::<u8>()).collect()
+        }
+    })
+}
+
+pub fn scoping_enabled() -> bool {
+    env::var("SCOPE_SESSIONS_TO_BROWSER")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false)
+}
+
+// Keyed MAC of a browser ID; `purpose` keeps the cookie signature apart from values derived
+// for other uses, such as CSRF tokens
+fn mac(purpose: &str, id: Uuid) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret()).expect("HMAC accepts keys of any length");
+    mac.update(purpose.as_bytes());
+    mac.update(id.as_bytes());
+    mac
+}
+
+pub(crate) fn signature(purpose: &str, id: Uuid) -> String {
+    hex::encode(mac(purpose, id).finalize().into_bytes())
+}
+
+// `<id>.<signature>`, so a client can't claim another browser's ID
+fn cookie_value(id: Uuid) -> String {
+    format!("{}.{}", id, signature("browser", id))
+}
+
+fn verify(value: &str) -> Option<Uuid> {
+    let (id, signature) = value.split_once('.')?;
+    let id: Uuid = id.parse().ok()?;
+    let expected = hex::decode(signature).ok()?;
+    mac("browser", id).verify_slice(&expected).ok()?;
+    Some(id)
+}
+
+// The browser's identity from its cookie, or a new one; true when the cookie must be set
+pub fn identify(req: &ServiceRequest) -> (BrowserId, bool) {
+    match req.cookie(BROWSER_COOKIE).and_then(|cookie| verify(cookie.value())) {
+        Some(id) => (BrowserId(id), false),
+        None => (BrowserId(Uuid::new_v4()), true),
+    }
+}
+
+// Hand a new identity to the browser
+pub fn set_cookie<B>(res: &mut ServiceResponse<B>, browser: BrowserId) {
+    let secure = res.request().connection_info().scheme() == "https";
+    let cookie = Cookie::build(BROWSER_COOKIE, cookie_value(browser.0))
+        .path("/")
+        .http_only(true)
+        .secure(secure)
+        .same_site(SameSite::Lax)
+        .max_age(CookieDuration::days(BROWSER_COOKIE_MAX_AGE_DAYS))
+        .finish();
+    if let Err(e) = res.response_mut().add_cookie(&cookie) {
+        error!("Failed to set browser cookie: {}", e);
+    }
+}
+
+// Identity attached to the request by the middleware
+pub fn browser_id(req: &HttpRequest) -> Option<BrowserId> {
+    req.extensions().get::<BrowserId>().copied()
+}
+
+// Whether the request may see a session: always without scoping, for admins and for sessions
+// started before scoping or outside a browser
+pub fn can_access(req: &HttpRequest, session: &Session) -> bool {
+    if !scoping_enabled() || is_admin(req) {
+        return true;
+    }
+    match session.owner {
+        None => true,
+        Some(owner) => browser_id(req).is_some_and(|browser| browser.0 == owner),
+    }
+}
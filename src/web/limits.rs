@@ -0,0 +1,118 @@
+use std::env;
+use actix_web::error::JsonPayloadError;
+use actix_web::web;
+use futures::StreamExt;
+use serde_json::json;
+
+use crate::web::error::{ApiError, ErrorCode};
+
+// Default size of a JSON request body
+const DEFAULT_MAX_JSON_PAYLOAD_BYTES: usize = 1024 * 1024;
+// Default length of a single message, in characters
+const DEFAULT_MAX_MESSAGE_LENGTH: usize = 32_000;
+// Default length of a document sent for summarization, in characters
+const DEFAULT_MAX_DOCUMENT_LENGTH: usize = 400_000;
+// Default number of messages kept in a session's history
+const DEFAULT_MAX_SESSION_HISTORY: usize = 200;
+// Default size of a server state archive accepted for import
+const DEFAULT_MAX_STATE_IMPORT_BYTES: usize = 256 * 1024 * 1024;
+// Default size of an uploaded document file
+const DEFAULT_MAX_UPLOAD_BYTES: usize = 20 * 1024 * 1024;
+
+/// Environment variables for request guardrails:
+///
+/// - `MAX_JSON_PAYLOAD_BYTES`: Largest accepted JSON body; larger bodies get a 413 (default: 1048576)
+/// - `MAX_MESSAGE_LENGTH`: Longest accepted message or prompt, in characters (default: 32000)
+/// - `MAX_DOCUMENT_LENGTH`: Longest accepted document, e.g. a text to summarize, in characters
+///   (default: 400000)
+/// - `MAX_SESSION_HISTORY`: Messages kept per session; older ones are dropped (default: 200)
+/// - `MAX_STATE_IMPORT_BYTES`: Largest server state archive accepted by the import endpoint
+///   (default: 268435456)
+/// - `MAX_UPLOAD_BYTES`: Largest document file accepted for a collection (default: 20971520)
+/// - `REASONING_IN_HISTORY`: Keep reasoning models' thinking in session history, where it
+///   counts against the context window of later turns (default: false)
+
+fn env_usize(name: &str, default: usize) -> usize {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(default)
+}
+
+pub fn max_session_history() -> usize {
+    env_usize("MAX_SESSION_HISTORY", DEFAULT_MAX_SESSION_HISTORY).max(2)
+}
+
+pub fn reasoning_in_history() -> bool {
+    env::var("REASONING_IN_HISTORY")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false)
+}
+
+// JSON extractor settings: bounded body size, and errors reported as JSON
+pub fn json_config() -> web::JsonConfig {
+    json_config_with_limit(env_usize("MAX_JSON_PAYLOAD_BYTES", DEFAULT_MAX_JSON_PAYLOAD_BYTES))
+}
+
+// The same for server state archives, which hold every session and are far larger
+pub fn state_import_json_config() -> web::JsonConfig {
+    json_config_with_limit(env_usize("MAX_STATE_IMPORT_BYTES", DEFAULT_MAX_STATE_IMPORT_BYTES))
+}
+
+fn json_config_with_limit(limit: usize) -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(limit)
+        .error_handler(move |err, _req| {
+            let error = match &err {
+                JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } => {
+                    ApiError::new(ErrorCode::PayloadTooLarge, format!("Request body exceeds {} bytes", limit))
+                }
+                _ => ApiError::invalid_request(format!("Invalid JSON body: {}", err)),
+            };
+            error.into()
+        })
+}
+
+// Read a raw request body, such as an uploaded file, of at most MAX_UPLOAD_BYTES
+pub async fn read_upload(mut payload: web::Payload) -> Result<web::Bytes, ApiError> {
+    let limit = env_usize("MAX_UPLOAD_BYTES", DEFAULT_MAX_UPLOAD_BYTES);
+    let mut body = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| ApiError::invalid_request(format!("Failed to read the upload: {}", e)))?;
+        if body.len() + chunk.len() > limit {
+            return Err(ApiError::new(ErrorCode::PayloadTooLarge, format!("Upload exceeds {} bytes", limit))
+                .with_details(json!({ "max_bytes": limit })));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body.freeze())
+}
+
+// Reject messages over MAX_MESSAGE_LENGTH characters
+pub fn check_message_length(message: &str) -> Result<(), ApiError> {
+    let max = env_usize("MAX_MESSAGE_LENGTH", DEFAULT_MAX_MESSAGE_LENGTH);
+    let length = message.chars().count();
+    if length > max {
+        return Err(ApiError::new(
+            ErrorCode::PayloadTooLarge,
+            format!("Message is {} characters long; the limit is {}", length, max),
+        )
+        .with_details(json!({ "max_length": max })));
+    }
+    Ok(())
+}
+
+// Reject documents over MAX_DOCUMENT_LENGTH characters
+pub fn check_document_length(document: &str) -> Result<(), ApiError> {
+    let max = env_usize("MAX_DOCUMENT_LENGTH", DEFAULT_MAX_DOCUMENT_LENGTH);
+    let length = document.chars().count();
+    if length > max {
+        return Err(ApiError::new(
+            ErrorCode::PayloadTooLarge,
+            format!("Document is {} characters long; the limit is {}", length, max),
+        )
+        .with_details(json!({ "max_length": max })));
+    }
+    Ok(())
+}
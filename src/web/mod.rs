@@ -1,3 +1,19 @@
 pub mod routes;
 pub mod handlers;
-pub mod models;
\ No newline at end of file
+pub mod models;
+pub mod auth;
+pub mod error;
+pub mod limits;
+pub mod mode;
+pub mod proxy;
+pub mod idempotency;
+pub mod identity;
+pub mod etag;
+pub mod csrf;
+pub mod request_id;
+pub mod compression;
+pub mod assets;
+pub mod templates;
+pub mod openapi;
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,84 @@
+use std::sync::{PoisonError, RwLock};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use utoipa::ToSchema;
+
+use crate::web::error::{ApiError, ErrorCode};
+
+// Retry-After sent in maintenance mode when the operator didn't give one
+const DEFAULT_MAINTENANCE_RETRY_AFTER_SECS: u64 = 300;
+
+// Whether the server takes generation requests, as set by an operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ServerMode {
+    #[default]
+    Healthy,
+    // Requests are served, but users are warned that answers may be slow or fail
+    Degraded,
+    // Generation endpoints are closed, e.g. while backend models are swapped
+    Maintenance,
+}
+
+// Body of `POST /api/admin/mode`
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ModeRequest {
+    pub mode: ServerMode,
+    // Shown to users in the banner and in maintenance errors
+    pub message: Option<String>,
+    // Seconds clients should wait before retrying in maintenance mode (default: 300)
+    pub retry_after_secs: Option<u64>,
+}
+
+// Mode in effect and when it was entered
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct ModeState {
+    pub mode: ServerMode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after_secs: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since: Option<DateTime<Utc>>,
+}
+
+// The server's mode; every instance starts healthy, as the mode isn't persisted
+#[derive(Default)]
+pub struct ModeSwitch {
+    state: RwLock<ModeState>,
+}
+
+impl ModeSwitch {
+    pub fn current(&self) -> ModeState {
+        self.state.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    pub fn set(&self, req: ModeRequest) -> ModeState {
+        let retry_after_secs = match req.mode {
+            ServerMode::Maintenance => Some(req.retry_after_secs.unwrap_or(DEFAULT_MAINTENANCE_RETRY_AFTER_SECS)),
+            _ => None,
+        };
+        let state = ModeState {
+            mode: req.mode,
+            message: req.message.filter(|message| !message.trim().is_empty()),
+            retry_after_secs,
+            since: Some(Utc::now()),
+        };
+        *self.state.write().unwrap_or_else(PoisonError::into_inner) = state.clone();
+        state
+    }
+
+    // Reject generation requests while the server is in maintenance
+    pub fn ensure_available(&self) -> Result<(), ApiError> {
+        let state = self.current();
+        if state.mode != ServerMode::Maintenance {
+            return Ok(());
+        }
+        let retry_after = state.retry_after_secs.unwrap_or(DEFAULT_MAINTENANCE_RETRY_AFTER_SECS);
+        let message = state.message.as_deref().unwrap_or("The server is under maintenance");
+        Err(ApiError::new(ErrorCode::Maintenance, message)
+            .with_details(json!({ "retry_after_secs": retry_after, "since": state.since }))
+            .with_retry_after(retry_after))
+    }
+}
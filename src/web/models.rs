@@ -1,20 +1,173 @@
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::assistants::FewShotExample;
+use crate::collections::Citation;
+use crate::collections::ingest::DocumentFormat;
+use crate::consensus::Consensus;
+use crate::extract::{LabelScore, Metadata};
+use crate::members::{Invite, MemberRole};
+use crate::model::length::ResponseLength;
+use crate::model::protocol::ReasoningEffort;
+use crate::pipelines::StepKind;
+use crate::model::registry::SpeculativeConfig;
+use crate::session::SessionSettings;
+use crate::summarize::SummaryStyle;
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ChatRequest {
+    #[serde(default)]
     pub message: String,
     pub session_id: Option<Uuid>,
     pub max_tokens: Option<usize>,
+    pub model: Option<String>,
+    #[serde(default)]
+    pub bypass_cache: bool,
+    // Name of a prompt template to render into the message
+    pub template: Option<String>,
+    // Variables available to the template, alongside `message`
+    #[serde(default)]
+    #[schema(value_type = Object)]
+    pub vars: Map<String, Value>,
+    // Assistant profile to use; it stays selected for the rest of the session
+    pub assistant: Option<String>,
+    // Identifier of the end user, used for usage accounting
+    pub user: Option<String>,
+    // Backend-specific parameters merged into the upstream payload, e.g. mistral.rs `min_p`
+    #[serde(default)]
+    #[schema(value_type = Option<Object>)]
+    pub extra_body: Option<Value>,
+    // Thinking effort of a reasoning model, as a level or a token budget
+    pub reasoning_effort: Option<ReasoningEffort>,
+    pub thinking_budget_tokens: Option<usize>,
+    // Go ahead with a message that guardrail rules asked to confirm
+    #[serde(default)]
+    pub confirm: bool,
+    // Language of the reply: "auto" for the language of the message, or a language
+    pub language: Option<String>,
+    // Length of the reply; sets max_tokens, unless the request sets it, and asks the model to match
+    pub length: Option<ResponseLength>,
+    // Have the model critique and rewrite its draft in a second request before replying
+    #[serde(default)]
+    pub refine: bool,
+    // Sample this many answers and reply with the one most of them agree on
+    pub samples: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AsyncGenerateRequest {
+    pub message: String,
+    pub max_tokens: Option<usize>,
+    pub model: Option<String>,
+    #[serde(default)]
+    pub bypass_cache: bool,
+    pub callback_url: Option<String>,
+    pub user: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BatchChatRequest {
+    pub prompts: Vec<String>,
+    pub max_tokens: Option<usize>,
+    pub model: Option<String>,
+    #[serde(default)]
+    pub bypass_cache: bool,
+    // Queue every prompt as an async job and return job IDs instead of waiting
+    #[serde(default, rename = "async")]
+    pub run_async: bool,
+    pub user: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CompareRequest {
+    pub message: String,
+    // Names of the models to compare, at least two
+    pub models: Vec<String>,
+    pub max_tokens: Option<usize>,
+    pub temperature: Option<f32>,
+    // Answers are generated fresh unless set, so latencies are comparable
+    #[serde(default)]
+    pub use_cache: bool,
+    pub user: Option<String>,
+}
+
+// One model's answer in a comparison
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CompareResult {
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    pub latency_ms: u64,
+    pub usage: Usage,
+    // Completion tokens per second of wall-clock latency
+    pub tokens_per_sec: f64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BatchItemResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    // Machine-readable error code, as in error responses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+}
+
+// Tokens a response consumed and their estimated price; zero for answers served from the cache
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, ToSchema)]
+pub struct Usage {
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    // Prompt tokens the backend reused from its prefix cache; part of `prompt_tokens`
+    #[serde(default)]
+    pub cached_tokens: usize,
+    pub estimated_cost: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ChatResponse {
     pub response: String,
+    // Thinking of a reasoning model, separate from the answer; not kept in the session by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<String>,
     pub session_id: Uuid,
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assistant: Option<String>,
+    // Settings actually used to generate this response
+    pub settings: SessionSettings,
+    // ISO 639-3 code of the language the message was detected to be in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detected_language: Option<String>,
+    // Of both passes in refine mode, and of every sample when sampling
+    #[serde(default)]
+    pub usage: Usage,
+    // Usage of each pass, in refine mode only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub passes: Option<RefinePasses>,
+    // How much the samples agreed, when sampling
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub consensus: Option<Consensus>,
+    // Passages of the session's collections the answer was given, numbered as its `[n]` markers
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub citations: Vec<Citation>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// Usage of the two requests of a refined response
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+pub struct RefinePasses {
+    pub draft: Usage,
+    pub refine: Usage,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Role {
     #[serde(rename = "user")]
     User,
@@ -24,8 +177,403 @@ pub enum Role {
     System,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
     pub role: Role,
     pub content: String,
-} 
\ No newline at end of file
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ModelInfo {
+    pub name: String,
+    pub context_window: usize,
+    pub max_tokens: usize,
+    pub default: bool,
+    // Draft model the backend decodes speculatively with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speculative: Option<SpeculativeConfig>,
+    // Estimated VRAM of the loaded model, in MiB
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vram_mb: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CompleteRequest {
+    pub prompt: String,
+    pub model: Option<String>,
+    pub max_tokens: Option<usize>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    // Stop sequences, in addition to the model's own
+    #[serde(default)]
+    pub stop: Vec<String>,
+    // Return the prompt followed by the completion
+    #[serde(default)]
+    pub echo: bool,
+    pub user: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CompleteResponse {
+    pub text: String,
+    pub model: String,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct FimRequest {
+    // Code before the cursor
+    pub prefix: String,
+    // Code after the cursor
+    #[serde(default)]
+    pub suffix: String,
+    pub model: Option<String>,
+    pub max_tokens: Option<usize>,
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub stop: Vec<String>,
+    pub user: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TranslateRequest {
+    pub text: String,
+    // Language of the text; detected when not given
+    pub source_lang: Option<String>,
+    pub target_lang: String,
+    pub model: Option<String>,
+    pub max_tokens: Option<usize>,
+    pub user: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TranslateResponse {
+    pub translation: String,
+    // As given, or the ISO 639-3 code of the detected language; None when it couldn't be told
+    pub source_lang: Option<String>,
+    pub target_lang: String,
+    pub model: String,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SummarizeRequest {
+    pub text: String,
+    #[serde(default)]
+    pub style: SummaryStyle,
+    // Rough length of the summary, in words
+    pub target_length: Option<usize>,
+    pub model: Option<String>,
+    pub user: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SummarizeResponse {
+    pub summary: String,
+    pub style: SummaryStyle,
+    pub model: String,
+    // Chunks the text was split into; 1 when it fit in one request
+    pub chunks: usize,
+    // Rounds of partial summaries before the final one
+    pub passes: usize,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ExtractRequest {
+    pub text: String,
+    pub model: Option<String>,
+    pub user: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ExtractResponse {
+    pub metadata: Metadata,
+    pub model: String,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ClassifyRequest {
+    pub text: String,
+    // Labels to choose from, in any words the model understands
+    pub labels: Vec<String>,
+    // Labels returned, best first (default: 1)
+    pub top_k: Option<usize>,
+    pub model: Option<String>,
+    pub user: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ClassifyResponse {
+    // The best label and its confidence
+    pub label: String,
+    pub confidence: f32,
+    // Up to top_k labels, best first
+    pub labels: Vec<LabelScore>,
+    pub model: String,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PipelineRunRequest {
+    // Variables available to the steps' templates as `input.<name>`
+    #[serde(default)]
+    pub input: Map<String, Value>,
+    // Runs the steps that don't name a model
+    pub model: Option<String>,
+    pub user: Option<String>,
+}
+
+// Output of a pipeline step
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PipelineStepOutput {
+    pub id: String,
+    pub kind: StepKind,
+    // A string, or the JSON object of an extract or classify step
+    pub output: Value,
+    // None for template steps, which don't call a model
+    pub model: Option<String>,
+    pub usage: Option<Usage>,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PipelineRunResponse {
+    pub pipeline: String,
+    // Output of the last step
+    pub output: Value,
+    // Every step, in the pipeline's order
+    pub steps: Vec<PipelineStepOutput>,
+    // Usage of all steps together
+    pub usage: Usage,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TokenizeRequest {
+    pub text: String,
+    pub model: Option<String>,
+    // Include the token IDs in the response
+    #[serde(default)]
+    pub return_tokens: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TokenizeResponse {
+    pub model: String,
+    pub count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<Vec<u32>>,
+    // True when the backend couldn't tokenize and `count` is a character-based estimate
+    pub estimated: bool,
+    pub context_window: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PromptTemplateRequest {
+    pub template: String,
+    pub description: Option<String>,
+}
+
+// Query parameters of a session history page
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PageQuery {
+    // Index of the first message returned (default: 0)
+    pub offset: Option<usize>,
+    // Messages returned, at most 200 (default: 50)
+    pub limit: Option<usize>,
+}
+
+// A message of a session's history
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SessionMessage {
+    // Position in the session's history
+    pub index: usize,
+    pub role: String,
+    pub content: String,
+}
+
+// One page of a session's history, oldest message first
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SessionPage {
+    pub session_id: Uuid,
+    pub assistant: Option<String>,
+    pub messages_total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    pub messages: Vec<SessionMessage>,
+}
+
+// Query parameters of the session list
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SessionListQuery {
+    // Only sessions whose requests named this user
+    pub user: Option<String>,
+    // Only sessions carrying this tag
+    pub tag: Option<String>,
+    // Only pinned (true) or unpinned (false) sessions
+    pub pinned: Option<bool>,
+    // "active" (default), "archived" or "all"
+    #[serde(default)]
+    pub status: SessionStatus,
+}
+
+// Which sessions the session list includes, by archive state
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStatus {
+    #[default]
+    Active,
+    Archived,
+    All,
+}
+
+// Tags added to a session
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TagsRequest {
+    pub tags: Vec<String>,
+}
+
+// A new document collection
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CollectionRequest {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+// A plain text document added to a collection
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct DocumentRequest {
+    // Shown with the passages retrieved from it, e.g. a file name
+    pub name: String,
+    pub text: String,
+}
+
+// A web page, or a document file on the web, fetched into a collection
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UrlRequest {
+    pub url: String,
+    // Name of the document; defaults to the page's title, or else the URL
+    pub name: Option<String>,
+}
+
+// Query parameters of a document file upload
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UploadQuery {
+    // Name of the document, usually its file name, whose extension gives the format when
+    // the Content-Type doesn't
+    pub name: String,
+    // Read the file as this format, whatever its name and Content-Type
+    pub format: Option<DocumentFormat>,
+}
+
+// Collections attached to a session
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SessionCollectionsRequest {
+    pub collections: Vec<Uuid>,
+}
+
+// Ordered few-shot examples of an assistant
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ExamplesRequest {
+    pub examples: Vec<FewShotExample>,
+}
+
+// Query parameters of a conversation search
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchQuery {
+    // Words that must all appear in a message, matched case-insensitively
+    pub q: String,
+    // User whose sessions are searched
+    pub user: String,
+    // Sessions returned, at most 100 (default: 20)
+    pub limit: Option<usize>,
+}
+
+// Starts a benchmark; unset fields use the defaults documented in the README
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BenchRequest {
+    #[serde(default)]
+    pub model: Option<String>,
+    // Total requests sent (default: 20, at most 1000)
+    #[serde(default)]
+    pub requests: Option<usize>,
+    // Requests in flight at once (default: 4, at most 64)
+    #[serde(default)]
+    pub concurrency: Option<usize>,
+    #[serde(default)]
+    pub max_tokens: Option<usize>,
+    // Sent as every request's message instead of the built-in synthetic prompts
+    #[serde(default)]
+    pub prompt: Option<String>,
+}
+
+// Starts an evaluation run
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct EvalRequest {
+    // Suite to run, by file name
+    pub suite: String,
+    // Model under test; the default model when omitted
+    #[serde(default)]
+    pub model: Option<String>,
+    // Model that grades `judge` cases; the model under test when omitted
+    #[serde(default)]
+    pub judge_model: Option<String>,
+}
+
+// Query parameters of the evaluation run listing
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EvalRunsQuery {
+    pub suite: Option<String>,
+    pub model: Option<String>,
+}
+
+// Query parameters of a server state import
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StateImportQuery {
+    // Drop the sessions that aren't in the archive instead of keeping them alongside
+    #[serde(default)]
+    pub replace: bool,
+}
+
+// Query parameters of a user data deletion request
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeletionQuery {
+    // Token returned by the first, unconfirmed request
+    pub confirm: Option<String>,
+}
+
+// An invitation to join the workspace
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct InviteRequest {
+    pub email: String,
+    // Role the invitee gets on accepting; member when omitted
+    #[serde(default)]
+    pub role: MemberRole,
+}
+
+// A new invitation and the token that accepts it, which is only returned here
+#[derive(Debug, Serialize, ToSchema)]
+pub struct InviteResponse {
+    pub invite: Invite,
+    pub token: String,
+}
+
+// Accepting an invitation with the token it was created with
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AcceptInviteRequest {
+    pub token: String,
+}
+
+// A member's new role
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MemberRoleRequest {
+    pub role: MemberRole,
+}
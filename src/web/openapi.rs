@@ -0,0 +1,173 @@
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use crate::assistants::{Assistant, FewShotExample};
+use crate::audit::usage::{UsageGrouping, UsagePoint, UsageSeries};
+use crate::audit::AuditEntry;
+use crate::collections::{Collection, Document};
+use crate::collections::ingest::DocumentFormat;
+use crate::consensus::Consensus;
+use crate::eval::{CaseResult, EvalCase, EvalRun, EvalSuite, RunStatus, Scoring};
+use crate::extract::{Entity, EntityKind, LabelScore, Metadata, Sentiment};
+use crate::jobs::{Job, JobStatus};
+use crate::members::{Invite, Member, MemberRole};
+use crate::memory::Memory;
+use crate::model::bench::{BenchReport, Percentiles};
+use crate::model::length::ResponseLength;
+use crate::model::protocol::ReasoningEffort;
+use crate::model::registry::SpeculativeConfig;
+use crate::pipelines::{Pipeline, PipelineStep, StepKind};
+use crate::prompt_log::PromptLogSettings;
+use crate::prompts::PromptTemplate;
+use crate::schedules::{Schedule, ScheduleInfo, ScheduleState};
+use crate::session::search::{Highlight, MessageMatch, SessionMatch};
+use crate::session::{SessionSettings, SessionSummary};
+use crate::summarize::SummaryStyle;
+use crate::web::error::ErrorBody;
+use crate::web::handlers;
+use crate::model::downloads::{Download, DownloadRequest, DownloadStatus, LocalModelFile, ModelsDiskUsage};
+use crate::model::resources::DiskSpace;
+use crate::web::mode::{ModeRequest, ModeState, ServerMode};
+use crate::web::models::{
+    AsyncGenerateRequest, BatchChatRequest, BatchItemResult, ChatRequest, ChatResponse, RefinePasses, ModelInfo,
+    PromptTemplateRequest, TokenizeRequest, TokenizeResponse, Usage, CompleteRequest, CompleteResponse,
+    FimRequest, TranslateRequest, TranslateResponse, SummarizeRequest, SummarizeResponse, ExtractRequest, ExtractResponse, ClassifyRequest, ClassifyResponse, PipelineRunRequest, PipelineRunResponse, PipelineStepOutput, CompareRequest, CompareResult, EvalRequest,
+    BenchRequest, SessionMessage, SessionPage, TagsRequest, SessionStatus, ExamplesRequest,
+    CollectionRequest, DocumentRequest, UrlRequest, SessionCollectionsRequest,
+    InviteRequest, InviteResponse, AcceptInviteRequest, MemberRoleRequest,
+};
+
+// OpenAPI description of the `/api` routes, served at `/api/openapi.json`
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "llama-on-rust API", description = "Chat with local LLaMA models over HTTP"),
+    paths(
+        handlers::chat,
+        handlers::chat_batch,
+        handlers::complete,
+        handlers::fim,
+        handlers::translate,
+        handlers::summarize,
+        handlers::extract,
+        handlers::classify,
+        handlers::list_pipelines,
+        handlers::run_pipeline,
+        handlers::compare,
+        handlers::list_models,
+        handlers::tokenize,
+        handlers::generate_async,
+        handlers::get_job,
+        handlers::list_prompts,
+        handlers::get_prompt,
+        handlers::put_prompt,
+        handlers::delete_prompt,
+        handlers::list_assistants,
+        handlers::get_assistant,
+        handlers::put_assistant,
+        handlers::delete_assistant,
+        handlers::list_assistant_examples,
+        handlers::put_assistant_examples,
+        handlers::add_assistant_example,
+        handlers::delete_assistant_example,
+        handlers::list_collections,
+        handlers::create_collection,
+        handlers::get_collection,
+        handlers::delete_collection,
+        handlers::add_collection_document,
+        handlers::upload_collection_document,
+        handlers::add_collection_url,
+        handlers::delete_collection_document,
+        handlers::list_schedules,
+        handlers::get_schedule,
+        handlers::put_schedule,
+        handlers::delete_schedule,
+        handlers::run_schedule_now,
+        handlers::list_sessions,
+        handlers::list_trash,
+        handlers::delete_session,
+        handlers::restore_session,
+        handlers::purge_session,
+        handlers::get_session,
+        handlers::pin_session,
+        handlers::unpin_session,
+        handlers::archive_session,
+        handlers::unarchive_session,
+        handlers::add_session_tags,
+        handlers::delete_session_tag,
+        handlers::list_session_collections,
+        handlers::attach_session_collections,
+        handlers::detach_session_collection,
+        handlers::get_session_settings,
+        handlers::put_session_settings,
+        handlers::export_session,
+        handlers::search_sessions,
+        handlers::admin_stats,
+        handlers::admin_audit,
+        handlers::admin_usage,
+        handlers::run_bench,
+        handlers::list_eval_suites,
+        handlers::run_eval,
+        handlers::list_eval_runs,
+        handlers::get_eval_run,
+        handlers::get_prompt_log_settings,
+        handlers::put_prompt_log_settings,
+        handlers::list_model_downloads,
+        handlers::start_model_download,
+        handlers::activate_model,
+        handlers::set_mode,
+        handlers::export_state,
+        handlers::import_state,
+        handlers::list_members,
+        handlers::create_invite,
+        handlers::revoke_invite,
+        handlers::accept_invite,
+        handlers::set_member_role,
+        handlers::remove_member,
+        handlers::list_memories,
+        handlers::delete_memory,
+        handlers::export_user_data,
+        handlers::delete_user_data,
+        handlers::cancel_user_data_deletion,
+    ),
+    components(schemas(
+        ChatRequest, ChatResponse, RefinePasses, Consensus, Citation, ReasoningEffort, ResponseLength, Usage, CompleteRequest, CompleteResponse, FimRequest, TranslateRequest, TranslateResponse, SummarizeRequest, SummarizeResponse, SummaryStyle,
+        ExtractRequest, ExtractResponse, Metadata, Entity, EntityKind, Sentiment, ClassifyRequest, ClassifyResponse, LabelScore,
+        Pipeline, PipelineStep, StepKind, PipelineRunRequest, PipelineRunResponse, PipelineStepOutput,
+        CompareRequest, CompareResult, BatchChatRequest, BatchItemResult, AsyncGenerateRequest,
+        Collection, Document, DocumentFormat, CollectionRequest, DocumentRequest, UrlRequest, SessionCollectionsRequest,
+        Schedule, ScheduleInfo, ScheduleState, ModelInfo, SpeculativeConfig, SessionSummary, SessionStatus, TagsRequest, SessionPage, SessionMessage, TokenizeRequest, TokenizeResponse, PromptTemplateRequest, PromptTemplate, Assistant, FewShotExample, ExamplesRequest, SessionSettings,
+        Job, JobStatus, AuditEntry, UsageGrouping, UsageSeries, UsagePoint, PromptLogSettings,
+        BenchRequest, BenchReport, Percentiles, EvalRequest, EvalSuite, EvalCase, Scoring, EvalRun, RunStatus, CaseResult,
+        SessionMatch, MessageMatch, Highlight, Memory, ModeRequest, ModeState, ServerMode, DownloadRequest, Download, DownloadStatus, LocalModelFile, ModelsDiskUsage, DiskSpace,
+        Member, MemberRole, Invite, InviteRequest, InviteResponse, AcceptInviteRequest, MemberRoleRequest, ErrorBody,
+    )),
+    modifiers(&AdminToken),
+    tags(
+        (name = "chat", description = "Synchronous and batch chat, model comparison, plain completions and fill-in-the-middle"),
+        (name = "jobs", description = "Background generation"),
+        (name = "models", description = "Model registry and tokenization"),
+        (name = "prompts", description = "Prompt template library; changing it requires ADMIN_TOKEN"),
+        (name = "pipelines", description = "Multi-step prompt chains run on the server"),
+        (name = "assistants", description = "Assistant profiles; changing them requires ADMIN_TOKEN"),
+        (name = "collections", description = "Document collections that sessions answer from; changing them requires ADMIN_TOKEN"),
+        (name = "schedules", description = "Prompts run on a cron schedule; requires ADMIN_TOKEN"),
+        (name = "sessions", description = "Session list, history, tags, pinning and archiving, attached collections, deletion and trash, per-session generation settings, export and search"),
+        (name = "admin", description = "Statistics, usage, benchmarks, evaluations, audit log and prompt logging; requires ADMIN_TOKEN"),
+        (name = "members", description = "Workspace members and invitations; requires ADMIN_TOKEN, except accepting an invitation"),
+        (name = "privacy", description = "User data export and deletion, and remembered facts; requires ADMIN_TOKEN"),
+    )
+)]
+pub struct ApiDoc;
+
+// Declares the bearer scheme referenced by admin-only operations
+struct AdminToken;
+
+impl Modify for AdminToken {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "admin_token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use std::env;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::OnceLock;
+use actix_web::HttpRequest;
+use log::{info, warn};
+
+/// Environment variables for running behind a reverse proxy:
+///
+/// - `TRUSTED_PROXIES`: Comma-separated IP addresses or CIDR ranges of proxies whose
+///   `Forwarded` / `X-Forwarded-For` headers are believed, e.g. "127.0.0.1,10.0.0.0/8".
+///   Forwarding headers are ignored when unset.
+
+// An address or CIDR range
+struct TrustedNet {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl TrustedNet {
+    fn parse(spec: &str) -> Option<Self> {
+        let (addr, prefix) = match spec.split_once('/') {
+            Some((addr, prefix)) => (addr.parse::<IpAddr>().ok()?, Some(prefix.parse::<u8>().ok()?)),
+            None => (spec.parse::<IpAddr>().ok()?, None),
+        };
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(max);
+        (prefix <= max).then_some(Self { addr, prefix })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+static TRUSTED_PROXIES: OnceLock<Vec<TrustedNet>> = OnceLock::new();
+
+fn trusted_proxies() -> &'static [TrustedNet] {
+    TRUSTED_PROXIES.get_or_init(|| {
+        let nets: Vec<TrustedNet> = env::var("TRUSTED_PROXIES")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|spec| !spec.is_empty())
+            .filter_map(|spec| {
+                let net = TrustedNet::parse(spec);
+                if net.is_none() {
+                    warn!("Ignoring invalid TRUSTED_PROXIES entry: {}", spec);
+                }
+                net
+            })
+            .collect();
+        if !nets.is_empty() {
+            info!("Trusting forwarding headers from {} proxy range(s)", nets.len());
+        }
+        nets
+    })
+}
+
+fn is_trusted(ip: IpAddr) -> bool {
+    trusted_proxies().iter().any(|net| net.contains(ip))
+}
+
+// Parse a forwarded node such as `192.0.2.1`, `192.0.2.1:4711` or `"[2001:db8::1]:4711"`
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    node.parse::<IpAddr>()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| node.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>().ok())
+}
+
+// Addresses listed by forwarding headers, client first; `Forwarded` wins over `X-Forwarded-For`
+fn forwarded_chain(req: &HttpRequest) -> Vec<IpAddr> {
+    if let Some(forwarded) = req.headers().get("Forwarded").and_then(|h| h.to_str().ok()) {
+        return forwarded
+            .split(',')
+            .filter_map(|element| {
+                element.split(';').find_map(|pair| {
+                    let (key, value) = pair.split_once('=')?;
+                    key.trim().eq_ignore_ascii_case("for").then(|| parse_node(value))?
+                })
+            })
+            .collect();
+    }
+
+    req.headers()
+        .get("X-Forwarded-For")
+        .and_then(|h| h.to_str().ok())
+        .map(|list| list.split(',').filter_map(parse_node).collect())
+        .unwrap_or_default()
+}
+
+// The address of the client that made the request. Forwarding headers are only
+// believed when they come from a trusted proxy; the chain is walked from the
+// nearest hop and the first untrusted address is the client.
+pub fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    if !is_trusted(peer) {
+        return Some(peer);
+    }
+
+    let chain = forwarded_chain(req);
+    let client = chain
+        .iter()
+        .rev()
+        .find(|ip| !is_trusted(**ip))
+        .or_else(|| chain.first())
+        .copied()
+        .unwrap_or(peer);
+    Some(client)
+}
@@ -0,0 +1,49 @@
+use std::future::Future;
+use actix_web::HttpRequest;
+use reqwest::RequestBuilder;
+use uuid::Uuid;
+
+// Header carrying the request ID in both directions, and to the backends
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+// Longest accepted incoming ID; longer ones are replaced
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+tokio::task_local! {
+    // ID of the request the current task is working on
+    static REQUEST_ID: String;
+}
+
+// The request ID supplied by the client, or a fresh one when it's missing or unusable
+pub fn from_request(req: &HttpRequest) -> String {
+    req.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .map(str::trim)
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LENGTH)
+        .filter(|id| id.chars().all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c)))
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+// ID of the request being handled by this task, if any
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+// Run a synchronous step with the request ID set
+pub fn sync_scope<R>(id: String, f: impl FnOnce() -> R) -> R {
+    REQUEST_ID.sync_scope(id, f)
+}
+
+// Pass the current request ID on to an upstream call
+pub fn forward(request: RequestBuilder) -> RequestBuilder {
+    match current() {
+        Some(id) => request.header(REQUEST_ID_HEADER, id),
+        None => request,
+    }
+}
+
+// Run a future with the request ID set, e.g. work continuing in a background task
+pub fn scope<F: Future>(id: String, fut: F) -> impl Future<Output = F::Output> {
+    REQUEST_ID.scope(id, fut)
+}
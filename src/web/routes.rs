@@ -1,11 +1,111 @@
 use actix_web::web;
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+
 use crate::web::handlers;
+use crate::web::limits;
+use crate::web::openapi::ApiDoc;
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
+    // Registered ahead of the `/api` scope, which would otherwise claim these paths
     cfg.service(
+        SwaggerUi::new("/api/docs/{_:.*}").url("/api/openapi.json", ApiDoc::openapi())
+    )
+    .service(
         web::scope("/api")
             .route("/chat", web::post().to(handlers::chat))
+            .route("/chat/batch", web::post().to(handlers::chat_batch))
+            .route("/complete", web::post().to(handlers::complete))
+            .route("/fim", web::post().to(handlers::fim))
+            .route("/translate", web::post().to(handlers::translate))
+            .route("/summarize", web::post().to(handlers::summarize))
+            .route("/extract", web::post().to(handlers::extract))
+            .route("/classify", web::post().to(handlers::classify))
+            .route("/compare", web::post().to(handlers::compare))
+            .route("/models", web::get().to(handlers::list_models))
+            .route("/tokenize", web::post().to(handlers::tokenize))
+            .route("/generate/async", web::post().to(handlers::generate_async))
+            .route("/jobs/{id}", web::get().to(handlers::get_job))
+            .route("/prompts", web::get().to(handlers::list_prompts))
+            .route("/pipelines", web::get().to(handlers::list_pipelines))
+            .route("/pipelines/{name}/run", web::post().to(handlers::run_pipeline))
+            .route("/prompts/{name}", web::get().to(handlers::get_prompt))
+            .route("/prompts/{name}", web::put().to(handlers::put_prompt))
+            .route("/prompts/{name}", web::delete().to(handlers::delete_prompt))
+            .route("/assistants", web::get().to(handlers::list_assistants))
+            .route("/assistants/{name}", web::get().to(handlers::get_assistant))
+            .route("/assistants/{name}", web::put().to(handlers::put_assistant))
+            .route("/assistants/{name}", web::delete().to(handlers::delete_assistant))
+            .route("/assistants/{name}/examples", web::get().to(handlers::list_assistant_examples))
+            .route("/assistants/{name}/examples", web::put().to(handlers::put_assistant_examples))
+            .route("/assistants/{name}/examples", web::post().to(handlers::add_assistant_example))
+            .route("/assistants/{name}/examples/{index}", web::delete().to(handlers::delete_assistant_example))
+            .route("/collections", web::get().to(handlers::list_collections))
+            .route("/collections", web::post().to(handlers::create_collection))
+            .route("/collections/{id}", web::get().to(handlers::get_collection))
+            .route("/collections/{id}", web::delete().to(handlers::delete_collection))
+            .route("/collections/{id}/documents", web::post().to(handlers::add_collection_document))
+            .route("/collections/{id}/files", web::post().to(handlers::upload_collection_document))
+            .route("/collections/{id}/urls", web::post().to(handlers::add_collection_url))
+            .route("/collections/{id}/documents/{document_id}", web::delete().to(handlers::delete_collection_document))
+            .route("/schedules", web::get().to(handlers::list_schedules))
+            .route("/schedules/{name}", web::get().to(handlers::get_schedule))
+            .route("/schedules/{name}", web::put().to(handlers::put_schedule))
+            .route("/schedules/{name}", web::delete().to(handlers::delete_schedule))
+            .route("/schedules/{name}/run", web::post().to(handlers::run_schedule_now))
+            .route("/sessions", web::get().to(handlers::list_sessions))
+            .route("/sessions/trash", web::get().to(handlers::list_trash))
+            .route("/sessions/trash/{id}", web::delete().to(handlers::purge_session))
+            .route("/sessions/{id}", web::get().to(handlers::get_session))
+            .route("/sessions/{id}", web::delete().to(handlers::delete_session))
+            .route("/sessions/{id}/restore", web::post().to(handlers::restore_session))
+            .route("/sessions/{id}/pin", web::put().to(handlers::pin_session))
+            .route("/sessions/{id}/pin", web::delete().to(handlers::unpin_session))
+            .route("/sessions/{id}/archive", web::put().to(handlers::archive_session))
+            .route("/sessions/{id}/archive", web::delete().to(handlers::unarchive_session))
+            .route("/sessions/{id}/tags", web::post().to(handlers::add_session_tags))
+            .route("/sessions/{id}/tags/{tag}", web::delete().to(handlers::delete_session_tag))
+            .route("/sessions/{id}/collections", web::get().to(handlers::list_session_collections))
+            .route("/sessions/{id}/collections", web::post().to(handlers::attach_session_collections))
+            .route("/sessions/{id}/collections/{collection_id}", web::delete().to(handlers::detach_session_collection))
+            .route("/sessions/{id}/settings", web::get().to(handlers::get_session_settings))
+            .route("/sessions/{id}/settings", web::put().to(handlers::put_session_settings))
+            .route("/sessions/{id}/export", web::get().to(handlers::export_session))
+            .route("/search", web::get().to(handlers::search_sessions))
+            .route("/admin/stats", web::get().to(handlers::admin_stats))
+            .route("/admin/audit", web::get().to(handlers::admin_audit))
+            .route("/admin/usage", web::get().to(handlers::admin_usage))
+            .route("/admin/bench", web::post().to(handlers::run_bench))
+            .route("/admin/eval", web::post().to(handlers::run_eval))
+            .route("/admin/eval/suites", web::get().to(handlers::list_eval_suites))
+            .route("/admin/eval/runs", web::get().to(handlers::list_eval_runs))
+            .route("/admin/eval/runs/{id}", web::get().to(handlers::get_eval_run))
+            .route("/admin/prompt-log", web::get().to(handlers::get_prompt_log_settings))
+            .route("/admin/prompt-log", web::put().to(handlers::put_prompt_log_settings))
+            .route("/admin/mode", web::post().to(handlers::set_mode))
+            .route("/admin/models/downloads", web::get().to(handlers::list_model_downloads))
+            .route("/admin/models/downloads", web::post().to(handlers::start_model_download))
+            .route("/admin/models/{name}/activate", web::post().to(handlers::activate_model))
+            .route("/admin/state/export", web::get().to(handlers::export_state))
+            .service(
+                web::resource("/admin/state/import")
+                    .app_data(limits::state_import_json_config())
+                    .route(web::post().to(handlers::import_state))
+            )
+            .route("/admin/members", web::get().to(handlers::list_members))
+            .route("/admin/members/invites", web::post().to(handlers::create_invite))
+            .route("/admin/members/invites/{id}", web::delete().to(handlers::revoke_invite))
+            .route("/admin/members/{id}/role", web::put().to(handlers::set_member_role))
+            .route("/admin/members/{id}", web::delete().to(handlers::remove_member))
+            .route("/members/accept", web::post().to(handlers::accept_invite))
+            .route("/users/{id}/memories", web::get().to(handlers::list_memories))
+            .route("/users/{id}/memories/{memory_id}", web::delete().to(handlers::delete_memory))
+            .route("/users/{id}/data", web::delete().to(handlers::delete_user_data))
+            .route("/users/{id}/data/export", web::get().to(handlers::export_user_data))
+            .route("/users/{id}/data/cancel-deletion", web::post().to(handlers::cancel_user_data_deletion))
     )
     .route("/", web::get().to(handlers::index))
-    .route("/health", web::get().to(handlers::health_check));
+    .route("/admin", web::get().to(handlers::admin_page))
+    .route("/health", web::get().to(handlers::health_check))
+    .route("/metrics", web::get().to(handlers::metrics));
 } 
\ No newline at end of file
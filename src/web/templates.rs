@@ -0,0 +1,117 @@
+use std::env;
+use std::path::Path;
+use std::time::Duration;
+use actix_web::web::Data;
+use anyhow::{Context, Result};
+use log::{info, error};
+use notify::{RecursiveMode, Watcher};
+use rust_embed::RustEmbed;
+use tera::Tera;
+
+use crate::AppState;
+
+// Directory whose templates override the embedded ones
+const DEFAULT_TEMPLATES_DIR: &str = "templates";
+// Quiet period after a change before reloading, so one save triggers one reload
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Environment variables for loading page templates:
+///
+/// - `TEMPLATES_DIR`: Directory of Tera templates; a file there replaces the embedded
+///   template of the same name (default: "templates"). The templates compiled into the
+///   binary are used alone when the directory doesn't exist.
+/// - `TEMPLATE_HOT_RELOAD`: Watch `TEMPLATES_DIR` and reload the templates when a file
+///   changes, "true" or "false" (default: false). Meant for development.
+
+// The templates/ directory as it was at build time
+#[derive(RustEmbed)]
+#[folder = "templates/"]
+struct EmbeddedTemplates;
+
+fn embedded() -> Result<Tera> {
+    let templates = EmbeddedTemplates::iter()
+        .filter_map(|name| {
+            let file = EmbeddedTemplates::get(&name)?;
+            let source = String::from_utf8(file.data.into_owned()).ok()?;
+            Some((name.into_owned(), source))
+        })
+        .collect::<Vec<_>>();
+
+    let mut tera = Tera::default();
+    tera.add_raw_templates(templates).context("Invalid embedded template")?;
+    Ok(tera)
+}
+
+fn templates_dir() -> String {
+    env::var("TEMPLATES_DIR").unwrap_or_else(|_| DEFAULT_TEMPLATES_DIR.to_string())
+}
+
+// Templates from TEMPLATES_DIR, with the embedded copies filling in anything missing
+pub fn load() -> Result<Tera> {
+    let dir = templates_dir();
+
+    let mut tera = if Path::new(&dir).is_dir() {
+        let mut tera = Tera::new(&format!("{}/**/*", dir))
+            .with_context(|| format!("Failed to parse templates in {}", dir))?;
+        // Existing templates are kept, so files on disk win
+        tera.extend(&embedded()?).context("Failed to merge embedded templates")?;
+        info!("Loaded templates from {}", dir);
+        tera
+    } else {
+        info!("Template directory {} not found; using embedded templates", dir);
+        embedded()?
+    };
+
+    tera.autoescape_on(vec![".html", ".sql"]);
+    Ok(tera)
+}
+
+// Reload the templates whenever a file in TEMPLATES_DIR changes, if TEMPLATE_HOT_RELOAD is set.
+// A template that fails to parse is reported and the previous templates stay in use.
+pub fn start_hot_reload(data: Data<AppState>) {
+    let enabled = env::var("TEMPLATE_HOT_RELOAD")
+        .map(|v| v == "true" || v == "1")
+        .unwrap_or(false);
+    if !enabled {
+        return;
+    }
+
+    let dir = templates_dir();
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if event.is_ok_and(|event| !event.kind.is_access()) {
+            let _ = sender.send(());
+        }
+    });
+    let mut watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            error!("Failed to start template watcher: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = watcher.watch(Path::new(&dir), RecursiveMode::Recursive) {
+        error!("Failed to watch template directory {}: {}", dir, e);
+        return;
+    }
+    info!("Watching {} for template changes", dir);
+
+    tokio::spawn(async move {
+        // The watcher stops when dropped
+        let _watcher = watcher;
+        while receiver.recv().await.is_some() {
+            tokio::time::sleep(RELOAD_DEBOUNCE).await;
+            while receiver.try_recv().is_ok() {}
+
+            match load() {
+                Ok(tera) => {
+                    if let Ok(mut current) = data.tera.write() {
+                        *current = tera;
+                        info!("Reloaded templates");
+                    }
+                }
+                Err(e) => error!("Keeping previous templates; reload failed: {:#}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,136 @@
+use std::env;
+use std::sync::{Arc, RwLock};
+use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::web::Data;
+use actix_web::{test, App, Error};
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::assistants::AssistantStore;
+use crate::collections::CollectionStore;
+use crate::eval::EvalStore;
+use crate::guardrails::Guardrails;
+use crate::jobs::JobQueue;
+use crate::members::MemberStore;
+use crate::memory::MemoryStore;
+use crate::model::downloads::DownloadManager;
+use crate::model::mock::MockBackend;
+use crate::model::ModelManager;
+use crate::moderation::ModerationPipeline;
+use crate::pii::PiiFilter;
+use crate::pipelines::PipelineStore;
+use crate::privacy::PrivacyManager;
+use crate::prompts::PromptLibrary;
+use crate::schedules::ScheduleStore;
+use crate::session::SessionStore;
+use crate::web::idempotency::IdempotencyStore;
+use crate::web::mode::ModeSwitch;
+use crate::web::{limits, routes, templates};
+use crate::AppState;
+
+// The model manager reads its backend URL from the environment, which tests share
+static BACKEND_URL: Mutex<()> = Mutex::const_new(());
+
+// The API routes, backed by a mock backend of their own, with state built as `main` does
+async fn app() -> App<
+    impl ServiceFactory<ServiceRequest, Config = (), Response = ServiceResponse, Error = Error, InitError = ()>,
+> {
+    let url = MockBackend::ephemeral().start().expect("mock backend starts");
+    let model_manager = {
+        let _env = BACKEND_URL.lock().await;
+        env::set_var("MISTRAL_SERVER_URL", &url);
+        Data::new(ModelManager::new().await.expect("model manager starts"))
+    };
+    let app_state = Data::new(AppState {
+        tera: RwLock::new(templates::load().expect("templates parse")),
+        model: model_manager.clone(),
+        sessions: SessionStore::default(),
+        jobs: JobQueue::start(),
+        prompts: PromptLibrary::load(),
+        pipelines: PipelineStore::load(&model_manager),
+        assistants: AssistantStore::load(),
+        moderation: ModerationPipeline::load(&model_manager).expect("moderation config loads"),
+        guardrails: Guardrails::load(&model_manager).expect("guardrails config loads"),
+        pii: PiiFilter::from_env().expect("PII filter configures"),
+        privacy: PrivacyManager::from_env(),
+        idempotency: IdempotencyStore::from_env(),
+        members: MemberStore::load(),
+        memory: Arc::new(MemoryStore::load()),
+        collections: CollectionStore::load(),
+        evals: Arc::new(EvalStore::load()),
+        schedules: ScheduleStore::load(),
+        mode: ModeSwitch::default(),
+        downloads: Arc::new(DownloadManager::from_env()),
+    });
+    App::new()
+        .app_data(app_state)
+        .app_data(model_manager)
+        .app_data(limits::json_config())
+        .configure(routes::configure)
+}
+
+fn chat(body: Value) -> test::TestRequest {
+    test::TestRequest::post().uri("/api/chat").set_json(body)
+}
+
+fn history(session_id: &str) -> test::TestRequest {
+    test::TestRequest::get().uri(&format!("/api/sessions/{}", session_id))
+}
+
+fn messages(page: &Value) -> Vec<(&str, &str)> {
+    page["messages"]
+        .as_array()
+        .expect("page has messages")
+        .iter()
+        .map(|m| (m["role"].as_str().unwrap_or_default(), m["content"].as_str().unwrap_or_default()))
+        .collect()
+}
+
+#[actix_web::test]
+async fn chat_echoes_the_message_and_records_the_turn() {
+    let app = test::init_service(app().await).await;
+
+    let res = test::call_service(&app, chat(json!({ "message": "hello there" })).to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let reply: Value = test::read_body_json(res).await;
+    assert_eq!(reply["response"], "Echo: hello there");
+    let session_id = reply["session_id"].as_str().expect("reply names its session");
+
+    let res = test::call_service(&app, history(session_id).to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let page: Value = test::read_body_json(res).await;
+    assert_eq!(page["messages_total"], 2);
+    assert_eq!(messages(&page), [("user", "hello there"), ("assistant", "Echo: hello there")]);
+}
+
+#[actix_web::test]
+async fn follow_up_continues_the_session() {
+    let app = test::init_service(app().await).await;
+
+    let first: Value = test::call_and_read_body_json(&app, chat(json!({ "message": "first question" })).to_request()).await;
+    let session_id = first["session_id"].as_str().expect("reply names its session");
+    let second: Value = test::call_and_read_body_json(
+        &app,
+        chat(json!({ "message": "second question", "session_id": session_id })).to_request(),
+    ).await;
+    assert_eq!(second["response"], "Echo: second question");
+    assert_eq!(second["session_id"], session_id);
+
+    let page: Value = test::call_and_read_body_json(&app, history(session_id).to_request()).await;
+    assert_eq!(messages(&page), [
+        ("user", "first question"),
+        ("assistant", "Echo: first question"),
+        ("user", "second question"),
+        ("assistant", "Echo: second question"),
+    ]);
+}
+
+#[actix_web::test]
+async fn unknown_session_is_not_found() {
+    let app = test::init_service(app().await).await;
+
+    let res = test::call_service(&app, history(&Uuid::new_v4().to_string()).to_request()).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,65 @@
+document.addEventListener('DOMContentLoaded', () => {
+    const REFRESH_INTERVAL_MS = 5000;
+    
+    // Build a table row from a list of cell values
+    function row(cells) {
+        const tr = document.createElement('tr');
+        cells.forEach(value => {
+            const td = document.createElement('td');
+            td.textContent = value;
+            tr.appendChild(td);
+        });
+        return tr;
+    }
+    
+    function render(stats) {
+        document.getElementById('stat-sessions').textContent = stats.active_sessions;
+        document.getElementById('stat-queued').textContent = stats.queue.queued_jobs;
+        document.getElementById('stat-in-flight').textContent =
+            `${stats.queue.in_flight_requests} / ${stats.queue.max_concurrent_requests}`;
+        document.getElementById('stat-tps').textContent = stats.tokens_per_sec.toFixed(1);
+        document.getElementById('stat-uptime').textContent = stats.uptime_secs;
+        
+        const backendRows = document.getElementById('backend-rows');
+        backendRows.replaceChildren();
+        Object.entries(stats.backends).forEach(([model, backends]) => {
+            backends.forEach(backend => {
+                backendRows.appendChild(row([model, backend.url, backend.healthy ? 'yes' : 'no', backend.in_flight]));
+            });
+        });
+        
+        const usageRows = document.getElementById('usage-rows');
+        usageRows.replaceChildren();
+        Object.entries(stats.usage_by_user).forEach(([user, usage]) => {
+            usageRows.appendChild(row([user, usage.requests, usage.prompt_tokens, usage.completion_tokens]));
+        });
+        
+        const errorList = document.getElementById('error-list');
+        errorList.replaceChildren();
+        if (stats.recent_errors.length === 0) {
+            const li = document.createElement('li');
+            li.textContent = 'No recent errors';
+            errorList.appendChild(li);
+        }
+        stats.recent_errors.forEach(error => {
+            const li = document.createElement('li');
+            li.textContent = `${error.at} [${error.source}] ${error.message}`;
+            errorList.appendChild(li);
+        });
+    }
+    
+    // The browser resends the Basic credentials used to open the page
+    async function refresh() {
+        try {
+            const response = await fetch('/api/admin/stats');
+            if (!response.ok) {
+                throw new Error(`Server responded with status: ${response.status}`);
+            }
+            render(await response.json());
+        } catch (error) {
+            console.error('Failed to refresh stats:', error);
+        }
+    }
+    
+    setInterval(refresh, REFRESH_INTERVAL_MS);
+});
@@ -3,6 +3,9 @@ document.addEventListener('DOMContentLoaded', () => {
     const userInput = document.getElementById('user-input');
     const chatMessages = document.getElementById('chat-messages');
     
+    // Sent with every state-changing request so the server knows it came from this page
+    const csrfToken = document.querySelector('meta[name="csrf-token"]').content;
+    
     // Session ID for tracking conversation
     let sessionId = null;
     
@@ -90,7 +93,8 @@ document.addEventListener('DOMContentLoaded', () => {
             const response = await fetch('/api/chat', {
                 method: 'POST',
                 headers: {
-                    'Content-Type': 'application/json'
+                    'Content-Type': 'application/json',
+                    'X-CSRF-Token': csrfToken
                 },
                 body: JSON.stringify({
                     message,